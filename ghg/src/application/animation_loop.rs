@@ -1,67 +1,192 @@
 use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
-use single_thread_executor::new_executor_and_spawner;
+use single_thread_executor::{new_executor_and_spawner, Monitor};
 use wasm_bindgen_futures::spawn_local;
 use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
 
+use crate::application::camera_controller::{
+	set_active_camera_controller, CameraController, CameraControllerSettings,
+};
 use crate::application::control::controller_frame;
 // use crate::application::data::load_temp_data;
-use crate::application::shaders::get_planet_shaders;
+use crate::application::lighting::LightingOptions;
+use crate::application::shaders::{get_planet_shaders, get_sky_shaders};
+use crate::application::sphere::SphereTessellation;
+use crate::application::time_cursor::TimeCursor;
 use crate::application::{data, planet};
-use crate::render_core::animation::{wrap_animation_body, AnimationFn};
+use crate::interaction_core::input_batch::begin_input_frame;
+use crate::render_core::animation::{wrap_animation_body, AnimationFn, FrameStatus};
 use crate::render_core::animation_params::AnimationParams;
-use crate::render_core::camera::Camera;
-use crate::render_core::frame_sequencer::{FrameGate, FrameMarker, FrameSequencer};
+use crate::render_core::camera::{Camera, ClipPlanes};
+use crate::render_core::culling::TerrainShell;
+use crate::render_core::frame_sequencer::{
+	BackPressure, FrameGate, FrameMarker, FramePhase, FrameSequencer,
+};
 use crate::utils::prelude::*;
 
+/// Frames a gate can go without finishing one before it's reported, about five
+/// seconds at 60 FPS
+const STALL_WARNING_FRAMES: u64 = 300;
+
+/// Animation frames to hold back while the planet is still drawing the last
+/// one, before marking another anyway
+const MAX_HELD_FRAMES: u32 = 4;
+
+/// Height of the terrain relief, as a proportion of the planet's radius,
+/// before it's changed with the controls
+pub const INITIAL_TERRAIN_SCALE: f32 = 0.03;
+
+thread_local! {
+	static SCHEDULER_MONITOR: RefCell<Option<(Monitor, Rc<FrameSequencer<AnimationParams>>)>> =
+		const { RefCell::new(None) };
+}
+
+/// Per-task poll statistics from the executor, and how each frame gate is
+/// keeping up, as JSON
+#[wasm_bindgen]
+pub fn scheduler_statistics() -> String {
+	SCHEDULER_MONITOR.with(|monitor| {
+		let monitor = monitor.borrow();
+		let Some((executor_monitor, frame_sequencer)) = monitor.as_ref() else {
+			return "null".to_owned();
+		};
+
+		let tasks: Vec<_> = executor_monitor
+			.snapshot()
+			.into_iter()
+			.map(|task| {
+				serde_json::json!({
+					"name": task.name,
+					"polls": task.polls,
+					"timeInPollMs": task.time_in_poll.as_secs_f64() * 1000.0,
+					"lastPolledMs": task.last_polled.map(|t| t.as_secs_f64() * 1000.0),
+					"queued": task.queued,
+				})
+			})
+			.collect();
+		let gates: Vec<_> = frame_sequencer
+			.statistics()
+			.into_iter()
+			.map(|gate| {
+				serde_json::json!({
+					"name": gate.name,
+					"phase": format!("{:?}", gate.phase),
+					"framesCompleted": gate.frames_completed,
+					"lastWokenFrame": gate.last_woken_frame,
+					"framesBehind": gate.frames_behind,
+					"framesSkipped": gate.frames_skipped,
+				})
+			})
+			.collect();
+
+		serde_json::json!({
+			"frame": frame_sequencer.current_frame(),
+			"framesAbandoned": frame_sequencer.frames_abandoned(),
+			"framesHeld": frame_sequencer.frames_held(),
+			"tasks": tasks,
+			"gates": gates,
+		})
+		.to_string()
+	})
+}
+
 pub fn get_animation_loop(
 	canvas: HtmlCanvasElement,
 	context: WebGl2RenderingContext,
 ) -> Result<AnimationFn, JsValue> {
 	let (executor, spawner) = new_executor_and_spawner();
+	let executor_monitor = executor.instrument();
 	spawn_local(async move {
 		executor.run().await;
 	});
 
-	let camera =
-		Rc::new(RefCell::new(Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::vec3(0.0, 0.0, 0.0))));
+	let terrain_scale = Rc::new(Cell::new(INITIAL_TERRAIN_SCALE));
 
-	let planet_shader = get_planet_shaders(&context)?;
+	let mut initial_camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::vec3(0.0, 0.0, 0.0));
+	initial_camera.set_clip_planes(ClipPlanes::AroundShell(TerrainShell::from_terrain_scale(
+		terrain_scale.get(),
+	)));
+	let camera = Rc::new(RefCell::new(initial_camera));
 
-	let current_month = Rc::new(Cell::new(0));
+	let camera_controller = Rc::new(RefCell::new(CameraController::new(
+		camera.clone(),
+		CameraControllerSettings::default(),
+	)));
+	set_active_camera_controller(&camera_controller);
+
+	let planet_shaders = get_planet_shaders(&context)?;
+	let sky_shader = get_sky_shaders(&context)?;
+
+	let time_cursor = Rc::new(Cell::new(TimeCursor::default()));
+	let lighting_options = Rc::new(Cell::new(LightingOptions::default()));
 
 	let frame_sequencer = Rc::new(FrameSequencer::<AnimationParams>::new());
-	spawner.spawn(planet::load_textures(
-		FrameGate::new(frame_sequencer.clone(), "Load Textures".to_owned()),
-		spawner.clone(),
-		context.clone(),
-		camera.clone(),
-	));
-
-	spawner.spawn(data::handle_data(
-		FrameGate::new(frame_sequencer.clone(), "Handle Data".to_owned()),
-		planet_shader.clone(),
-		current_month.clone(),
-	));
-
-	spawner.spawn(controller_frame(
-		FrameGate::new(frame_sequencer.clone(), "Controller".to_owned()),
-		canvas.clone(),
-		planet_shader.clone(),
-		camera.clone(),
-		current_month.clone(),
-	));
+	frame_sequencer.set_stall_threshold(Some(STALL_WARNING_FRAMES));
+	frame_sequencer
+		.set_back_pressure(BackPressure::WaitForCritical { max_held_frames: MAX_HELD_FRAMES });
+	SCHEDULER_MONITOR.with(|monitor| {
+		monitor.replace(Some((executor_monitor, frame_sequencer.clone())));
+	});
+	spawner.spawn_named(
+		"Load Textures",
+		planet::load_textures(
+			FrameGate::new(frame_sequencer.clone(), FramePhase::Update, "Load Textures".to_owned()),
+			spawner.clone(),
+			context.clone(),
+			camera.clone(),
+			lighting_options.clone(),
+		),
+	);
 
-	spawner.spawn(planet::draw(
-		FrameGate::new(frame_sequencer.clone(), "Draw Planet".to_owned()),
-		planet_shader.clone(),
-		camera.clone(),
-	));
+	spawner.spawn_named(
+		"Handle Data",
+		data::handle_data(
+			FrameGate::new(frame_sequencer.clone(), FramePhase::Upload, "Handle Data".to_owned()),
+			planet_shaders.clone(),
+			time_cursor.clone(),
+		),
+	);
+
+	spawner.spawn_named(
+		"Controller",
+		controller_frame(
+			FrameGate::new(frame_sequencer.clone(), FramePhase::Input, "Controller".to_owned()),
+			canvas.clone(),
+			camera_controller.clone(),
+			terrain_scale.clone(),
+			time_cursor.clone(),
+			lighting_options.clone(),
+		),
+	);
+
+	spawner.spawn_named(
+		"Draw Planet",
+		planet::draw(
+			FrameGate::new(frame_sequencer.clone(), FramePhase::Render, "Draw Planet".to_owned())
+				.critical(),
+			planet_shaders,
+			sky_shader,
+			camera.clone(),
+			SphereTessellation::default(),
+			terrain_scale.clone(),
+			time_cursor.clone(),
+			lighting_options.clone(),
+		),
+	);
 
 	let frame_marker = FrameMarker::new(frame_sequencer.clone());
 
+	// Replays bring their own frame times, so that every gate ends up where it
+	// did when recorded
 	Ok(wrap_animation_body(move |params: AnimationParams| {
-		frame_marker.frame(params);
+		let frame = frame_marker.frame(|| AnimationParams {
+			delta_time: begin_input_frame(params.delta_time),
+			..params
+		});
+		match frame {
+			Some(_) => FrameStatus::Started,
+			None => FrameStatus::Held,
+		}
 	}))
 }
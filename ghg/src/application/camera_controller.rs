@@ -0,0 +1,510 @@
+use std::cell::RefCell;
+use std::ops::Deref;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::application::geo::{geo_to_world, GeoPosition};
+use crate::render_core::camera::{Camera, CameraMode};
+#[allow(unused_imports)]
+use crate::utils::prelude::*;
+
+const PLANET_RADIUS: f32 = 1.0;
+
+/// Keeps fly-to destinations away from the poles, where the camera's up vector
+/// is undefined
+const MAX_FLY_TO_LATITUDE: f32 = 89.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraControllerSettings {
+	/// How quickly orbiting momentum dies off, per second
+	pub damping: f32,
+	/// Orbit speeds below this (in degrees per second) come to a stop
+	pub stop_speed: f32,
+	/// Orbit sensitivity at `reference_altitude`. It scales with the altitude,
+	/// so the ground moves under the pointer at about the same rate at any
+	/// zoom.
+	pub orbit_sensitivity: f32,
+	pub reference_altitude: f32,
+	/// Each unit of zoom multiplies the altitude by `e^zoom_rate`
+	pub zoom_rate: f32,
+	/// How quickly the altitude catches up to the zoom target, per second
+	pub zoom_smoothing: f32,
+	pub min_altitude: f32,
+	pub max_altitude: f32,
+}
+
+impl Default for CameraControllerSettings {
+	fn default() -> Self {
+		Self {
+			damping: 4.0,
+			stop_speed: 0.5,
+			orbit_sensitivity: 0.4,
+			reference_altitude: 2.0,
+			zoom_rate: 0.003,
+			zoom_smoothing: 12.0,
+			min_altitude: 0.2,
+			max_altitude: 3.0,
+		}
+	}
+}
+
+/// An animated move between two points above the globe
+#[derive(Clone, Debug)]
+struct Flight {
+	start_direction: nglm::Vec3,
+	end_direction: nglm::Vec3,
+	start_altitude: f32,
+	end_altitude: f32,
+	duration: Duration,
+	elapsed: Duration,
+}
+
+impl Flight {
+	fn progress(&self) -> f32 {
+		if self.duration.is_zero() {
+			1.0
+		} else {
+			(self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+		}
+	}
+
+	/// Camera position at the current point in the flight
+	fn position(&self) -> nglm::Vec3 {
+		let t = ease_in_out(self.progress());
+		let direction = slerp(&self.start_direction, &self.end_direction, t);
+		let altitude = interpolate_altitude(self.start_altitude, self.end_altitude, t);
+		direction * (PLANET_RADIUS + altitude)
+	}
+
+	fn is_finished(&self) -> bool { self.elapsed >= self.duration }
+}
+
+/// Smooths out the camera's movement around the globe. Input adds momentum or
+/// zoom, and `update` moves the camera once per frame.
+pub struct CameraController {
+	camera: Rc<RefCell<Camera>>,
+	settings: CameraControllerSettings,
+	/// Sphere movement in degrees per second, in
+	/// `Camera::orbit_around_target`'s terms
+	velocity: nglm::Vec2,
+	pending_orbit: nglm::Vec2,
+	held: bool,
+	target_altitude: f32,
+	flight: Option<Flight>,
+}
+
+impl CameraController {
+	pub fn new(camera: Rc<RefCell<Camera>>, settings: CameraControllerSettings) -> Self {
+		let altitude = altitude_of(&camera.deref().borrow().position());
+		let target_altitude = altitude.clamp(settings.min_altitude, settings.max_altitude);
+
+		Self {
+			camera,
+			settings,
+			velocity: nglm::Vec2::zeros(),
+			pending_orbit: nglm::Vec2::zeros(),
+			held: false,
+			target_altitude,
+			flight: None,
+		}
+	}
+
+	/// Queues a drag for the next `update`. Any flight in progress is
+	/// cancelled.
+	pub fn orbit(&mut self, sphere_movement: &nglm::Vec2) {
+		self.flight = None;
+		self.pending_orbit += sphere_movement;
+	}
+
+	/// While held, the camera only moves with the pointer. On release, it keeps
+	/// going with whatever speed it had.
+	pub fn hold(&mut self, held: bool) {
+		if held {
+			self.flight = None;
+		}
+		self.held = held;
+	}
+
+	/// Positive amounts zoom out, negative zoom in
+	pub fn zoom(&mut self, amount: f32) {
+		self.flight = None;
+		self.target_altitude = zoomed_altitude(self.target_altitude, amount, &self.settings);
+	}
+
+	pub fn fly_to(&mut self, latitude: f32, longitude: f32, altitude: f32, duration: Duration) {
+		let destination = GeoPosition {
+			latitude: latitude.clamp(-MAX_FLY_TO_LATITUDE, MAX_FLY_TO_LATITUDE),
+			longitude,
+		};
+		let end_altitude = altitude.clamp(self.settings.min_altitude, self.settings.max_altitude);
+
+		let position = self.camera.deref().borrow().position();
+		self.velocity = nglm::Vec2::zeros();
+		self.pending_orbit = nglm::Vec2::zeros();
+		self.target_altitude = end_altitude;
+		self.flight = Some(Flight {
+			start_direction: position.normalize(),
+			end_direction: geo_to_world(&destination),
+			start_altitude: altitude_of(&position),
+			end_altitude,
+			duration,
+			elapsed: Duration::ZERO,
+		});
+	}
+
+	pub fn is_flying(&self) -> bool { self.flight.is_some() }
+
+	/// Switches between orbit and arcball cameras, see `CameraMode`
+	pub fn cycle_camera_mode(&mut self) -> CameraMode {
+		let mut camera = self.camera.deref().borrow_mut();
+		let mode = camera.mode().next();
+		camera.set_mode(mode);
+		mode
+	}
+
+	pub fn toggle_logarithmic_depth(&mut self) -> bool {
+		let mut camera = self.camera.deref().borrow_mut();
+		let mut projection = *camera.projection();
+		projection.logarithmic_depth = !projection.logarithmic_depth;
+		camera.set_projection(projection);
+		projection.logarithmic_depth
+	}
+
+	pub fn update(&mut self, delta_time: Duration) {
+		let seconds = delta_time.as_secs_f32();
+
+		if let Some(flight) = &mut self.flight {
+			flight.elapsed += delta_time;
+
+			let mut camera = self.camera.deref().borrow_mut();
+			camera.set_position(flight.position());
+			camera.set_target(nglm::Vec3::zeros());
+
+			if flight.is_finished() {
+				self.flight = None;
+			}
+			return;
+		}
+
+		let movement = if self.pending_orbit != nglm::Vec2::zeros() || self.held {
+			if seconds > 0.0 {
+				self.velocity = self.pending_orbit / seconds;
+			}
+			self.pending_orbit
+		} else {
+			self.velocity *= (-self.settings.damping * seconds).exp();
+			if self.velocity.magnitude() < self.settings.stop_speed {
+				self.velocity = nglm::Vec2::zeros();
+			}
+			self.velocity * seconds
+		};
+		self.pending_orbit = nglm::Vec2::zeros();
+
+		let mut camera = self.camera.deref().borrow_mut();
+		let altitude = altitude_of(&camera.position());
+
+		if movement != nglm::Vec2::zeros() {
+			let sensitivity =
+				self.settings.orbit_sensitivity * altitude / self.settings.reference_altitude;
+			camera.orbit_around_target(&nglm::Vec3::zeros(), &movement, sensitivity);
+		}
+
+		let new_altitude = approach_altitude(
+			altitude,
+			self.target_altitude,
+			self.settings.zoom_smoothing * seconds,
+		);
+		if new_altitude != altitude {
+			let direction = camera.position().normalize();
+			camera.set_position(direction * (PLANET_RADIUS + new_altitude));
+		}
+	}
+}
+
+fn altitude_of(position: &nglm::Vec3) -> f32 { position.magnitude() - PLANET_RADIUS }
+
+fn zoomed_altitude(altitude: f32, amount: f32, settings: &CameraControllerSettings) -> f32 {
+	(altitude * (amount * settings.zoom_rate).exp())
+		.clamp(settings.min_altitude, settings.max_altitude)
+}
+
+/// Moves a fraction of the way towards `target`, in log space so that zooming
+/// feels the same at any altitude
+fn approach_altitude(altitude: f32, target: f32, rate: f32) -> f32 {
+	const SNAP_RATIO: f32 = 1.0e-4;
+
+	let log_difference = (target / altitude).ln();
+	if log_difference.abs() < SNAP_RATIO {
+		target
+	} else {
+		altitude * (log_difference * (1.0 - (-rate).exp())).exp()
+	}
+}
+
+fn interpolate_altitude(start: f32, end: f32, t: f32) -> f32 {
+	(start.ln() + (end.ln() - start.ln()) * t).exp()
+}
+
+/// Cubic ease-in/ease-out, for `t` between 0 and 1
+fn ease_in_out(t: f32) -> f32 {
+	let t = t.clamp(0.0, 1.0);
+	t * t * (3.0 - 2.0 * t)
+}
+
+/// Spherical interpolation between unit vectors, along the great circle that
+/// joins them
+fn slerp(from: &nglm::Vec3, to: &nglm::Vec3, t: f32) -> nglm::Vec3 {
+	const PARALLEL_EPSILON: f32 = 1.0e-5;
+
+	let cos_angle = from.dot(to).clamp(-1.0, 1.0);
+
+	if cos_angle > 1.0 - PARALLEL_EPSILON {
+		return nglm::lerp(from, to, t).normalize();
+	}
+
+	// Any great circle joins opposite points, so pick one through the poles if
+	// possible
+	if cos_angle < -1.0 + PARALLEL_EPSILON {
+		let polar_axis = from.cross(&nglm::Vec3::y());
+		let axis = if polar_axis.magnitude() > PARALLEL_EPSILON {
+			polar_axis.normalize()
+		} else {
+			nglm::Vec3::x()
+		};
+		return nglm::rotate_vec3(from, nglm::pi::<f32>() * t, &axis);
+	}
+
+	let angle = cos_angle.acos();
+	(from * ((1.0 - t) * angle).sin() + to * (t * angle).sin()) / angle.sin()
+}
+
+thread_local! {
+	static ACTIVE_CAMERA_CONTROLLER: RefCell<Option<Rc<RefCell<CameraController>>>> = const { RefCell::new(None) };
+}
+
+/// Makes `controller` the target of the JS exports below
+pub fn set_active_camera_controller(controller: &Rc<RefCell<CameraController>>) {
+	ACTIVE_CAMERA_CONTROLLER.with(|active| active.replace(Some(controller.clone())));
+}
+
+/// Animates the camera to look down at the given point, with the altitude in
+/// planet radii. Negative durations jump straight there. Positions that aren't
+/// finite, like an `undefined` from JS, and durations too long to represent are
+/// ignored.
+#[wasm_bindgen]
+pub fn fly_to(latitude: f32, longitude: f32, altitude: f32, duration_seconds: f32) {
+	if ![latitude, longitude, altitude].iter().all(|value| value.is_finite()) {
+		ghg_error!("Invalid fly_to destination {}, {} at {}", latitude, longitude, altitude);
+		return;
+	}
+
+	let duration = match Duration::try_from_secs_f32(duration_seconds.max(0.0)) {
+		Ok(duration) => duration,
+		Err(e) => {
+			ghg_error!("Invalid fly_to duration {}: {}", duration_seconds, e);
+			return;
+		}
+	};
+
+	ACTIVE_CAMERA_CONTROLLER.with(|active| {
+		if let Some(controller) = active.borrow().as_ref() {
+			controller.deref().borrow_mut().fly_to(latitude, longitude, altitude, duration);
+		}
+	});
+}
+
+/// Whether a `fly_to` is still in progress
+#[wasm_bindgen]
+pub fn camera_is_flying() -> bool {
+	ACTIVE_CAMERA_CONTROLLER.with(|active| {
+		active.borrow().as_ref().is_some_and(|controller| controller.deref().borrow().is_flying())
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const EPSILON: f32 = 1.0e-4;
+	const FRAME: Duration = Duration::from_millis(16);
+
+	fn controller_at(position: nglm::Vec3) -> CameraController {
+		let camera = Rc::new(RefCell::new(Camera::new(&position, &nglm::Vec3::zeros())));
+		CameraController::new(camera, CameraControllerSettings::default())
+	}
+
+	fn camera_position(controller: &CameraController) -> nglm::Vec3 {
+		controller.camera.deref().borrow().position()
+	}
+
+	#[test]
+	fn slerp_follows_great_circle() {
+		let from = nglm::Vec3::z();
+		let to = nglm::Vec3::x();
+
+		assert!(slerp(&from, &to, 0.0).metric_distance(&from) < EPSILON);
+		assert!(slerp(&from, &to, 1.0).metric_distance(&to) < EPSILON);
+
+		let quarter = slerp(&from, &to, 0.25);
+		assert!((quarter.magnitude() - 1.0).abs() < EPSILON);
+		assert!(quarter.y.abs() < EPSILON);
+		assert!((quarter.dot(&from).acos().to_degrees() - 22.5).abs() < 1.0e-2);
+	}
+
+	#[test]
+	fn slerp_handles_opposite_points() {
+		let from = nglm::Vec3::z();
+		let to = -nglm::Vec3::z();
+
+		let halfway = slerp(&from, &to, 0.5);
+		assert!((halfway.magnitude() - 1.0).abs() < EPSILON);
+		assert!(halfway.dot(&from).abs() < EPSILON);
+		assert!(slerp(&from, &to, 1.0).metric_distance(&to) < EPSILON);
+	}
+
+	#[test]
+	fn easing_is_monotonic() {
+		assert_eq!(ease_in_out(0.0), 0.0);
+		assert_eq!(ease_in_out(0.5), 0.5);
+		assert_eq!(ease_in_out(1.0), 1.0);
+
+		let samples: Vec<f32> = (0..=20).map(|i| ease_in_out(i as f32 / 20.0)).collect();
+		assert!(samples.windows(2).all(|pair| pair[1] >= pair[0]));
+
+		// Starts and ends slowly
+		assert!(samples[1] < 0.05);
+		assert!(samples[19] > 0.95);
+	}
+
+	#[test]
+	fn altitude_interpolates_geometrically() {
+		assert!((interpolate_altitude(0.5, 2.0, 0.5) - 1.0).abs() < EPSILON);
+		assert!((interpolate_altitude(0.5, 2.0, 1.0) - 2.0).abs() < EPSILON);
+	}
+
+	#[test]
+	fn zoom_is_proportional_to_altitude() {
+		let settings = CameraControllerSettings::default();
+
+		let near = zoomed_altitude(0.4, 100.0, &settings);
+		let far = zoomed_altitude(2.0, 100.0, &settings);
+		assert!((near / 0.4 - far / 2.0).abs() < EPSILON);
+
+		assert_eq!(zoomed_altitude(0.3, -1.0e4, &settings), settings.min_altitude);
+		assert_eq!(zoomed_altitude(2.0, 1.0e4, &settings), settings.max_altitude);
+	}
+
+	#[test]
+	fn zoom_settles_on_target() {
+		let mut controller = controller_at(nglm::vec3(0.0, 0.0, 3.0));
+		controller.zoom(-200.0);
+		let target = controller.target_altitude;
+		assert!(target < 2.0);
+
+		for _ in 0..120 {
+			controller.update(FRAME);
+		}
+
+		assert!((altitude_of(&camera_position(&controller)) - target).abs() < 1.0e-3);
+	}
+
+	#[test]
+	fn momentum_continues_after_release() {
+		let mut controller = controller_at(nglm::vec3(0.0, 0.0, 3.0));
+
+		controller.hold(true);
+		controller.orbit(&nglm::vec2(10.0, 0.0));
+		controller.update(FRAME);
+		controller.hold(false);
+
+		let released_at = camera_position(&controller);
+		controller.update(FRAME);
+		let coasted_to = camera_position(&controller);
+		assert!(coasted_to.metric_distance(&released_at) > EPSILON);
+
+		for _ in 0..300 {
+			controller.update(FRAME);
+		}
+		assert_eq!(controller.velocity, nglm::Vec2::zeros());
+
+		let stopped_at = camera_position(&controller);
+		controller.update(FRAME);
+		assert!(camera_position(&controller).metric_distance(&stopped_at) < EPSILON);
+	}
+
+	#[test]
+	fn holding_still_stops_momentum() {
+		let mut controller = controller_at(nglm::vec3(0.0, 0.0, 3.0));
+
+		controller.hold(true);
+		controller.orbit(&nglm::vec2(10.0, 0.0));
+		controller.update(FRAME);
+		controller.update(FRAME);
+		controller.hold(false);
+
+		let released_at = camera_position(&controller);
+		controller.update(FRAME);
+		assert!(camera_position(&controller).metric_distance(&released_at) < EPSILON);
+	}
+
+	#[test]
+	fn fly_to_arrives() {
+		let mut controller = controller_at(nglm::vec3(0.0, 0.0, 3.0));
+		controller.fly_to(40.0, -75.0, 0.5, Duration::from_secs(2));
+
+		let mut last_distance = f32::MAX;
+		let destination = geo_to_world(&GeoPosition { latitude: 40.0, longitude: -75.0 });
+		while controller.is_flying() {
+			controller.update(FRAME);
+
+			let position = camera_position(&controller);
+			let distance = position.normalize().metric_distance(&destination);
+			assert!(distance <= last_distance + EPSILON);
+			last_distance = distance;
+		}
+
+		let position = camera_position(&controller);
+		assert!(position.normalize().metric_distance(&destination) < EPSILON);
+		assert!((altitude_of(&position) - 0.5).abs() < EPSILON);
+		assert!(
+			controller.camera.deref().borrow().forward().metric_distance(&-destination) < 1.0e-3
+		);
+	}
+
+	#[test]
+	fn input_cancels_flight() {
+		let mut controller = controller_at(nglm::vec3(0.0, 0.0, 3.0));
+		controller.fly_to(0.0, 90.0, 1.0, Duration::from_secs(2));
+		controller.update(FRAME);
+
+		controller.orbit(&nglm::vec2(1.0, 0.0));
+		assert!(!controller.is_flying());
+	}
+
+	#[test]
+	fn exported_fly_to_ignores_unrepresentable_durations() {
+		let controller = Rc::new(RefCell::new(controller_at(nglm::vec3(0.0, 0.0, 3.0))));
+		set_active_camera_controller(&controller);
+
+		fly_to(0.0, 90.0, 1.0, f32::INFINITY);
+		fly_to(0.0, 90.0, 1.0, f32::MAX);
+		assert!(!camera_is_flying());
+
+		fly_to(0.0, 90.0, 1.0, 2.0);
+		assert!(camera_is_flying());
+	}
+
+	#[test]
+	fn exported_fly_to_ignores_destinations_that_are_not_finite() {
+		let controller = Rc::new(RefCell::new(controller_at(nglm::vec3(0.0, 0.0, 3.0))));
+		set_active_camera_controller(&controller);
+
+		fly_to(f32::NAN, 0.0, 2.0, 1.0);
+		fly_to(0.0, f32::INFINITY, 2.0, 1.0);
+		fly_to(0.0, 90.0, f32::NAN, 1.0);
+		assert!(!camera_is_flying());
+
+		controller.deref().borrow_mut().update(Duration::from_secs(2));
+		assert!(controller.borrow().camera.borrow().position().iter().all(|v| v.is_finite()));
+	}
+}
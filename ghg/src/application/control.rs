@@ -2,144 +2,326 @@ use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::rc::Rc;
+use std::time::Duration;
 
 use web_sys::HtmlCanvasElement;
 
-use crate::application::shaders::ShaderContext;
+use crate::application::camera_controller::CameraController;
+use crate::application::lighting::LightingOptions;
+use crate::application::time_cursor::TimeCursor;
+use crate::interaction_core::actions::{Action, KeyBindings};
+use crate::interaction_core::gestures::{Gesture, GestureRecognizer, GestureSettings};
 use crate::interaction_core::input_subscriber::{
 	FrameInputSubscriber, InputState, KeyState, MouseButton, MouseButtonState, MouseMovement,
-	Scroll, SwitchState, TouchMovement, TouchState,
+	Propagation, Scroll, Subscription, SwitchState, TouchState, DEFAULT_PRIORITY,
 };
 use crate::render_core::animation_params::AnimationParams;
-use crate::render_core::camera::Camera;
 use crate::render_core::frame_sequencer::FrameGate;
-use crate::render_core::uniform;
-use crate::render_core::uniform::Uniform;
 use crate::utils::prelude::*;
 
+/// Where `Action::FlyHome` flies to: latitude, longitude, altitude and duration
+/// in seconds
+const HOME_VIEW: (f32, f32, f32, f32) = (20.0, 0.0, 2.0, 1.5);
+
+const TERRAIN_SCALE_MAX: f32 = 0.1;
+/// Fractions of `TERRAIN_SCALE_MAX`
+const TERRAIN_SCALE_PRESETS: [f32; 6] = [0.1, 0.3, 0.5, 0.7, 0.9, 1.5];
+
+/// Orbit per degree of two finger rotation
+const ROTATE_MULTIPLIER: f32 = 2.0;
+
+/// How long each month is shown for during playback
+const PLAYBACK_INTERVAL: Duration = Duration::from_secs(1);
+
+thread_local! {
+	static ACTIVE_KEY_BINDINGS: RefCell<Option<Rc<RefCell<KeyBindings>>>> = const { RefCell::new(None) };
+}
+
+/// Replaces every key binding. See `KeyBindings::from_json` for the format.
+#[wasm_bindgen]
+pub fn set_key_bindings(json: &str) -> Result<(), JsValue> {
+	let bindings = KeyBindings::from_json(json).map_err(|e| JsValue::from(e.to_string()))?;
+	ACTIVE_KEY_BINDINGS.with(|active| match active.borrow().as_ref() {
+		Some(current) => {
+			current.replace(bindings);
+			Ok(())
+		}
+		None => Err(JsValue::from("The controller hasn't started yet")),
+	})
+}
+
+/// The current key bindings as JSON, each with a description for a help overlay
+#[wasm_bindgen]
+pub fn get_key_bindings() -> String {
+	ACTIVE_KEY_BINDINGS.with(|active| {
+		let described: Vec<serde_json::Value> = active
+			.borrow()
+			.as_ref()
+			.map(|bindings| {
+				bindings
+					.borrow()
+					.bindings()
+					.iter()
+					.map(|b| {
+						let mut value = serde_json::to_value(b).expect("Bindings are serializable");
+						value["description"] = serde_json::Value::from(b.action.description());
+						value
+					})
+					.collect()
+			})
+			.unwrap_or_default();
+
+		serde_json::Value::from(described).to_string()
+	})
+}
+
+/// Everything the keyboard's actions can change
+struct ActionTargets {
+	camera_controller: Rc<RefCell<CameraController>>,
+	terrain_scale: Rc<Cell<f32>>,
+	time_cursor: Rc<Cell<TimeCursor>>,
+	lighting_options: Rc<Cell<LightingOptions>>,
+	/// Time since the month last changed, or None while paused
+	playback: Cell<Option<Duration>>,
+}
+
+impl ActionTargets {
+	fn perform(&self, action: Action) {
+		let time_cursor = &self.time_cursor;
+
+		match action {
+			Action::OrbitModifier => {}
+			Action::NextTimeStep => time_cursor.set(time_cursor.get().next_month()),
+			Action::PreviousTimeStep => time_cursor.set(time_cursor.get().previous_month()),
+			Action::NextHour => time_cursor.set(time_cursor.get().add_hours(1.0)),
+			Action::PreviousHour => time_cursor.set(time_cursor.get().add_hours(-1.0)),
+			Action::TogglePlayback => {
+				let playback = match self.playback.get() {
+					Some(_) => None,
+					None => Some(Duration::ZERO),
+				};
+				self.playback.set(playback);
+			}
+			Action::TerrainScaleUp => {
+				let current = self.terrain_scale.get();
+				if let Some(preset) = terrain_scale_presets().find(|p| *p > current * 1.001) {
+					self.terrain_scale.set(preset);
+				}
+			}
+			Action::TerrainScaleDown => {
+				let current = self.terrain_scale.get();
+				if let Some(preset) = terrain_scale_presets().rev().find(|p| *p < current * 0.999) {
+					self.terrain_scale.set(preset);
+				}
+			}
+			Action::TerrainScalePreset(index) => {
+				if let Some(preset) = terrain_scale_presets().nth(index as usize) {
+					self.terrain_scale.set(preset);
+				}
+			}
+			Action::ToggleLightSource => {
+				self.lighting_options.set(self.lighting_options.get().toggle_source())
+			}
+			Action::ToggleStars => {
+				self.lighting_options.set(self.lighting_options.get().toggle_stars())
+			}
+			Action::ToggleAtmosphere => {
+				self.lighting_options.set(self.lighting_options.get().toggle_atmosphere())
+			}
+			Action::CycleCameraMode => {
+				let mode = self.camera_controller.deref().borrow_mut().cycle_camera_mode();
+				ghg_log!("Camera mode: {:?}", mode);
+			}
+			Action::ToggleLogarithmicDepth => {
+				let enabled =
+					self.camera_controller.deref().borrow_mut().toggle_logarithmic_depth();
+				ghg_log!("Logarithmic depth: {enabled}");
+			}
+			Action::FlyHome => {
+				let (latitude, longitude, altitude, seconds) = HOME_VIEW;
+				self.camera_controller.deref().borrow_mut().fly_to(
+					latitude,
+					longitude,
+					altitude,
+					Duration::from_secs_f32(seconds),
+				);
+			}
+		}
+	}
+
+	fn advance_playback(&self, delta_time: Duration) {
+		if let Some(elapsed) = self.playback.get() {
+			let elapsed = elapsed + delta_time;
+			if elapsed >= PLAYBACK_INTERVAL {
+				self.time_cursor.set(self.time_cursor.get().next_month());
+				self.playback.set(Some(elapsed - PLAYBACK_INTERVAL));
+			} else {
+				self.playback.set(Some(elapsed));
+			}
+		}
+	}
+}
+
+fn terrain_scale_presets() -> impl DoubleEndedIterator<Item = f32> {
+	TERRAIN_SCALE_PRESETS.into_iter().map(|p| p * TERRAIN_SCALE_MAX)
+}
+
 pub struct Controller {
 	input_subscriber: FrameInputSubscriber,
+	camera_controller: Rc<RefCell<CameraController>>,
+	action_targets: Rc<ActionTargets>,
+	gesture_recognizer: GestureRecognizer,
+	_subscriptions: Vec<Subscription>,
 }
 
 impl Controller {
 	pub fn new(
-		canvas: HtmlCanvasElement,
-		camera: Rc<RefCell<Camera>>,
-		planet_shader: ShaderContext,
-		terrain_scale: Uniform<f32>,
-		current_month: Rc<Cell<usize>>,
+		mut input_subscriber: FrameInputSubscriber,
+		camera_controller: Rc<RefCell<CameraController>>,
+		terrain_scale: Rc<Cell<f32>>,
+		time_cursor: Rc<Cell<TimeCursor>>,
+		lighting_options: Rc<Cell<LightingOptions>>,
 	) -> Self {
-		let mut input_subscriber = FrameInputSubscriber::new(canvas);
-
-		let mouse_move_camera = camera.clone();
-		input_subscriber.subscribe_on_mouse_move(Box::new(
-			move |movement: MouseMovement, current_state: InputState| {
-				let should_rotate = current_state.is_key_active("ShiftLeft".to_owned())
-					|| current_state.is_mouse_button_active(MouseButton::Left);
+		let key_bindings = Rc::new(RefCell::new(KeyBindings::default()));
+		ACTIVE_KEY_BINDINGS.with(|active| active.replace(Some(key_bindings.clone())));
+
+		let action_targets = Rc::new(ActionTargets {
+			camera_controller: camera_controller.clone(),
+			terrain_scale,
+			time_cursor,
+			lighting_options,
+			playback: Cell::new(None),
+		});
+
+		let mut subscriptions = Vec::new();
+
+		let mouse_move_camera = camera_controller.clone();
+		let mouse_move_bindings = key_bindings.clone();
+		subscriptions.push(input_subscriber.subscribe_on_mouse_move(
+			DEFAULT_PRIORITY,
+			Box::new(move |movement: MouseMovement, current_state: InputState| {
+				let should_rotate =
+					mouse_move_bindings.borrow().is_active(Action::OrbitModifier, &current_state)
+						|| current_state.is_mouse_button_active(MouseButton::Left);
 
 				if should_rotate {
-					let mut borrowed_cam = mouse_move_camera.deref().borrow_mut();
-					borrowed_cam.orbit_around_target(
-						&nglm::Vec3::zeros(),
-						&nglm::vec2(-movement.x as f32, movement.y as f32),
-						0.4,
-					);
+					mouse_move_camera
+						.deref()
+						.borrow_mut()
+						.orbit(&nglm::vec2(-movement.x as f32, movement.y as f32));
 				}
-			},
+				Propagation::Continue
+			}),
 		));
 
-		let touch_move_camera = camera.clone();
-		input_subscriber.subscribe_on_touch_move(Box::new(
-			move |all_movement: HashMap<i32, TouchMovement>, current_state: InputState| {
-				let all_active = current_state.active_touch_identifiers();
-
-				if all_active.len() == 1 {
-					if let Some(movement) = all_movement.get(&all_active[0]) {
-						let mut borrowed_cam = touch_move_camera.deref().borrow_mut();
-						borrowed_cam.orbit_around_target(
-							&nglm::Vec3::zeros(),
-							&nglm::vec2(
-								-movement.difference.x as f32,
-								movement.difference.y as f32,
-							),
-							0.4,
-						);
-					}
-				} else if all_active.len() == 2 {
-					zoom::handle_pinch(
-						&touch_move_camera,
-						all_movement,
-						current_state,
-						all_active[0],
-						all_active[1],
-					);
-				}
-			},
+		let keyboard_targets = action_targets.clone();
+		subscriptions.push(input_subscriber.subscribe_on_keyboard_event(
+			DEFAULT_PRIORITY,
+			Box::new(move |key_states: Vec<KeyState>, current_state: InputState| {
+				key_bindings
+					.borrow()
+					.resolve(&key_states, &current_state)
+					.into_iter()
+					.filter(|event| event.state == SwitchState::Pressed)
+					.for_each(|event| keyboard_targets.perform(event.action));
+				Propagation::Continue
+			}),
 		));
 
-		input_subscriber.subscribe_on_keyboard_event(Box::new(
-			move |key_states: Vec<KeyState>, _current_state: InputState| {
-				let scale_max = 0.1f32;
-
-				planet_shader.use_shader();
-
-				key_states.iter().for_each(|k| match k {
-					KeyState { key, state: SwitchState::Pressed } => match key.as_str() {
-						"Digit1" => terrain_scale.write_unchecked(0.1 * scale_max),
-						"Digit2" => terrain_scale.write_unchecked(0.3 * scale_max),
-						"Digit3" => terrain_scale.write_unchecked(0.5 * scale_max),
-						"Digit4" => terrain_scale.write_unchecked(0.7 * scale_max),
-						"Digit5" => terrain_scale.write_unchecked(0.9 * scale_max),
-						"Digit6" => terrain_scale.write_unchecked(1.5 * scale_max),
-						"ArrowRight" => {
-							current_month.replace((current_month.get() + 1) % 12);
-						}
-						"ArrowLeft" => {
-							current_month.replace(((current_month.get() - 1) + 12) % 12);
-						}
-						other => ghg_log!("{:?}", other),
-					},
-					_ => {}
-				})
-			},
+		subscriptions.push(input_subscriber.subscribe_on_scroll_event(
+			DEFAULT_PRIORITY,
+			zoom::make_scroll_handler(&camera_controller),
 		));
 
-		input_subscriber.subscribe_on_scroll_event(zoom::make_scroll_handler(&camera));
-
-		input_subscriber.subscribe_on_mouse_button_event(Box::new(
-			|button_states: Vec<MouseButtonState>, _current_state: InputState| {
-				ghg_log!("Mouse button changes: {:?}", button_states);
-			},
+		let mouse_button_camera = camera_controller.clone();
+		subscriptions.push(input_subscriber.subscribe_on_mouse_button_event(
+			DEFAULT_PRIORITY,
+			Box::new(move |_button_states: Vec<MouseButtonState>, current_state: InputState| {
+				mouse_button_camera
+					.deref()
+					.borrow_mut()
+					.hold(current_state.is_mouse_button_active(MouseButton::Left));
+				Propagation::Continue
+			}),
 		));
 
-		input_subscriber.subscribe_on_touch_state_event(Box::new(
-			|touch_state: HashMap<i32, TouchState>, _current_state: InputState| {
-				ghg_log!("Touch state changes: {:?}", touch_state);
-			},
+		let touch_state_camera = camera_controller.clone();
+		subscriptions.push(input_subscriber.subscribe_on_touch_state_event(
+			DEFAULT_PRIORITY,
+			Box::new(move |_touch_state: HashMap<i32, TouchState>, current_state: InputState| {
+				touch_state_camera
+					.deref()
+					.borrow_mut()
+					.hold(!current_state.active_touch_identifiers().is_empty());
+				Propagation::Continue
+			}),
 		));
 
-		Self { input_subscriber }
+		Self {
+			input_subscriber,
+			camera_controller,
+			action_targets,
+			gesture_recognizer: GestureRecognizer::new(GestureSettings::default()),
+			_subscriptions: subscriptions,
+		}
+	}
+
+	/// While replaying, `delta_time` is the recorded frame time, from
+	/// `begin_input_frame`
+	pub fn frame(&mut self, delta_time: Duration) {
+		self.input_subscriber.frame();
+
+		let current_state = self.input_subscriber.get_current_state();
+		for gesture in self.gesture_recognizer.frame(&current_state, delta_time) {
+			self.handle_gesture(gesture);
+		}
+
+		self.action_targets.advance_playback(delta_time);
+		self.camera_controller.deref().borrow_mut().update(delta_time);
 	}
 
-	pub fn frame(&mut self) { self.input_subscriber.frame(); }
+	fn handle_gesture(&self, gesture: Gesture) {
+		let mut camera_controller = self.camera_controller.deref().borrow_mut();
+
+		match gesture {
+			Gesture::Pan { delta } => camera_controller.orbit(&nglm::vec2(-delta.x, delta.y)),
+			Gesture::Pinch { scale } => {
+				camera_controller.zoom(-scale.ln() * zoom::PINCH_MULTIPLIER)
+			}
+			// The camera always looks at the planet's centre, so it has no roll
+			// or tilt of its own. Spin the planet and move towards the poles
+			// instead.
+			Gesture::Rotate { angle } => {
+				camera_controller.orbit(&nglm::vec2(angle.to_degrees() * ROTATE_MULTIPLIER, 0.0))
+			}
+			Gesture::Tilt { delta } => camera_controller.orbit(&nglm::vec2(0.0, delta)),
+			Gesture::DoubleTap { .. } => camera_controller.zoom(-zoom::DOUBLE_TAP_ZOOM),
+			Gesture::LongPress { position } => {
+				ghg_log!("Long press at {}, {}", position.x, position.y);
+			}
+		}
+	}
 }
 
 pub async fn controller_frame(
 	gate: FrameGate<AnimationParams>,
 	canvas: HtmlCanvasElement,
-	planet_shader: ShaderContext,
-	camera: Rc<RefCell<Camera>>,
-	current_month: Rc<Cell<usize>>,
+	camera_controller: Rc<RefCell<CameraController>>,
+	terrain_scale: Rc<Cell<f32>>,
+	time_cursor: Rc<Cell<TimeCursor>>,
+	lighting_options: Rc<Cell<LightingOptions>>,
 ) {
-	planet_shader.use_shader();
-	let terrain_scale = uniform::init_f32("u_terrainScale", &planet_shader, 0.03);
-
-	let mut controller =
-		Controller::new(canvas, camera, planet_shader.clone(), terrain_scale, current_month);
+	let mut controller = Controller::new(
+		FrameInputSubscriber::new(canvas),
+		camera_controller,
+		terrain_scale,
+		time_cursor,
+		lighting_options,
+	);
 
 	loop {
-		let _params = (&gate).await;
-		controller.frame();
+		let params = (&gate).await;
+		controller.frame(params.delta_time);
 	}
 }
 
@@ -147,72 +329,79 @@ mod zoom {
 	use super::*;
 	use crate::interaction_core::input_subscriber::ScrollCallback;
 
-	const SCROLL_MULTIPLIER: f32 = 0.01;
 	const SCROLL_THRESHOLD: f32 = 0.001;
 
-	pub fn make_scroll_handler(camera: &Rc<RefCell<Camera>>) -> ScrollCallback {
+	pub fn make_scroll_handler(camera: &Rc<RefCell<CameraController>>) -> ScrollCallback {
 		let scroll_camera = camera.clone();
 		Box::new(move |scroll: Scroll, _current_state: InputState| {
 			if scroll.delta_y.abs() > SCROLL_THRESHOLD {
-				move_camera(&scroll_camera, scroll.delta_y, SCROLL_MULTIPLIER);
+				scroll_camera.deref().borrow_mut().zoom(scroll.delta_y);
 			}
+			Propagation::Continue
 		})
 	}
 
-	const ZOOM_CLOSEST: f32 = 1.2;
-	const ZOOM_FARTHEST: f32 = 4.0;
+	/// Scroll units per e-fold change in the distance between the fingers
+	pub const PINCH_MULTIPLIER: f32 = 400.0;
 
-	fn move_camera(camera: &Rc<RefCell<Camera>>, distance: f32, multiplier: f32) {
-		let mut borrowed_cam = camera.deref().borrow_mut();
-		let last_position = borrowed_cam.position();
-		let direction_to_camera = last_position.normalize();
-		let last_distance = last_position.magnitude();
+	/// Scroll units, which halve the altitude at the default zoom rate
+	pub const DOUBLE_TAP_ZOOM: f32 = 230.0;
+}
 
-		let desired_new_distance = last_distance + distance * multiplier;
-		let capped_new_distance = desired_new_distance.clamp(ZOOM_CLOSEST, ZOOM_FARTHEST);
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::application::camera_controller::CameraControllerSettings;
+	use crate::interaction_core::recording::InputRecording;
+	use crate::render_core::camera::Camera;
+
+	/// Runs a recording through a fresh controller, returning where the camera
+	/// and time cursor ended up
+	fn replay(recording: &InputRecording) -> (nglm::Vec3, TimeCursor) {
+		let camera =
+			Rc::new(RefCell::new(Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::Vec3::zeros())));
+		let camera_controller = Rc::new(RefCell::new(CameraController::new(
+			camera.clone(),
+			CameraControllerSettings::default(),
+		)));
+		let time_cursor = Rc::new(Cell::new(TimeCursor::default()));
+
+		let mut controller = Controller::new(
+			FrameInputSubscriber::from_recording(recording.clone()),
+			camera_controller,
+			Rc::new(Cell::new(0.03)),
+			time_cursor.clone(),
+			Rc::new(Cell::new(LightingOptions::default())),
+		);
 
-		let new_position = direction_to_camera * capped_new_distance;
+		for _ in &recording.frames {
+			// Live frame times are ignored while replaying
+			let delta_time = controller.input_subscriber.begin_frame(Duration::from_secs(1));
+			controller.frame(delta_time);
+		}
 
-		borrowed_cam.set_position(new_position);
+		let position = camera.borrow().position();
+		(position, time_cursor.get())
 	}
 
-	const PINCH_MULTIPLIER: f32 = 0.01;
-
-	pub fn handle_pinch(
-		camera: &Rc<RefCell<Camera>>,
-		all_movement: HashMap<i32, TouchMovement>,
-		current_state: InputState,
-		a_id: i32,
-		b_id: i32,
-	) {
-		let (maybe_a_pos, maybe_b_pos) = (
-			current_state.current_touch_position(a_id),
-			current_state.current_touch_position(b_id),
-		);
-		if maybe_a_pos.is_some() && maybe_b_pos.is_some() {
-			let (a_pos, b_pos) = (maybe_a_pos.unwrap(), maybe_b_pos.unwrap());
-			let a_prev = if let Some(movement) = all_movement.get(&a_id) {
-				a_pos - movement.difference
-			} else {
-				a_pos
-			};
+	fn drag_zoom_and_step() -> InputRecording {
+		InputRecording::from_json(include_str!("fixtures/drag_zoom_and_step.json")).unwrap()
+	}
 
-			let b_prev = if let Some(movement) = all_movement.get(&b_id) {
-				b_pos - movement.difference
-			} else {
-				b_pos
-			};
+	#[test]
+	fn replay_is_deterministic() {
+		let recording = drag_zoom_and_step();
+		assert_eq!(replay(&recording), replay(&recording));
+	}
 
-			// Casting should be easy...
-			let prev_cord = a_prev - b_prev;
-			let current_cord = a_pos - b_pos;
-			let prev_magnitude = nglm::length(&nglm::vec2(prev_cord.x as f32, prev_cord.y as f32));
-			let current_magnitude =
-				nglm::length(&nglm::vec2(current_cord.x as f32, current_cord.y as f32));
+	#[test]
+	fn replay_drives_camera_and_time() {
+		let (position, time_cursor) = replay(&drag_zoom_and_step());
 
-			let difference = current_magnitude - prev_magnitude;
+		assert_eq!(time_cursor, TimeCursor::default().next_month());
 
-			move_camera(camera, -1.0 * difference, PINCH_MULTIPLIER);
-		}
+		let start = nglm::vec3(0.0, 0.0, 3.0);
+		assert!(nglm::distance(&position.normalize(), &start.normalize()) > 0.01, "Orbited");
+		assert!(position.norm() < start.norm(), "Zoomed in");
 	}
 }
@@ -5,16 +5,21 @@ use std::rc::Rc;
 
 use ghg_data_core::metadata::Metadata;
 use image::Rgba;
+#[cfg(not(target_arch = "wasm32"))]
+use serde::{Deserialize, Serialize};
 use serde_json::from_slice;
 use wasm_bindgen::JsValue;
 use web_sys::WebGl2RenderingContext;
 
-use crate::application::shaders::ShaderContext;
+use crate::application::shaders::{PlanetShaders, ShaderContext};
+use crate::application::time_cursor::TimeCursor;
 use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::backend::{GraphicsBackend, TextureFilter};
 use crate::render_core::frame_sequencer::FrameGate;
 use crate::render_core::image::load_into_texture_with_filters;
 use crate::render_core::uniform;
 use crate::request_data::fetch_bytes;
+#[allow(unused_imports)]
 use crate::utils::prelude::*;
 
 // struct DataMapping {
@@ -40,7 +45,7 @@ const MONTH_NAMES: [&str; 12] = [
 ];
 
 async fn load_temp_data(
-	shader_context: ShaderContext,
+	shader_context: ShaderContext<WebGl2RenderingContext>,
 	file_stem: &str,
 	texture_index: i32,
 ) -> Result<Metadata, JsValue> {
@@ -55,28 +60,85 @@ async fn load_temp_data(
 	let metadata: Metadata = from_slice(&metadata_bytes).map_err(|e| e.to_string())?;
 
 	shader_context.use_shader();
-	load_into_texture_with_filters::<Rgba<u8>>(
-		shader_context.context.clone(),
-		&texture,
-		WebGl2RenderingContext::TEXTURE0 + texture_index as u32,
-		WebGl2RenderingContext::LINEAR,
-		WebGl2RenderingContext::NEAREST,
-	)?;
+	upload_data_map(&shader_context.context, &texture, texture_index as u32)?;
 
 	Ok(metadata)
 }
 
+/// Each map packs four months into its channels
+pub const NUM_CHANNELS: i32 = 4;
+
+/// The texture unit of a year's first map. The others follow it.
+pub const FIRST_MAP_UNIT: i32 = 2;
+
+/// Which of a year's maps holds `month`
+pub fn map_index(month: i32) -> i32 { month / NUM_CHANNELS }
+
+/// How data values are turned into colors on the globe. Keep in sync with
+/// colormaps.glsl. Only offline renders pick one; the web app always uses the
+/// default.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Colormap {
+	#[default]
+	Hue,
+	Grayscale,
+	Diverging,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Colormap {
+	/// What `u_colormap` is set to for this colormap
+	pub fn uniform_value(self) -> i32 {
+		match self {
+			Colormap::Hue => 0,
+			Colormap::Grayscale => 1,
+			Colormap::Diverging => 2,
+		}
+	}
+}
+
+/// Uploads one of a year's maps to `texture_unit`. Values are packed, so
+/// they're never blended when magnified.
+pub fn upload_data_map<B: GraphicsBackend>(
+	context: &B,
+	png_bytes: &[u8],
+	texture_unit: u32,
+) -> Result<(), String> {
+	load_into_texture_with_filters::<Rgba<u8>, _>(
+		context,
+		png_bytes,
+		texture_unit,
+		TextureFilter::Linear,
+		TextureFilter::Nearest,
+	)
+}
+
+/// Puts each map's channel ranges into a column, for `u_dataMinValues` and
+/// `u_dataMaxValues`
+pub fn range_matrices(metadata: &[Metadata]) -> Result<(nglm::Mat4x3, nglm::Mat4x3), String> {
+	let ranges = metadata
+		.iter()
+		.map(|metadata| metadata.clone().try_into())
+		.collect::<Result<Vec<(nglm::Vec4, nglm::Vec4)>, String>>()?;
+	if ranges.len() != 3 {
+		return Err(format!("Expected 3 maps for a year, but there are {}", ranges.len()));
+	}
+
+	let (mins, maxes): (Vec<nglm::Vec4>, Vec<nglm::Vec4>) = ranges.into_iter().unzip();
+	Ok((nglm::Mat4x3::from_columns(&mins), nglm::Mat4x3::from_columns(&maxes)))
+}
+
 pub async fn handle_data(
 	gate: FrameGate<AnimationParams>,
-	shader_context: ShaderContext,
-	current_month: Rc<Cell<usize>>,
+	shaders: PlanetShaders<WebGl2RenderingContext>,
+	time_cursor: Rc<Cell<TimeCursor>>,
 ) {
-	let first_map_index: i32 = 2;
-
 	let load_all_results = join!(
-		load_temp_data(shader_context.clone(), "2021.01.04", first_map_index + 0),
-		load_temp_data(shader_context.clone(), "2021.05.08", first_map_index + 1),
-		load_temp_data(shader_context.clone(), "2021.09.12", first_map_index + 2),
+		load_temp_data(shaders.standard.clone(), "2021.01.04", FIRST_MAP_UNIT + 0),
+		load_temp_data(shaders.standard.clone(), "2021.05.08", FIRST_MAP_UNIT + 1),
+		load_temp_data(shaders.standard.clone(), "2021.09.12", FIRST_MAP_UNIT + 2),
 	)
 	.await;
 
@@ -84,32 +146,37 @@ pub async fn handle_data(
 		ghg_error!("Failed to load some temperature data: {:?}", load_all_results)
 	}
 
-	let mins_and_maxes: [(nglm::Vec4, nglm::Vec4); 3] = [
-		load_all_results.0.ok().unwrap().clone().try_into().expect("Failed to convert metadata"),
-		load_all_results.1.ok().unwrap().clone().try_into().expect("Failed to convert metadata"),
-		load_all_results.2.ok().unwrap().clone().try_into().expect("Failed to convert metadata"),
+	let metadata = [
+		load_all_results.0.ok().unwrap(),
+		load_all_results.1.ok().unwrap(),
+		load_all_results.2.ok().unwrap(),
 	];
-
-	const NUM_CHANNELS: i32 = 4;
-
-	let (mins, maxes): (Vec<nglm::Vec4>, Vec<nglm::Vec4>) = mins_and_maxes.into_iter().unzip();
-
-	let min_mat = nglm::Mat4x3::from_columns(&mins);
-	let max_mat = nglm::Mat4x3::from_columns(&maxes);
-
-	let _min_uniforms = uniform::init_smart_mat4x3("u_dataMinValues", &shader_context, min_mat);
-	let _max_uniforms = uniform::init_smart_mat4x3("u_dataMaxValues", &shader_context, max_mat);
-
-	let mut texture_uniform = uniform::new_smart_i32("s_dataMap", &shader_context);
-	let mut data_month_uniform = uniform::new_smart_i32("u_dataMonth", &shader_context);
+	let (min_mat, max_mat) = range_matrices(&metadata).expect("Failed to convert metadata");
+
+	let mut month_uniforms: Vec<_> = shaders
+		.variants()
+		.into_iter()
+		.map(|shader| {
+			shader.use_shader();
+			uniform::init_mat4x3("u_dataMinValues", shader, min_mat);
+			uniform::init_mat4x3("u_dataMaxValues", shader, max_mat);
+
+			(
+				shader,
+				uniform::new_smart_i32("s_dataMap", shader),
+				uniform::new_smart_i32("u_dataMonth", shader),
+			)
+		})
+		.collect();
 
 	loop {
 		let _params = (&gate).await;
 
-		let current_month = current_month.get() as i32;
-		let current_map_index = current_month / NUM_CHANNELS;
-
-		texture_uniform.smart_write(first_map_index + current_map_index);
-		data_month_uniform.smart_write(current_month);
+		let current_month = time_cursor.get().month as i32;
+		for (shader, texture_uniform, data_month_uniform) in month_uniforms.iter_mut() {
+			shader.use_shader();
+			texture_uniform.smart_write(FIRST_MAP_UNIT + map_index(current_month));
+			data_month_uniform.smart_write(current_month);
+		}
 	}
 }
@@ -0,0 +1,62 @@
+/// A point on the globe, in degrees. Latitude is positive to the north, and
+/// longitude is positive to the east.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GeoPosition {
+	pub latitude: f32,
+	pub longitude: f32,
+}
+
+/// The inverse of `pointToUv` in `pointmapping.glsl`, so that geographic
+/// positions line up with the equirectangular textures.
+pub fn geo_to_world(position: &GeoPosition) -> nglm::Vec3 {
+	let latitude = position.latitude.to_radians();
+	let longitude = position.longitude.to_radians();
+
+	nglm::vec3(latitude.cos() * longitude.sin(), latitude.sin(), latitude.cos() * longitude.cos())
+}
+
+#[allow(dead_code)]
+pub fn world_to_geo(point: &nglm::Vec3) -> GeoPosition {
+	let direction = point.normalize();
+
+	GeoPosition {
+		latitude: direction.y.clamp(-1.0, 1.0).asin().to_degrees(),
+		longitude: direction.x.atan2(direction.z).to_degrees(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		for (latitude, longitude) in [(0.0, 0.0), (45.0, 90.0), (-30.0, -120.0), (60.0, 179.0)] {
+			let position = GeoPosition { latitude, longitude };
+			let round_tripped = world_to_geo(&geo_to_world(&position));
+
+			assert!((round_tripped.latitude - latitude).abs() < 1.0e-3);
+			assert!((round_tripped.longitude - longitude).abs() < 1.0e-3);
+		}
+	}
+
+	#[test]
+	fn axes() {
+		let epsilon = 1.0e-6;
+		assert!(
+			geo_to_world(&GeoPosition { latitude: 0.0, longitude: 0.0 })
+				.metric_distance(&nglm::Vec3::z())
+				< epsilon
+		);
+		assert!(
+			geo_to_world(&GeoPosition { latitude: 90.0, longitude: 0.0 })
+				.metric_distance(&nglm::Vec3::y())
+				< epsilon
+		);
+		assert!(
+			geo_to_world(&GeoPosition { latitude: 0.0, longitude: 90.0 })
+				.metric_distance(&nglm::Vec3::x())
+				< epsilon
+		);
+	}
+}
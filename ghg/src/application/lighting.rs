@@ -1,32 +1,140 @@
 use crate::application::shaders::ShaderContext;
+use crate::application::solar::sun_direction;
+use crate::application::time_cursor::TimeCursor;
+use crate::render_core::backend::GraphicsBackend;
 use crate::render_core::uniform;
 use crate::render_core::uniform::SmartUniform;
 
-pub struct LightParameters {
-	pub ambient_strength: SmartUniform<f32>,
-	pub specular_strength: SmartUniform<f32>,
-	pub ambient_color: SmartUniform<nglm::Vec3>,
-	pub light_color: SmartUniform<nglm::Vec3>,
-	pub light_position: SmartUniform<nglm::Vec3>,
-	pub camera_position: SmartUniform<nglm::Vec3>,
+/// Far enough away that the sun is effectively a directional light
+const SUN_DISTANCE: f32 = 1000.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LightSource {
+	/// Front-lit from wherever the camera is
+	Camera,
+	/// Positioned from the subsolar point at the current time cursor
+	Sun,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LightingOptions {
+	pub source: LightSource,
+	pub night_lights_available: bool,
+	/// Whether the sky pass draws its starfield and the atmosphere's glow
+	pub stars_enabled: bool,
+	pub atmosphere_enabled: bool,
+}
+
+impl Default for LightingOptions {
+	fn default() -> Self {
+		Self {
+			source: LightSource::Camera,
+			night_lights_available: false,
+			stars_enabled: true,
+			atmosphere_enabled: true,
+		}
+	}
 }
 
-impl LightParameters {
-	pub fn new(shader_context: &ShaderContext) -> Self {
-		let ambient_strength = uniform::init_smart_f32("u_ambientStrength", &shader_context, 0.3);
-		let specular_strength = uniform::init_smart_f32("u_specularStrength", &shader_context, 0.5);
-		let ambient_color =
-			uniform::init_smart_vec3("u_ambientColor", &shader_context, nglm::vec3(0.8, 0.8, 1.0));
-		let light_color =
-			uniform::init_smart_vec3("u_lightColor", &shader_context, nglm::vec3(1.0, 1.0, 1.0));
+impl LightingOptions {
+	pub fn toggle_source(self) -> Self {
+		let source = match self.source {
+			LightSource::Camera => LightSource::Sun,
+			LightSource::Sun => LightSource::Camera,
+		};
+		Self { source, ..self }
+	}
+
+	pub fn toggle_stars(self) -> Self { Self { stars_enabled: !self.stars_enabled, ..self } }
+
+	pub fn toggle_atmosphere(self) -> Self {
+		Self { atmosphere_enabled: !self.atmosphere_enabled, ..self }
+	}
+}
+
+/// Settings for the pass drawn behind the planet, see `sky::Sky`. Distances
+/// are in planet radii. Whether the stars and atmosphere are drawn at all is
+/// up to `LightingOptions`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkyParameters {
+	pub background_color: nglm::Vec3,
+	/// Roughly how many cells the sky is split into along each axis, where each
+	/// cell may hold a star
+	pub star_density: f32,
+	pub atmosphere_thickness: f32,
+	/// Height over which the atmosphere's density falls off by a factor of e
+	pub atmosphere_scale_height: f32,
+	/// Rayleigh scattering per unit distance for red, green and blue
+	pub scattering_coefficients: nglm::Vec3,
+	pub atmosphere_intensity: f32,
+}
 
+impl Default for SkyParameters {
+	fn default() -> Self {
 		Self {
-			ambient_strength,
-			specular_strength,
-			ambient_color,
-			light_color,
-			light_position: uniform::new_smart_vec3("u_lightPosition", &shader_context),
-			camera_position: uniform::new_smart_vec3("u_cameraPosition", &shader_context),
+			background_color: nglm::vec3(0.0, 0.0, 0.02),
+			star_density: 300.0,
+			atmosphere_thickness: 0.06,
+			atmosphere_scale_height: 0.015,
+			scattering_coefficients: nglm::vec3(5.5, 13.0, 22.4),
+			atmosphere_intensity: 10.0,
 		}
 	}
 }
+
+/// Where the light should be this frame
+pub fn light_position(
+	camera_position: &nglm::Vec3,
+	options: &LightingOptions,
+	time_cursor: &TimeCursor,
+) -> nglm::Vec3 {
+	match options.source {
+		LightSource::Camera => *camera_position,
+		LightSource::Sun => sun_direction(time_cursor) * SUN_DISTANCE,
+	}
+}
+
+/// The lighting uniforms that change from frame to frame. The rest are set
+/// once, by `new`.
+pub struct LightParameters<B: GraphicsBackend> {
+	pub light_position: SmartUniform<nglm::Vec3, B>,
+	pub camera_position: SmartUniform<nglm::Vec3, B>,
+	pub solar_lighting: SmartUniform<i32, B>,
+	pub night_lights_enabled: SmartUniform<i32, B>,
+	pub sky: SkyParameters,
+}
+
+impl<B: GraphicsBackend> LightParameters<B> {
+	pub fn new(shader_context: &ShaderContext<B>) -> Self {
+		uniform::init_f32("u_ambientStrength", shader_context, 0.3);
+		uniform::init_f32("u_specularStrength", shader_context, 0.5);
+		uniform::init_vec3("u_ambientColor", shader_context, nglm::vec3(0.8, 0.8, 1.0));
+		uniform::init_vec3("u_lightColor", shader_context, nglm::vec3(1.0, 1.0, 1.0));
+
+		Self {
+			light_position: uniform::new_smart_vec3("u_lightPosition", shader_context),
+			camera_position: uniform::new_smart_vec3("u_cameraPosition", shader_context),
+			solar_lighting: uniform::init_smart_i32("u_solarLighting", shader_context, 0),
+			night_lights_enabled: uniform::init_smart_i32(
+				"u_nightLightsEnabled",
+				shader_context,
+				0,
+			),
+			sky: SkyParameters::default(),
+		}
+	}
+
+	pub fn update(
+		&mut self,
+		camera_position: &nglm::Vec3,
+		light_position: &nglm::Vec3,
+		options: &LightingOptions,
+	) {
+		self.camera_position.smart_write(*camera_position);
+		self.light_position.smart_write(*light_position);
+
+		let solar = options.source == LightSource::Sun;
+		self.solar_lighting.smart_write(solar as i32);
+		self.night_lights_enabled.smart_write((solar && options.night_lights_available) as i32);
+	}
+}
@@ -0,0 +1,116 @@
+use crate::application::shaders::ShaderContext;
+use crate::application::sphere::{generate_lod_sphere, lod_statistics, SphereTessellation};
+use crate::application::vertex::BasicMesh;
+use crate::render_core::backend::GraphicsBackend;
+use crate::render_core::camera::Camera;
+use crate::render_core::culling::{Culler, Visibility};
+use crate::render_core::mesh::{add_mesh, draw_buffers, DrawBuffers, DrawMode, MeshMode, ToMesh};
+#[allow(unused_imports)]
+use crate::utils::prelude::*;
+
+/// A sphere whose patches are uploaded once per level of detail, so that each
+/// frame can pick the level for every patch based on its distance from the
+/// camera.
+pub struct LodSphere<B: GraphicsBackend> {
+	patches: Vec<LodPatch<B>>,
+	lod_switch_distance: f32,
+}
+
+struct LodPatch<B: GraphicsBackend> {
+	levels: Vec<(BasicMesh, DrawBuffers<B>)>,
+	size: f32,
+}
+
+impl<B: GraphicsBackend> LodSphere<B> {
+	pub fn new(
+		shader_context: &ShaderContext<B>,
+		tessellation: &SphereTessellation,
+	) -> Result<Self, String> {
+		let generated = generate_lod_sphere(tessellation);
+		ghg_log!("Planet LOD statistics: {:?}", lod_statistics(&generated));
+
+		let patches = generated
+			.into_iter()
+			.map(|patch| {
+				let size = patch.size();
+				let levels = patch
+					.lods
+					.into_iter()
+					.map(|mesh| {
+						let buffers = add_mesh(shader_context, &mesh, MeshMode::Static)?;
+						Ok((mesh, buffers))
+					})
+					.collect::<Result<Vec<_>, String>>()?;
+
+				Ok(LodPatch { levels, size })
+			})
+			.collect::<Result<Vec<_>, String>>()?;
+
+		Ok(Self { patches, lod_switch_distance: tessellation.lod_switch_distance })
+	}
+
+	/// Levels are chosen based on `camera`, and each chosen mesh is tested
+	/// against `culler` before being drawn.
+	pub fn draw(&self, context: &B, camera: &Camera, culler: &Culler, draw_mode: DrawMode) {
+		let camera_position = camera.position();
+
+		for patch in self.patches.iter() {
+			let Some(center) = patch.levels[0].0.get_center() else {
+				culler.record(Visibility::Invalid);
+				continue;
+			};
+
+			let level = select_lod(
+				camera_position.metric_distance(&center),
+				patch.size,
+				self.lod_switch_distance,
+				patch.levels.len(),
+			);
+
+			let (mesh, buffers) = &patch.levels[level];
+			if mesh.is_visible(culler) {
+				draw_buffers(context, buffers, &draw_mode);
+			}
+		}
+	}
+}
+
+/// Picks the finest level (0) within `lod_switch_distance` patch sizes of the
+/// camera, then drops one level each time the distance doubles.
+pub fn select_lod(
+	distance: f32,
+	patch_size: f32,
+	lod_switch_distance: f32,
+	num_levels: usize,
+) -> usize {
+	let relative_distance = distance / (patch_size * lod_switch_distance);
+
+	if num_levels == 0 || relative_distance <= 1.0 {
+		0
+	} else {
+		(relative_distance.log2().floor() as usize + 1).min(num_levels - 1)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn closer_is_never_coarser() {
+		let levels: Vec<usize> =
+			(1..200).map(|d| select_lod(d as f32 * 0.05, 0.3, 2.0, 4)).collect();
+
+		assert_eq!(levels.first(), Some(&0));
+		assert_eq!(levels.last(), Some(&3));
+		assert!(levels.windows(2).all(|l| l[0] <= l[1]));
+	}
+
+	#[test]
+	fn switches_when_distance_doubles() {
+		assert_eq!(select_lod(0.6, 0.3, 2.0, 4), 0);
+		assert_eq!(select_lod(1.1, 0.3, 2.0, 4), 1);
+		assert_eq!(select_lod(2.3, 0.3, 2.0, 4), 2);
+		assert_eq!(select_lod(100.0, 0.3, 2.0, 4), 3);
+	}
+}
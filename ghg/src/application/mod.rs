@@ -1,8 +1,18 @@
 pub mod animation_loop;
+pub mod camera_controller;
 pub mod control;
 pub mod data;
+pub mod geo;
 pub mod lighting;
+pub mod lod;
 pub mod planet;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod planet_shading;
 pub mod shaders;
+pub mod sky;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sky_shading;
+pub mod solar;
 pub mod sphere;
+pub mod time_cursor;
 pub mod vertex;
@@ -9,42 +9,72 @@ use single_thread_executor::Spawner;
 use wasm_bindgen::JsValue;
 use web_sys::WebGl2RenderingContext;
 
-use crate::application::lighting::LightParameters;
-use crate::application::shaders::ShaderContext;
-use crate::application::sphere::generate_sphere;
-use crate::application::vertex::BasicMesh;
+use crate::application::lighting::{light_position, LightParameters, LightingOptions};
+use crate::application::lod::LodSphere;
+use crate::application::shaders::{PlanetShaders, ShaderContext};
+use crate::application::sky::Sky;
+use crate::application::sphere::SphereTessellation;
+use crate::application::time_cursor::TimeCursor;
 use crate::render_core::animation_params::AnimationParams;
-use crate::render_core::camera::Camera;
+use crate::render_core::backend::GraphicsBackend;
+use crate::render_core::camera::{log_depth_coefficient, Camera, ClipPlanes};
+use crate::render_core::culling::{Culler, CullingStatistics, TerrainShell};
 use crate::render_core::frame_sequencer::FrameGate;
 use crate::render_core::image::load_into_texture;
-use crate::render_core::mesh::{
-	add_mesh, clear_frame, draw_meshes, DrawBuffers, DrawMode, MeshMode,
-};
+use crate::render_core::mesh::{clear_frame, DrawMode};
 use crate::render_core::uniform;
+use crate::render_core::uniform::SmartUniform;
 use crate::request_data::fetch_bytes;
 #[allow(unused_imports)]
 use crate::utils::prelude::*;
 
+/// Where the planet's textures are served from, and the units they're bound to
+pub const HEIGHT_MAP_PATH: &str = "images/earth_height/2/full.png";
+pub const HEIGHT_MAP_UNIT: u32 = 0;
+pub const COLOR_MAP_PATH: &str = "images/earth_color/2/full.png";
+pub const COLOR_MAP_UNIT: u32 = 1;
+pub const NIGHT_MAP_PATH: &str = "images/earth_night/2/full.png";
+pub const NIGHT_MAP_UNIT: u32 = 5;
+
 async fn load_planet_terrain(context: WebGl2RenderingContext) -> Result<(), JsValue> {
-	let texture = fetch_bytes("images/earth_height/2/full.png").await?;
-	load_into_texture::<Luma<u8>>(context, &texture, WebGl2RenderingContext::TEXTURE0)
+	let texture = fetch_bytes(HEIGHT_MAP_PATH).await?;
+	Ok(load_into_texture::<Luma<u8>, _>(&context, &texture, HEIGHT_MAP_UNIT)?)
 }
 
 async fn load_planet_color(context: WebGl2RenderingContext) -> Result<(), JsValue> {
-	let texture = fetch_bytes("images/earth_color/2/full.png").await?;
-	load_into_texture::<Rgb<u8>>(context, &texture, WebGl2RenderingContext::TEXTURE1)?;
+	let texture = fetch_bytes(COLOR_MAP_PATH).await?;
+	load_into_texture::<Rgb<u8>, _>(&context, &texture, COLOR_MAP_UNIT)?;
 	Ok(())
 }
 
-async fn load_all_textures(context: WebGl2RenderingContext, done: Rc<Cell<bool>>) {
-	let (color_result, terrain_result) =
-		join!(load_planet_color(context.clone()), load_planet_terrain(context.clone()),).await;
+async fn load_planet_night_lights(context: WebGl2RenderingContext) -> Result<(), JsValue> {
+	let texture = fetch_bytes(NIGHT_MAP_PATH).await?;
+	Ok(load_into_texture::<Rgb<u8>, _>(&context, &texture, NIGHT_MAP_UNIT)?)
+}
+
+async fn load_all_textures(
+	context: WebGl2RenderingContext,
+	lighting_options: Rc<Cell<LightingOptions>>,
+) -> Result<(), JsValue> {
+	let (color_result, terrain_result, night_result) = join!(
+		load_planet_color(context.clone()),
+		load_planet_terrain(context.clone()),
+		load_planet_night_lights(context.clone()),
+	)
+	.await;
+
+	color_result?;
+	terrain_result?;
 
-	assert!(color_result.is_ok(), "Color load failed");
-	assert!(terrain_result.is_ok(), "Terrain load failed");
+	// Night lights are optional, the dark side is just left unlit without them
+	match night_result {
+		Ok(()) => lighting_options
+			.set(LightingOptions { night_lights_available: true, ..lighting_options.get() }),
+		Err(e) => ghg_log!("Night lights unavailable: {:?}", e),
+	}
 
 	remove_overlay();
-	done.replace(true);
+	Ok(())
 }
 
 // TODO: Move to module
@@ -53,28 +83,15 @@ extern "C" {
 	fn remove_overlay();
 }
 
-fn generate_drawable_sphere(
-	subdivisions: u32,
-	points_per_subdivision: u32,
-	shader_context: ShaderContext,
-) -> Vec<(BasicMesh, DrawBuffers)> {
-	let sphere_meshes = generate_sphere(subdivisions, points_per_subdivision);
-	let buffers: Vec<DrawBuffers> = sphere_meshes
-		.iter()
-		.map(|m| add_mesh(&shader_context, m, MeshMode::Static).unwrap())
-		.collect();
-
-	sphere_meshes.into_iter().zip(buffers.into_iter()).collect()
-}
-
 pub async fn load_textures(
 	gate: FrameGate<AnimationParams>,
 	spawner: Spawner,
 	context: WebGl2RenderingContext,
 	camera: Rc<RefCell<Camera>>,
+	lighting_options: Rc<Cell<LightingOptions>>,
 ) {
-	let textures_loaded = Rc::new(Cell::new(false));
-	spawner.spawn(load_all_textures(context, textures_loaded.clone()));
+	let loading =
+		spawner.spawn_named("Load All Textures", load_all_textures(context, lighting_options));
 
 	let mut initial_spin = 3.0f32;
 	let mut spinner = move |delta_time: Duration, mut camera: RefMut<Camera>| -> bool {
@@ -93,84 +110,322 @@ pub async fn load_textures(
 		}
 	};
 
+	// The overlay stays up if this fails, as there's no planet to show
+	let result = loading.await.unwrap_or_else(|e| Err(e.to_string().into()));
+	if let Err(e) = result {
+		ghg_log!("Texture load failed: {:?}", e);
+		return;
+	}
+
 	loop {
 		let params = (&gate).await;
+		if !spinner(params.delta_time, camera.deref().borrow_mut()) {
+			break;
+		}
+	}
+}
+
+thread_local! {
+	static LAST_CULLING_STATISTICS: Cell<CullingStatistics> = Cell::new(CullingStatistics::default());
+}
+
+/// Culling results from the most recently drawn frame, as JSON
+#[wasm_bindgen]
+pub fn culling_statistics() -> String {
+	let statistics = LAST_CULLING_STATISTICS.with(|s| s.get());
+	serde_json::json!({
+		"tested": statistics.tested,
+		"visible": statistics.visible,
+		"outsideFrustum": statistics.outside_frustum,
+		"beyondHorizon": statistics.beyond_horizon,
+		"invalid": statistics.invalid,
+	})
+	.to_string()
+}
+
+/// What changes from one frame of the planet to the next, apart from the
+/// camera
+#[derive(Clone, Copy, Debug)]
+pub struct PlanetFrame {
+	pub width: i32,
+	pub height: i32,
+	pub delta_time: Duration,
+	pub terrain_scale: f32,
+	pub time_cursor: TimeCursor,
+	pub lighting_options: LightingOptions,
+}
 
-		if textures_loaded.get() {
-			if !spinner(params.delta_time, camera.deref().borrow_mut()) {
-				break;
-			}
+/// One variant of the planet program, with the uniforms the renderer writes
+struct PlanetProgram<B: GraphicsBackend> {
+	shader: ShaderContext<B>,
+	lighting: LightParameters<B>,
+
+	// TODO: Need to make a uniform that can bind into multiple shader programs
+	model: SmartUniform<nglm::Mat4, B>,
+	view: SmartUniform<nglm::Mat4, B>,
+	projection: SmartUniform<nglm::Mat4, B>,
+	terrain_scale: SmartUniform<f32, B>,
+	log_depth_coefficient: SmartUniform<f32, B>,
+}
+
+impl<B: GraphicsBackend> PlanetProgram<B> {
+	fn new(shader: &ShaderContext<B>) -> Self {
+		shader.use_shader();
+
+		uniform::init_i32("s_textureMap", shader, HEIGHT_MAP_UNIT as i32);
+		uniform::init_i32("s_colorMap", shader, COLOR_MAP_UNIT as i32);
+		uniform::init_i32("s_nightMap", shader, NIGHT_MAP_UNIT as i32);
+
+		Self {
+			lighting: LightParameters::new(shader),
+			model: uniform::new_smart_mat4("u_model", shader),
+			view: uniform::new_smart_mat4("u_view", shader),
+			projection: uniform::new_smart_mat4("u_projection", shader),
+			terrain_scale: uniform::new_smart_f32("u_terrainScale", shader),
+			log_depth_coefficient: uniform::new_smart_f32("u_logDepthCoefficient", shader),
+			shader: shader.clone(),
 		}
 	}
 }
 
-pub async fn draw(
-	gate: FrameGate<AnimationParams>,
-	shader: ShaderContext,
-	camera: Rc<RefCell<Camera>>,
-) {
-	let mut frustum_test_camera =
-		Camera::new(&nglm::vec3(1.1, 0.0, 0.0), &nglm::vec3(0.0, 0.0, 0.0));
-	const DEBUG_FRUSTUM: bool = false;
+/// Draws the planet and the sky behind it, one frame at a time
+pub struct PlanetRenderer<B: GraphicsBackend> {
+	standard: PlanetProgram<B>,
+	logarithmic_depth: PlanetProgram<B>,
+	sky: Sky<B>,
+	planet: LodSphere<B>,
+	frustum_test_camera: Camera,
+}
 
-	shader.use_shader();
+impl<B: GraphicsBackend> PlanetRenderer<B> {
+	pub fn new(
+		shaders: PlanetShaders<B>,
+		sky_shader: ShaderContext<B>,
+		tessellation: &SphereTessellation,
+	) -> Result<Self, String> {
+		let frustum_test_camera =
+			Camera::new(&nglm::vec3(1.1, 0.0, 0.0), &nglm::vec3(0.0, 0.0, 0.0));
 
-	let planet_meshes_and_buffers = generate_drawable_sphere(10, 10, shader.clone());
+		let sky = Sky::new(sky_shader)?;
 
-	let mut lighting = LightParameters::new(&shader);
+		let logarithmic_depth = PlanetProgram::new(&shaders.logarithmic_depth);
+		let standard = PlanetProgram::new(&shaders.standard);
 
-	uniform::init_i32("s_textureMap", &shader, 0);
-	uniform::init_i32("s_colorMap", &shader, 1);
+		// Both variants declare the same attribute locations, so either can
+		// draw these buffers
+		let planet = LodSphere::new(&shaders.standard, tessellation)?;
 
-	// TODO: Need to make a uniform that can bind into multiple shader programs
-	let mut planet_model = uniform::new_smart_mat4("u_model", &shader);
-	let mut planet_view = uniform::new_smart_mat4("u_view", &shader);
-	let mut planet_projection = uniform::new_smart_mat4("u_projection", &shader);
+		Ok(Self { standard, logarithmic_depth, sky, planet, frustum_test_camera })
+	}
 
-	loop {
-		let params = (&gate).await;
+	/// Fits the camera's clip planes around the terrain, if it's set up to be,
+	/// then draws. Returns how culling went.
+	pub fn draw_frame(&mut self, camera: &mut Camera, frame: &PlanetFrame) -> CullingStatistics {
+		const DEBUG_FRUSTUM: bool = false;
 
 		if DEBUG_FRUSTUM {
-			frustum_test_camera.orbit_around_target(
+			self.frustum_test_camera.orbit_around_target(
 				&nglm::zero(),
-				&nglm::vec2(params.delta_time.as_millis() as f32, 0.0),
+				&nglm::vec2(frame.delta_time.as_millis() as f32, 0.0),
 				0.05,
 			);
 		}
 
-		clear_frame(params.viewport.context());
+		let program = if camera.projection().logarithmic_depth {
+			&mut self.logarithmic_depth
+		} else {
+			&mut self.standard
+		};
 
-		let camera_position = camera.deref().borrow().position();
-		lighting.camera_position.smart_write(camera_position.clone());
-		lighting.light_position.smart_write(camera_position.clone()); // - nglm::vec3(0.0, 0.5, 0.3)
+		// The sky pass switches programs at the end of every frame
+		program.shader.use_shader();
 
-		let width = params.viewport.width() as i32;
-		let height = params.viewport.height() as i32;
+		clear_frame(&program.shader.context, &program.lighting.sky.background_color);
 
-		let mvp = camera.deref().borrow().get_perspective_matrices(width, height);
+		let options = frame.lighting_options;
+		let camera_position = camera.position();
+		let light_position = light_position(&camera_position, &options, &frame.time_cursor);
+		program.lighting.update(&camera_position, &light_position, &options);
 
-		if DEBUG_FRUSTUM {
-			planet_model.smart_write(mvp.model.clone());
-			planet_view.smart_write(mvp.view.clone());
-			planet_projection.smart_write(mvp.projection.clone());
-
-			draw_meshes(
-				params.viewport.context(),
-				&frustum_test_camera,
-				&planet_meshes_and_buffers,
-				DrawMode::Surface,
-			);
-		} else {
-			planet_model.smart_write(mvp.model.clone());
-			planet_view.smart_write(mvp.view.clone());
-			planet_projection.smart_write(mvp.projection.clone());
-
-			draw_meshes(
-				params.viewport.context(),
-				camera.deref().borrow().deref(),
-				&planet_meshes_and_buffers,
-				DrawMode::Surface,
-			);
+		program.terrain_scale.smart_write(frame.terrain_scale);
+
+		let terrain_shell = TerrainShell::from_terrain_scale(frame.terrain_scale);
+		if let ClipPlanes::AroundShell(_) = camera.projection().clip_planes {
+			camera.set_clip_planes(ClipPlanes::AroundShell(terrain_shell));
+		}
+
+		let (width, height) = (frame.width, frame.height);
+		let mvp = camera.get_perspective_matrices(width, height);
+
+		program.model.smart_write(mvp.model.clone());
+		program.view.smart_write(mvp.view.clone());
+		program.projection.smart_write(mvp.projection.clone());
+		program.log_depth_coefficient.smart_write(log_depth_coefficient(mvp.far));
+
+		let culling_camera = if DEBUG_FRUSTUM { &self.frustum_test_camera } else { &*camera };
+		let culler = Culler::new(
+			culling_camera,
+			&culling_camera.get_perspective_matrices(width, height),
+			Some(terrain_shell),
+		);
+
+		self.planet.draw(&program.shader.context, culling_camera, &culler, DrawMode::Surface);
+
+		self.sky.draw(&mvp, &camera_position, &light_position, &program.lighting.sky, &options);
+		culler.statistics()
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn draw(
+	gate: FrameGate<AnimationParams>,
+	shaders: PlanetShaders<WebGl2RenderingContext>,
+	sky_shader: ShaderContext<WebGl2RenderingContext>,
+	camera: Rc<RefCell<Camera>>,
+	tessellation: SphereTessellation,
+	terrain_scale: Rc<Cell<f32>>,
+	time_cursor: Rc<Cell<TimeCursor>>,
+	lighting_options: Rc<Cell<LightingOptions>>,
+) {
+	let mut renderer = PlanetRenderer::new(shaders, sky_shader, &tessellation)
+		.expect("Failed to set up the planet");
+
+	loop {
+		let params = (&gate).await;
+
+		let frame = PlanetFrame {
+			width: params.viewport.width() as i32,
+			height: params.viewport.height() as i32,
+			delta_time: params.delta_time,
+			terrain_scale: terrain_scale.get(),
+			time_cursor: time_cursor.get(),
+			lighting_options: lighting_options.get(),
+		};
+		let statistics = renderer.draw_frame(&mut camera.deref().borrow_mut(), &frame);
+
+		LAST_CULLING_STATISTICS.with(|s| s.set(statistics));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::application::shaders::{get_planet_shaders, get_sky_shaders};
+	use crate::render_core::backend::UniformData;
+	use crate::render_core::camera::Projection;
+	use crate::render_core::recording_backend::{Command, RecordingBackend};
+
+	fn frame() -> PlanetFrame {
+		PlanetFrame {
+			width: 640,
+			height: 480,
+			delta_time: Duration::from_millis(16),
+			terrain_scale: 0.03,
+			time_cursor: TimeCursor::default(),
+			lighting_options: LightingOptions::default(),
+		}
+	}
+
+	fn written_uniforms(commands: &[Command]) -> Vec<&str> {
+		commands
+			.iter()
+			.filter_map(|command| match command {
+				Command::Uniform { name, .. } => Some(name.as_str()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn draws_planet_then_sky() {
+		let backend = RecordingBackend::new();
+		let tessellation = SphereTessellation {
+			subdivisions: 1,
+			lod_points_per_subdivision: vec![5, 3],
+			..SphereTessellation::default()
+		};
+		let mut renderer = PlanetRenderer::new(
+			get_planet_shaders(&backend).unwrap(),
+			get_sky_shaders(&backend).unwrap(),
+			&tessellation,
+		)
+		.unwrap();
+		let mut camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::zero());
+		backend.take_commands();
+
+		let statistics = renderer.draw_frame(&mut camera, &frame());
+		let commands = backend.take_commands();
+		assert_eq!(backend.errors(), Vec::<String>::new());
+
+		// Only the side facing the camera is drawn
+		let planet_draws = commands
+			.iter()
+			.filter(|command| matches!(command, Command::DrawElements { .. }))
+			.count();
+		assert_eq!(planet_draws, statistics.visible);
+		assert!(statistics.visible > 0 && statistics.visible < statistics.tested);
+		assert_eq!(
+			commands.last(),
+			Some(&Command::SetDepth(crate::render_core::backend::DepthFunction::Less, true))
+		);
+		assert!(commands.contains(&Command::Uniform {
+			name: "u_terrainScale".to_owned(),
+			value: UniformData::Float(0.03),
+		}));
+
+		// Nothing has changed, so only the sky's program switch needs redoing
+		renderer.draw_frame(&mut camera, &frame());
+		let commands = backend.take_commands();
+		assert_eq!(written_uniforms(&commands), Vec::<&str>::new());
+		assert_eq!(backend.errors(), Vec::<String>::new());
+	}
+
+	#[test]
+	fn sky_toggles_follow_the_lighting_options() {
+		let backend = RecordingBackend::new();
+		let mut renderer = PlanetRenderer::new(
+			get_planet_shaders(&backend).unwrap(),
+			get_sky_shaders(&backend).unwrap(),
+			&SphereTessellation { subdivisions: 1, ..SphereTessellation::default() },
+		)
+		.unwrap();
+		let mut camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::zero());
+		renderer.draw_frame(&mut camera, &frame());
+		backend.take_commands();
+
+		let options = LightingOptions::default().toggle_stars().toggle_atmosphere();
+		renderer.draw_frame(&mut camera, &PlanetFrame { lighting_options: options, ..frame() });
+		let commands = backend.take_commands();
+		for name in ["u_starsEnabled", "u_atmosphereEnabled"] {
+			assert!(commands
+				.contains(&Command::Uniform { name: name.to_owned(), value: UniformData::Int(0) }));
+		}
+		assert_eq!(backend.errors(), Vec::<String>::new());
+	}
+
+	#[test]
+	fn logarithmic_depth_uses_its_own_program() {
+		let backend = RecordingBackend::new();
+		let shaders = get_planet_shaders(&backend).unwrap();
+		let mut renderer = PlanetRenderer::new(
+			shaders.clone(),
+			get_sky_shaders(&backend).unwrap(),
+			&SphereTessellation { subdivisions: 1, ..SphereTessellation::default() },
+		)
+		.unwrap();
+		let mut camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::zero());
+
+		for logarithmic_depth in [false, true, false] {
+			camera.set_projection(Projection { logarithmic_depth, ..*camera.projection() });
+			backend.take_commands();
+			renderer.draw_frame(&mut camera, &frame());
+
+			let program = match logarithmic_depth {
+				true => shaders.logarithmic_depth.program,
+				false => shaders.standard.program,
+			};
+			assert_eq!(backend.take_commands().first(), Some(&Command::UseProgram(Some(program))));
+			assert_eq!(backend.errors(), Vec::<String>::new());
 		}
 	}
 }
@@ -0,0 +1,483 @@
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use crate::application::data::Colormap;
+use crate::application::shaders::{planet_fragment_source, PLANET_VERTEX_SOURCE};
+use crate::render_core::software_backend::{Sampler, ShaderInputs, ShaderPort, SoftwareBackend};
+
+// Where planet.vert's outputs sit among the varyings
+const FRAG_POSITION: usize = 0;
+const FRAG_NORMAL: usize = 3;
+const FRAG_COLOR: usize = 6;
+const FRAG_LOG_DEPTH: usize = 10;
+const VARYINGS: usize = 11;
+
+const NUM_MAPS_PER_YEAR: i32 = 3;
+const NUM_CHANNELS_IN_MAP: i32 = 4;
+const NIGHT_AMBIENT_SCALE: f32 = 0.15;
+const TWILIGHT_WIDTH: f32 = 0.1;
+const SHININESS: f32 = 32.0;
+
+/// planet.vert and planet.frag, ported so `SoftwareBackend` can draw the
+/// globe. Follow the shaders closely when changing either, as golden images
+/// depend on them agreeing.
+pub struct PlanetShading {
+	/// Whether this is the variant compiled with `LOG_DEPTH`
+	pub logarithmic_depth: bool,
+}
+
+/// Draws both variants of the planet program on `backend` with
+/// `PlanetShading`
+pub fn add_planet_port(backend: &SoftwareBackend) {
+	for logarithmic_depth in [false, true] {
+		backend.add_port(
+			PLANET_VERTEX_SOURCE,
+			&planet_fragment_source(logarithmic_depth),
+			Rc::new(PlanetShading { logarithmic_depth }),
+		);
+	}
+}
+
+// pointmapping.glsl
+fn point_to_uv(point_on_sphere: &nglm::Vec3) -> nglm::Vec2 {
+	let u = (0.5 + point_on_sphere.x.atan2(point_on_sphere.z) / 2.0 / PI).clamp(0.0, 1.0);
+	let v = (0.5 + point_on_sphere.y.asin() / PI).clamp(0.0, 1.0);
+	nglm::vec2(u, v)
+}
+
+// terrain.glsl
+fn terrain_radius(height_map: &Sampler, point_on_sphere: &nglm::Vec3, terrain_scale: f32) -> f32 {
+	let terrain_value = height_map.sample(&point_to_uv(point_on_sphere)).x;
+	1.0 + (terrain_value * terrain_scale) - terrain_scale / 2.0
+}
+
+fn displaced_point(
+	height_map: &Sampler,
+	point_on_sphere: &nglm::Vec3,
+	terrain_scale: f32,
+) -> nglm::Vec3 {
+	point_on_sphere * terrain_radius(height_map, point_on_sphere, terrain_scale)
+}
+
+fn terrain_normal(
+	height_map: &Sampler,
+	point_on_sphere: &nglm::Vec3,
+	terrain_scale: f32,
+) -> nglm::Vec3 {
+	let up = if point_on_sphere.y.abs() < 0.999 {
+		nglm::vec3(0.0, 1.0, 0.0)
+	} else {
+		nglm::vec3(1.0, 0.0, 0.0)
+	};
+	let east = nglm::normalize(&nglm::cross(&up, point_on_sphere));
+	let north = nglm::cross(point_on_sphere, &east);
+
+	let texel_angle = 2.0 * PI / height_map.size().0 as f32;
+
+	let displaced = |offset: nglm::Vec3| {
+		displaced_point(height_map, &nglm::normalize(&(point_on_sphere + offset)), terrain_scale)
+	};
+	let east_point = displaced(east * texel_angle);
+	let west_point = displaced(-east * texel_angle);
+	let north_point = displaced(north * texel_angle);
+	let south_point = displaced(-north * texel_angle);
+
+	nglm::normalize(&nglm::cross(&(east_point - west_point), &(north_point - south_point)))
+}
+
+// channels.glsl
+fn channel_values(
+	data_map: &Sampler,
+	texture_point: &nglm::Vec2,
+	min_values: &nglm::Vec4,
+	max_values: &nglm::Vec4,
+) -> nglm::Vec4 {
+	let channels = data_map.sample(texture_point);
+	let ranges = max_values - min_values;
+	channels.component_mul(&ranges) + min_values
+}
+
+fn channel_index(source: &nglm::Vec4, channel: i32) -> f32 {
+	match channel {
+		0..=3 => source[channel as usize],
+		_ => -1.0,
+	}
+}
+
+// color.glsl
+fn hue_to_rgb(f1: f32, f2: f32, mut hue: f32) -> f32 {
+	if hue < 0.0 {
+		hue += 1.0;
+	} else if hue > 1.0 {
+		hue -= 1.0;
+	}
+
+	if (6.0 * hue) < 1.0 {
+		f1 + (f2 - f1) * 6.0 * hue
+	} else if (2.0 * hue) < 1.0 {
+		f2
+	} else if (3.0 * hue) < 2.0 {
+		f1 + (f2 - f1) * ((2.0 / 3.0) - hue) * 6.0
+	} else {
+		f1
+	}
+}
+
+fn hsl_to_rgb(hsl: &nglm::Vec3) -> nglm::Vec3 {
+	if hsl.y == 0.0 {
+		return nglm::vec3(hsl.z, hsl.z, hsl.z);
+	}
+
+	let f2 = if hsl.z < 0.5 { hsl.z * (1.0 + hsl.y) } else { hsl.z + hsl.y - hsl.y * hsl.z };
+	let f1 = 2.0 * hsl.z - f2;
+
+	nglm::vec3(
+		hue_to_rgb(f1, f2, hsl.x + (1.0 / 3.0)),
+		hue_to_rgb(f1, f2, hsl.x),
+		hue_to_rgb(f1, f2, hsl.x - (1.0 / 3.0)),
+	)
+}
+
+// colormaps.glsl
+fn colormap(map: i32, proportion: f32) -> nglm::Vec3 {
+	if map == Colormap::Grayscale.uniform_value() {
+		nglm::vec3(proportion, proportion, proportion)
+	} else if map == Colormap::Diverging.uniform_value() {
+		let low = nglm::vec3(0.23, 0.3, 0.75);
+		let middle = nglm::vec3(0.87, 0.87, 0.87);
+		let high = nglm::vec3(0.71, 0.02, 0.15);
+		if proportion < 0.5 {
+			nglm::lerp(&low, &middle, proportion * 2.0)
+		} else {
+			nglm::lerp(&middle, &high, proportion * 2.0 - 1.0)
+		}
+	} else {
+		let truncate_color_space = 0.9;
+		hsl_to_rgb(&nglm::vec3((1.0 - proportion) * truncate_color_space, 1.0, 0.5))
+	}
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+	let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+	t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 { a * (1.0 - t) + b * t }
+
+fn vec3_at(varyings: &[f32], start: usize) -> nglm::Vec3 {
+	nglm::vec3(varyings[start], varyings[start + 1], varyings[start + 2])
+}
+
+/// The fragment shader's view of one pixel
+struct Fragment<'a, 'b> {
+	position: nglm::Vec3,
+	normal: nglm::Vec3,
+	color: nglm::Vec4,
+	inputs: &'a ShaderInputs<'b>,
+}
+
+impl<'a, 'b> Fragment<'a, 'b> {
+	fn ambient_light(&self) -> nglm::Vec3 {
+		self.inputs.vec3("u_ambientColor") * self.inputs.float("u_ambientStrength")
+	}
+
+	fn diffuse_light(&self, light_dir: &nglm::Vec3, norm: &nglm::Vec3) -> nglm::Vec3 {
+		let diff = nglm::dot(norm, light_dir).max(0.0);
+		self.inputs.vec3("u_lightColor") * diff
+	}
+
+	fn specular_light(&self, light_dir: &nglm::Vec3, norm: &nglm::Vec3) -> nglm::Vec3 {
+		let view_dir = nglm::normalize(&(self.inputs.vec3("u_cameraPosition") - self.position));
+		let reflect_dir = nglm::reflect_vec(&-light_dir, norm);
+		let spec = nglm::dot(&view_dir, &reflect_dir).max(0.0).powf(SHININESS);
+		self.inputs.vec3("u_lightColor") * (self.inputs.float("u_specularStrength") * spec)
+	}
+
+	fn terrain_color(&self) -> nglm::Vec4 {
+		let sample_position = point_to_uv(&nglm::normalize(&self.position));
+		let mapped_color = self.inputs.sampler("s_colorMap").sample(&sample_position);
+		nglm::lerp(&self.color, &mapped_color, 0.99)
+	}
+
+	fn data_color(&self) -> nglm::Vec4 {
+		let month = self.inputs.int("u_dataMonth");
+		// There are only three maps, and GLSL leaves reading past the end of
+		// the matrix undefined, so December borrows the last one here
+		let map_index = (month / NUM_MAPS_PER_YEAR).clamp(0, 2) as usize;
+		let channel_in_map = month % NUM_CHANNELS_IN_MAP;
+
+		let min_values: nglm::Vec4 = self.inputs.mat4x3("u_dataMinValues").column(map_index).into();
+		let max_values: nglm::Vec4 = self.inputs.mat4x3("u_dataMaxValues").column(map_index).into();
+
+		let texture_point = point_to_uv(&nglm::normalize(&self.position));
+		let data_real_value = channel_values(
+			&self.inputs.sampler("s_dataMap"),
+			&texture_point,
+			&min_values,
+			&max_values,
+		);
+
+		let data_range = max_values - min_values;
+		let data_proportion = (data_real_value - min_values).component_div(&data_range);
+		let channel_value = channel_index(&data_proportion, channel_in_map);
+
+		colormap(self.inputs.int("u_colormap"), channel_value).push(1.0)
+	}
+
+	fn daylight(&self) -> f32 {
+		if !self.inputs.bool("u_solarLighting") {
+			return 1.0;
+		}
+
+		let sun_elevation = nglm::dot(
+			&nglm::normalize(&self.position),
+			&nglm::normalize(&self.inputs.vec3("u_lightPosition")),
+		);
+		smoothstep(-TWILIGHT_WIDTH, TWILIGHT_WIDTH, sun_elevation)
+	}
+
+	fn night_lights(&self) -> nglm::Vec3 {
+		if !self.inputs.bool("u_nightLightsEnabled") {
+			return nglm::zero();
+		}
+
+		let night_map = self.inputs.sampler("s_nightMap");
+		night_map.sample(&point_to_uv(&nglm::normalize(&self.position))).xyz()
+	}
+}
+
+impl ShaderPort for PlanetShading {
+	fn attributes(&self) -> &[(&'static str, u32)] {
+		&[("position", 0), ("normal", 1), ("color", 2)]
+	}
+
+	fn varyings(&self) -> usize { VARYINGS }
+
+	fn vertex(
+		&self,
+		_: u32,
+		attributes: &[nglm::Vec4],
+		inputs: &ShaderInputs,
+		varyings: &mut [f32],
+	) -> nglm::Vec4 {
+		let position = attributes[0].xyz();
+		let height_map = inputs.sampler("s_textureMap");
+		let terrain_scale = inputs.float("u_terrainScale");
+
+		let point_on_sphere = nglm::normalize(&position);
+		let position_scale = terrain_radius(&height_map, &point_on_sphere, terrain_scale);
+		let scaled_position = (position * position_scale).push(1.0);
+
+		let model = inputs.mat4("u_model");
+		let world_position = model * scaled_position;
+		let clip_position = inputs.mat4("u_projection") * inputs.mat4("u_view") * world_position;
+
+		// depth.glsl's logDepthInput
+		varyings[FRAG_LOG_DEPTH] = 1.0 + clip_position.w;
+
+		let terrain_surface_normal = terrain_normal(&height_map, &point_on_sphere, terrain_scale);
+		let normal_matrix = nglm::mat4_to_mat3(&nglm::transpose(&nglm::inverse(&model)));
+		let normal = normal_matrix * terrain_surface_normal;
+
+		varyings[FRAG_POSITION..FRAG_POSITION + 3].copy_from_slice(world_position.xyz().as_slice());
+		varyings[FRAG_NORMAL..FRAG_NORMAL + 3].copy_from_slice(normal.as_slice());
+		varyings[FRAG_COLOR..FRAG_COLOR + 4].copy_from_slice(attributes[2].as_slice());
+		clip_position
+	}
+
+	fn fragment(
+		&self,
+		varyings: &[f32],
+		window_depth: f32,
+		inputs: &ShaderInputs,
+	) -> (nglm::Vec4, f32) {
+		let fragment = Fragment {
+			position: vec3_at(varyings, FRAG_POSITION),
+			normal: vec3_at(varyings, FRAG_NORMAL),
+			color: nglm::make_vec4(&varyings[FRAG_COLOR..FRAG_COLOR + 4]),
+			inputs,
+		};
+
+		let light_dir = nglm::normalize(&(inputs.vec3("u_lightPosition") - fragment.position));
+		let norm = nglm::normalize(&fragment.normal);
+		let daylight = fragment.daylight();
+
+		let total_light_color = fragment.ambient_light() * mix(NIGHT_AMBIENT_SCALE, 1.0, daylight)
+			+ (fragment.diffuse_light(&light_dir, &norm)
+				+ fragment.specular_light(&light_dir, &norm))
+				* daylight;
+
+		let surface_color = nglm::lerp(&fragment.terrain_color(), &fragment.data_color(), 0.7);
+		let night_color = fragment.night_lights() * (1.0 - daylight);
+
+		let lit_color = surface_color.xyz().component_mul(&total_light_color) + night_color;
+		let color = lit_color.push(surface_color.w);
+
+		let depth = if self.logarithmic_depth {
+			// depth.glsl's logarithmicDepth
+			varyings[FRAG_LOG_DEPTH].log2() * inputs.float("u_logDepthCoefficient") * 0.5
+		} else {
+			window_depth
+		};
+		(color, depth)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+	use std::time::Duration;
+
+	use ghg_data_core::metadata::Metadata;
+
+	use super::*;
+	use crate::application::data::{map_index, range_matrices, upload_data_map, FIRST_MAP_UNIT};
+	use crate::application::lighting::{LightSource, LightingOptions};
+	use crate::application::planet::{PlanetFrame, PlanetRenderer};
+	use crate::application::shaders::{get_planet_shaders, get_sky_shaders, PlanetShaders};
+	use crate::application::sky_shading::add_sky_port;
+	use crate::application::sphere::SphereTessellation;
+	use crate::application::time_cursor::TimeCursor;
+	use crate::render_core::backend::{GraphicsBackend, TextureFilter, TextureFormat};
+	use crate::render_core::camera::Camera;
+	use crate::render_core::software_backend::golden::{compare_with_golden, GoldenTolerance};
+	use crate::render_core::uniform;
+
+	const WIDTH: u32 = 160;
+	const HEIGHT: u32 = 120;
+
+	const DATA_MAPS: [(&[u8], &str); 3] = [
+		(
+			include_bytes!("../../www/images/earth_temp/2021.01.04.png"),
+			include_str!("../../www/images/earth_temp/2021.01.04.metadata"),
+		),
+		(
+			include_bytes!("../../www/images/earth_temp/2021.05.08.png"),
+			include_str!("../../www/images/earth_temp/2021.05.08.metadata"),
+		),
+		(
+			include_bytes!("../../www/images/earth_temp/2021.09.12.png"),
+			include_str!("../../www/images/earth_temp/2021.09.12.metadata"),
+		),
+	];
+
+	#[test]
+	fn point_to_uv_matches_the_texture_layout() {
+		assert_eq!(point_to_uv(&nglm::vec3(0.0, 0.0, 1.0)), nglm::vec2(0.5, 0.5));
+		assert_eq!(point_to_uv(&nglm::vec3(0.0, 1.0, 0.0)).y, 1.0);
+		assert_eq!(point_to_uv(&nglm::vec3(1.0, 0.0, 0.0)).x, 0.75);
+	}
+
+	/// Made-up terrain, as the real maps are too big to keep in the repo: a
+	/// few continents of hills, with ice at the poles
+	fn upload_terrain(backend: &SoftwareBackend) {
+		const MAP_WIDTH: u32 = 64;
+		const MAP_HEIGHT: u32 = 32;
+
+		let mut heights = Vec::new();
+		let mut colors = Vec::new();
+		for y in 0..MAP_HEIGHT {
+			for x in 0..MAP_WIDTH {
+				let longitude = (x as f32 + 0.5) / MAP_WIDTH as f32 * 2.0 * PI;
+				let latitude = ((y as f32 + 0.5) / MAP_HEIGHT as f32 - 0.5) * PI;
+				let height = 0.5 + 0.5 * (3.0 * longitude).sin() * (2.0 * latitude).cos();
+				heights.push((height * 255.0) as u8);
+
+				let color = if latitude.abs() > 1.2 {
+					[235, 240, 245]
+				} else if height > 0.6 {
+					[90, 140, 60]
+				} else {
+					[30, 60, 140]
+				};
+				colors.extend(color);
+			}
+		}
+
+		for (unit, format, pixels) in
+			[(0, TextureFormat::Luminance, heights), (1, TextureFormat::Rgb, colors)]
+		{
+			let texture = backend.create_texture().unwrap();
+			backend.active_texture(unit);
+			backend.bind_texture(Some(&texture));
+			backend.texture_sampling(TextureFilter::Linear, TextureFilter::Linear);
+			backend.texture_image(format, MAP_WIDTH, MAP_HEIGHT, &pixels).unwrap();
+		}
+	}
+
+	/// The temperature maps, set up the way `data::handle_data` does
+	fn upload_data(shaders: &PlanetShaders<SoftwareBackend>, month: i32) {
+		let metadata: Vec<Metadata> = (0..)
+			.zip(DATA_MAPS)
+			.map(|(i, (png, metadata))| {
+				upload_data_map(&shaders.standard.context, png, (FIRST_MAP_UNIT + i) as u32)
+					.unwrap();
+				serde_json::from_str(metadata).unwrap()
+			})
+			.collect();
+		let (min_values, max_values) = range_matrices(&metadata).unwrap();
+
+		for shader in shaders.variants() {
+			shader.use_shader();
+			uniform::init_mat4x3("u_dataMinValues", shader, min_values);
+			uniform::init_mat4x3("u_dataMaxValues", shader, max_values);
+			uniform::init_i32("s_dataMap", shader, FIRST_MAP_UNIT + map_index(month));
+			uniform::init_i32("u_dataMonth", shader, month);
+		}
+	}
+
+	fn render(
+		camera_position: nglm::Vec3,
+		time_cursor: TimeCursor,
+		source: LightSource,
+	) -> image::RgbaImage {
+		let backend = SoftwareBackend::new(WIDTH, HEIGHT);
+		add_planet_port(&backend);
+		add_sky_port(&backend);
+
+		let shaders = get_planet_shaders(&backend).unwrap();
+		let tessellation = SphereTessellation {
+			subdivisions: 4,
+			lod_points_per_subdivision: vec![9, 5],
+			..SphereTessellation::default()
+		};
+		let mut renderer =
+			PlanetRenderer::new(shaders.clone(), get_sky_shaders(&backend).unwrap(), &tessellation)
+				.unwrap();
+		upload_terrain(&backend);
+		upload_data(&shaders, time_cursor.month as i32);
+
+		let frame = PlanetFrame {
+			width: WIDTH as i32,
+			height: HEIGHT as i32,
+			delta_time: Duration::from_millis(16),
+			terrain_scale: 0.05,
+			time_cursor,
+			lighting_options: LightingOptions { source, ..LightingOptions::default() },
+		};
+		let mut camera = Camera::new(&camera_position, &nglm::zero());
+		renderer.draw_frame(&mut camera, &frame);
+		backend.snapshot()
+	}
+
+	fn assert_matches_golden(image: &image::RgbaImage, name: &str) {
+		let golden: PathBuf =
+			[env!("CARGO_MANIFEST_DIR"), "src/application/fixtures/goldens", name].iter().collect();
+		if let Err(e) = compare_with_golden(image, &golden, GoldenTolerance::default()) {
+			panic!("{e}");
+		}
+	}
+
+	#[test]
+	fn january_lit_from_the_camera() {
+		let image = render(nglm::vec3(0.0, 0.0, 3.0), TimeCursor::default(), LightSource::Camera);
+		assert_matches_golden(&image, "planet_january_camera_light.png");
+	}
+
+	#[test]
+	fn july_from_above_lit_by_the_sun() {
+		let time_cursor = TimeCursor { month: 6, utc_hours: 6.0, ..TimeCursor::default() };
+		let image = render(nglm::vec3(1.6, 1.8, 1.6), time_cursor, LightSource::Sun);
+		assert_matches_golden(&image, "planet_july_sun_light.png");
+	}
+}
@@ -1,34 +1,76 @@
-use web_sys::{WebGl2RenderingContext, WebGlProgram};
-
+use crate::render_core::backend::{GraphicsBackend, ShaderKind};
 use crate::render_core::shader;
 
 #[derive(Clone, Debug)]
-pub struct ShaderContext {
-	pub context: WebGl2RenderingContext,
-	pub program: WebGlProgram,
+pub struct ShaderContext<B: GraphicsBackend> {
+	pub context: B,
+	pub program: B::Program,
 }
 
-impl ShaderContext {
-	pub fn new(context: &WebGl2RenderingContext, program: &WebGlProgram) -> Self {
+impl<B: GraphicsBackend> ShaderContext<B> {
+	pub fn new(context: &B, program: &B::Program) -> Self {
 		Self { context: context.clone(), program: program.clone() }
 	}
 
 	pub fn use_shader(&self) { self.context.use_program(Some(&self.program)); }
 }
 
-pub fn get_planet_shaders(context: &WebGl2RenderingContext) -> Result<ShaderContext, String> {
-	let vert_shader = shader::preprocess_and_compile_shader(
-		&context,
-		WebGl2RenderingContext::VERTEX_SHADER,
-		include_str!("shaders/planet.vert"),
-	)?;
+pub const PLANET_VERTEX_SOURCE: &str = include_str!("shaders/planet.vert");
+pub const PLANET_FRAGMENT_SOURCE: &str = include_str!("shaders/planet.frag");
+
+pub const SKY_VERTEX_SOURCE: &str = include_str!("shaders/sky.vert");
+pub const SKY_FRAGMENT_SOURCE: &str = include_str!("shaders/sky.frag");
+
+/// planet.frag only writes `gl_FragDepth` with this defined, as any write to
+/// it turns off early depth testing
+pub const LOG_DEPTH_DEFINE: &str = "LOG_DEPTH";
+
+/// The planet program, compiled once for each kind of depth. Anything written
+/// to one variant's uniforms needs writing to the other's too.
+#[derive(Clone, Debug)]
+pub struct PlanetShaders<B: GraphicsBackend> {
+	pub standard: ShaderContext<B>,
+	pub logarithmic_depth: ShaderContext<B>,
+}
+
+impl<B: GraphicsBackend> PlanetShaders<B> {
+	pub fn variants(&self) -> [&ShaderContext<B>; 2] { [&self.standard, &self.logarithmic_depth] }
+}
+
+pub fn planet_fragment_source(logarithmic_depth: bool) -> String {
+	if logarithmic_depth {
+		shader::with_defines(PLANET_FRAGMENT_SOURCE, &[LOG_DEPTH_DEFINE])
+	} else {
+		PLANET_FRAGMENT_SOURCE.to_owned()
+	}
+}
+
+pub fn get_planet_shaders<B: GraphicsBackend>(context: &B) -> Result<PlanetShaders<B>, String> {
+	Ok(PlanetShaders {
+		standard: compile_program(context, PLANET_VERTEX_SOURCE, &planet_fragment_source(false))?,
+		logarithmic_depth: compile_program(
+			context,
+			PLANET_VERTEX_SOURCE,
+			&planet_fragment_source(true),
+		)?,
+	})
+}
+
+pub fn get_sky_shaders<B: GraphicsBackend>(context: &B) -> Result<ShaderContext<B>, String> {
+	compile_program(context, SKY_VERTEX_SOURCE, SKY_FRAGMENT_SOURCE)
+}
+
+fn compile_program<B: GraphicsBackend>(
+	context: &B,
+	vert_source: &str,
+	frag_source: &str,
+) -> Result<ShaderContext<B>, String> {
+	let vert_shader =
+		shader::preprocess_and_compile_shader(context, ShaderKind::Vertex, vert_source)?;
 
-	let frag_shader = shader::preprocess_and_compile_shader(
-		&context,
-		WebGl2RenderingContext::FRAGMENT_SHADER,
-		include_str!("shaders/planet.frag"),
-	)?;
+	let frag_shader =
+		shader::preprocess_and_compile_shader(context, ShaderKind::Fragment, frag_source)?;
 
-	let program = shader::link_program(&context, &vert_shader, &frag_shader)?;
-	Ok(ShaderContext::new(&context, &program))
+	let program = shader::link_program(context, &vert_shader, &frag_shader)?;
+	Ok(ShaderContext::new(context, &program))
 }
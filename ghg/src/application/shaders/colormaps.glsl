@@ -0,0 +1,26 @@
+// Requires color.glsl
+
+// Ways of coloring the data, picked with u_colormap. Keep in sync with `Colormap` in data.rs.
+const int COLORMAP_HUE = 0;
+const int COLORMAP_GRAYSCALE = 1;
+const int COLORMAP_DIVERGING = 2;
+
+// `proportion` runs from 0 at the data's minimum to 1 at its maximum
+vec3 colormap(int map, float proportion) {
+    if (map == COLORMAP_GRAYSCALE) {
+        return vec3(proportion);
+    } else if (map == COLORMAP_DIVERGING) {
+        // Blue through white to red
+        const vec3 LOW = vec3(0.23, 0.3, 0.75);
+        const vec3 MIDDLE = vec3(0.87, 0.87, 0.87);
+        const vec3 HIGH = vec3(0.71, 0.02, 0.15);
+        if (proportion < 0.5) {
+            return mix(LOW, MIDDLE, proportion * 2.0);
+        }
+        return mix(MIDDLE, HIGH, proportion * 2.0 - 1.0);
+    }
+
+    // Red at the maximum, around the color wheel to violet at the minimum
+    float truncateColorSpace = 0.9;
+    return hsl2rgb(vec3((1.0 - proportion) * truncateColorSpace, 1.0, 0.5));
+}
@@ -0,0 +1,12 @@
+// Logarithmic depth spreads precision evenly over orders of magnitude, so that
+// terrain close to the camera doesn't z-fight. See `log_depth_coefficient` in camera.rs.
+
+// In the vertex shader: the value to pass on for `logarithmicDepth`
+float logDepthInput(vec4 clipPosition) {
+    return 1.0 + clipPosition.w;
+}
+
+// In the fragment shader: a value for gl_FragDepth, between 0 and 1
+float logarithmicDepth(float depthInput, float coefficient) {
+    return log2(depthInput) * coefficient * 0.5;
+}
@@ -4,15 +4,19 @@ precision mediump float;
 
 #include <application/shaders/channels.glsl>
 #include <application/shaders/color.glsl>
+#include <application/shaders/colormaps.glsl>
 #include <application/shaders/pointmapping.glsl>
 #include <application/shaders/math.glsl>
+#include <application/shaders/depth.glsl>
 
 in vec3 fragPosition;
 in vec3 fragNormal;
 in vec4 fragColor;
+in highp float fragLogDepth;
 
 uniform sampler2D s_textureMap;
 uniform sampler2D s_colorMap;
+uniform sampler2D s_nightMap;
 
 out vec4 outColor;
 
@@ -26,6 +30,16 @@ uniform vec3 u_lightColor;
 uniform vec3 u_cameraPosition;
 uniform float u_specularStrength;
 
+// Solar lighting parameters
+uniform bool u_solarLighting;
+uniform bool u_nightLightsEnabled;
+const float NIGHT_AMBIENT_SCALE = 0.15;
+const float TWILIGHT_WIDTH = 0.1;
+
+// Depth parameters. Writing gl_FragDepth at all turns off early depth testing,
+// so it's only written by the variant compiled with LOG_DEPTH defined.
+uniform highp float u_logDepthCoefficient;
+
 // Data parameters
 const int NUM_MAPS_PER_YEAR = 3;
 const int NUM_CHANNELS_IN_MAP = 4;
@@ -33,6 +47,7 @@ uniform int u_dataMonth;
 uniform sampler2D s_dataMap;
 uniform mat3x4 u_dataMinValues; // TOOD: float for year- or data-length min/max
 uniform mat3x4 u_dataMaxValues;
+uniform int u_colormap;
 
 vec3 getAmbientLight() {
     return u_ambientStrength * u_ambientColor;
@@ -81,13 +96,9 @@ vec4 getDataColor() {
     vec4 dataRange = maxValues - minValues;
 
     vec4 dataProportion = (dataRealValue - minValues) / dataRange;
-    float truncateColorSpace = 0.9;
-    vec4 truncatedProportion = (vec4(1.0) - dataProportion) * truncateColorSpace;
-
-    float channelValue = channelIndex(truncatedProportion, channelInMap);
+    float channelValue = channelIndex(dataProportion, channelInMap);
 
-    vec3 withinColorSpace = hsl2rgb(vec3(channelValue, 1.0, 0.5));
-    return vec4(withinColorSpace, 1.0);
+    return vec4(colormap(u_colormap, channelValue), 1.0);
 
     //    if (channelInMap == 0) {
     //        return vec4(vec3(dataRealValue.r), 1.0);
@@ -100,18 +111,44 @@ vec4 getDataColor() {
     //    }
 }
 
+// 1.0 on the day side, 0.0 on the night side, blending across the terminator.
+// Uses the sphere's normal rather than the terrain's, so relief doesn't break up the boundary.
+float getDaylight() {
+    if (!u_solarLighting) {
+        return 1.0;
+    }
+
+    float sunElevation = dot(normalize(fragPosition), normalize(u_lightPosition));
+    return smoothstep(-TWILIGHT_WIDTH, TWILIGHT_WIDTH, sunElevation);
+}
+
+vec3 getNightLights() {
+    if (!u_nightLightsEnabled) {
+        return vec3(0.0);
+    }
+
+    return texture(s_nightMap, pointToUv(normalize(fragPosition))).rgb;
+}
+
 void main() {
     vec3 lightDir = normalize(u_lightPosition - fragPosition);
     vec3 norm = normalize(fragNormal);
+    float daylight = getDaylight();
 
-    vec3 totalLightColor = getAmbientLight()
-    + getDiffuseLight(lightDir, norm)
-    + getSpecularLight(lightDir, norm);
+    vec3 totalLightColor = getAmbientLight() * mix(NIGHT_AMBIENT_SCALE, 1.0, daylight)
+    + (getDiffuseLight(lightDir, norm)
+    + getSpecularLight(lightDir, norm)) * daylight;
 
     vec4 terrainColor = getTerrainColor();
     vec4 dataColor = getDataColor();
 
     vec4 surfaceColor = mix(terrainColor, dataColor, 0.7);
 
-    outColor = surfaceColor * vec4(totalLightColor, 1.0);
+    vec3 nightColor = getNightLights() * (1.0 - daylight);
+
+    outColor = vec4(surfaceColor.rgb * totalLightColor + nightColor, surfaceColor.a);
+
+#ifdef LOG_DEPTH
+    gl_FragDepth = logarithmicDepth(fragLogDepth, u_logDepthCoefficient);
+#endif
 }
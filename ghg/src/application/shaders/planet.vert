@@ -1,6 +1,8 @@
 #version 300 es
 
-#define M_PI 3.1415926535898
+#include <application/shaders/pointmapping.glsl>
+#include <application/shaders/terrain.glsl>
+#include <application/shaders/depth.glsl>
 
 layout (location = 0) in vec3 position;
 layout (location = 1) in vec3 normal;
@@ -18,13 +20,7 @@ uniform mat4 u_projection;
 out vec3 fragPosition;
 out vec3 fragNormal;
 out vec4 fragColor;
-
-vec2 pointToUv(vec3 pointOnSphere) {
-    float u = clamp(0.5 + atan(pointOnSphere.x, pointOnSphere.z) / 2.0 / M_PI, 0.0, 1.0);
-    float v = clamp(0.5 + asin(pointOnSphere.y) / M_PI, 0.0, 1.0);
-    return vec2(u, v);
-}
-
+out float fragLogDepth;
 
 //bool isWater(vec2 texturePoint) {
 //    vec4 color = texture(s_colorMap, texturePoint);
@@ -32,16 +28,18 @@ vec2 pointToUv(vec3 pointOnSphere) {
 //}
 
 void main() {
-    vec2 texturePoint = pointToUv(normalize(position));
-    float terrainValue = texture(s_textureMap, texturePoint).r;
+    vec3 pointOnSphere = normalize(position);
 
-    float positionScale = 1.0 + (terrainValue * u_terrainScale) - u_terrainScale / 2.0;
+    float positionScale = terrainRadius(s_textureMap, pointOnSphere, u_terrainScale);
     vec3 scaled_position = position * positionScale;
 
     gl_Position = u_projection * u_view * u_model * vec4(scaled_position, 1.0);
+    fragLogDepth = logDepthInput(gl_Position);
 
     fragPosition = vec3(u_model * vec4(scaled_position, 1.0));
-    fragNormal = mat3(transpose(inverse(u_model))) * normal; // TODO: Inverse is very slow
+
+    vec3 terrainSurfaceNormal = terrainNormal(s_textureMap, pointOnSphere, u_terrainScale);
+    fragNormal = mat3(transpose(inverse(u_model))) * terrainSurfaceNormal; // TODO: Inverse is very slow
 
     fragColor = color;
 }
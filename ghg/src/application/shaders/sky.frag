@@ -0,0 +1,147 @@
+#version 300 es
+
+precision highp float;
+
+#include <application/shaders/math.glsl>
+#include <application/shaders/pointmapping.glsl>
+
+in vec2 ndcPosition;
+
+out vec4 outColor;
+
+uniform mat4 u_inverseViewProjection;
+uniform vec3 u_cameraPosition;
+uniform vec3 u_lightPosition;
+
+uniform vec3 u_backgroundColor;
+
+uniform bool u_starsEnabled;
+uniform float u_starDensity;
+
+uniform bool u_atmosphereEnabled;
+uniform float u_atmosphereThickness;
+uniform float u_atmosphereScaleHeight;
+uniform vec3 u_scatteringCoefficients;
+uniform float u_atmosphereIntensity;
+
+const float PLANET_RADIUS = 1.0;
+const int VIEW_SAMPLES = 16;
+const int LIGHT_SAMPLES = 4;
+const float STAR_THRESHOLD = 0.985;
+
+vec3 getViewDirection() {
+    vec4 nearPoint = u_inverseViewProjection * vec4(ndcPosition, -1.0, 1.0);
+    vec4 farPoint = u_inverseViewProjection * vec4(ndcPosition, 1.0, 1.0);
+    return normalize(farPoint.xyz / farPoint.w - nearPoint.xyz / nearPoint.w);
+}
+
+// Distances along the ray to the near and far intersections. x > y if the ray misses.
+vec2 intersectSphere(vec3 origin, vec3 direction, float radius) {
+    float b = dot(origin, direction);
+    float c = dot(origin, origin) - radius * radius;
+    float discriminant = b * b - c;
+    if (discriminant < 0.0) {
+        return vec2(1.0, -1.0);
+    }
+
+    float root = sqrt(discriminant);
+    return vec2(-b - root, -b + root);
+}
+
+bool hitsAhead(vec2 hit) {
+    return hit.x <= hit.y && hit.x > 0.0;
+}
+
+float atmosphereDensity(vec3 point) {
+    float height = max(length(point) - PLANET_RADIUS, 0.0);
+    return exp(-height / u_atmosphereScaleHeight);
+}
+
+float opticalDepth(vec3 origin, vec3 direction, float distance) {
+    float stepSize = distance / float(LIGHT_SAMPLES);
+    float depth = 0.0;
+    for (int i = 0; i < LIGHT_SAMPLES; i++) {
+        depth += atmosphereDensity(origin + direction * (float(i) + 0.5) * stepSize) * stepSize;
+    }
+    return depth;
+}
+
+// Single Rayleigh scattering along the view ray, with the planet's shadow
+vec3 getAtmosphere(vec3 origin, vec3 direction, vec3 lightDir) {
+    float atmosphereRadius = PLANET_RADIUS + u_atmosphereThickness;
+    vec2 atmosphereHit = intersectSphere(origin, direction, atmosphereRadius);
+    if (atmosphereHit.x > atmosphereHit.y || atmosphereHit.y < 0.0) {
+        return vec3(0.0);
+    }
+
+    float start = max(atmosphereHit.x, 0.0);
+    float end = atmosphereHit.y;
+    vec2 planetHit = intersectSphere(origin, direction, PLANET_RADIUS);
+    if (hitsAhead(planetHit)) {
+        end = min(end, planetHit.x);
+    }
+
+    float stepSize = (end - start) / float(VIEW_SAMPLES);
+    float viewDepth = 0.0;
+    vec3 scattered = vec3(0.0);
+    for (int i = 0; i < VIEW_SAMPLES; i++) {
+        vec3 point = origin + direction * (start + (float(i) + 0.5) * stepSize);
+        float localDepth = atmosphereDensity(point) * stepSize;
+        viewDepth += localDepth;
+
+        if (hitsAhead(intersectSphere(point, lightDir, PLANET_RADIUS))) {
+            continue;
+        }
+
+        float lightDistance = intersectSphere(point, lightDir, atmosphereRadius).y;
+        float lightDepth = opticalDepth(point, lightDir, lightDistance);
+        scattered += localDepth * exp(-(lightDepth + viewDepth) * u_scatteringCoefficients);
+    }
+
+    float cosTheta = dot(direction, lightDir);
+    float phase = 3.0 / (16.0 * M_PI) * (1.0 + cosTheta * cosTheta);
+
+    vec3 radiance = scattered * u_scatteringCoefficients * phase * u_atmosphereIntensity;
+    return vec3(1.0) - exp(-radiance);
+}
+
+float hash(vec3 p) {
+    p = fract(p * 0.3183099 + 0.1);
+    p *= 17.0;
+    return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+}
+
+// Splits the sky into cells, and places at most one star somewhere in each
+vec3 getStars(vec3 direction) {
+    vec3 cell = floor(direction * u_starDensity);
+    float presence = hash(cell);
+    if (presence < STAR_THRESHOLD) {
+        return vec3(0.0);
+    }
+
+    vec3 jitter = vec3(hash(cell + 1.3), hash(cell + 2.7), hash(cell + 4.1)) - 0.5;
+    vec3 center = normalize((cell + 0.5 + jitter * 0.6) / u_starDensity);
+    float distance = length(direction - center) * u_starDensity;
+
+    float brightness = (presence - STAR_THRESHOLD) / (1.0 - STAR_THRESHOLD);
+    return vec3(smoothstep(0.15, 0.0, distance) * brightness);
+}
+
+void main() {
+    vec3 direction = getViewDirection();
+    vec3 lightDir = normalize(u_lightPosition);
+
+    vec3 color = u_backgroundColor;
+
+    vec3 atmosphere = vec3(0.0);
+    if (u_atmosphereEnabled) {
+        atmosphere = getAtmosphere(u_cameraPosition, direction, lightDir);
+    }
+
+    if (u_starsEnabled) {
+        // Stars are washed out by a bright sky
+        color += getStars(direction) * (1.0 - clamp(max3(atmosphere), 0.0, 1.0));
+    }
+
+    outColor = vec4(color + atmosphere, 1.0);
+}
@@ -0,0 +1,12 @@
+#version 300 es
+
+out vec2 ndcPosition;
+
+void main() {
+    // A single triangle that covers the whole screen, so no vertex buffers are needed
+    vec2 corner = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+    ndcPosition = corner * 2.0 - 1.0;
+
+    // On the far plane, so that anything already drawn stays in front
+    gl_Position = vec4(ndcPosition, 1.0, 1.0);
+}
@@ -0,0 +1,31 @@
+// Requires pointmapping.glsl
+
+// Radius of the displaced surface above a point on the unit sphere.
+// Keep in sync with TerrainShell, which bounds this for culling.
+float terrainRadius(sampler2D heightMap, vec3 pointOnSphere, float terrainScale) {
+    float terrainValue = texture(heightMap, pointToUv(pointOnSphere)).r;
+    return 1.0 + (terrainValue * terrainScale) - terrainScale / 2.0;
+}
+
+vec3 displacedPoint(sampler2D heightMap, vec3 pointOnSphere, float terrainScale) {
+    return pointOnSphere * terrainRadius(heightMap, pointOnSphere, terrainScale);
+}
+
+// Surface normal of the displaced terrain, using central differences between the
+// displaced positions one texel east/west and north/south of the point.
+vec3 terrainNormal(sampler2D heightMap, vec3 pointOnSphere, float terrainScale) {
+    // Any axis works, as long as it isn't parallel to the point
+    vec3 up = abs(pointOnSphere.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 east = normalize(cross(up, pointOnSphere));
+    vec3 north = cross(pointOnSphere, east);
+
+    // The map spans the whole circumference horizontally
+    float texelAngle = 2.0 * M_PI / float(textureSize(heightMap, 0).x);
+
+    vec3 eastPoint = displacedPoint(heightMap, normalize(pointOnSphere + east * texelAngle), terrainScale);
+    vec3 westPoint = displacedPoint(heightMap, normalize(pointOnSphere - east * texelAngle), terrainScale);
+    vec3 northPoint = displacedPoint(heightMap, normalize(pointOnSphere + north * texelAngle), terrainScale);
+    vec3 southPoint = displacedPoint(heightMap, normalize(pointOnSphere - north * texelAngle), terrainScale);
+
+    return normalize(cross(eastPoint - westPoint, northPoint - southPoint));
+}
@@ -0,0 +1,88 @@
+use crate::application::lighting::{LightingOptions, SkyParameters};
+use crate::application::shaders::ShaderContext;
+use crate::render_core::backend::{DepthFunction, GraphicsBackend, Primitive};
+use crate::render_core::camera::MvpMatrices;
+use crate::render_core::uniform;
+use crate::render_core::uniform::SmartUniform;
+
+/// Background pass with a starfield and the atmosphere's glow around the
+/// planet's limb. It's drawn after the planet, on the far plane, so it only
+/// fills in pixels that the planet didn't cover.
+pub struct Sky<B: GraphicsBackend> {
+	shader: ShaderContext<B>,
+	/// Left empty, the fullscreen triangle is generated in the vertex shader
+	vertex_array_object: B::VertexArray,
+	inverse_view_projection: SmartUniform<nglm::Mat4, B>,
+	camera_position: SmartUniform<nglm::Vec3, B>,
+	light_position: SmartUniform<nglm::Vec3, B>,
+	background_color: SmartUniform<nglm::Vec3, B>,
+	stars_enabled: SmartUniform<i32, B>,
+	star_density: SmartUniform<f32, B>,
+	atmosphere_enabled: SmartUniform<i32, B>,
+	atmosphere_thickness: SmartUniform<f32, B>,
+	atmosphere_scale_height: SmartUniform<f32, B>,
+	scattering_coefficients: SmartUniform<nglm::Vec3, B>,
+	atmosphere_intensity: SmartUniform<f32, B>,
+}
+
+impl<B: GraphicsBackend> Sky<B> {
+	pub fn new(shader: ShaderContext<B>) -> Result<Self, String> {
+		let vertex_array_object =
+			shader.context.create_vertex_array().ok_or("Could not create vertex array object")?;
+
+		shader.use_shader();
+
+		Ok(Self {
+			vertex_array_object,
+			inverse_view_projection: uniform::new_smart_mat4("u_inverseViewProjection", &shader),
+			camera_position: uniform::new_smart_vec3("u_cameraPosition", &shader),
+			light_position: uniform::new_smart_vec3("u_lightPosition", &shader),
+			background_color: uniform::new_smart_vec3("u_backgroundColor", &shader),
+			stars_enabled: uniform::new_smart_i32("u_starsEnabled", &shader),
+			star_density: uniform::new_smart_f32("u_starDensity", &shader),
+			atmosphere_enabled: uniform::new_smart_i32("u_atmosphereEnabled", &shader),
+			atmosphere_thickness: uniform::new_smart_f32("u_atmosphereThickness", &shader),
+			atmosphere_scale_height: uniform::new_smart_f32("u_atmosphereScaleHeight", &shader),
+			scattering_coefficients: uniform::new_smart_vec3("u_scatteringCoefficients", &shader),
+			atmosphere_intensity: uniform::new_smart_f32("u_atmosphereIntensity", &shader),
+			shader,
+		})
+	}
+
+	/// Leaves the sky's program in use
+	pub fn draw(
+		&mut self,
+		mvp: &MvpMatrices,
+		camera_position: &nglm::Vec3,
+		light_position: &nglm::Vec3,
+		parameters: &SkyParameters,
+		options: &LightingOptions,
+	) {
+		self.shader.use_shader();
+
+		self.inverse_view_projection.smart_write(nglm::inverse(&(mvp.projection * mvp.view)));
+		self.camera_position.smart_write(*camera_position);
+		self.light_position.smart_write(*light_position);
+		self.background_color.smart_write(parameters.background_color);
+		self.stars_enabled.smart_write(options.stars_enabled as i32);
+		self.star_density.smart_write(parameters.star_density);
+		self.atmosphere_enabled.smart_write(options.atmosphere_enabled as i32);
+		self.atmosphere_thickness.smart_write(parameters.atmosphere_thickness);
+		self.atmosphere_scale_height.smart_write(parameters.atmosphere_scale_height);
+		self.scattering_coefficients.smart_write(parameters.scattering_coefficients);
+		self.atmosphere_intensity.smart_write(parameters.atmosphere_intensity);
+
+		let context = &self.shader.context;
+
+		// The triangle is on the far plane, which only passes against a cleared depth
+		// buffer with LEQUAL. It shouldn't write depth either, so that later passes
+		// don't see it.
+		context.set_depth(DepthFunction::LessOrEqual, false);
+
+		context.bind_vertex_array(Some(&self.vertex_array_object));
+		context.draw_arrays(Primitive::Triangles, 0, 3);
+		context.bind_vertex_array(None);
+
+		context.set_depth(DepthFunction::Less, true);
+	}
+}
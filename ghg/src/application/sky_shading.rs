@@ -0,0 +1,236 @@
+use std::f32::consts::{FRAC_1_PI, PI};
+use std::rc::Rc;
+
+use crate::application::shaders::{SKY_FRAGMENT_SOURCE, SKY_VERTEX_SOURCE};
+use crate::render_core::software_backend::{ShaderInputs, ShaderPort, SoftwareBackend};
+
+const PLANET_RADIUS: f32 = 1.0;
+const VIEW_SAMPLES: i32 = 16;
+const LIGHT_SAMPLES: i32 = 4;
+const STAR_THRESHOLD: f32 = 0.985;
+
+/// sky.vert and sky.frag, ported so `SoftwareBackend` can draw the starfield
+/// and the atmosphere behind the globe. Follow the shaders closely when
+/// changing either, as golden images depend on them agreeing.
+pub struct SkyShading;
+
+/// Draws the sky program on `backend` with `SkyShading`
+pub fn add_sky_port(backend: &SoftwareBackend) {
+	backend.add_port(SKY_VERTEX_SOURCE, SKY_FRAGMENT_SOURCE, Rc::new(SkyShading));
+}
+
+/// Distances along the ray to the near and far intersections. x > y if the
+/// ray misses.
+fn intersect_sphere(origin: &nglm::Vec3, direction: &nglm::Vec3, radius: f32) -> nglm::Vec2 {
+	let b = nglm::dot(origin, direction);
+	let c = nglm::dot(origin, origin) - radius * radius;
+	let discriminant = b * b - c;
+	if discriminant < 0.0 {
+		return nglm::vec2(1.0, -1.0);
+	}
+
+	let root = discriminant.sqrt();
+	nglm::vec2(-b - root, -b + root)
+}
+
+fn hits_ahead(hit: &nglm::Vec2) -> bool { hit.x <= hit.y && hit.x > 0.0 }
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+	let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+	t * t * (3.0 - 2.0 * t)
+}
+
+fn fract(value: f32) -> f32 { value - value.floor() }
+
+fn hash(p: &nglm::Vec3) -> f32 {
+	let p = p.map(|x| fract(x * FRAC_1_PI + 0.1)) * 17.0;
+	fract(p.x * p.y * p.z * (p.x + p.y + p.z))
+}
+
+/// The fragment shader's view of one pixel
+struct Fragment<'a, 'b> {
+	inputs: &'a ShaderInputs<'b>,
+}
+
+impl<'a, 'b> Fragment<'a, 'b> {
+	fn view_direction(&self, ndc_position: &nglm::Vec2) -> nglm::Vec3 {
+		let inverse_view_projection = self.inputs.mat4("u_inverseViewProjection");
+		let near_point =
+			inverse_view_projection * nglm::vec4(ndc_position.x, ndc_position.y, -1.0, 1.0);
+		let far_point =
+			inverse_view_projection * nglm::vec4(ndc_position.x, ndc_position.y, 1.0, 1.0);
+		nglm::normalize(&(far_point.xyz() / far_point.w - near_point.xyz() / near_point.w))
+	}
+
+	fn atmosphere_density(&self, point: &nglm::Vec3) -> f32 {
+		let height = (nglm::length(point) - PLANET_RADIUS).max(0.0);
+		(-height / self.inputs.float("u_atmosphereScaleHeight")).exp()
+	}
+
+	fn optical_depth(&self, origin: &nglm::Vec3, direction: &nglm::Vec3, distance: f32) -> f32 {
+		let step_size = distance / LIGHT_SAMPLES as f32;
+		(0..LIGHT_SAMPLES)
+			.map(|i| {
+				let point = origin + direction * ((i as f32 + 0.5) * step_size);
+				self.atmosphere_density(&point) * step_size
+			})
+			.sum()
+	}
+
+	/// Single Rayleigh scattering along the view ray, with the planet's shadow
+	fn atmosphere(
+		&self,
+		origin: &nglm::Vec3,
+		direction: &nglm::Vec3,
+		light_dir: &nglm::Vec3,
+	) -> nglm::Vec3 {
+		let atmosphere_radius = PLANET_RADIUS + self.inputs.float("u_atmosphereThickness");
+		let atmosphere_hit = intersect_sphere(origin, direction, atmosphere_radius);
+		if atmosphere_hit.x > atmosphere_hit.y || atmosphere_hit.y < 0.0 {
+			return nglm::zero();
+		}
+
+		let start = atmosphere_hit.x.max(0.0);
+		let mut end = atmosphere_hit.y;
+		let planet_hit = intersect_sphere(origin, direction, PLANET_RADIUS);
+		if hits_ahead(&planet_hit) {
+			end = end.min(planet_hit.x);
+		}
+
+		let scattering_coefficients = self.inputs.vec3("u_scatteringCoefficients");
+		let step_size = (end - start) / VIEW_SAMPLES as f32;
+		let mut view_depth = 0.0;
+		let mut scattered = nglm::Vec3::zeros();
+		for i in 0..VIEW_SAMPLES {
+			let point = origin + direction * (start + (i as f32 + 0.5) * step_size);
+			let local_depth = self.atmosphere_density(&point) * step_size;
+			view_depth += local_depth;
+
+			if hits_ahead(&intersect_sphere(&point, light_dir, PLANET_RADIUS)) {
+				continue;
+			}
+
+			let light_distance = intersect_sphere(&point, light_dir, atmosphere_radius).y;
+			let light_depth = self.optical_depth(&point, light_dir, light_distance);
+			let transmittance =
+				(-(light_depth + view_depth) * scattering_coefficients).map(f32::exp);
+			scattered += local_depth * transmittance;
+		}
+
+		let cos_theta = nglm::dot(direction, light_dir);
+		let phase = 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+
+		let radiance = scattered.component_mul(&scattering_coefficients)
+			* (phase * self.inputs.float("u_atmosphereIntensity"));
+		nglm::vec3(1.0, 1.0, 1.0) - radiance.map(|x| (-x).exp())
+	}
+
+	/// Splits the sky into cells, and places at most one star somewhere in each
+	fn stars(&self, direction: &nglm::Vec3) -> nglm::Vec3 {
+		let star_density = self.inputs.float("u_starDensity");
+		let cell = (direction * star_density).map(f32::floor);
+		let presence = hash(&cell);
+		if presence < STAR_THRESHOLD {
+			return nglm::zero();
+		}
+
+		let offset = |by: f32| hash(&cell.add_scalar(by));
+		let jitter = nglm::vec3(offset(1.3), offset(2.7), offset(4.1)).add_scalar(-0.5);
+		let center = nglm::normalize(&((cell.add_scalar(0.5) + jitter * 0.6) / star_density));
+		let distance = nglm::length(&(direction - center)) * star_density;
+
+		let brightness = (presence - STAR_THRESHOLD) / (1.0 - STAR_THRESHOLD);
+		let star = smoothstep(0.15, 0.0, distance) * brightness;
+		nglm::vec3(star, star, star)
+	}
+}
+
+impl ShaderPort for SkyShading {
+	fn attributes(&self) -> &[(&'static str, u32)] { &[] }
+
+	fn varyings(&self) -> usize { 2 }
+
+	fn vertex(
+		&self,
+		vertex_id: u32,
+		_: &[nglm::Vec4],
+		_: &ShaderInputs,
+		varyings: &mut [f32],
+	) -> nglm::Vec4 {
+		// A single triangle that covers the whole screen
+		let corner = nglm::vec2(((vertex_id << 1) & 2) as f32, (vertex_id & 2) as f32);
+		let ndc_position = corner * 2.0 - nglm::vec2(1.0, 1.0);
+		varyings.copy_from_slice(ndc_position.as_slice());
+
+		// On the far plane, so that anything already drawn stays in front
+		nglm::vec4(ndc_position.x, ndc_position.y, 1.0, 1.0)
+	}
+
+	fn fragment(
+		&self,
+		varyings: &[f32],
+		window_depth: f32,
+		inputs: &ShaderInputs,
+	) -> (nglm::Vec4, f32) {
+		let fragment = Fragment { inputs };
+		let direction = fragment.view_direction(&nglm::make_vec2(varyings));
+		let light_dir = nglm::normalize(&inputs.vec3("u_lightPosition"));
+
+		let mut color = inputs.vec3("u_backgroundColor");
+
+		let atmosphere = if inputs.bool("u_atmosphereEnabled") {
+			fragment.atmosphere(&inputs.vec3("u_cameraPosition"), &direction, &light_dir)
+		} else {
+			nglm::zero()
+		};
+
+		if inputs.bool("u_starsEnabled") {
+			// Stars are washed out by a bright sky
+			color += fragment.stars(&direction) * (1.0 - atmosphere.max().clamp(0.0, 1.0));
+		}
+
+		((color + atmosphere).push(1.0), window_depth)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::application::lighting::{LightingOptions, SkyParameters};
+	use crate::application::shaders::get_sky_shaders;
+	use crate::application::sky::Sky;
+	use crate::render_core::backend::GraphicsBackend;
+	use crate::render_core::camera::Camera;
+
+	const SIZE: u32 = 64;
+
+	/// Just the sky, around a globe at the origin lit from the camera
+	fn render(options: &LightingOptions) -> image::RgbaImage {
+		let backend = SoftwareBackend::new(SIZE, SIZE);
+		add_sky_port(&backend);
+		let mut sky = Sky::new(get_sky_shaders(&backend).unwrap()).unwrap();
+
+		// Something other than the background, to show the sky covers it all
+		backend.clear(&nglm::vec3(1.0, 0.0, 0.0));
+
+		let camera_position = nglm::vec3(0.0, 0.0, 3.0);
+		let camera = Camera::new(&camera_position, &nglm::zero());
+		let mvp = camera.get_perspective_matrices(SIZE as i32, SIZE as i32);
+		sky.draw(&mvp, &camera_position, &camera_position, &SkyParameters::default(), options);
+		backend.snapshot()
+	}
+
+	#[test]
+	fn atmosphere_glows_around_the_limb_only() {
+		let options = LightingOptions { stars_enabled: false, ..LightingOptions::default() };
+		let image = render(&options);
+
+		let background = image::Rgba([0, 0, 5, 255]);
+		assert_eq!(*image.get_pixel(0, 0), background);
+		let mut middle_row = (0..SIZE).map(|x| image.get_pixel(x, SIZE / 2));
+		assert!(middle_row.any(|pixel| pixel[2] > background[2] + 20));
+
+		let unlit = render(&LightingOptions { atmosphere_enabled: false, ..options });
+		assert!(unlit.pixels().all(|pixel| *pixel == background));
+	}
+}
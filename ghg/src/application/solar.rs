@@ -0,0 +1,96 @@
+use crate::application::geo::{geo_to_world, GeoPosition};
+use crate::application::time_cursor::TimeCursor;
+
+/// The point on the Earth where the sun is directly overhead.
+///
+/// Uses the low-precision solar coordinates from the Astronomical Almanac,
+/// which are good to about 0.01 degrees between 1950 and 2050.
+pub fn subsolar_point(days_since_j2000: f64) -> GeoPosition {
+	let n = days_since_j2000;
+
+	let mean_longitude = 280.460 + 0.9856474 * n;
+	let mean_anomaly = (357.528 + 0.9856003 * n).to_radians();
+	let ecliptic_longitude =
+		(mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin())
+			.to_radians();
+	let obliquity = (23.439 - 0.0000004 * n).to_radians();
+
+	let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+	let right_ascension =
+		(obliquity.cos() * ecliptic_longitude.sin()).atan2(ecliptic_longitude.cos());
+
+	// The sun is overhead wherever the local sidereal time matches its right
+	// ascension
+	let sidereal_time = 280.46061837 + 360.98564736629 * n;
+	let longitude =
+		(right_ascension.to_degrees() - sidereal_time + 180.0).rem_euclid(360.0) - 180.0;
+
+	GeoPosition { latitude: declination.to_degrees() as f32, longitude: longitude as f32 }
+}
+
+/// Unit vector from the center of the globe towards the sun
+pub fn sun_direction(time_cursor: &TimeCursor) -> nglm::Vec3 {
+	geo_to_world(&subsolar_point(time_cursor.days_since_j2000()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const AXIAL_TILT: f32 = 23.44;
+
+	fn at(month: usize, day: u32, utc_hours: f32) -> GeoPosition {
+		subsolar_point(TimeCursor { year: 2021, month, day, utc_hours }.days_since_j2000())
+	}
+
+	#[test]
+	fn march_equinox() {
+		// March 20, 2021 09:37 UTC
+		let position = at(2, 20, 9.62);
+		assert!(position.latitude.abs() < 0.05, "{position:?}");
+	}
+
+	#[test]
+	fn september_equinox() {
+		// September 22, 2021 19:21 UTC
+		let position = at(8, 22, 19.35);
+		assert!(position.latitude.abs() < 0.05, "{position:?}");
+	}
+
+	#[test]
+	fn june_solstice() {
+		// June 21, 2021 03:32 UTC
+		let position = at(5, 21, 3.53);
+		assert!((position.latitude - AXIAL_TILT).abs() < 0.05, "{position:?}");
+	}
+
+	#[test]
+	fn december_solstice() {
+		// December 21, 2021 15:59 UTC
+		let position = at(11, 21, 15.98);
+		assert!((position.latitude + AXIAL_TILT).abs() < 0.05, "{position:?}");
+	}
+
+	#[test]
+	fn equation_of_time() {
+		// Solar noon is about 14 minutes late in mid-February, and 16 minutes early
+		// in early November, which moves the subsolar point off the prime meridian
+		assert!((at(1, 11, 12.0).longitude - 3.55).abs() < 0.1);
+		assert!((at(10, 3, 12.0).longitude + 4.1).abs() < 0.1);
+	}
+
+	#[test]
+	fn sun_moves_west() {
+		let noon = at(5, 21, 12.0);
+		let evening = at(5, 21, 18.0);
+
+		assert!(noon.longitude.abs() < 1.0, "{noon:?}");
+		assert!((evening.longitude - noon.longitude + 90.0).abs() < 0.1, "{evening:?}");
+	}
+
+	#[test]
+	fn direction_is_unit_length() {
+		let direction = sun_direction(&TimeCursor::default());
+		assert!((direction.magnitude() - 1.0).abs() < 1.0e-5);
+	}
+}
@@ -1,32 +1,112 @@
 use nglm::{Vec2, Vec3, Vec4};
 
 use crate::application::vertex::{BasicMesh, Vertex};
+use crate::render_core::mesh::ToMesh;
 #[allow(unused_imports)]
 use crate::utils::prelude::*;
 
-// Thanks, Sebastian Lague. https://www.youtube.com/watch?v=sLqXFF8mlEU
-pub fn generate_sphere(subdivisions: u32, points_per_subdivision: u32) -> Vec<BasicMesh> {
-	generate_sphere_with_position(subdivisions, points_per_subdivision, nglm::zero())
+/// Describes how the sphere is split up into meshes. Each cube face is split
+/// into `subdivisions` x `subdivisions` patches, and each patch is generated
+/// once per entry in `lod_points_per_subdivision`, from finest to coarsest.
+///
+/// Using `2^n + 1` points per side means each coarser level's vertices are a
+/// subset of the finer level's, so neighbouring patches at different levels
+/// only ever disagree in between shared vertices. The skirts hide those gaps.
+#[derive(Clone, Debug)]
+pub struct SphereTessellation {
+	pub subdivisions: u32,
+	pub lod_points_per_subdivision: Vec<u32>,
+	/// How far (as a proportion of the radius) the skirts hang below each patch
+	pub skirt_depth: f32,
+	/// Distance, in multiples of a patch's size, at which the finest level is
+	/// swapped for the next one. Each doubling of distance drops a level.
+	pub lod_switch_distance: f32,
+}
+
+impl Default for SphereTessellation {
+	fn default() -> Self {
+		Self {
+			subdivisions: 10,
+			lod_points_per_subdivision: vec![17, 9, 5, 3],
+			skirt_depth: 0.05,
+			lod_switch_distance: 2.0,
+		}
+	}
 }
 
-pub fn generate_sphere_with_position(
-	subdivisions: u32,
-	points_per_subdivision: u32,
-	center: Vec3,
-) -> Vec<BasicMesh> {
+/// One patch of a cube face, generated at every level of detail
+pub struct SpherePatch {
+	pub lods: Vec<BasicMesh>,
+}
+
+impl SpherePatch {
+	/// Diagonal of the finest level's bounding box
+	pub fn size(&self) -> f32 {
+		self.lods
+			.first()
+			.and_then(|mesh| mesh.get_bounding_box())
+			.map(|bounds| bounds.column(1).metric_distance(&bounds.column(0)))
+			.unwrap_or(0.0)
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MeshStatistics {
+	pub meshes: usize,
+	pub vertices: usize,
+	pub triangles: usize,
+}
+
+// Thanks, Sebastian Lague. https://www.youtube.com/watch?v=sLqXFF8mlEU
+/// Generates every patch of a sphere centered on the origin, at every level of
+/// detail described by `tessellation`.
+pub fn generate_lod_sphere(tessellation: &SphereTessellation) -> Vec<SpherePatch> {
 	let subface_generator = QuadSubface {};
+	let subdivision_size = 1.0 / (tessellation.subdivisions as f32);
+
 	cube_normals()
 		.iter()
-		.map(|n| {
-			generate_face::<QuadSubface>(
-				subface_generator.clone(),
-				n,
-				subdivisions,
-				points_per_subdivision,
-				center.clone(),
+		.flat_map(|normal| {
+			subdivision_starts(tessellation.subdivisions).into_iter().map(move |s| (normal, s))
+		})
+		.map(|(normal, subdivision_start)| {
+			let lods = tessellation
+				.lod_points_per_subdivision
+				.iter()
+				.map(|&points_per_side| {
+					let mut mesh = generate_subface(
+						subface_generator.clone(),
+						normal,
+						points_per_side,
+						subdivision_start,
+						subdivision_size,
+						nglm::zero(),
+					);
+					add_skirt(&mut mesh, points_per_side, tessellation.skirt_depth);
+					mesh
+				})
+				.collect();
+
+			SpherePatch { lods }
+		})
+		.collect()
+}
+
+/// Totals for each level of detail, across all patches
+pub fn lod_statistics(patches: &[SpherePatch]) -> Vec<MeshStatistics> {
+	let num_levels = patches.iter().map(|p| p.lods.len()).max().unwrap_or(0);
+
+	(0..num_levels)
+		.map(|level| {
+			patches.iter().filter_map(|p| p.lods.get(level)).fold(
+				MeshStatistics::default(),
+				|stats, mesh| MeshStatistics {
+					meshes: stats.meshes + 1,
+					vertices: stats.vertices + mesh.vertices().len(),
+					triangles: stats.triangles + mesh.get_flat_index_buffer().len() / 3,
+				},
 			)
 		})
-		.flatten()
 		.collect()
 }
 
@@ -41,35 +121,13 @@ fn cube_normals() -> Vec<Vec3> {
 	]
 }
 
-fn generate_face<T: SubfaceGenerator + Clone>(
-	subface_generator: T,
-	normal: &Vec3,
-	subdivisions: u32,
-	points_per_subdivision: u32,
-	sphere_center: Vec3,
-) -> Vec<BasicMesh> {
-	let mut meshes = Vec::new();
-
+fn subdivision_starts(subdivisions: u32) -> Vec<Vec2> {
 	let subdivision_size = 1.0 / (subdivisions as f32);
 
-	for y in 0..subdivisions {
-		for x in 0..subdivisions {
-			let subdivision_x = (x as f32) * subdivision_size;
-			let subdivision_y = (y as f32) * subdivision_size;
-			let subdivision_start = nglm::vec2(subdivision_x, subdivision_y);
-
-			meshes.push(generate_subface(
-				subface_generator.clone(),
-				normal,
-				points_per_subdivision,
-				subdivision_start,
-				subdivision_size,
-				sphere_center.clone(),
-			));
-		}
-	}
-
-	meshes
+	(0..subdivisions)
+		.flat_map(|y| (0..subdivisions).map(move |x| (x, y)))
+		.map(|(x, y)| nglm::vec2((x as f32) * subdivision_size, (y as f32) * subdivision_size))
+		.collect()
 }
 
 fn generate_subface<T: SubfaceGenerator>(
@@ -130,6 +188,49 @@ fn point_on_cube_to_point_on_sphere(p: Vec3) -> Vec3 {
 	nglm::vec3(x, y, z)
 }
 
+/// Hangs a strip of triangles below each edge of a patch, towards the center of
+/// the sphere. Neighbouring patches drawn at different levels of detail only
+/// share some of their edge vertices, so the skirt covers the seam between
+/// them.
+/// NOTE: Assumes the sphere is centered on the origin
+fn add_skirt(mesh: &mut BasicMesh, points_per_side: u32, skirt_depth: f32) {
+	if skirt_depth <= 0.0 {
+		return;
+	}
+
+	let last = points_per_side - 1;
+	let edges: [Vec<u32>; 4] = [
+		(0..points_per_side).collect(),
+		(0..points_per_side).map(|y| y * points_per_side + last).collect(),
+		(0..points_per_side).rev().map(|x| last * points_per_side + x).collect(),
+		(0..points_per_side).rev().map(|y| y * points_per_side).collect(),
+	];
+
+	for edge in edges {
+		let first_skirt_index = mesh.vertices().len() as u32;
+
+		for &index in edge.iter() {
+			let mut skirt_vertex = mesh.vertices()[index as usize];
+			skirt_vertex.set_position(skirt_vertex.get_position() * (1.0 - skirt_depth));
+			mesh.push_vertex(skirt_vertex);
+		}
+
+		for (i, pair) in edge.windows(2).enumerate() {
+			let (top_a, top_b) = (pair[0], pair[1]);
+			let bottom_a = first_skirt_index + i as u32;
+			let bottom_b = bottom_a + 1;
+
+			mesh.push_index(top_a);
+			mesh.push_index(bottom_a);
+			mesh.push_index(top_b);
+			mesh.push_index(top_b);
+			mesh.push_index(bottom_a);
+			mesh.push_index(bottom_b);
+		}
+	}
+}
+
+#[cfg(target_arch = "wasm32")]
 fn determine_face_color(_normal: &Vec3) -> Vec4 {
 	let r = js_sys::Math::random() as f32;
 	let g = js_sys::Math::random() as f32;
@@ -137,6 +238,14 @@ fn determine_face_color(_normal: &Vec3) -> Vec4 {
 	nglm::vec4(r, g, b, 1.0)
 }
 
+/// `js_sys` can't be called natively, and native renders need to be
+/// reproducible, so each face gets a fixed color instead.
+#[cfg(not(target_arch = "wasm32"))]
+fn determine_face_color(normal: &Vec3) -> Vec4 {
+	let color = (normal.abs() + nglm::vec3(1.0, 1.0, 1.0)) / 2.0;
+	nglm::vec4(color.x, color.y, color.z, 1.0)
+}
+
 trait SubfaceGenerator {
 	fn add_for_point(
 		&self,
@@ -192,3 +301,91 @@ impl SubfaceGenerator for QuadSubface {
 		)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn single_lod_tessellation(points_per_side: u32, skirt_depth: f32) -> SphereTessellation {
+		SphereTessellation {
+			subdivisions: 2,
+			lod_points_per_subdivision: vec![points_per_side],
+			skirt_depth,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn statistics_without_skirts() {
+		let patches = generate_lod_sphere(&single_lod_tessellation(5, 0.0));
+		let stats = lod_statistics(&patches);
+
+		assert_eq!(
+			stats,
+			vec![MeshStatistics { meshes: 24, vertices: 24 * 25, triangles: 24 * 32 }]
+		);
+	}
+
+	#[test]
+	fn statistics_per_lod() {
+		let tessellation = SphereTessellation::default();
+		let patches = generate_lod_sphere(&tessellation);
+		let stats = lod_statistics(&patches);
+
+		let num_patches = (6 * tessellation.subdivisions * tessellation.subdivisions) as usize;
+		assert_eq!(patches.len(), num_patches);
+		assert_eq!(stats.len(), tessellation.lod_points_per_subdivision.len());
+
+		for (level, &points) in tessellation.lod_points_per_subdivision.iter().enumerate() {
+			let points = points as usize;
+			let skirt_vertices = 4 * points;
+			let skirt_triangles = 4 * 2 * (points - 1);
+
+			assert_eq!(
+				stats[level],
+				MeshStatistics {
+					meshes: num_patches,
+					vertices: num_patches * (points * points + skirt_vertices),
+					triangles: num_patches * (2 * (points - 1) * (points - 1) + skirt_triangles),
+				}
+			);
+		}
+
+		assert!(stats.windows(2).all(|s| s[0].triangles > s[1].triangles));
+	}
+
+	#[test]
+	fn coarse_edges_are_subsets_of_fine_edges() {
+		let tessellation = SphereTessellation { subdivisions: 2, ..Default::default() };
+		let patches = generate_lod_sphere(&tessellation);
+		let epsilon = 1.0e-5;
+
+		for patch in patches.iter() {
+			let finest = &patch.lods[0];
+			for coarse in patch.lods.iter().skip(1) {
+				for vertex in coarse.vertices().iter() {
+					let position = vertex.get_position();
+					assert!(finest
+						.vertices()
+						.iter()
+						.any(|v| v.get_position().metric_distance(&position) < epsilon));
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn skirts_hang_below_the_surface() {
+		let skirt_depth = 0.1;
+		let patches = generate_lod_sphere(&single_lod_tessellation(3, skirt_depth));
+
+		for patch in patches.iter() {
+			let radii: Vec<f32> =
+				patch.lods[0].vertices().iter().map(|v| v.get_position().magnitude()).collect();
+			let (surface, skirt) = radii.split_at(9);
+
+			assert!(surface.iter().all(|r| (r - 1.0).abs() < 1.0e-5));
+			assert!(skirt.iter().all(|r| (r - (1.0 - skirt_depth)).abs() < 1.0e-5));
+		}
+	}
+}
@@ -0,0 +1,91 @@
+const DAYS_BEFORE_MONTH: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+
+/// The year that the bundled data was collected in
+const DATA_YEAR: i32 = 2021;
+
+/// The moment being shown on the globe. The data is monthly, so only `month`
+/// chooses what's displayed; the rest positions the sun.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimeCursor {
+	pub year: i32,
+	/// Zero-based, January is 0
+	pub month: usize,
+	/// One-based day of the month
+	pub day: u32,
+	pub utc_hours: f32,
+}
+
+impl Default for TimeCursor {
+	fn default() -> Self { Self { year: DATA_YEAR, month: 0, day: 15, utc_hours: 12.0 } }
+}
+
+fn is_leap_year(year: i32) -> bool { (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 }
+
+/// Leap days in years 1 through `year`, inclusive
+fn leap_days_through(year: i32) -> i32 { year / 4 - year / 100 + year / 400 }
+
+impl TimeCursor {
+	pub fn next_month(self) -> Self { Self { month: (self.month + 1) % 12, ..self } }
+
+	pub fn previous_month(self) -> Self { Self { month: (self.month + 11) % 12, ..self } }
+
+	/// Wraps around within the same day
+	pub fn add_hours(self, hours: f32) -> Self {
+		Self { utc_hours: (self.utc_hours + hours).rem_euclid(24.0), ..self }
+	}
+
+	/// One-based, so January 1st is day 1
+	pub fn day_of_year(&self) -> u32 {
+		let month = self.month % 12;
+		let leap_day = (month >= 2 && is_leap_year(self.year)) as u32;
+		DAYS_BEFORE_MONTH[month] + leap_day + self.day
+	}
+
+	/// Days (and fractions of a day) since the J2000.0 epoch, 2000-01-01 12:00
+	/// UTC
+	pub fn days_since_j2000(&self) -> f64 {
+		let whole_years = 365 * (self.year - 2000) as i64
+			+ (leap_days_through(self.year - 1) - leap_days_through(1999)) as i64;
+
+		whole_years as f64 + (self.day_of_year() - 1) as f64 + (self.utc_hours as f64 - 12.0) / 24.0
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn date(year: i32, month: usize, day: u32, utc_hours: f32) -> TimeCursor {
+		TimeCursor { year, month, day, utc_hours }
+	}
+
+	#[test]
+	fn months_wrap() {
+		let december = TimeCursor { month: 11, ..Default::default() };
+		assert_eq!(december.next_month().month, 0);
+		assert_eq!(december.next_month().previous_month(), december);
+	}
+
+	#[test]
+	fn hours_wrap() {
+		let cursor = TimeCursor { utc_hours: 23.0, ..Default::default() };
+		assert_eq!(cursor.add_hours(2.0).utc_hours, 1.0);
+		assert_eq!(cursor.add_hours(-24.0).utc_hours, 23.0);
+	}
+
+	#[test]
+	fn day_of_year() {
+		assert_eq!(date(2021, 0, 1, 0.0).day_of_year(), 1);
+		assert_eq!(date(2021, 2, 20, 0.0).day_of_year(), 79);
+		assert_eq!(date(2020, 2, 20, 0.0).day_of_year(), 80);
+		assert_eq!(date(2021, 11, 31, 0.0).day_of_year(), 365);
+	}
+
+	#[test]
+	fn days_since_j2000() {
+		assert_eq!(date(2000, 0, 1, 12.0).days_since_j2000(), 0.0);
+		assert_eq!(date(2001, 0, 1, 12.0).days_since_j2000(), 366.0);
+		// 2021-01-01 00:00 is JD 2459215.5
+		assert_eq!(date(2021, 0, 1, 0.0).days_since_j2000(), 2459215.5 - 2451545.0);
+	}
+}
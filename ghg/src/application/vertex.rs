@@ -1,8 +1,6 @@
 use memoffset::offset_of;
 
-use crate::render_core::camera::Camera;
 use crate::render_core::mesh::{ToMesh, VertexAttribute};
-use crate::utils::prelude::*;
 
 #[repr(C, packed)]
 #[derive(Clone, Copy)]
@@ -24,7 +22,6 @@ impl Vertex {
 		nglm::vec3(slice[0], slice[1], slice[2])
 	}
 
-	#[allow(dead_code)]
 	pub fn set_position(&mut self, position: nglm::Vec3) {
 		let position_data = std::ptr::addr_of_mut!(self.position.data);
 		unsafe { std::ptr::write_unaligned(position_data, position.data) };
@@ -63,6 +60,8 @@ impl BasicMesh {
 
 	pub fn push_index(&mut self, index: u32) { self.indices.push(index); }
 
+	pub fn vertices(&self) -> &[Vertex] { &self.vertices }
+
 	// pub fn vertices_mut(&mut self) -> &mut Vec<Vertex> {
 	//     &mut self.vertices
 	// }
@@ -169,22 +168,6 @@ impl ToMesh for BasicMesh {
 	fn get_bounding_box(&self) -> Option<nglm::Mat3x2> { self.bounding_box }
 
 	fn get_center(&self) -> Option<nglm::Vec3> { self.center }
-
-	// TODO: This is sphere-specific code, so I need to implement Sphere as its
-	// own mesh collection.
-	fn is_visible(&self, camera: &Camera) -> bool {
-		// true
-		let mesh_center = self.center;
-		if mesh_center.is_none() {
-			ghg_error!("Invalid mesh!");
-			return false;
-		}
-		let camera_position = camera.position();
-		let dist = camera_position.metric_distance(&mesh_center.unwrap());
-		let camera_from_origin = camera_position.magnitude();
-
-		dist <= camera_from_origin
-	}
 }
 
 #[cfg(test)]
@@ -194,7 +177,6 @@ mod tests {
 	use crate::application::sphere::*;
 	use crate::application::vertex::{BasicMesh, Vertex};
 	use crate::render_core::mesh::ToMesh;
-	use crate::utils::prelude::*;
 
 	#[wasm_bindgen_test]
 	fn origin_mesh() {
@@ -232,7 +214,14 @@ mod tests {
 
 	#[wasm_bindgen_test]
 	fn center() {
-		let cube_meshes = generate_sphere(1, 2); // Actually a cube
+		// Actually a cube
+		let tessellation = SphereTessellation {
+			subdivisions: 1,
+			lod_points_per_subdivision: vec![2],
+			skirt_depth: 0.0,
+			..Default::default()
+		};
+		let cube_meshes = generate_lod_sphere(&tessellation).into_iter().flat_map(|p| p.lods);
 
 		for mesh in cube_meshes {
 			let vertices = &mesh.vertices;
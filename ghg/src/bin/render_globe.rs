@@ -0,0 +1,257 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(not(target_arch = "wasm32"))]
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+#[cfg(not(target_arch = "wasm32"))]
+use ghg::offline::{
+	render_catalog, render_time_lapse, AnimationFormat, CameraPose, Catalog, Colormap, Dataset,
+	OfflineRenderer, Terrain, TimeCursor, TimeLapse, View, INITIAL_TERRAIN_SCALE,
+};
+
+// The offline renderer isn't built for the web app
+#[cfg(target_arch = "wasm32")]
+fn main() { panic!("render_globe only runs natively") }
+
+/// Renders PNGs of the globe without a browser, for the gallery and link
+/// previews. Expects to be run with CWD in the project root, like
+/// texture_splitter, unless `--assets` says where the web app's files are.
+///
+/// One image:
+///     render_globe image --month 7 --camera 1.4,0.6,2.2 --output july.png
+/// Everything in a catalog, see render_catalog.json:
+///     render_globe catalog render_catalog.json --output renders
+/// A time-lapse, see timelapse_1980_2021.json:
+///     render_globe timelapse timelapse_1980_2021.json --output timelapse
+/// --animation gif
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+	let matches = command().get_matches();
+	if let Err(e) = run(&matches) {
+		eprintln!("{e}");
+		std::process::exit(1);
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn command() -> Command {
+	Command::new("render_globe")
+		.about("Renders the globe to PNG images, entirely offline")
+		.arg(
+			Arg::new("assets")
+				.long("assets")
+				.help("Directory the web app is served from")
+				.default_value("www")
+				.value_parser(value_parser!(PathBuf)),
+		)
+		.subcommand_required(true)
+		.subcommand(
+			Command::new("image")
+				.about("Renders one dataset at one time step")
+				.arg(
+					Arg::new("dataset")
+						.long("dataset")
+						.help("Directory of the dataset's maps, within the assets")
+						.default_value("images/earth_temp"),
+				)
+				.arg(
+					Arg::new("maps")
+						.long("maps")
+						.help("File stems of the year's three maps, separated by commas")
+						.default_value("2021.01.04,2021.05.08,2021.09.12"),
+				)
+				.arg(
+					Arg::new("month")
+						.long("month")
+						.help("From 1 for January")
+						.default_value("1")
+						.value_parser(value_parser!(u32).range(1..=12)),
+				)
+				.arg(
+					Arg::new("utc-hours")
+						.long("utc-hours")
+						.help("Time of day, which places the sun")
+						.default_value("12")
+						.value_parser(value_parser!(f32)),
+				)
+				.arg(
+					Arg::new("camera")
+						.long("camera")
+						.help("Camera position as x,y,z, in planet radii")
+						.default_value("0,0,3")
+						.value_parser(parse_vec3),
+				)
+				.arg(
+					Arg::new("target")
+						.long("target")
+						.help("Point the camera looks at, as x,y,z")
+						.default_value("0,0,0")
+						.value_parser(parse_vec3),
+				)
+				.arg(Arg::new("colormap").long("colormap").default_value("hue").value_parser([
+					"hue",
+					"grayscale",
+					"diverging",
+				]))
+				.arg(
+					Arg::new("width")
+						.long("width")
+						.default_value("1200")
+						.value_parser(value_parser!(u32).range(1..)),
+				)
+				.arg(
+					Arg::new("height")
+						.long("height")
+						.default_value("630")
+						.value_parser(value_parser!(u32).range(1..)),
+				)
+				.arg(
+					Arg::new("sunlight")
+						.long("sunlight")
+						.help("Light the globe from the sun, rather than from the camera")
+						.action(ArgAction::SetTrue),
+				)
+				.arg(
+					Arg::new("output")
+						.long("output")
+						.required(true)
+						.value_parser(value_parser!(PathBuf)),
+				),
+		)
+		.subcommand(
+			Command::new("catalog")
+				.about("Renders every dataset, month and view in a catalog file")
+				.arg(Arg::new("catalog").required(true).value_parser(value_parser!(PathBuf)))
+				.arg(
+					Arg::new("output")
+						.long("output")
+						.help("Images are written to <output>/<dataset>/<view>/<month>.png")
+						.default_value("renders")
+						.value_parser(value_parser!(PathBuf)),
+				),
+		)
+		.subcommand(
+			Command::new("timelapse")
+				.about("Renders a frame for every month of a time-lapse file")
+				.arg(Arg::new("time-lapse").required(true).value_parser(value_parser!(PathBuf)))
+				.arg(
+					Arg::new("output")
+						.long("output")
+						.help("Frames are written to <output>/frame_00001.png onwards")
+						.default_value("timelapse")
+						.value_parser(value_parser!(PathBuf)),
+				)
+				.arg(
+					Arg::new("animation")
+						.long("animation")
+						.help("Also writes the frames into one animated image")
+						.default_value("gif")
+						.value_parser(["gif", "apng", "none"]),
+				),
+		)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_vec3(value: &str) -> Result<[f32; 3], String> {
+	let components = value
+		.split(',')
+		.map(|component| component.trim().parse::<f32>().map_err(|e| e.to_string()))
+		.collect::<Result<Vec<f32>, String>>()?;
+	components.try_into().map_err(|_| format!("Expected x,y,z, not {value}"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_colormap(value: &str) -> Colormap {
+	match value {
+		"grayscale" => Colormap::Grayscale,
+		"diverging" => Colormap::Diverging,
+		_ => Colormap::Hue,
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn run(matches: &ArgMatches) -> Result<(), String> {
+	let assets = matches.get_one::<PathBuf>("assets").unwrap();
+	let terrain = Terrain::from_assets(assets)?;
+	let mut renderer = OfflineRenderer::new(assets, &terrain)?;
+
+	match matches.subcommand() {
+		Some(("image", matches)) => render_image(&mut renderer, matches),
+		Some(("catalog", matches)) => {
+			let path = matches.get_one::<PathBuf>("catalog").unwrap();
+			let json = std::fs::read_to_string(path)
+				.map_err(|e| format!("Couldn't read {path:?}: {e}"))?;
+			let catalog = Catalog::from_json(&json)?;
+			let output = matches.get_one::<PathBuf>("output").unwrap();
+
+			let start = Instant::now();
+			let count = render_catalog(&mut renderer, &catalog, output, |path| {
+				println!("Wrote {}", path.display())
+			})?;
+			println!("Rendered {count} images in {:.1?}", start.elapsed());
+			Ok(())
+		}
+		Some(("timelapse", matches)) => {
+			let path = matches.get_one::<PathBuf>("time-lapse").unwrap();
+			let json = std::fs::read_to_string(path)
+				.map_err(|e| format!("Couldn't read {path:?}: {e}"))?;
+			let time_lapse = TimeLapse::from_json(&json)?;
+			let output = matches.get_one::<PathBuf>("output").unwrap();
+			let animation = match matches.get_one::<String>("animation").unwrap().as_str() {
+				"gif" => Some(AnimationFormat::Gif),
+				"apng" => Some(AnimationFormat::Apng),
+				_ => None,
+			};
+
+			let start = Instant::now();
+			let frame_count = time_lapse.frame_count();
+			let mut written = 0;
+			let count = render_time_lapse(&mut renderer, &time_lapse, output, animation, |path| {
+				written += 1;
+				println!("Wrote {written} of {frame_count}: {}", path.display())
+			})?;
+			println!("Rendered {count} frames in {:.1?}", start.elapsed());
+			Ok(())
+		}
+		_ => unreachable!("A subcommand is required"),
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn render_image(renderer: &mut OfflineRenderer, matches: &ArgMatches) -> Result<(), String> {
+	let directory = PathBuf::from(matches.get_one::<String>("dataset").unwrap());
+	let dataset = Dataset {
+		name: directory
+			.file_name()
+			.map(|name| name.to_string_lossy().into_owned())
+			.unwrap_or_default(),
+		maps: matches.get_one::<String>("maps").unwrap().split(',').map(str::to_owned).collect(),
+		directory,
+		colormap: Colormap::default(),
+	};
+	let view = View {
+		name: "image".to_owned(),
+		width: *matches.get_one::<u32>("width").unwrap(),
+		height: *matches.get_one::<u32>("height").unwrap(),
+		camera: CameraPose {
+			position: *matches.get_one::<[f32; 3]>("camera").unwrap(),
+			target: *matches.get_one::<[f32; 3]>("target").unwrap(),
+		},
+		sunlight: matches.get_flag("sunlight"),
+		terrain_scale: INITIAL_TERRAIN_SCALE,
+	};
+	let time_cursor = TimeCursor {
+		month: *matches.get_one::<u32>("month").unwrap() as usize - 1,
+		utc_hours: *matches.get_one::<f32>("utc-hours").unwrap(),
+		..TimeCursor::default()
+	};
+	let colormap = parse_colormap(matches.get_one::<String>("colormap").unwrap());
+
+	let output: &Path = matches.get_one::<PathBuf>("output").unwrap();
+	let image = renderer.render(&dataset, time_cursor, &view, colormap)?;
+	image.save(output).map_err(|e| format!("Couldn't write {output:?}: {e}"))?;
+	println!("Wrote {}", output.display());
+	Ok(())
+}
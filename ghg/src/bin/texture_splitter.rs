@@ -211,6 +211,7 @@ fn create_subimages<Map: ColorMapping + 'static>(
 enum WhichToGenerate {
 	Color,
 	Height,
+	Night,
 }
 
 fn main() {
@@ -242,5 +243,15 @@ fn main() {
 			create_downscaled_originals(image_root, "earth_height", 2, original);
 			create_subimages::<Luma<u8>>(image_root, "earth_height", 2, 4, 2);
 		}
+		WhichToGenerate::Night => {
+			let night_image = read_image(&image_root.join("earth_night/0/full.png"));
+			let original = night_image.to_rgb8();
+
+			let (width_0, height_0) = original.dimensions();
+			println!("Loaded image: {} x {}", width_0, height_0);
+
+			create_downscaled_originals(image_root, "earth_night", 2, &original);
+			create_subimages::<Rgb<u8>>(image_root, "earth_night", 2, 4, 2);
+		}
 	}
 }
@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+
+use crate::interaction_core::input_state::{InputState, KeyState};
+use crate::interaction_core::key_code::{self, KeyCode};
+use crate::interaction_core::user_inputs::SwitchState;
+
+/// Something the user can do from the keyboard, independent of which keys do
+/// it
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+	/// Held to orbit with the mouse without pressing a button
+	OrbitModifier,
+	NextTimeStep,
+	PreviousTimeStep,
+	NextHour,
+	PreviousHour,
+	TogglePlayback,
+	TerrainScaleUp,
+	TerrainScaleDown,
+	/// Index into the terrain scale presets
+	TerrainScalePreset(u8),
+	ToggleLightSource,
+	ToggleStars,
+	ToggleAtmosphere,
+	CycleCameraMode,
+	ToggleLogarithmicDepth,
+	FlyHome,
+}
+
+impl Action {
+	/// Short description for a help overlay
+	pub fn description(&self) -> String {
+		match self {
+			Action::OrbitModifier => "Hold to orbit with the mouse".to_owned(),
+			Action::NextTimeStep => "Next month".to_owned(),
+			Action::PreviousTimeStep => "Previous month".to_owned(),
+			Action::NextHour => "One hour later".to_owned(),
+			Action::PreviousHour => "One hour earlier".to_owned(),
+			Action::TogglePlayback => "Play or pause the months".to_owned(),
+			Action::TerrainScaleUp => "Exaggerate the terrain".to_owned(),
+			Action::TerrainScaleDown => "Flatten the terrain".to_owned(),
+			Action::TerrainScalePreset(preset) => format!("Terrain scale preset {}", preset + 1),
+			Action::ToggleLightSource => "Switch between sun and camera lighting".to_owned(),
+			Action::ToggleStars => "Show or hide the stars".to_owned(),
+			Action::ToggleAtmosphere => "Show or hide the atmosphere".to_owned(),
+			Action::CycleCameraMode => "Switch camera mode".to_owned(),
+			Action::ToggleLogarithmicDepth => "Toggle logarithmic depth".to_owned(),
+			Action::FlyHome => "Fly back to the starting view".to_owned(),
+		}
+	}
+}
+
+/// Performs `action` when `key` changes while every one of `modifiers` is held
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+	pub action: Action,
+	#[serde(deserialize_with = "key_code::deserialize_known")]
+	pub key: KeyCode,
+	#[serde(
+		default,
+		skip_serializing_if = "Vec::is_empty",
+		deserialize_with = "key_code::deserialize_known_list"
+	)]
+	pub modifiers: Vec<KeyCode>,
+}
+
+impl Binding {
+	fn modifiers_held(&self, state: &InputState) -> bool {
+		self.modifiers.iter().all(|m| state.is_key_active(m))
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionEvent {
+	pub action: Action,
+	pub state: SwitchState,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyBindings {
+	bindings: Vec<Binding>,
+}
+
+impl KeyBindings {
+	pub fn new(bindings: Vec<Binding>) -> Self { Self { bindings } }
+
+	/// Expects a list of bindings, e.g.
+	/// `[{ "action": "FlyHome", "key": "KeyH", "modifiers": ["ShiftLeft"] }]`
+	pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+		Ok(Self::new(serde_json::from_str(json)?))
+	}
+
+	pub fn bindings(&self) -> &[Binding] { &self.bindings }
+
+	/// Turns this frame's key changes into actions. When several chords share a
+	/// pressed key, only those with the most modifiers held fire, so that e.g.
+	/// Shift+L doesn't also trigger L. Releases fire for every binding on the
+	/// key, so that held actions always end.
+	pub fn resolve(&self, key_changes: &[KeyState], state: &InputState) -> Vec<ActionEvent> {
+		let mut events = Vec::new();
+
+		for change in key_changes {
+			let on_key = self.bindings.iter().filter(|b| b.key == change.key);
+
+			match change.state {
+				SwitchState::Pressed => {
+					let matching: Vec<&Binding> =
+						on_key.filter(|b| b.modifiers_held(state)).collect();
+					let most_specific = matching.iter().map(|b| b.modifiers.len()).max();
+
+					events.extend(
+						matching
+							.iter()
+							.filter(|b| Some(b.modifiers.len()) == most_specific)
+							.map(|b| ActionEvent { action: b.action, state: SwitchState::Pressed }),
+					);
+				}
+				SwitchState::Released => {
+					events.extend(
+						on_key.map(|b| ActionEvent {
+							action: b.action,
+							state: SwitchState::Released,
+						}),
+					);
+				}
+			}
+		}
+
+		events
+	}
+
+	/// Whether any chord for `action` is fully held right now
+	pub fn is_active(&self, action: Action, state: &InputState) -> bool {
+		self.bindings
+			.iter()
+			.any(|b| b.action == action && state.is_key_active(&b.key) && b.modifiers_held(state))
+	}
+}
+
+impl Default for KeyBindings {
+	fn default() -> Self {
+		Self::from_json(include_str!("default_key_bindings.json"))
+			.expect("Default key bindings are invalid")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pressed(key: KeyCode) -> KeyState { KeyState { key, state: SwitchState::Pressed } }
+
+	fn released(key: KeyCode) -> KeyState { KeyState { key, state: SwitchState::Released } }
+
+	fn actions(events: Vec<ActionEvent>) -> Vec<Action> {
+		events.into_iter().map(|e| e.action).collect()
+	}
+
+	#[test]
+	fn defaults_parse() {
+		let bindings = KeyBindings::default();
+		assert!(bindings.bindings().iter().any(|b| b.action == Action::FlyHome));
+	}
+
+	#[test]
+	fn json_round_trip() {
+		let bindings = KeyBindings::default();
+		assert_eq!(
+			KeyBindings::from_json(&serde_json::to_string(bindings.bindings()).unwrap()).unwrap(),
+			bindings
+		);
+	}
+
+	#[test]
+	fn invalid_json_is_rejected() {
+		assert!(KeyBindings::from_json(r#"[{ "action": "NotAnAction", "key": "KeyA" }]"#).is_err());
+		assert!(KeyBindings::from_json(r#"{ "action": "FlyHome" }"#).is_err());
+
+		let misspelled = KeyBindings::from_json(r#"[{ "action": "FlyHome", "key": "KeyHH" }]"#);
+		assert!(misspelled.unwrap_err().to_string().contains("Unknown key code \"KeyHH\""));
+		assert!(KeyBindings::from_json(
+			r#"[{ "action": "FlyHome", "key": "KeyH", "modifiers": ["Shift"] }]"#
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn single_key() {
+		let bindings = KeyBindings::default();
+		let state = InputState::with_keys_pressed(&[KeyCode::ArrowRight]);
+
+		let events = bindings.resolve(&[pressed(KeyCode::ArrowRight)], &state);
+		assert_eq!(
+			events,
+			vec![ActionEvent { action: Action::NextTimeStep, state: SwitchState::Pressed }]
+		);
+
+		assert!(bindings.resolve(&[pressed(KeyCode::KeyQ)], &state).is_empty());
+	}
+
+	#[test]
+	fn chords_prefer_most_modifiers() {
+		let bindings = KeyBindings::from_json(
+			r#"[
+				{ "action": "ToggleLightSource", "key": "KeyL" },
+				{ "action": "FlyHome", "key": "KeyL", "modifiers": ["ShiftLeft"] },
+				{ "action": "CycleCameraMode", "key": "KeyL", "modifiers": ["ShiftLeft", "ControlLeft"] }
+			]"#,
+		)
+		.unwrap();
+
+		let plain = InputState::with_keys_pressed(&[KeyCode::KeyL]);
+		assert_eq!(
+			actions(bindings.resolve(&[pressed(KeyCode::KeyL)], &plain)),
+			vec![Action::ToggleLightSource]
+		);
+
+		let shifted = InputState::with_keys_pressed(&[KeyCode::ShiftLeft, KeyCode::KeyL]);
+		assert_eq!(
+			actions(bindings.resolve(&[pressed(KeyCode::KeyL)], &shifted)),
+			vec![Action::FlyHome]
+		);
+
+		let both = InputState::with_keys_pressed(&[
+			KeyCode::ShiftLeft,
+			KeyCode::ControlLeft,
+			KeyCode::KeyL,
+		]);
+		assert_eq!(
+			actions(bindings.resolve(&[pressed(KeyCode::KeyL)], &both)),
+			vec![Action::CycleCameraMode]
+		);
+	}
+
+	#[test]
+	fn releases_end_held_actions() {
+		let bindings = KeyBindings::default();
+
+		let held = InputState::with_keys_pressed(&[KeyCode::ShiftLeft]);
+		assert!(bindings.is_active(Action::OrbitModifier, &held));
+		assert_eq!(
+			bindings.resolve(&[pressed(KeyCode::ShiftLeft)], &held),
+			vec![ActionEvent { action: Action::OrbitModifier, state: SwitchState::Pressed }]
+		);
+
+		let let_go = InputState::with_keys_pressed(&[]);
+		assert!(!bindings.is_active(Action::OrbitModifier, &let_go));
+		assert_eq!(
+			bindings.resolve(&[released(KeyCode::ShiftLeft)], &let_go),
+			vec![ActionEvent { action: Action::OrbitModifier, state: SwitchState::Released }]
+		);
+	}
+
+	#[test]
+	fn rebinding() {
+		let bindings = KeyBindings::new(vec![Binding {
+			action: Action::NextTimeStep,
+			key: KeyCode::KeyD,
+			modifiers: vec![],
+		}]);
+		let state = InputState::with_keys_pressed(&[KeyCode::KeyD]);
+
+		assert_eq!(
+			actions(bindings.resolve(&[pressed(KeyCode::KeyD)], &state)),
+			vec![Action::NextTimeStep]
+		);
+		assert!(bindings.resolve(&[pressed(KeyCode::ArrowRight)], &state).is_empty());
+	}
+}
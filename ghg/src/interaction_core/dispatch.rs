@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+/// Identifies a subscription. Allocated in increasing order, so they also
+/// record which of two subscriptions came first.
+pub type Handle = u32;
+
+/// Higher priorities are called first. Subscriptions with the same priority
+/// are called in the order they were made.
+pub type Priority = i32;
+
+pub const DEFAULT_PRIORITY: Priority = 0;
+
+/// Returned by callbacks to say whether lower priority callbacks should also
+/// see the event
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Propagation {
+	Continue,
+	/// The event has been handled. No more callbacks are called for it.
+	Stop,
+}
+
+/// Keeps a callback subscribed. Dropping it unsubscribes the callback, which
+/// is safe to do at any time, including from inside a callback.
+#[must_use = "The callback is unsubscribed as soon as this is dropped"]
+pub struct Subscription {
+	unsubscribe: Option<Box<dyn FnOnce()>>,
+}
+
+impl Drop for Subscription {
+	fn drop(&mut self) {
+		if let Some(unsubscribe) = self.unsubscribe.take() {
+			unsubscribe();
+		}
+	}
+}
+
+struct Subscriber<C: ?Sized> {
+	handle: Handle,
+	priority: Priority,
+	callback: Rc<C>,
+}
+
+/// Callbacks of one type, kept in the order they're called in
+pub struct Subscribers<C: ?Sized> {
+	entries: Rc<RefCell<Vec<Subscriber<C>>>>,
+}
+
+impl<C: ?Sized + 'static> Subscribers<C> {
+	pub fn new() -> Self { Self { entries: Default::default() } }
+
+	pub fn subscribe(&self, handle: Handle, priority: Priority, callback: Box<C>) -> Subscription {
+		let mut entries = self.entries.borrow_mut();
+		let index = entries.partition_point(|entry| entry.priority >= priority);
+		entries.insert(index, Subscriber { handle, priority, callback: Rc::from(callback) });
+
+		let entries: Weak<RefCell<Vec<Subscriber<C>>>> = Rc::downgrade(&self.entries);
+		Subscription {
+			unsubscribe: Some(Box::new(move || {
+				if let Some(entries) = entries.upgrade() {
+					entries.borrow_mut().retain(|entry| entry.handle != handle);
+				}
+			})),
+		}
+	}
+
+	pub fn is_subscribed(&self, handle: Handle) -> bool {
+		self.entries.borrow().iter().any(|entry| entry.handle == handle)
+	}
+
+	/// Calls `call` with each callback in priority order, until one returns
+	/// `Propagation::Stop`. Callbacks may subscribe or unsubscribe anything
+	/// while this runs. New ones wait for the next dispatch, and unsubscribed
+	/// ones are skipped straight away.
+	pub fn dispatch(&self, mut call: impl FnMut(&C) -> Propagation) {
+		let snapshot: Vec<(Handle, Rc<C>)> = self
+			.entries
+			.borrow()
+			.iter()
+			.map(|entry| (entry.handle, entry.callback.clone()))
+			.collect();
+
+		for (handle, callback) in snapshot {
+			if !self.is_subscribed(handle) {
+				continue;
+			}
+			if call(&callback) == Propagation::Stop {
+				break;
+			}
+		}
+	}
+}
+
+impl<C: ?Sized + 'static> Default for Subscribers<C> {
+	fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+
+	use super::*;
+
+	type Callback = dyn Fn(&mut Vec<&'static str>) -> Propagation;
+
+	fn calls(subscribers: &Subscribers<Callback>) -> Vec<&'static str> {
+		let mut calls = Vec::new();
+		subscribers.dispatch(|callback| callback(&mut calls));
+		calls
+	}
+
+	fn named(name: &'static str, propagation: Propagation) -> Box<Callback> {
+		Box::new(move |calls| {
+			calls.push(name);
+			propagation
+		})
+	}
+
+	#[test]
+	fn priority_then_subscription_order() {
+		let subscribers = Subscribers::<Callback>::new();
+		let _a = subscribers.subscribe(0, DEFAULT_PRIORITY, named("a", Propagation::Continue));
+		let _b = subscribers.subscribe(1, 10, named("b", Propagation::Continue));
+		let _c = subscribers.subscribe(2, DEFAULT_PRIORITY, named("c", Propagation::Continue));
+		let _d = subscribers.subscribe(3, -5, named("d", Propagation::Continue));
+
+		assert_eq!(calls(&subscribers), vec!["b", "a", "c", "d"]);
+	}
+
+	#[test]
+	fn stop_consumes_the_event() {
+		let subscribers = Subscribers::<Callback>::new();
+		let _low = subscribers.subscribe(0, DEFAULT_PRIORITY, named("low", Propagation::Continue));
+		let _high = subscribers.subscribe(1, 1, named("high", Propagation::Stop));
+
+		assert_eq!(calls(&subscribers), vec!["high"]);
+	}
+
+	#[test]
+	fn dropping_unsubscribes() {
+		let subscribers = Subscribers::<Callback>::new();
+		let a = subscribers.subscribe(0, DEFAULT_PRIORITY, named("a", Propagation::Continue));
+		let _b = subscribers.subscribe(1, DEFAULT_PRIORITY, named("b", Propagation::Continue));
+
+		drop(a);
+		assert!(!subscribers.is_subscribed(0));
+		assert_eq!(calls(&subscribers), vec!["b"]);
+	}
+
+	#[test]
+	fn unsubscribing_during_dispatch() {
+		let subscribers = Subscribers::<Callback>::new();
+		let victim: Rc<Cell<Option<Subscription>>> = Default::default();
+
+		let dropper = victim.clone();
+		let _first = subscribers.subscribe(
+			0,
+			DEFAULT_PRIORITY,
+			Box::new(move |calls| {
+				calls.push("first");
+				drop(dropper.take());
+				Propagation::Continue
+			}),
+		);
+		victim.set(Some(subscribers.subscribe(
+			1,
+			DEFAULT_PRIORITY,
+			named("victim", Propagation::Continue),
+		)));
+
+		assert_eq!(calls(&subscribers), vec!["first"]);
+	}
+
+	#[test]
+	fn outliving_the_subscribers_is_fine() {
+		let subscribers = Subscribers::<Callback>::new();
+		let subscription =
+			subscribers.subscribe(0, DEFAULT_PRIORITY, named("a", Propagation::Continue));
+
+		drop(subscribers);
+		drop(subscription);
+	}
+}
@@ -0,0 +1,585 @@
+use std::time::Duration;
+
+use crate::interaction_core::input_state::InputState;
+use crate::interaction_core::user_inputs::SwitchState;
+
+/// A high-level touch gesture. Positions and distances are in the same screen
+/// pixels as `TouchMovement`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Gesture {
+	/// One finger dragging
+	Pan {
+		delta: nglm::Vec2,
+	},
+	/// Two fingers spreading or closing. `scale` is the distance between them
+	/// divided by what it was when the last `Pinch` was sent.
+	Pinch {
+		scale: f32,
+	},
+	/// Two fingers twisting, in radians. Positive is clockwise on screen.
+	Rotate {
+		angle: f32,
+	},
+	/// Two fingers sliding up or down together. Positive is down the screen.
+	Tilt {
+		delta: f32,
+	},
+	DoubleTap {
+		position: nglm::Vec2,
+	},
+	LongPress {
+		position: nglm::Vec2,
+	},
+}
+
+#[derive(Clone, Debug)]
+pub struct GestureSettings {
+	/// How far a finger can wander and still count as a tap or long press
+	pub touch_slop: f32,
+	pub tap_max_duration: Duration,
+	/// Longest gap between the end of one tap and the end of the next
+	pub double_tap_interval: Duration,
+	pub double_tap_max_distance: f32,
+	pub long_press_duration: Duration,
+	/// Change in the distance between two fingers before they count as a pinch
+	pub pinch_threshold: f32,
+	/// Radians two fingers have to twist before they count as a rotation
+	pub rotate_threshold: f32,
+	/// How far two fingers have to move vertically together to count as a tilt
+	pub tilt_threshold: f32,
+}
+
+impl Default for GestureSettings {
+	fn default() -> Self {
+		Self {
+			touch_slop: 10.0,
+			tap_max_duration: Duration::from_millis(250),
+			double_tap_interval: Duration::from_millis(300),
+			double_tap_max_distance: 40.0,
+			long_press_duration: Duration::from_millis(500),
+			pinch_threshold: 12.0,
+			rotate_threshold: 0.15,
+			tilt_threshold: 15.0,
+		}
+	}
+}
+
+type Touch = (i32, nglm::Vec2);
+
+#[derive(Clone, Debug)]
+enum Phase {
+	Idle,
+	/// One finger down that hasn't moved past the slop yet
+	Pressing {
+		identifier: i32,
+		start: nglm::Vec2,
+		since: Duration,
+		long_pressed: bool,
+	},
+	Panning {
+		identifier: i32,
+	},
+	/// Two fingers down. Pinching and rotating each unlock once their
+	/// threshold is crossed, and can then happen together.
+	TwoFingers {
+		start: (nglm::Vec2, nglm::Vec2),
+		pinching: bool,
+		rotating: bool,
+	},
+	Tilting,
+	/// Too many fingers. Nothing else is recognized until they've all lifted.
+	Cancelled,
+}
+
+/// Turns the touches in each frame's `InputState` into `Gesture`s. Call
+/// `frame` once per frame, whether or not anything changed, so that long
+/// presses are noticed. The state's touch transitions are read too, so that
+/// taps that begin and end between two frames still count.
+pub struct GestureRecognizer {
+	settings: GestureSettings,
+	phase: Phase,
+	/// Touches as of the previous frame, sorted by identifier
+	touches: Vec<Touch>,
+	/// Where and when the last single tap ended
+	last_tap: Option<(nglm::Vec2, Duration)>,
+	time: Duration,
+}
+
+impl GestureRecognizer {
+	pub fn new(settings: GestureSettings) -> Self {
+		Self {
+			settings,
+			phase: Phase::Idle,
+			touches: Vec::new(),
+			last_tap: None,
+			time: Duration::ZERO,
+		}
+	}
+
+	pub fn frame(&mut self, state: &InputState, delta_time: Duration) -> Vec<Gesture> {
+		self.time += delta_time;
+
+		let current = located_touches(state);
+		let mut gestures = Vec::new();
+		let phase = std::mem::replace(&mut self.phase, Phase::Idle);
+
+		self.phase = match (phase, current.as_slice()) {
+			(phase, []) => {
+				self.finish(phase, &mut gestures);
+				Phase::Idle
+			}
+			(Phase::Cancelled, _) => Phase::Cancelled,
+			(Phase::Idle, [(identifier, position)]) => Phase::Pressing {
+				identifier: *identifier,
+				start: *position,
+				since: self.time,
+				long_pressed: false,
+			},
+			(
+				Phase::Pressing { identifier, start, since, long_pressed },
+				[(current_identifier, position)],
+			) if identifier == *current_identifier => {
+				self.press(identifier, start, since, long_pressed, position, &mut gestures)
+			}
+			(Phase::Panning { identifier }, [(current_identifier, position)])
+				if identifier == *current_identifier =>
+			{
+				if let Some(previous) = self.previous_position(identifier) {
+					let delta = position - previous;
+					if delta != nglm::Vec2::zeros() {
+						gestures.push(Gesture::Pan { delta });
+					}
+				}
+				Phase::Panning { identifier }
+			}
+			// A finger lifted off a two finger gesture, or was swapped for
+			// another. Carry on from wherever the remaining one is.
+			(_, [(identifier, _)]) => Phase::Panning { identifier: *identifier },
+			(phase @ (Phase::TwoFingers { .. } | Phase::Tilting), [a, b])
+				if self.same_touches(&current) =>
+			{
+				self.two_fingers(phase, (a.1, b.1), &mut gestures)
+			}
+			(_, [a, b]) => {
+				Phase::TwoFingers { start: (a.1, b.1), pinching: false, rotating: false }
+			}
+			(_, _) => Phase::Cancelled,
+		};
+
+		if current.is_empty() {
+			self.unseen_taps(state, &mut gestures);
+		}
+
+		self.touches = current;
+		gestures
+	}
+
+	/// Touches that went down and lifted again since the previous frame were
+	/// never active in a frame, so they're only visible as transitions
+	fn unseen_taps(&mut self, state: &InputState, gestures: &mut Vec<Gesture>) {
+		let changes = state.unhandled_changes();
+		let mut taps: Vec<Touch> = changes
+			.touch_state_changes
+			.values()
+			.filter(|change| change.state == SwitchState::Released)
+			.filter(|change| self.previous_position(change.identifier).is_none())
+			.filter(|change| {
+				changes.touch_movement.get(&change.identifier).is_none_or(|movement| {
+					nglm::vec2(movement.difference.x as f32, movement.difference.y as f32).norm()
+						<= self.settings.touch_slop
+				})
+			})
+			.filter_map(|change| {
+				let position = changes.released_touch_positions.get(&change.identifier)?;
+				Some((change.identifier, nglm::vec2(position.x as f32, position.y as f32)))
+			})
+			.collect();
+
+		taps.sort_by_key(|(identifier, _)| *identifier);
+		for (_, position) in taps {
+			self.tap(position, gestures);
+		}
+	}
+
+	fn press(
+		&mut self,
+		identifier: i32,
+		start: nglm::Vec2,
+		since: Duration,
+		long_pressed: bool,
+		position: &nglm::Vec2,
+		gestures: &mut Vec<Gesture>,
+	) -> Phase {
+		if nglm::distance(&start, position) > self.settings.touch_slop {
+			// Include the slop, so that whatever is dragged stays under the finger
+			gestures.push(Gesture::Pan { delta: position - start });
+			return Phase::Panning { identifier };
+		}
+
+		let long_pressed =
+			if !long_pressed && self.time - since >= self.settings.long_press_duration {
+				gestures.push(Gesture::LongPress { position: start });
+				true
+			} else {
+				long_pressed
+			};
+
+		Phase::Pressing { identifier, start, since, long_pressed }
+	}
+
+	fn finish(&mut self, phase: Phase, gestures: &mut Vec<Gesture>) {
+		if let Phase::Pressing { start, since, long_pressed: false, .. } = phase {
+			if self.time - since <= self.settings.tap_max_duration {
+				self.tap(start, gestures);
+			}
+		}
+	}
+
+	fn tap(&mut self, position: nglm::Vec2, gestures: &mut Vec<Gesture>) {
+		let is_double = self.last_tap.is_some_and(|(previous, time)| {
+			self.time - time <= self.settings.double_tap_interval
+				&& nglm::distance(&previous, &position) <= self.settings.double_tap_max_distance
+		});
+
+		if is_double {
+			gestures.push(Gesture::DoubleTap { position });
+			self.last_tap = None;
+		} else {
+			self.last_tap = Some((position, self.time));
+		}
+	}
+
+	fn two_fingers(
+		&self,
+		phase: Phase,
+		(a, b): (nglm::Vec2, nglm::Vec2),
+		gestures: &mut Vec<Gesture>,
+	) -> Phase {
+		let (previous_a, previous_b) = (self.touches[0].1, self.touches[1].1);
+
+		let (start, mut pinching, mut rotating) = match phase {
+			Phase::Tilting => {
+				let delta = ((a.y - previous_a.y) + (b.y - previous_b.y)) / 2.0;
+				if delta != 0.0 {
+					gestures.push(Gesture::Tilt { delta });
+				}
+				return Phase::Tilting;
+			}
+			Phase::TwoFingers { start, pinching, rotating } => (start, pinching, rotating),
+			_ => unreachable!("Only two finger phases get here"),
+		};
+
+		let (start_a, start_b) = start;
+		let start_chord = start_b - start_a;
+		let chord = b - a;
+
+		if !pinching && !rotating && self.is_tilt(start, (a, b)) {
+			let delta = ((a.y - start_a.y) + (b.y - start_b.y)) / 2.0;
+			gestures.push(Gesture::Tilt { delta });
+			return Phase::Tilting;
+		}
+
+		// Once unlocked, each sends everything since the fingers went down, then
+		// only the changes since the previous frame
+		let previous_chord = previous_b - previous_a;
+
+		if pinching {
+			push_pinch(gestures, &previous_chord, &chord);
+		} else if (chord.norm() - start_chord.norm()).abs() > self.settings.pinch_threshold {
+			pinching = true;
+			push_pinch(gestures, &start_chord, &chord);
+		}
+
+		if rotating {
+			push_rotate(gestures, &previous_chord, &chord);
+		} else if signed_angle(&start_chord, &chord).abs() > self.settings.rotate_threshold {
+			rotating = true;
+			push_rotate(gestures, &start_chord, &chord);
+		}
+
+		Phase::TwoFingers { start, pinching, rotating }
+	}
+
+	/// Both fingers moving mostly vertically in the same direction, while
+	/// roughly side by side
+	fn is_tilt(
+		&self,
+		(start_a, start_b): (nglm::Vec2, nglm::Vec2),
+		(a, b): (nglm::Vec2, nglm::Vec2),
+	) -> bool {
+		let (moved_a, moved_b) = (a - start_a, b - start_b);
+		let side_by_side = {
+			let chord = start_b - start_a;
+			chord.x.abs() > chord.y.abs()
+		};
+		let vertical = |moved: &nglm::Vec2| {
+			moved.y.abs() > self.settings.tilt_threshold && moved.y.abs() > 2.0 * moved.x.abs()
+		};
+
+		side_by_side
+			&& vertical(&moved_a)
+			&& vertical(&moved_b)
+			&& moved_a.y.signum() == moved_b.y.signum()
+	}
+
+	fn previous_position(&self, identifier: i32) -> Option<nglm::Vec2> {
+		self.touches.iter().find(|(id, _)| *id == identifier).map(|(_, position)| *position)
+	}
+
+	fn same_touches(&self, current: &[Touch]) -> bool {
+		self.touches.len() == current.len()
+			&& self.touches.iter().zip(current).all(|((a, _), (b, _))| a == b)
+	}
+}
+
+fn push_pinch(gestures: &mut Vec<Gesture>, from: &nglm::Vec2, to: &nglm::Vec2) {
+	let (from, to) = (from.norm(), to.norm());
+	if from > 0.0 && from != to {
+		gestures.push(Gesture::Pinch { scale: to / from });
+	}
+}
+
+fn push_rotate(gestures: &mut Vec<Gesture>, from: &nglm::Vec2, to: &nglm::Vec2) {
+	let angle = signed_angle(from, to);
+	if angle != 0.0 {
+		gestures.push(Gesture::Rotate { angle });
+	}
+}
+
+/// Screen y points down, so positive angles are clockwise
+fn signed_angle(from: &nglm::Vec2, to: &nglm::Vec2) -> f32 {
+	let cross = from.x * to.y - from.y * to.x;
+	cross.atan2(from.dot(to))
+}
+
+/// Touches that have a position yet, sorted by identifier
+fn located_touches(state: &InputState) -> Vec<Touch> {
+	let mut touches: Vec<Touch> = state
+		.active_touch_identifiers()
+		.into_iter()
+		.filter_map(|identifier| {
+			let position = state.current_touch_position(identifier)?;
+			Some((identifier, nglm::vec2(position.x as f32, position.y as f32)))
+		})
+		.collect();
+
+	touches.sort_by_key(|(identifier, _)| *identifier);
+	touches
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::interaction_core::input_state::InputSource;
+	use crate::interaction_core::key_code::KeyCode;
+	use crate::interaction_core::user_inputs::{
+		LogicalTouchPosition, SwitchState, TouchState, UserInput,
+	};
+
+	const FRAME: Duration = Duration::from_millis(16);
+
+	/// Feeds touches through an `InputSource` the way the browser would
+	struct Script {
+		source: InputSource,
+		recognizer: GestureRecognizer,
+	}
+
+	impl Script {
+		fn new() -> Self {
+			Self {
+				source: InputSource::new(),
+				recognizer: GestureRecognizer::new(GestureSettings::default()),
+			}
+		}
+
+		fn input(&mut self, input: UserInput<KeyCode>) { self.source.push(input); }
+
+		fn down(&mut self, identifier: i32, x: i32, y: i32) -> &mut Self {
+			self.input(UserInput::Touch(TouchState { identifier, state: SwitchState::Pressed }));
+			self.moved(identifier, x, y)
+		}
+
+		fn moved(&mut self, identifier: i32, x: i32, y: i32) -> &mut Self {
+			let position = nglm::vec2(x, y);
+			self.input(UserInput::TouchPosition(LogicalTouchPosition { identifier, position }));
+			self
+		}
+
+		fn up(&mut self, identifier: i32) -> &mut Self {
+			self.input(UserInput::Touch(TouchState { identifier, state: SwitchState::Released }));
+			self
+		}
+
+		fn frame(&mut self) -> Vec<Gesture> { self.wait(FRAME) }
+
+		fn wait(&mut self, time: Duration) -> Vec<Gesture> {
+			let gestures = self.recognizer.frame(self.source.state(), time);
+			self.source.end_frame();
+			gestures
+		}
+	}
+
+	#[test]
+	fn drag_pans_after_the_slop() {
+		let mut script = Script::new();
+		script.down(0, 100, 100);
+		assert!(script.frame().is_empty());
+
+		script.moved(0, 105, 100);
+		assert!(script.frame().is_empty(), "Within the slop");
+
+		script.moved(0, 120, 100);
+		assert_eq!(script.frame(), vec![Gesture::Pan { delta: nglm::vec2(20.0, 0.0) }]);
+
+		script.moved(0, 120, 90);
+		assert_eq!(script.frame(), vec![Gesture::Pan { delta: nglm::vec2(0.0, -10.0) }]);
+
+		script.up(0);
+		assert!(script.frame().is_empty(), "A drag isn't a tap");
+	}
+
+	#[test]
+	fn double_tap() {
+		let mut script = Script::new();
+		script.down(0, 100, 100);
+		script.frame();
+		script.up(0);
+		assert!(script.frame().is_empty());
+
+		script.down(1, 110, 95);
+		script.wait(Duration::from_millis(100));
+		script.up(1);
+		assert_eq!(script.frame(), vec![Gesture::DoubleTap { position: nglm::vec2(110.0, 95.0) }]);
+	}
+
+	#[test]
+	fn taps_between_frames_are_not_missed() {
+		let mut script = Script::new();
+		script.down(0, 100, 100).up(0);
+		assert!(script.frame().is_empty());
+
+		script.down(1, 104, 98).up(1);
+		assert_eq!(script.frame(), vec![Gesture::DoubleTap { position: nglm::vec2(104.0, 98.0) }]);
+
+		// Both taps of a double tap can land in one slow frame
+		script.wait(Duration::from_secs(1));
+		script.down(2, 50, 50).up(2).down(3, 52, 50).up(3);
+		assert_eq!(script.frame(), vec![Gesture::DoubleTap { position: nglm::vec2(52.0, 50.0) }]);
+	}
+
+	#[test]
+	fn swipes_between_frames_are_not_taps() {
+		let mut script = Script::new();
+		script.down(0, 100, 100).moved(0, 200, 100).up(0);
+		script.down(1, 100, 100).moved(1, 200, 100).up(1);
+		assert!(script.frame().is_empty());
+	}
+
+	#[test]
+	fn slow_taps_are_not_a_double_tap() {
+		let mut script = Script::new();
+		script.down(0, 100, 100);
+		script.frame();
+		script.up(0);
+		script.frame();
+
+		script.wait(Duration::from_millis(500));
+		script.down(1, 100, 100);
+		script.frame();
+		script.up(1);
+		assert!(script.frame().is_empty());
+	}
+
+	#[test]
+	fn long_press_fires_once() {
+		let mut script = Script::new();
+		script.down(0, 50, 60);
+		assert!(script.frame().is_empty());
+		assert!(script.wait(Duration::from_millis(300)).is_empty());
+
+		assert_eq!(
+			script.wait(Duration::from_millis(300)),
+			vec![Gesture::LongPress { position: nglm::vec2(50.0, 60.0) }]
+		);
+		assert!(script.wait(Duration::from_millis(600)).is_empty());
+
+		script.up(0);
+		assert!(script.frame().is_empty(), "A long press isn't a tap");
+	}
+
+	#[test]
+	fn pinch() {
+		let mut script = Script::new();
+		script.down(0, 100, 100).down(1, 200, 100);
+		assert!(script.frame().is_empty());
+
+		script.moved(0, 95, 100).moved(1, 205, 100);
+		assert!(script.frame().is_empty(), "Within the threshold");
+
+		script.moved(0, 50, 100).moved(1, 250, 100);
+		assert_eq!(script.frame(), vec![Gesture::Pinch { scale: 2.0 }]);
+
+		script.moved(0, 100, 100).moved(1, 200, 100);
+		assert_eq!(script.frame(), vec![Gesture::Pinch { scale: 0.5 }]);
+	}
+
+	#[test]
+	fn rotate() {
+		let mut script = Script::new();
+		script.down(0, 100, 100).down(1, 200, 100);
+		script.frame();
+
+		// A quarter turn clockwise on screen, about the midpoint
+		script.moved(0, 150, 50).moved(1, 150, 150);
+		let gestures = script.frame();
+
+		assert_eq!(gestures.len(), 1);
+		match gestures[0] {
+			Gesture::Rotate { angle } => {
+				assert!((angle - std::f32::consts::FRAC_PI_2).abs() < 1.0e-5)
+			}
+			ref other => panic!("Expected a rotation, got {other:?}"),
+		}
+	}
+
+	#[test]
+	fn tilt() {
+		let mut script = Script::new();
+		script.down(0, 100, 100).down(1, 200, 100);
+		script.frame();
+
+		script.moved(0, 100, 120).moved(1, 200, 124);
+		assert_eq!(script.frame(), vec![Gesture::Tilt { delta: 22.0 }]);
+
+		// Spreading afterwards doesn't turn into a pinch
+		script.moved(0, 50, 110).moved(1, 250, 110);
+		assert_eq!(script.frame(), vec![Gesture::Tilt { delta: -12.0 }]);
+	}
+
+	#[test]
+	fn three_fingers_cancel() {
+		let mut script = Script::new();
+		script.down(0, 100, 100).down(1, 200, 100).down(2, 300, 100);
+		script.frame();
+
+		script.up(2);
+		script.moved(0, 0, 100).moved(1, 300, 100);
+		assert!(script.frame().is_empty());
+
+		script.up(0).up(1);
+		assert!(script.frame().is_empty());
+	}
+
+	#[test]
+	fn lifting_a_finger_pans_with_the_other() {
+		let mut script = Script::new();
+		script.down(0, 100, 100).down(1, 200, 100);
+		script.frame();
+
+		script.up(1);
+		assert!(script.frame().is_empty());
+
+		script.moved(0, 100, 130);
+		assert_eq!(script.frame(), vec![Gesture::Pan { delta: nglm::vec2(0.0, 30.0) }]);
+	}
+}
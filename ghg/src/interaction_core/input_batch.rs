@@ -1,21 +1,69 @@
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::rc::Rc;
+use std::time::Duration;
 
 use wasm_bindgen::JsCast;
-use web_sys::{HtmlCanvasElement, KeyboardEvent, MouseEvent, TouchEvent, WheelEvent};
+use web_sys::{HtmlCanvasElement, KeyboardEvent, MouseEvent, PointerEvent, TouchEvent, WheelEvent};
 
+use crate::interaction_core::input_state::{
+	FrameDifferences, InputSource, InputState, KeyState, MouseMovement, TouchMovement,
+};
+use crate::interaction_core::key_code::KeyCode;
+use crate::interaction_core::recording::InputRecording;
 use crate::interaction_core::user_inputs::{
-	KeyCodeState, LogicalCursorPosition, LogicalTouchPosition, MouseButton, MouseButtonState,
-	Scroll, SwitchState, TouchState, UserInput,
+	LogicalCursorPosition, LogicalTouchPosition, MouseButton, MouseButtonState, Scroll,
+	SwitchState, TouchState, UserInput,
 };
 use crate::utils::prelude::*;
 
-pub type KeyState = KeyCodeState<KeyCode>;
+thread_local! {
+	static ACTIVE_INPUT_SOURCE: RefCell<Option<Rc<RefCell<InputSource>>>> = const { RefCell::new(None) };
+}
+
+/// Starts recording every input, replacing any recording in progress
+#[wasm_bindgen]
+pub fn start_input_recording() { with_active_input_source(|source| source.start_recording()); }
+
+/// Stops recording, and returns the recording as JSON, if there was one
+#[wasm_bindgen]
+pub fn stop_input_recording() -> Option<String> {
+	with_active_input_source(|source| source.stop_recording())
+		.flatten()
+		.map(|recording| recording.to_json())
+}
+
+/// Replays a recording from `stop_input_recording`, in place of live input,
+/// until it runs out
+#[wasm_bindgen]
+pub fn replay_input_recording(json: &str) -> Result<(), JsValue> {
+	let recording = InputRecording::from_json(json).map_err(|e| JsValue::from(e.to_string()))?;
+	with_active_input_source(|source| source.replay(recording))
+		.ok_or_else(|| JsValue::from("There's no input to replay into yet"))
+}
+
+/// Starts a frame of input on the page, with the time since the last one.
+/// Returns the time the frame should use instead, which differs while
+/// replaying, so that everything that animates keeps pace with the recording.
+pub fn begin_input_frame(delta_time: Duration) -> Duration {
+	with_active_input_source(|source| source.begin_frame(delta_time)).unwrap_or(delta_time)
+}
+
+fn with_active_input_source<T>(f: impl FnOnce(&mut InputSource) -> T) -> Option<T> {
+	ACTIVE_INPUT_SOURCE
+		.with(|active| active.borrow().as_ref().map(|source| f(&mut source.borrow_mut())))
+}
 
 // The handlers in this struct are just for lifetime, not really used after
 // register
+#[allow(dead_code)]
+struct DomHandlers {
+	pointer_handlers: PointerHandlers,
+	mouse_scroll_handler: MouseScrollHandler,
+	keyboard_handlers: KeyboardEventHandlers,
+}
+
 /// Subscribes to input events from the front end, and provides batches of
 /// events which have occurred since the last time it was cleared. Generally,
 /// this is used for batching inputs between frames, then handling them all at
@@ -23,46 +71,54 @@ pub type KeyState = KeyCodeState<KeyCode>;
 /// the batch on destruction.
 #[allow(dead_code)]
 pub struct InputBatcher {
-	current_state: Rc<RefCell<InputState>>,
+	input_source: Rc<RefCell<InputSource>>,
 	previous_state: InputState,
 
-	mouse_move_handler: MouseEventHandler,
-	mouse_button_handlers: MouseButtonHandlers,
-	mouse_scroll_handler: MouseScrollHandler,
-	keyboard_handlers: KeyboardEventHandlers,
-	touch_position_handler: TouchPositionHandler,
-	touch_state_handlers: TouchStateHandlers,
+	/// None when inputs only come from a recording
+	dom_handlers: Option<DomHandlers>,
 }
 
 impl InputBatcher {
 	pub fn new(canvas: HtmlCanvasElement) -> Self {
-		let current_state = Rc::new(RefCell::new(InputState::new()));
-		let mouse_move_handler = add_mouse_move_handler(&canvas, current_state.clone());
-		let mouse_button_handlers = add_mouse_button_handlers(&canvas, &current_state);
-		let mouse_scroll_handler = add_mouse_scroll_handler(&canvas, current_state.clone());
-		let keyboard_handlers = add_keyboard_handlers(&canvas, &current_state);
-		let touch_position_handler = add_touch_position_handler(&canvas, current_state.clone());
-		let touch_state_handlers = add_touch_state_handlers(&canvas, &current_state);
+		let input_source = Rc::new(RefCell::new(InputSource::new()));
+		let dom_handlers = DomHandlers {
+			pointer_handlers: add_pointer_handlers(&canvas, &input_source),
+			mouse_scroll_handler: add_mouse_scroll_handler(&canvas, input_source.clone()),
+			keyboard_handlers: add_keyboard_handlers(&canvas, &input_source),
+		};
+		ACTIVE_INPUT_SOURCE.with(|active| active.replace(Some(input_source.clone())));
+
+		Self { input_source, previous_state: InputState::new(), dom_handlers: Some(dom_handlers) }
+	}
+
+	/// Takes inputs only from `recording`, without needing a page
+	#[allow(dead_code)]
+	pub fn from_recording(recording: InputRecording) -> Self {
+		let mut input_source = InputSource::new();
+		input_source.replay(recording);
 
 		Self {
-			current_state,
+			input_source: Rc::new(RefCell::new(input_source)),
 			previous_state: InputState::new(),
-			mouse_move_handler,
-			mouse_button_handlers,
-			mouse_scroll_handler,
-			keyboard_handlers,
-			touch_position_handler,
-			touch_state_handlers,
+			dom_handlers: None,
 		}
 	}
 
-	pub fn get_current_state(&self) -> InputState { self.current_state.borrow().deref().clone() }
+	pub fn get_current_state(&self) -> InputState { self.input_source.borrow().state().clone() }
+
+	/// Call at the start of every frame, with the time since the last one.
+	/// Returns the time the frame should use instead, which differs while
+	/// replaying. The page's batcher is started by `begin_input_frame` instead.
+	#[allow(dead_code)]
+	pub fn begin_frame(&mut self, delta_time: Duration) -> Duration {
+		self.input_source.deref().borrow_mut().begin_frame(delta_time)
+	}
 
 	// Not for direct use. Use the FrameInputInterpreter to do this at the end
 	// of the frame.
 	fn store_last(&mut self) {
-		self.previous_state = self.current_state.borrow().clone();
-		self.current_state.deref().borrow_mut().unhandled_changes.clear();
+		self.previous_state = self.get_current_state();
+		self.input_source.deref().borrow_mut().end_frame();
 	}
 }
 
@@ -75,27 +131,31 @@ impl<'a> BatchedInputHandler<'a> {
 
 	// TODO: These should use a clearer diff algorithm with the previous frame.
 	pub fn get_mouse_movement(&self) -> Option<MouseMovement> {
-		self.input_batcher.current_state.borrow().unhandled_changes.mouse_movement
+		self.changes(|changes| changes.mouse_movement)
 	}
 
 	pub fn get_keyboard_changes(&self) -> Vec<KeyState> {
-		self.input_batcher.current_state.borrow().unhandled_changes.keyboard_changes.clone()
+		self.changes(|changes| changes.keyboard_changes.clone())
 	}
 
 	pub fn get_mouse_button_changes(&self) -> Vec<MouseButtonState> {
-		self.input_batcher.current_state.borrow().unhandled_changes.mouse_button_changes.clone()
+		self.changes(|changes| changes.mouse_button_changes.clone())
 	}
 
 	pub fn get_scroll_changes(&self) -> Option<Scroll> {
-		self.input_batcher.current_state.borrow().unhandled_changes.scroll_changes.clone()
+		self.changes(|changes| changes.scroll_changes.clone())
 	}
 
 	pub fn get_touch_state_changes(&self) -> HashMap<i32, TouchState> {
-		self.input_batcher.current_state.borrow().unhandled_changes.touch_state_changes.clone()
+		self.changes(|changes| changes.touch_state_changes.clone())
 	}
 
 	pub fn get_touch_movement(&self) -> HashMap<i32, TouchMovement> {
-		self.input_batcher.current_state.borrow().unhandled_changes.touch_movement.clone()
+		self.changes(|changes| changes.touch_movement.clone())
+	}
+
+	fn changes<T>(&self, f: impl FnOnce(&FrameDifferences) -> T) -> T {
+		f(self.input_batcher.input_source.borrow().state().unhandled_changes())
 	}
 }
 
@@ -103,64 +163,34 @@ impl Drop for BatchedInputHandler<'_> {
 	fn drop(&mut self) { self.input_batcher.store_last(); }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub enum ActiveInput {
-	Keyboard(KeyCode),
-	MouseButton(MouseButton),
-	Touch(i32),
-}
-
-#[derive(Clone, Debug)]
-pub struct InputState {
-	current_set: HashSet<ActiveInput>,
-	mouse_position: Option<LogicalCursorPosition>,
-	touch_position: HashMap<i32, LogicalCursorPosition>,
-	unhandled_changes: FrameDifferences,
-}
-
-impl InputState {
-	fn new() -> Self {
-		Self {
-			current_set: Default::default(),
-			mouse_position: None,
-			touch_position: Default::default(),
-			unhandled_changes: FrameDifferences::default(),
-		}
-	}
-
-	pub fn is_key_active(&self, key: KeyCode) -> bool {
-		self.current_set.contains(&ActiveInput::Keyboard(key))
-	}
-
-	pub fn is_mouse_button_active(&self, button: MouseButton) -> bool {
-		self.current_set.contains(&ActiveInput::MouseButton(button))
-	}
-
-	#[allow(dead_code)]
-	pub fn current_mouse_location(&self) -> Option<LogicalCursorPosition> { self.mouse_position }
-
-	pub fn active_touch_identifiers(&self) -> Vec<i32> {
-		self.current_set.iter().filter_map(|input| {
-			match input {
-				ActiveInput::Touch(i) => Some(*i),
-				_ => None,
-			}
-		}).collect()
-	}
-
-	pub fn current_touch_position(&self, identifier: i32) -> Option<LogicalCursorPosition> {
-		self.touch_position.get(&identifier).cloned()
-	}
-
-}
-
-type KeyCode = String;
-
 type MouseEventHandler = Closure<dyn FnMut(MouseEvent)>;
 type MouseButtonHandler = Closure<dyn FnMut(MouseEvent)>;
 type MouseScrollHandler = Closure<dyn FnMut(WheelEvent)>;
 type KeyboardEventHandler = Closure<dyn FnMut(KeyboardEvent)>;
 type TouchEventHandler = Closure<dyn FnMut(TouchEvent)>;
+type PointerEventHandler = Closure<dyn FnMut(PointerEvent)>;
+
+/// Where mouse and touch input comes from. Pointer Events cover mouse, touch
+/// and pen in one model, and with pointer capture they keep arriving when a
+/// drag leaves the canvas, so they're used wherever the browser has them.
+#[allow(dead_code)]
+enum PointerHandlers {
+	PointerEvents(PointerEventHandlers),
+	MouseAndTouch {
+		mouse_move: MouseEventHandler,
+		mouse_buttons: MouseButtonHandlers,
+		touch_position: TouchPositionHandler,
+		touch_state: TouchStateHandlers,
+	},
+}
+
+#[allow(dead_code)]
+struct PointerEventHandlers {
+	down: PointerEventHandler,
+	moved: PointerEventHandler,
+	up: PointerEventHandler,
+	cancel: PointerEventHandler,
+}
 
 #[allow(dead_code)]
 struct MouseButtonHandlers {
@@ -182,157 +212,173 @@ struct TouchStateHandlers {
 	touch_cancel: TouchEventHandler,
 }
 
-trait IncorporateState
-where
-	Self: Clone,
-{
-	fn incorporate(&self, new_input: UserInput<KeyCode>) -> Self;
+fn add_pointer_handlers(
+	canvas: &HtmlCanvasElement,
+	input_source: &Rc<RefCell<InputSource>>,
+) -> PointerHandlers {
+	let has_pointer_events =
+		js_sys::Reflect::has(&js_sys::global(), &JsValue::from_str("PointerEvent"))
+			.unwrap_or(false);
+
+	if has_pointer_events {
+		PointerHandlers::PointerEvents(add_pointer_event_handlers(canvas, input_source))
+	} else {
+		PointerHandlers::MouseAndTouch {
+			mouse_move: add_mouse_move_handler(canvas, input_source.clone()),
+			mouse_buttons: add_mouse_button_handlers(canvas, input_source),
+			touch_position: add_touch_position_handler(canvas, input_source.clone()),
+			touch_state: add_touch_state_handlers(canvas, input_source),
+		}
+	}
 }
 
-pub type MouseMovement = LogicalCursorPosition;
+fn add_pointer_event_handlers(
+	canvas: &HtmlCanvasElement,
+	input_source: &Rc<RefCell<InputSource>>,
+) -> PointerEventHandlers {
+	let down_source = input_source.clone();
+	let capture_canvas = canvas.clone();
+	let down = Closure::wrap(Box::new(move |e: PointerEvent| {
+		// Keeps moves and the release coming after the pointer leaves the canvas
+		if let Err(error) = capture_canvas.set_pointer_capture(e.pointer_id()) {
+			ghg_log!("Failed to capture pointer {}: {:?}", e.pointer_id(), error);
+		}
+		incorporate_pointer_event(&down_source, &e, PointerChange::Down);
+	}) as Box<dyn FnMut(PointerEvent)>);
+	canvas.set_onpointerdown(Some(down.as_ref().unchecked_ref()));
+
+	let moved = make_pointer_handler(input_source.clone(), PointerChange::Move);
+	canvas.set_onpointermove(Some(moved.as_ref().unchecked_ref()));
+
+	let up = make_pointer_handler(input_source.clone(), PointerChange::Up);
+	canvas.set_onpointerup(Some(up.as_ref().unchecked_ref()));
 
-#[derive(Clone, Debug, Default)]
-pub struct TouchMovement {
-	pub identifier: i32,
-	pub difference: LogicalCursorPosition,
+	let cancel = make_pointer_handler(input_source.clone(), PointerChange::Up);
+	canvas.set_onpointercancel(Some(cancel.as_ref().unchecked_ref()));
+
+	PointerEventHandlers { down, moved, up, cancel }
 }
 
-#[derive(Clone, Debug, Default)]
-struct FrameDifferences {
-	keyboard_changes: Vec<KeyState>,
-	mouse_button_changes: Vec<MouseButtonState>,
-	mouse_movement: Option<MouseMovement>,
-	scroll_changes: Option<Scroll>,
-	touch_movement: HashMap<i32, TouchMovement>,
-	touch_state_changes: HashMap<i32, TouchState>,
+fn make_pointer_handler(
+	input_source: Rc<RefCell<InputSource>>,
+	change: PointerChange,
+) -> PointerEventHandler {
+	Closure::wrap(Box::new(move |e: PointerEvent| {
+		incorporate_pointer_event(&input_source, &e, change);
+	}) as Box<dyn FnMut(PointerEvent)>)
 }
 
-impl FrameDifferences {
-	fn clear(&mut self) {
-		self.keyboard_changes.clear();
-		self.mouse_button_changes.clear();
-		self.mouse_movement = None;
-		self.scroll_changes = None;
-		self.touch_movement.clear();
-		self.touch_state_changes.clear();
+fn incorporate_pointer_event(
+	input_source: &Rc<RefCell<InputSource>>,
+	e: &PointerEvent,
+	change: PointerChange,
+) {
+	let pointer = PointerSample {
+		kind: e.pointer_type(),
+		identifier: e.pointer_id(),
+		button: e.button(),
+		buttons: e.buttons(),
+		position: nglm::vec2(e.screen_x(), e.screen_y()),
+	};
+
+	let inputs = pointer_inputs(&pointer, change, input_source.borrow().state());
+	for new_state in inputs {
+		input_source.borrow_mut().push(new_state);
 	}
 }
 
-impl IncorporateState for InputState {
-	fn incorporate(&self, new_input: UserInput<KeyCode>) -> Self {
-		let mut new_state = self.clone();
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PointerChange {
+	Down,
+	Move,
+	/// Released or cancelled
+	Up,
+}
 
-		match new_input {
-			UserInput::Keyboard(KeyState { key, state: SwitchState::Pressed }) => {
-				let is_new = new_state.current_set.insert(ActiveInput::Keyboard(key.clone()));
-				if is_new {
-					new_state
-						.unhandled_changes
-						.keyboard_changes
-						.push(KeyState { key, state: SwitchState::Pressed });
-				}
-			}
-			UserInput::Keyboard(KeyState { key, state: SwitchState::Released }) => {
-				let was_removed = new_state.current_set.remove(&ActiveInput::Keyboard(key.clone()));
-				if was_removed {
-					new_state
-						.unhandled_changes
-						.keyboard_changes
-						.push(KeyState { key, state: SwitchState::Released });
-				}
-			}
-			UserInput::MouseButton(MouseButtonState { button, state: SwitchState::Pressed }) => {
-				let is_new = new_state.current_set.insert(ActiveInput::MouseButton(button.clone()));
-				if is_new {
-					new_state
-						.unhandled_changes
-						.mouse_button_changes
-						.push(MouseButtonState { button, state: SwitchState::Pressed });
-				}
-			}
-			UserInput::MouseButton(MouseButtonState { button, state: SwitchState::Released }) => {
-				let was_removed =
-					new_state.current_set.remove(&ActiveInput::MouseButton(button.clone()));
-				if was_removed {
-					new_state
-						.unhandled_changes
-						.mouse_button_changes
-						.push(MouseButtonState { button, state: SwitchState::Released });
-				}
-			}
-			UserInput::CursorPosition(new_position) => {
-				let previous_position = new_state.mouse_position;
+/// The parts of a `PointerEvent` that matter here
+struct PointerSample {
+	kind: String,
+	identifier: i32,
+	/// The button that changed, or -1
+	button: i16,
+	/// Bit mask of every button held
+	buttons: u16,
+	position: LogicalCursorPosition,
+}
 
-				new_state.mouse_position = Some(new_position.clone());
+/// Turns a pointer event into the same inputs the mouse and touch events
+/// give, given what's held in `state`. Touch and pen both become touches, and
+/// only while in contact, so hovering a pen does nothing.
+fn pointer_inputs(
+	pointer: &PointerSample,
+	change: PointerChange,
+	state: &InputState,
+) -> Vec<UserInput<KeyCode>> {
+	if pointer.kind == "mouse" {
+		let mut inputs = vec![UserInput::CursorPosition(pointer.position)];
+
+		// A button pressed while another is held only shows up as a move, so
+		// go by the button mask rather than the event type
+		if pointer.button >= 0 {
+			let is_held = pointer.buttons & button_mask(pointer.button) != 0;
+			inputs.push(UserInput::MouseButton(MouseButtonState {
+				button: mouse_button(pointer.button),
+				state: if is_held { SwitchState::Pressed } else { SwitchState::Released },
+			}));
+		} else if change == PointerChange::Up {
+			// A cancelled pointer doesn't say which button it had, so let go of
+			// all of them
+			inputs.extend(state.active_mouse_buttons().into_iter().map(|button| {
+				UserInput::MouseButton(MouseButtonState { button, state: SwitchState::Released })
+			}));
+		}
 
-				if previous_position.is_some() {
-					new_state.unhandled_changes.mouse_movement =
-						Some(new_position - previous_position.unwrap());
-				}
-			}
-			UserInput::TouchPosition(LogicalTouchPosition { identifier, position }) => {
-				let previous_position = new_state.touch_position.get(&identifier).cloned();
-
-				new_state.touch_position.insert(identifier, position.clone());
-
-				if previous_position.is_some() {
-					let difference = position - previous_position.unwrap();
-					new_state
-						.unhandled_changes
-						.touch_movement
-						.insert(identifier, TouchMovement { identifier, difference });
-				}
-			}
-			UserInput::Scroll(scroll) => {
-				new_state.unhandled_changes.scroll_changes = Some(scroll);
-			}
-			UserInput::Touch(TouchState { identifier, state: SwitchState::Pressed }) => {
-				let is_new = new_state.current_set.insert(ActiveInput::Touch(identifier));
-				if is_new {
-					new_state
-						.unhandled_changes
-						.touch_state_changes
-						.insert(identifier, TouchState { identifier, state: SwitchState::Pressed });
-				}
-			}
-			UserInput::Touch(TouchState { identifier, state: SwitchState::Released }) => {
-				let was_removed = new_state.current_set.remove(&ActiveInput::Touch(identifier));
-				if was_removed {
-					new_state.unhandled_changes.touch_state_changes.insert(
-						identifier,
-						TouchState { identifier, state: SwitchState::Released },
-					);
-
-					// Don't store the old position, since this ID is no longer touching
-					if let None = new_state.touch_position.remove(&identifier) {
-						ghg_log!("Error removing touch position {identifier} from state!");
-					}
-
-				}
-			}
-			// UserInput::FocusChange(is_focused) => {
-			//     ghg_log!("Focus changed: is_focused = {}", is_focused);
-			//     new_state.mouse_position = None;
-			// }
-			#[allow(unreachable_patterns)]
-			unhandled => {
-				ghg_log!("Unhandled input: {:?}", unhandled);
-			}
+		return inputs;
+	}
+
+	let identifier = pointer.identifier;
+	let position =
+		UserInput::TouchPosition(LogicalTouchPosition { identifier, position: pointer.position });
+
+	match change {
+		PointerChange::Down => {
+			vec![UserInput::Touch(TouchState { identifier, state: SwitchState::Pressed }), position]
+		}
+		PointerChange::Move if pointer.buttons != 0 => vec![position],
+		PointerChange::Move => vec![],
+		PointerChange::Up => {
+			vec![UserInput::Touch(TouchState { identifier, state: SwitchState::Released })]
 		}
+	}
+}
+
+fn mouse_button(index: i16) -> MouseButton {
+	match index {
+		0 => MouseButton::Left,
+		2 => MouseButton::Right,
+		1 => MouseButton::Middle,
+		x => MouseButton::Extra(x as u16),
+	}
+}
 
-		new_state
+/// `MouseEvent.buttons` doesn't use the same order as `MouseEvent.button`
+fn button_mask(index: i16) -> u16 {
+	match index {
+		1 => 4,
+		2 => 2,
+		x => 1 << x,
 	}
 }
 
 fn add_mouse_move_handler(
 	canvas: &HtmlCanvasElement,
-	current_state: Rc<RefCell<InputState>>,
+	input_source: Rc<RefCell<InputSource>>,
 ) -> MouseEventHandler {
 	let mouse_move_event_handler = Closure::wrap(Box::new(move |e: MouseEvent| {
 		let new_state =
 			UserInput::<KeyCode>::CursorPosition(nglm::vec2(e.screen_x(), e.screen_y()));
 
-		current_state.replace_with(move |previous| previous.incorporate(new_state));
+		input_source.borrow_mut().push(new_state);
 	}) as Box<dyn FnMut(MouseEvent)>);
 
 	canvas.set_onmousemove(Some(mouse_move_event_handler.as_ref().unchecked_ref()));
@@ -341,43 +387,36 @@ fn add_mouse_move_handler(
 
 fn add_mouse_button_handlers(
 	canvas: &HtmlCanvasElement,
-	current_state: &Rc<RefCell<InputState>>,
+	input_source: &Rc<RefCell<InputSource>>,
 ) -> MouseButtonHandlers {
 	let mouse_down_event_handler =
-		make_mouse_button_handler(current_state.clone(), SwitchState::Pressed);
+		make_mouse_button_handler(input_source.clone(), SwitchState::Pressed);
 	canvas.set_onmousedown(Some(mouse_down_event_handler.as_ref().unchecked_ref()));
 
 	let mouse_up_event_handler =
-		make_mouse_button_handler(current_state.clone(), SwitchState::Released);
+		make_mouse_button_handler(input_source.clone(), SwitchState::Released);
 	canvas.set_onmouseup(Some(mouse_up_event_handler.as_ref().unchecked_ref()));
 
 	MouseButtonHandlers { down: mouse_down_event_handler, up: mouse_up_event_handler }
 }
 
 fn make_mouse_button_handler(
-	current_state: Rc<RefCell<InputState>>,
+	input_source: Rc<RefCell<InputSource>>,
 	switch_state: SwitchState,
 ) -> MouseButtonHandler {
 	Closure::wrap(Box::new(move |e: MouseEvent| {
-		let button_index = e.button();
-
-		let button = match button_index {
-			0 => MouseButton::Left,
-			2 => MouseButton::Right,
-			1 => MouseButton::Middle,
-			x => MouseButton::Extra(x as u16),
-		};
+		let button = mouse_button(e.button());
 
 		let new_state =
 			UserInput::<KeyCode>::MouseButton(MouseButtonState { button, state: switch_state });
 
-		current_state.replace_with(move |previous| previous.incorporate(new_state));
+		input_source.borrow_mut().push(new_state);
 	}) as Box<dyn FnMut(MouseEvent)>)
 }
 
 fn add_mouse_scroll_handler(
 	canvas: &HtmlCanvasElement,
-	current_state: Rc<RefCell<InputState>>,
+	input_source: Rc<RefCell<InputSource>>,
 ) -> MouseScrollHandler {
 	let mouse_scroll_handler = Closure::wrap(Box::new(move |e: WheelEvent| {
 		let new_state = UserInput::<KeyCode>::Scroll(Scroll::new(
@@ -385,7 +424,7 @@ fn add_mouse_scroll_handler(
 			e.delta_y() as f32,
 			e.delta_z() as f32,
 		));
-		current_state.replace_with(move |previous| previous.incorporate(new_state));
+		input_source.borrow_mut().push(new_state);
 	}) as Box<dyn FnMut(WheelEvent)>);
 
 	canvas.set_onwheel(Some(mouse_scroll_handler.as_ref().unchecked_ref()));
@@ -394,34 +433,36 @@ fn add_mouse_scroll_handler(
 
 fn add_keyboard_handlers(
 	canvas: &HtmlCanvasElement,
-	current_state: &Rc<RefCell<InputState>>,
+	input_source: &Rc<RefCell<InputSource>>,
 ) -> KeyboardEventHandlers {
-	let key_down_event_handler = make_key_handler(current_state.clone(), SwitchState::Pressed);
+	let key_down_event_handler = make_key_handler(input_source.clone(), SwitchState::Pressed);
 	canvas.set_onkeydown(Some(key_down_event_handler.as_ref().unchecked_ref()));
 
-	let key_up_event_handler = make_key_handler(current_state.clone(), SwitchState::Released);
+	let key_up_event_handler = make_key_handler(input_source.clone(), SwitchState::Released);
 	canvas.set_onkeyup(Some(key_up_event_handler.as_ref().unchecked_ref()));
 
 	KeyboardEventHandlers { down: key_down_event_handler, up: key_up_event_handler }
 }
 
 fn make_key_handler(
-	current_state: Rc<RefCell<InputState>>,
+	input_source: Rc<RefCell<InputSource>>,
 	switch_state: SwitchState,
 ) -> KeyboardEventHandler {
 	Closure::wrap(Box::new(move |e: KeyboardEvent| {
 		if !e.repeat() {
-			let new_state =
-				UserInput::<KeyCode>::Keyboard(KeyState { key: e.code(), state: switch_state });
+			let new_state = UserInput::<KeyCode>::Keyboard(KeyState {
+				key: KeyCode::from_code(&e.code()),
+				state: switch_state,
+			});
 
-			current_state.replace_with(move |previous| previous.incorporate(new_state));
+			input_source.borrow_mut().push(new_state);
 		}
 	}) as Box<dyn FnMut(KeyboardEvent)>)
 }
 
 fn add_touch_position_handler(
 	canvas: &HtmlCanvasElement,
-	current_state: Rc<RefCell<InputState>>,
+	input_source: Rc<RefCell<InputSource>>,
 ) -> TouchPositionHandler {
 	let handler = Closure::wrap(Box::new(move |e: TouchEvent| {
 		e.prevent_default();
@@ -434,7 +475,7 @@ fn add_touch_position_handler(
 				identifier: touch.identifier(),
 				position,
 			});
-			current_state.replace_with(move |previous| previous.incorporate(new_state));
+			input_source.borrow_mut().push(new_state);
 		}
 	}) as Box<dyn FnMut(TouchEvent)>);
 
@@ -445,12 +486,12 @@ fn add_touch_position_handler(
 
 fn add_touch_state_handlers(
 	canvas: &HtmlCanvasElement,
-	current_state: &Rc<RefCell<InputState>>,
+	input_source: &Rc<RefCell<InputSource>>,
 ) -> TouchStateHandlers {
-	let touch_start_handler = make_touch_state_handler(current_state.clone(), SwitchState::Pressed);
-	let touch_end_handler = make_touch_state_handler(current_state.clone(), SwitchState::Released);
+	let touch_start_handler = make_touch_state_handler(input_source.clone(), SwitchState::Pressed);
+	let touch_end_handler = make_touch_state_handler(input_source.clone(), SwitchState::Released);
 	let touch_cancel_handler =
-		make_touch_state_handler(current_state.clone(), SwitchState::Released);
+		make_touch_state_handler(input_source.clone(), SwitchState::Released);
 
 	canvas.set_ontouchstart(Some(touch_start_handler.as_ref().unchecked_ref()));
 	canvas.set_ontouchend(Some(touch_end_handler.as_ref().unchecked_ref()));
@@ -464,7 +505,7 @@ fn add_touch_state_handlers(
 }
 
 fn make_touch_state_handler(
-	current_state: Rc<RefCell<InputState>>,
+	input_source: Rc<RefCell<InputSource>>,
 	switch_state: SwitchState,
 ) -> TouchEventHandler {
 	Closure::wrap(Box::new(move |e: TouchEvent| {
@@ -477,7 +518,16 @@ fn make_touch_state_handler(
 				identifier: touch.identifier(),
 				state: switch_state,
 			});
-			current_state.replace_with(move |previous| previous.incorporate(new_state));
+			input_source.borrow_mut().push(new_state);
+
+			// Otherwise a finger has no position until it moves, so taps have none
+			if switch_state == SwitchState::Pressed {
+				let new_state = UserInput::<KeyCode>::TouchPosition(LogicalTouchPosition {
+					identifier: touch.identifier(),
+					position: nglm::vec2(touch.screen_x(), touch.screen_y()),
+				});
+				input_source.borrow_mut().push(new_state);
+			}
 		}
 	}) as Box<dyn FnMut(TouchEvent)>)
 }
@@ -485,6 +535,77 @@ fn make_touch_state_handler(
 // For debug only
 impl Drop for InputBatcher {
 	fn drop(&mut self) {
-		ghg_log!("Uh oh! InputHandler was dropped!");
+		if self.dom_handlers.is_some() {
+			ghg_log!("Uh oh! InputHandler was dropped!");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::interaction_core::input_state::IncorporateState;
+
+	fn sample(kind: &str, button: i16, buttons: u16) -> PointerSample {
+		PointerSample {
+			kind: kind.to_owned(),
+			identifier: 7,
+			button,
+			buttons,
+			position: nglm::vec2(10, 20),
+		}
+	}
+
+	/// Applies the inputs `pointer_inputs` gives for a pointer event to `state`
+	fn pointer(state: InputState, pointer: PointerSample, change: PointerChange) -> InputState {
+		let inputs = pointer_inputs(&pointer, change, &state);
+		inputs.into_iter().fold(state, |state, input| state.incorporate(input))
+	}
+
+	#[test]
+	fn mouse_pointer_buttons() {
+		let state = pointer(InputState::new(), sample("mouse", 0, 1), PointerChange::Down);
+		assert!(state.is_mouse_button_active(MouseButton::Left));
+		assert_eq!(state.current_mouse_location(), Some(nglm::vec2(10, 20)));
+
+		// Right button pressed mid-drag arrives as a move
+		let state = pointer(state, sample("mouse", 2, 3), PointerChange::Move);
+		assert!(state.is_mouse_button_active(MouseButton::Right));
+
+		let state = pointer(state, sample("mouse", 0, 2), PointerChange::Up);
+		assert!(!state.is_mouse_button_active(MouseButton::Left));
+		assert!(state.is_mouse_button_active(MouseButton::Right));
+	}
+
+	#[test]
+	fn cancelled_mouse_pointer_releases_its_buttons() {
+		let state = pointer(InputState::new(), sample("mouse", 0, 1), PointerChange::Down);
+		let state = pointer(state, sample("mouse", 2, 3), PointerChange::Move);
+
+		// pointercancel has no button, and nothing held any more
+		let state = pointer(state, sample("mouse", -1, 0), PointerChange::Up);
+		assert!(state.active_mouse_buttons().is_empty());
+	}
+
+	#[test]
+	fn touch_and_pen_pointers_are_touches() {
+		for kind in ["touch", "pen"] {
+			let state = pointer(InputState::new(), sample(kind, 0, 1), PointerChange::Down);
+			assert_eq!(state.active_touch_identifiers(), vec![7]);
+			assert_eq!(state.current_touch_position(7), Some(nglm::vec2(10, 20)));
+
+			let state = pointer(state, sample(kind, -1, 1), PointerChange::Move);
+			assert_eq!(state.active_touch_identifiers(), vec![7]);
+
+			let state = pointer(state, sample(kind, 0, 0), PointerChange::Up);
+			assert!(state.active_touch_identifiers().is_empty());
+			assert_eq!(state.current_touch_position(7), None);
+		}
+	}
+
+	#[test]
+	fn hovering_pen_is_ignored() {
+		assert!(pointer_inputs(&sample("pen", -1, 0), PointerChange::Move, &InputState::new())
+			.is_empty());
 	}
 }
@@ -0,0 +1,465 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::interaction_core::key_code::KeyCode;
+use crate::interaction_core::recording::{InputRecorder, InputRecording, InputReplay};
+use crate::interaction_core::user_inputs::{
+	KeyCodeState, LogicalCursorPosition, LogicalTouchPosition, MouseButton, MouseButtonState,
+	Scroll, SwitchState, TouchState, UserInput,
+};
+
+pub type KeyState = KeyCodeState<KeyCode>;
+
+/// Where inputs go before a frame handles them. Inputs can be recorded on
+/// the way in, and while a recording replays, live inputs are dropped so that
+/// they can't disturb it.
+pub(crate) struct InputSource {
+	state: InputState,
+	recorder: Option<InputRecorder>,
+	replay: Option<InputReplay>,
+}
+
+impl InputSource {
+	pub(crate) fn new() -> Self { Self { state: InputState::new(), recorder: None, replay: None } }
+
+	pub(crate) fn push(&mut self, input: UserInput<KeyCode>) {
+		if self.replay.is_none() {
+			self.incorporate(input);
+		}
+	}
+
+	fn incorporate(&mut self, input: UserInput<KeyCode>) {
+		if let Some(recorder) = &mut self.recorder {
+			recorder.record(&input);
+		}
+		self.state = self.state.incorporate(input);
+	}
+
+	/// Returns the time the frame should use. While replaying, that's the
+	/// recorded time, and the recorded inputs are applied first.
+	pub(crate) fn begin_frame(&mut self, delta_time: Duration) -> Duration {
+		let delta_time = match self.replay.as_mut().and_then(InputReplay::next_frame) {
+			Some(frame) => {
+				let recorded_time = frame.delta_time();
+				for input in frame.inputs {
+					self.incorporate(input);
+				}
+				recorded_time
+			}
+			None => delta_time,
+		};
+
+		if self.replay.as_ref().is_some_and(InputReplay::is_finished) {
+			self.replay = None;
+		}
+		if let Some(recorder) = &mut self.recorder {
+			recorder.begin_frame(delta_time);
+		}
+
+		delta_time
+	}
+
+	pub(crate) fn state(&self) -> &InputState { &self.state }
+
+	/// Call once a frame has handled everything
+	pub(crate) fn end_frame(&mut self) { self.state.unhandled_changes.clear(); }
+
+	pub(crate) fn start_recording(&mut self) { self.recorder = Some(InputRecorder::default()); }
+
+	pub(crate) fn stop_recording(&mut self) -> Option<InputRecording> {
+		self.recorder.take().map(InputRecorder::finish)
+	}
+
+	pub(crate) fn replay(&mut self, recording: InputRecording) {
+		// Whatever was held down live would otherwise stay held down
+		self.state = InputState::new();
+		self.replay = Some(InputReplay::new(recording));
+	}
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ActiveInput {
+	Keyboard(KeyCode),
+	MouseButton(MouseButton),
+	Touch(i32),
+}
+
+#[derive(Clone, Debug)]
+pub struct InputState {
+	current_set: HashSet<ActiveInput>,
+	mouse_position: Option<LogicalCursorPosition>,
+	touch_position: HashMap<i32, LogicalCursorPosition>,
+	unhandled_changes: FrameDifferences,
+}
+
+impl InputState {
+	pub(crate) fn new() -> Self {
+		Self {
+			current_set: Default::default(),
+			mouse_position: None,
+			touch_position: Default::default(),
+			unhandled_changes: FrameDifferences::default(),
+		}
+	}
+
+	pub fn is_key_active(&self, key: &KeyCode) -> bool {
+		self.current_set.contains(&ActiveInput::Keyboard(key.clone()))
+	}
+
+	pub fn is_mouse_button_active(&self, button: MouseButton) -> bool {
+		self.current_set.contains(&ActiveInput::MouseButton(button))
+	}
+
+	#[allow(dead_code)]
+	pub fn current_mouse_location(&self) -> Option<LogicalCursorPosition> { self.mouse_position }
+
+	pub fn active_mouse_buttons(&self) -> Vec<MouseButton> {
+		self.current_set
+			.iter()
+			.filter_map(|input| match input {
+				ActiveInput::MouseButton(button) => Some(*button),
+				_ => None,
+			})
+			.collect()
+	}
+
+	pub fn active_touch_identifiers(&self) -> Vec<i32> {
+		self.current_set
+			.iter()
+			.filter_map(|input| match input {
+				ActiveInput::Touch(i) => Some(*i),
+				_ => None,
+			})
+			.collect()
+	}
+
+	pub fn current_touch_position(&self, identifier: i32) -> Option<LogicalCursorPosition> {
+		self.touch_position.get(&identifier).cloned()
+	}
+
+	pub(crate) fn unhandled_changes(&self) -> &FrameDifferences { &self.unhandled_changes }
+
+	/// A state with only the given keys held down
+	#[cfg(test)]
+	pub(crate) fn with_keys_pressed(keys: &[KeyCode]) -> Self {
+		keys.iter().fold(Self::new(), |state, key| {
+			state.incorporate(UserInput::Keyboard(KeyState {
+				key: key.clone(),
+				state: SwitchState::Pressed,
+			}))
+		})
+	}
+}
+
+pub(crate) trait IncorporateState
+where
+	Self: Clone,
+{
+	fn incorporate(&self, new_input: UserInput<KeyCode>) -> Self;
+}
+
+pub type MouseMovement = LogicalCursorPosition;
+
+/// How far a touch moved. Which touch it was is the key it's stored under.
+#[derive(Clone, Debug, Default)]
+pub struct TouchMovement {
+	pub difference: LogicalCursorPosition,
+}
+
+/// Everything that changed since the last frame. Movement and scrolling add
+/// up, so nothing is lost when several events arrive in one frame.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FrameDifferences {
+	pub keyboard_changes: Vec<KeyState>,
+	pub mouse_button_changes: Vec<MouseButtonState>,
+	pub mouse_movement: Option<MouseMovement>,
+	pub scroll_changes: Option<Scroll>,
+	pub touch_movement: HashMap<i32, TouchMovement>,
+	pub touch_state_changes: HashMap<i32, TouchState>,
+	/// Where each touch that lifted this frame was last seen
+	pub released_touch_positions: HashMap<i32, LogicalCursorPosition>,
+}
+
+impl FrameDifferences {
+	fn clear(&mut self) {
+		self.keyboard_changes.clear();
+		self.mouse_button_changes.clear();
+		self.mouse_movement = None;
+		self.scroll_changes = None;
+		self.touch_movement.clear();
+		self.touch_state_changes.clear();
+		self.released_touch_positions.clear();
+	}
+}
+
+impl IncorporateState for InputState {
+	fn incorporate(&self, new_input: UserInput<KeyCode>) -> Self {
+		let mut new_state = self.clone();
+
+		match new_input {
+			UserInput::Keyboard(KeyState { key, state: SwitchState::Pressed }) => {
+				let is_new = new_state.current_set.insert(ActiveInput::Keyboard(key.clone()));
+				if is_new {
+					new_state
+						.unhandled_changes
+						.keyboard_changes
+						.push(KeyState { key, state: SwitchState::Pressed });
+				}
+			}
+			UserInput::Keyboard(KeyState { key, state: SwitchState::Released }) => {
+				let was_removed = new_state.current_set.remove(&ActiveInput::Keyboard(key.clone()));
+				if was_removed {
+					new_state
+						.unhandled_changes
+						.keyboard_changes
+						.push(KeyState { key, state: SwitchState::Released });
+				}
+			}
+			UserInput::MouseButton(MouseButtonState { button, state: SwitchState::Pressed }) => {
+				let is_new = new_state.current_set.insert(ActiveInput::MouseButton(button));
+				if is_new {
+					new_state
+						.unhandled_changes
+						.mouse_button_changes
+						.push(MouseButtonState { button, state: SwitchState::Pressed });
+				}
+			}
+			UserInput::MouseButton(MouseButtonState { button, state: SwitchState::Released }) => {
+				let was_removed = new_state.current_set.remove(&ActiveInput::MouseButton(button));
+				if was_removed {
+					new_state
+						.unhandled_changes
+						.mouse_button_changes
+						.push(MouseButtonState { button, state: SwitchState::Released });
+				}
+			}
+			UserInput::CursorPosition(new_position) => {
+				if let Some(previous_position) = new_state.mouse_position {
+					let movement = &mut new_state.unhandled_changes.mouse_movement;
+					*movement = Some(
+						movement.unwrap_or_else(MouseMovement::zeros)
+							+ (new_position - previous_position),
+					);
+				}
+
+				new_state.mouse_position = Some(new_position);
+			}
+			UserInput::TouchPosition(LogicalTouchPosition { identifier, position }) => {
+				if let Some(previous_position) = new_state.touch_position.get(&identifier) {
+					let difference = position - previous_position;
+					new_state
+						.unhandled_changes
+						.touch_movement
+						.entry(identifier)
+						.or_default()
+						.difference += difference;
+				}
+
+				new_state.touch_position.insert(identifier, position);
+			}
+			UserInput::Scroll(scroll) => {
+				let total = match new_state.unhandled_changes.scroll_changes.take() {
+					Some(previous) => Scroll::new(
+						previous.delta_x + scroll.delta_x,
+						previous.delta_y + scroll.delta_y,
+						previous.delta_z + scroll.delta_z,
+					),
+					None => scroll,
+				};
+				new_state.unhandled_changes.scroll_changes = Some(total);
+			}
+			UserInput::Touch(TouchState { identifier, state: SwitchState::Pressed }) => {
+				let is_new = new_state.current_set.insert(ActiveInput::Touch(identifier));
+				if is_new {
+					new_state
+						.unhandled_changes
+						.touch_state_changes
+						.insert(identifier, TouchState { identifier, state: SwitchState::Pressed });
+				}
+			}
+			UserInput::Touch(TouchState { identifier, state: SwitchState::Released }) => {
+				let was_removed = new_state.current_set.remove(&ActiveInput::Touch(identifier));
+				if was_removed {
+					new_state.unhandled_changes.touch_state_changes.insert(
+						identifier,
+						TouchState { identifier, state: SwitchState::Released },
+					);
+
+					// Don't store the old position, since this ID is no longer touching
+					if let Some(position) = new_state.touch_position.remove(&identifier) {
+						new_state
+							.unhandled_changes
+							.released_touch_positions
+							.insert(identifier, position);
+					}
+				}
+			}
+		}
+
+		new_state
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn key(key: KeyCode, state: SwitchState) -> UserInput<KeyCode> {
+		UserInput::Keyboard(KeyState { key, state })
+	}
+
+	fn button(button: MouseButton, state: SwitchState) -> UserInput<KeyCode> {
+		UserInput::MouseButton(MouseButtonState { button, state })
+	}
+
+	fn touch(identifier: i32, state: SwitchState) -> UserInput<KeyCode> {
+		UserInput::Touch(TouchState { identifier, state })
+	}
+
+	fn touch_at(identifier: i32, x: i32, y: i32) -> UserInput<KeyCode> {
+		UserInput::TouchPosition(LogicalTouchPosition { identifier, position: nglm::vec2(x, y) })
+	}
+
+	fn apply(inputs: Vec<UserInput<KeyCode>>) -> InputState {
+		inputs.into_iter().fold(InputState::new(), |state, input| state.incorporate(input))
+	}
+
+	#[test]
+	fn key_repeat_is_not_a_change() {
+		let state = apply(vec![
+			key(KeyCode::KeyA, SwitchState::Pressed),
+			key(KeyCode::KeyA, SwitchState::Pressed),
+			key(KeyCode::KeyA, SwitchState::Pressed),
+		]);
+		assert!(state.is_key_active(&KeyCode::KeyA));
+		assert_eq!(state.unhandled_changes().keyboard_changes.len(), 1);
+
+		let state = state
+			.incorporate(key(KeyCode::KeyA, SwitchState::Released))
+			.incorporate(key(KeyCode::KeyA, SwitchState::Released));
+		assert!(!state.is_key_active(&KeyCode::KeyA));
+		assert_eq!(state.unhandled_changes().keyboard_changes.len(), 2);
+	}
+
+	#[test]
+	fn button_chords() {
+		let state = apply(vec![
+			button(MouseButton::Left, SwitchState::Pressed),
+			button(MouseButton::Right, SwitchState::Pressed),
+		]);
+		assert!(state.is_mouse_button_active(MouseButton::Left));
+		assert!(state.is_mouse_button_active(MouseButton::Right));
+		assert_eq!(state.unhandled_changes().mouse_button_changes.len(), 2);
+
+		let state = state.incorporate(button(MouseButton::Left, SwitchState::Released));
+		assert!(!state.is_mouse_button_active(MouseButton::Left));
+		assert!(state.is_mouse_button_active(MouseButton::Right));
+		assert_eq!(
+			state.unhandled_changes().mouse_button_changes.last().map(|change| change.state),
+			Some(SwitchState::Released)
+		);
+	}
+
+	#[test]
+	fn multi_touch_add_and_remove() {
+		let state = apply(vec![
+			touch(1, SwitchState::Pressed),
+			touch_at(1, 10, 10),
+			touch(2, SwitchState::Pressed),
+			touch_at(2, 50, 50),
+		]);
+		let mut active = state.active_touch_identifiers();
+		active.sort();
+		assert_eq!(active, vec![1, 2]);
+		assert!(state.unhandled_changes().touch_movement.is_empty(), "Placing isn't moving");
+
+		let state = state.incorporate(touch_at(2, 55, 45)).incorporate(touch_at(2, 60, 45));
+		let movement = &state.unhandled_changes().touch_movement;
+		assert_eq!(movement.len(), 1);
+		assert_eq!(movement[&2].difference, nglm::vec2(10, -5));
+
+		let state = state.incorporate(touch(1, SwitchState::Released));
+		assert_eq!(state.active_touch_identifiers(), vec![2]);
+		assert_eq!(state.current_touch_position(1), None);
+		assert_eq!(state.current_touch_position(2), Some(nglm::vec2(60, 45)));
+		assert_eq!(state.unhandled_changes().touch_state_changes[&1].state, SwitchState::Released);
+
+		// Lifting a finger that never moved is fine too
+		let state = state.incorporate(touch(3, SwitchState::Pressed));
+		let state = state.incorporate(touch(3, SwitchState::Released));
+		assert_eq!(state.active_touch_identifiers(), vec![2]);
+	}
+
+	#[test]
+	fn scroll_accumulates_within_a_frame() {
+		let mut source = InputSource::new();
+		source.push(UserInput::Scroll(Scroll::new(1.0, -10.0, 0.0)));
+		source.push(UserInput::Scroll(Scroll::new(2.0, -15.0, 0.5)));
+
+		let scroll = source.state().unhandled_changes().scroll_changes.clone().unwrap();
+		assert_eq!((scroll.delta_x, scroll.delta_y, scroll.delta_z), (3.0, -25.0, 0.5));
+
+		source.end_frame();
+		assert!(source.state().unhandled_changes().scroll_changes.is_none());
+	}
+
+	#[test]
+	fn mouse_movement_accumulates_within_a_frame() {
+		let mut source = InputSource::new();
+		source.push(UserInput::CursorPosition(nglm::vec2(0, 0)));
+		assert_eq!(source.state().unhandled_changes().mouse_movement, None);
+
+		source.push(UserInput::CursorPosition(nglm::vec2(4, 1)));
+		source.push(UserInput::CursorPosition(nglm::vec2(10, -2)));
+		assert_eq!(source.state().unhandled_changes().mouse_movement, Some(nglm::vec2(10, -2)));
+
+		source.end_frame();
+		source.push(UserInput::CursorPosition(nglm::vec2(11, -2)));
+		assert_eq!(source.state().unhandled_changes().mouse_movement, Some(nglm::vec2(1, 0)));
+	}
+
+	#[test]
+	fn ending_a_frame_keeps_what_is_held() {
+		let mut source = InputSource::new();
+		source.push(key(KeyCode::ShiftLeft, SwitchState::Pressed));
+		source.push(button(MouseButton::Middle, SwitchState::Pressed));
+		source.end_frame();
+
+		let state = source.state();
+		assert!(state.is_key_active(&KeyCode::ShiftLeft));
+		assert!(state.is_mouse_button_active(MouseButton::Middle));
+		assert!(state.unhandled_changes().keyboard_changes.is_empty());
+		assert!(state.unhandled_changes().mouse_button_changes.is_empty());
+	}
+
+	#[test]
+	fn replay_reproduces_a_recording_and_ignores_live_input() {
+		let frame_time = Duration::from_millis(16);
+		let press = UserInput::MouseButton(MouseButtonState {
+			button: MouseButton::Left,
+			state: SwitchState::Pressed,
+		});
+
+		let mut live = InputSource::new();
+		live.start_recording();
+		live.begin_frame(frame_time);
+		live.push(press);
+		live.push(UserInput::CursorPosition(nglm::vec2(5, 6)));
+		live.begin_frame(frame_time * 2);
+		let recording = live.stop_recording().unwrap();
+
+		let mut replayed = InputSource::new();
+		replayed.replay(recording);
+		assert_eq!(replayed.begin_frame(Duration::ZERO), frame_time);
+
+		replayed.push(UserInput::CursorPosition(nglm::vec2(100, 100)));
+		assert_eq!(replayed.begin_frame(Duration::ZERO), frame_time * 2);
+		assert!(replayed.state().is_mouse_button_active(MouseButton::Left));
+		assert_eq!(replayed.state().current_mouse_location(), Some(nglm::vec2(5, 6)));
+
+		// Finished, so live input is back
+		assert_eq!(replayed.begin_frame(frame_time), frame_time);
+		replayed.push(UserInput::CursorPosition(nglm::vec2(7, 8)));
+		assert_eq!(replayed.state().current_mouse_location(), Some(nglm::vec2(7, 8)));
+	}
+}
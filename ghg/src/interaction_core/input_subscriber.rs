@@ -1,60 +1,88 @@
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::mem::transmute;
+use std::time::Duration;
 
 use web_sys::HtmlCanvasElement;
 
+pub use super::dispatch::{Handle, Priority, Propagation, Subscription, DEFAULT_PRIORITY};
 /// Types passed into callbacks
-pub use super::input_batch::{InputState, KeyState, MouseMovement, TouchMovement};
+pub use super::input_state::{InputState, KeyState, MouseMovement, TouchMovement};
 pub use super::user_inputs::{MouseButton, MouseButtonState, Scroll, SwitchState, TouchState};
+use crate::interaction_core::dispatch::Subscribers;
 use crate::interaction_core::input_batch::{BatchedInputHandler, InputBatcher};
+use crate::interaction_core::recording::InputRecording;
 
-/// Used to remove a callback after it's unneeded.
-pub type Handle = u32;
+type MouseMoveFn = dyn Fn(MouseMovement, InputState) -> Propagation;
+type KeyboardEventFn = dyn Fn(Vec<KeyState>, InputState) -> Propagation;
+type MouseButtonFn = dyn Fn(Vec<MouseButtonState>, InputState) -> Propagation;
+type ScrollFn = dyn Fn(Scroll, InputState) -> Propagation;
+type TouchMoveFn = dyn Fn(HashMap<i32, TouchMovement>, InputState) -> Propagation;
+type TouchStateFn = dyn Fn(HashMap<i32, TouchState>, InputState) -> Propagation;
 
 /// Function to be executed when a mouse movement occurs.
-pub type MouseMoveCallback = Box<dyn Fn(MouseMovement, InputState)>;
+pub type MouseMoveCallback = Box<MouseMoveFn>;
 
 /// Function to be executed when a keyboard change occurs.
-pub type KeyboardEventCallback = Box<dyn Fn(Vec<KeyState>, InputState)>;
+pub type KeyboardEventCallback = Box<KeyboardEventFn>;
 
 /// Function to be executed when a mouse button change occurs.
-pub type MouseButtonCallback = Box<dyn Fn(Vec<MouseButtonState>, InputState)>;
+pub type MouseButtonCallback = Box<MouseButtonFn>;
 
 /// Function to be executed when a scroll event occurs.
-pub type ScrollCallback = Box<dyn Fn(Scroll, InputState)>;
+pub type ScrollCallback = Box<ScrollFn>;
 
 /// Function to be executed when a touch movement occurs.
-pub type TouchMoveCallback = Box<dyn Fn(HashMap<i32, TouchMovement>, InputState)>;
+pub type TouchMoveCallback = Box<TouchMoveFn>;
 
 /// Function to be executed when a touch state change occurs.
-pub type TouchStateCallback = Box<dyn Fn(HashMap<i32, TouchState>, InputState)>;
+pub type TouchStateCallback = Box<TouchStateFn>;
 
 /// Provides a per-frame handler for user input registration.
 /// Subscribe to the different types of inputs using callbacks, then use frame()
 /// at the start of a frame to handle all of the available inputs, call the
-/// registered callbacks, and clear the queue. Order of execution of callbacks
-/// of the same type is not guaranteed.
+/// registered callbacks, and clear the queue.
+///
+/// Callbacks of the same type run highest priority first, then in the order
+/// they were subscribed. One returning `Propagation::Stop` consumes the event,
+/// so the rest of that type don't see it. Each stays subscribed until the
+/// `Subscription` returned for it is dropped.
 pub struct FrameInputSubscriber {
 	input_batcher: InputBatcher,
-	keyboard_callbacks: HashMap<Handle, KeyboardEventCallback>,
-	mouse_move_callbacks: HashMap<Handle, MouseMoveCallback>,
-	mouse_button_callbacks: HashMap<Handle, MouseButtonCallback>,
-	scroll_callbacks: HashMap<Handle, ScrollCallback>,
-	touch_move_callbacks: HashMap<Handle, TouchMoveCallback>,
-	touch_state_callbacks: HashMap<Handle, TouchStateCallback>,
+	keyboard_callbacks: Subscribers<KeyboardEventFn>,
+	mouse_move_callbacks: Subscribers<MouseMoveFn>,
+	mouse_button_callbacks: Subscribers<MouseButtonFn>,
+	scroll_callbacks: Subscribers<ScrollFn>,
+	touch_move_callbacks: Subscribers<TouchMoveFn>,
+	touch_state_callbacks: Subscribers<TouchStateFn>,
+	next_handle: Handle,
 }
 
 impl FrameInputSubscriber {
-	pub fn new(canvas: HtmlCanvasElement) -> Self {
+	pub fn new(canvas: HtmlCanvasElement) -> Self { Self::with_batcher(InputBatcher::new(canvas)) }
+
+	/// Takes inputs only from `recording`. See `InputBatcher::from_recording`.
+	#[allow(dead_code)]
+	pub fn from_recording(recording: InputRecording) -> Self {
+		Self::with_batcher(InputBatcher::from_recording(recording))
+	}
+
+	/// Starts a frame of input, for subscribers that aren't the page's. See
+	/// `InputBatcher::begin_frame`.
+	#[allow(dead_code)]
+	pub fn begin_frame(&mut self, delta_time: Duration) -> Duration {
+		self.input_batcher.begin_frame(delta_time)
+	}
+
+	fn with_batcher(input_batcher: InputBatcher) -> Self {
 		Self {
-			input_batcher: InputBatcher::new(canvas),
+			input_batcher,
 			keyboard_callbacks: Default::default(),
 			mouse_move_callbacks: Default::default(),
 			mouse_button_callbacks: Default::default(),
 			scroll_callbacks: Default::default(),
 			touch_move_callbacks: Default::default(),
 			touch_state_callbacks: Default::default(),
+			next_handle: 0,
 		}
 	}
 
@@ -62,82 +90,59 @@ impl FrameInputSubscriber {
 		self.input_batcher.borrow().get_current_state()
 	}
 
-	pub fn subscribe_on_keyboard_event(&mut self, callback: KeyboardEventCallback) -> Handle {
-		let handle = self.generate_unique_handle();
-		self.keyboard_callbacks.insert(handle, callback);
-		handle
-	}
-
-	#[allow(dead_code)]
-	pub fn unsubscribe_on_keyboard_event(
+	pub fn subscribe_on_keyboard_event(
 		&mut self,
-		handle: Handle,
-	) -> Option<KeyboardEventCallback> {
-		self.keyboard_callbacks.remove(&handle)
-	}
-
-	pub fn subscribe_on_mouse_move<F: 'static + Fn(MouseMovement, InputState)>(
-		&mut self,
-		callback: Box<F>,
-	) -> Handle {
+		priority: Priority,
+		callback: KeyboardEventCallback,
+	) -> Subscription {
 		let handle = self.generate_unique_handle();
-		self.mouse_move_callbacks.insert(handle, callback);
-		handle
+		self.keyboard_callbacks.subscribe(handle, priority, callback)
 	}
 
-	#[allow(dead_code)]
-	pub fn unsubscribe_on_mouse_move(&mut self, handle: Handle) -> Option<MouseMoveCallback> {
-		self.mouse_move_callbacks.remove(&handle)
-	}
-
-	pub fn subscribe_on_mouse_button_event(&mut self, callback: MouseButtonCallback) -> Handle {
+	pub fn subscribe_on_mouse_move(
+		&mut self,
+		priority: Priority,
+		callback: MouseMoveCallback,
+	) -> Subscription {
 		let handle = self.generate_unique_handle();
-		self.mouse_button_callbacks.insert(handle, callback);
-		handle
+		self.mouse_move_callbacks.subscribe(handle, priority, callback)
 	}
 
-	#[allow(dead_code)]
-	pub fn unsubscribe_on_mouse_button_event(
+	pub fn subscribe_on_mouse_button_event(
 		&mut self,
-		handle: Handle,
-	) -> Option<MouseButtonCallback> {
-		self.mouse_button_callbacks.remove(&handle)
-	}
-
-	pub fn subscribe_on_scroll_event(&mut self, callback: ScrollCallback) -> Handle {
+		priority: Priority,
+		callback: MouseButtonCallback,
+	) -> Subscription {
 		let handle = self.generate_unique_handle();
-		self.scroll_callbacks.insert(handle, callback);
-		handle
-	}
-
-	#[allow(dead_code)]
-	pub fn unsubscribe_on_scroll_event(&mut self, handle: Handle) -> Option<ScrollCallback> {
-		self.scroll_callbacks.remove(&handle)
+		self.mouse_button_callbacks.subscribe(handle, priority, callback)
 	}
 
-	pub fn subscribe_on_touch_move(&mut self, callback: TouchMoveCallback) -> Handle {
+	pub fn subscribe_on_scroll_event(
+		&mut self,
+		priority: Priority,
+		callback: ScrollCallback,
+	) -> Subscription {
 		let handle = self.generate_unique_handle();
-		self.touch_move_callbacks.insert(handle, callback);
-		handle
+		self.scroll_callbacks.subscribe(handle, priority, callback)
 	}
 
 	#[allow(dead_code)]
-	pub fn unsubscribe_on_touch_move(&mut self, handle: Handle) -> Option<TouchMoveCallback> {
-		self.touch_move_callbacks.remove(&handle)
-	}
-
-	pub fn subscribe_on_touch_state_event(&mut self, callback: TouchStateCallback) -> Handle {
+	pub fn subscribe_on_touch_move(
+		&mut self,
+		priority: Priority,
+		callback: TouchMoveCallback,
+	) -> Subscription {
 		let handle = self.generate_unique_handle();
-		self.touch_state_callbacks.insert(handle, callback);
-		handle
+		self.touch_move_callbacks.subscribe(handle, priority, callback)
 	}
 
-	#[allow(dead_code)]
-	pub fn unsubscribe_on_touch_state_event(
+	pub fn subscribe_on_touch_state_event(
 		&mut self,
-		handle: Handle,
-	) -> Option<TouchStateCallback> {
-		self.touch_state_callbacks.remove(&handle)
+		priority: Priority,
+		callback: TouchStateCallback,
+	) -> Subscription {
+		let handle = self.generate_unique_handle();
+		self.touch_state_callbacks.subscribe(handle, priority, callback)
 	}
 
 	/// Executes callbacks in this order, if any changes have occurred for each:
@@ -148,65 +153,123 @@ impl FrameInputSubscriber {
 	///  - Mouse movement
 	///  - Touch movement
 	/// Thus, all callbacks are guaranteed to have non-empty values when called.
+	///
+	/// The page's input source starts its frames from the animation loop, with
+	/// `begin_input_frame`. Others need `begin_frame` called first.
 	pub fn frame(&mut self) {
 		let current_state = self.get_current_state();
 		let input_handler = BatchedInputHandler::new(&mut self.input_batcher);
 
 		let keyboard_changes = input_handler.get_keyboard_changes();
 		if !keyboard_changes.is_empty() {
-			self.keyboard_callbacks.iter().for_each(|(_handle, cb)| {
-				cb(keyboard_changes.clone(), current_state.clone());
-			});
+			self.keyboard_callbacks
+				.dispatch(|cb| cb(keyboard_changes.clone(), current_state.clone()));
 		}
 
 		let mouse_button_changes = input_handler.get_mouse_button_changes();
 		if !mouse_button_changes.is_empty() {
-			self.mouse_button_callbacks.iter().for_each(|(_handle, cb)| {
-				cb(mouse_button_changes.clone(), current_state.clone());
-			});
+			self.mouse_button_callbacks
+				.dispatch(|cb| cb(mouse_button_changes.clone(), current_state.clone()));
 		}
 
 		let touch_state_changes = input_handler.get_touch_state_changes();
 		if !touch_state_changes.is_empty() {
-			self.touch_state_callbacks.iter().for_each(|(_handle, cb)| {
-				cb(touch_state_changes.clone(), current_state.clone());
-			});
+			self.touch_state_callbacks
+				.dispatch(|cb| cb(touch_state_changes.clone(), current_state.clone()));
 		}
 
-		let scroll_changes = input_handler.get_scroll_changes();
-		if scroll_changes.is_some() {
-			let scroll = scroll_changes.unwrap();
-			self.scroll_callbacks.iter().for_each(|(_handle, cb)| {
-				cb(scroll.clone(), current_state.clone());
-			})
+		if let Some(scroll) = input_handler.get_scroll_changes() {
+			self.scroll_callbacks.dispatch(|cb| cb(scroll.clone(), current_state.clone()));
 		}
 
-		let mouse_changes = input_handler.get_mouse_movement();
-		if mouse_changes.is_some() {
-			let movement = mouse_changes.unwrap();
-			self.mouse_move_callbacks.iter().for_each(|(_handle, cb)| {
-				cb(movement.clone(), current_state.clone());
-			});
+		if let Some(movement) = input_handler.get_mouse_movement() {
+			self.mouse_move_callbacks.dispatch(|cb| cb(movement.clone(), current_state.clone()));
 		}
 
 		let touch_move_changes = input_handler.get_touch_movement();
 		if !touch_move_changes.is_empty() {
-			self.touch_move_callbacks.iter().for_each(|(_handle, cb)| {
-				cb(touch_move_changes.clone(), current_state.clone());
-			});
+			self.touch_move_callbacks
+				.dispatch(|cb| cb(touch_move_changes.clone(), current_state.clone()));
 		}
 	}
 
-	fn generate_unique_handle(&self) -> Handle {
-		for _i in 0..10 {
-			let rand_float = js_sys::Math::random() as f32;
-			let handle = unsafe { transmute::<f32, Handle>(rand_float) };
+	fn generate_unique_handle(&mut self) -> Handle {
+		let handle = self.next_handle;
+		self.next_handle += 1;
+		handle
+	}
+}
 
-			if !self.mouse_move_callbacks.contains_key(&handle) {
-				return handle;
-			}
-		}
+#[cfg(test)]
+mod tests {
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::interaction_core::key_code::KeyCode;
+	use crate::interaction_core::recording::RecordedFrame;
+	use crate::interaction_core::user_inputs::{KeyCodeState, UserInput};
+
+	fn press(key: KeyCode) -> FrameInputSubscriber {
+		FrameInputSubscriber::from_recording(InputRecording {
+			frames: vec![RecordedFrame {
+				delta_micros: 16_667,
+				inputs: vec![UserInput::Keyboard(KeyCodeState {
+					key,
+					state: SwitchState::Pressed,
+				})],
+			}],
+		})
+	}
+
+	fn logger(
+		log: &Rc<RefCell<Vec<&'static str>>>,
+		name: &'static str,
+		propagation: Propagation,
+	) -> KeyboardEventCallback {
+		let log = log.clone();
+		Box::new(move |_key_states, _current_state| {
+			log.borrow_mut().push(name);
+			propagation
+		})
+	}
+
+	#[test]
+	fn keyboard_events_are_dispatched_by_priority() {
+		let mut subscriber = press(KeyCode::KeyH);
+		let log = Rc::new(RefCell::new(Vec::new()));
+
+		let _camera = subscriber.subscribe_on_keyboard_event(
+			DEFAULT_PRIORITY,
+			logger(&log, "camera", Propagation::Continue),
+		);
+		let _overlay =
+			subscriber.subscribe_on_keyboard_event(10, logger(&log, "overlay", Propagation::Stop));
+		let _background = subscriber
+			.subscribe_on_keyboard_event(-10, logger(&log, "background", Propagation::Continue));
+
+		subscriber.begin_frame(Duration::ZERO);
+		subscriber.frame();
+		assert_eq!(log.take(), vec!["overlay"]);
+	}
 
-		panic!("Failed to generate unique handle! Infinite loop.");
+	#[test]
+	fn dropped_subscriptions_are_not_called() {
+		let mut subscriber = press(KeyCode::KeyH);
+		let log = Rc::new(RefCell::new(Vec::new()));
+
+		let first = subscriber.subscribe_on_keyboard_event(
+			DEFAULT_PRIORITY,
+			logger(&log, "first", Propagation::Continue),
+		);
+		let _second = subscriber.subscribe_on_keyboard_event(
+			DEFAULT_PRIORITY,
+			logger(&log, "second", Propagation::Continue),
+		);
+		drop(first);
+
+		subscriber.begin_frame(Duration::ZERO);
+		subscriber.frame();
+		assert_eq!(log.take(), vec!["second"]);
 	}
 }
@@ -0,0 +1,138 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Declares `KeyCode` with one variant per `KeyboardEvent.code` value, named
+/// exactly like the code string, so both directions are generated from the
+/// same list.
+macro_rules! key_codes {
+	($($key:ident),* $(,)?) => {
+		/// A physical key, as reported by `KeyboardEvent.code`. The layout doesn't
+		/// matter: `KeyQ` is the key labelled Q on a QWERTY keyboard, wherever that
+		/// letter actually is.
+		#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+		pub enum KeyCode {
+			$($key,)*
+			/// Anything the browser sends that isn't listed above
+			Unknown(String),
+		}
+
+		impl KeyCode {
+			pub fn from_code(code: &str) -> Self {
+				match code {
+					$(stringify!($key) => KeyCode::$key,)*
+					other => KeyCode::Unknown(other.to_owned()),
+				}
+			}
+
+			pub fn as_str(&self) -> &str {
+				match self {
+					$(KeyCode::$key => stringify!($key),)*
+					KeyCode::Unknown(code) => code,
+				}
+			}
+		}
+	};
+}
+
+key_codes! {
+	// Writing system keys
+	Backquote, Backslash, BracketLeft, BracketRight, Comma, Equal, IntlBackslash, IntlRo, IntlYen,
+	Minus, Period, Quote, Semicolon, Slash,
+	Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+	KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM, KeyN, KeyO, KeyP,
+	KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+	// Functional keys
+	AltLeft, AltRight, Backspace, CapsLock, ContextMenu, ControlLeft, ControlRight, Enter, MetaLeft,
+	MetaRight, ShiftLeft, ShiftRight, Space, Tab, Convert, KanaMode, Lang1, Lang2, NonConvert,
+	// Control pad and arrows
+	Delete, End, Help, Home, Insert, PageDown, PageUp,
+	ArrowDown, ArrowLeft, ArrowRight, ArrowUp,
+	// Numpad
+	NumLock, Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8,
+	Numpad9, NumpadAdd, NumpadComma, NumpadDecimal, NumpadDivide, NumpadEnter, NumpadEqual,
+	NumpadMultiply, NumpadParenLeft, NumpadParenRight, NumpadSubtract,
+	// Function keys
+	Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15, F16, F17, F18, F19,
+	F20, F21, F22, F23, F24, Fn, PrintScreen, ScrollLock, Pause,
+	// Media keys
+	BrowserBack, BrowserFavorites, BrowserForward, BrowserHome, BrowserRefresh, BrowserSearch,
+	BrowserStop, Eject, LaunchApp1, LaunchApp2, LaunchMail, MediaPlayPause, MediaSelect, MediaStop,
+	MediaTrackNext, MediaTrackPrevious, Power, Sleep, AudioVolumeDown, AudioVolumeMute,
+	AudioVolumeUp, WakeUp,
+}
+
+impl fmt::Display for KeyCode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.as_str()) }
+}
+
+// Stored as the plain code string, so that hand-written bindings read like
+// `"key": "KeyH"`. Unlisted codes are still accepted and become `Unknown`, as
+// recorded events can hold anything the browser sent. Bindings use
+// `deserialize_known` instead.
+impl Serialize for KeyCode {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.as_str())
+	}
+}
+
+impl<'de> Deserialize<'de> for KeyCode {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let code = String::deserialize(deserializer)?;
+		Ok(KeyCode::from_code(&code))
+	}
+}
+
+/// For `#[serde(deserialize_with)]`, where only listed codes make sense. A typo
+/// in hand-written JSON then fails to load, rather than becoming an `Unknown`
+/// that never matches anything.
+pub fn deserialize_known<'de, D: Deserializer<'de>>(deserializer: D) -> Result<KeyCode, D::Error> {
+	known(String::deserialize(deserializer)?)
+}
+
+/// `deserialize_known`, for a list of codes
+pub fn deserialize_known_list<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Vec<KeyCode>, D::Error> {
+	Vec::<String>::deserialize(deserializer)?.into_iter().map(known).collect()
+}
+
+fn known<E: serde::de::Error>(code: String) -> Result<KeyCode, E> {
+	match KeyCode::from_code(&code) {
+		KeyCode::Unknown(code) => Err(E::custom(format!("Unknown key code {code:?}"))),
+		key => Ok(key),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn codes_round_trip() {
+		for code in ["KeyA", "Digit1", "ShiftLeft", "ArrowRight", "Space", "F12", "NumpadEnter"] {
+			let key = KeyCode::from_code(code);
+			assert!(!matches!(key, KeyCode::Unknown(_)), "{code} should be known");
+			assert_eq!(key.as_str(), code);
+		}
+
+		assert_eq!(KeyCode::from_code("Digit1"), KeyCode::Digit1);
+	}
+
+	#[test]
+	fn unknown_codes_are_kept() {
+		let key = KeyCode::from_code("Hyper");
+		assert_eq!(key, KeyCode::Unknown("Hyper".to_owned()));
+		assert_eq!(key.to_string(), "Hyper");
+	}
+
+	#[test]
+	fn serializes_as_code_string() {
+		assert_eq!(serde_json::to_string(&KeyCode::KeyH).unwrap(), r#""KeyH""#);
+		assert_eq!(serde_json::from_str::<KeyCode>(r#""ShiftLeft""#).unwrap(), KeyCode::ShiftLeft);
+		assert_eq!(
+			serde_json::from_str::<KeyCode>(r#""Hyper""#).unwrap(),
+			KeyCode::Unknown("Hyper".to_owned())
+		);
+	}
+}
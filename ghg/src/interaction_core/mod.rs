@@ -1,4 +1,10 @@
-mod input_batch;
+mod dispatch;
+mod input_state;
 mod user_inputs;
 
+pub mod actions;
+pub mod gestures;
+pub mod input_batch;
 pub mod input_subscriber;
+pub mod key_code;
+pub mod recording;
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::interaction_core::key_code::KeyCode;
+use crate::interaction_core::user_inputs::UserInput;
+
+/// Every input the browser sent, grouped by the frame that handled it, along
+/// with each frame's time. Replaying one feeds the same inputs to the same
+/// frames with the same times, so anything driven only by input and frame time
+/// ends up in the same state.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputRecording {
+	pub frames: Vec<RecordedFrame>,
+}
+
+impl InputRecording {
+	pub fn from_json(json: &str) -> Result<Self, serde_json::Error> { serde_json::from_str(json) }
+
+	pub fn to_json(&self) -> String {
+		serde_json::to_string(self).expect("Recordings are always serializable")
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+	/// Time since the previous frame, at the animation loop's precision
+	pub delta_micros: u64,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub inputs: Vec<UserInput<KeyCode>>,
+}
+
+impl RecordedFrame {
+	pub fn delta_time(&self) -> Duration { Duration::from_micros(self.delta_micros) }
+}
+
+#[derive(Default)]
+pub struct InputRecorder {
+	recording: InputRecording,
+	/// Inputs since the last frame started
+	pending: Vec<UserInput<KeyCode>>,
+}
+
+impl InputRecorder {
+	pub fn record(&mut self, input: &UserInput<KeyCode>) { self.pending.push(input.clone()); }
+
+	/// Everything recorded since the previous frame goes to the frame starting
+	/// now
+	pub fn begin_frame(&mut self, delta_time: Duration) {
+		self.recording.frames.push(RecordedFrame {
+			delta_micros: delta_time.as_micros() as u64,
+			inputs: std::mem::take(&mut self.pending),
+		});
+	}
+
+	/// Inputs that no frame has handled yet are left out
+	pub fn finish(self) -> InputRecording { self.recording }
+}
+
+pub struct InputReplay {
+	frames: VecDeque<RecordedFrame>,
+}
+
+impl InputReplay {
+	pub fn new(recording: InputRecording) -> Self { Self { frames: recording.frames.into() } }
+
+	pub fn next_frame(&mut self) -> Option<RecordedFrame> { self.frames.pop_front() }
+
+	pub fn is_finished(&self) -> bool { self.frames.is_empty() }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::interaction_core::user_inputs::{KeyCodeState, Scroll, SwitchState};
+
+	fn frame_time() -> Duration { Duration::from_micros(16_667) }
+
+	#[test]
+	fn recorder_groups_inputs_by_frame() {
+		let mut recorder = InputRecorder::default();
+		recorder.begin_frame(frame_time());
+
+		recorder.record(&UserInput::CursorPosition(nglm::vec2(3, 4)));
+		recorder.record(&UserInput::Scroll(Scroll::new(0.0, -10.0, 0.0)));
+		recorder.begin_frame(frame_time());
+
+		recorder.record(&UserInput::CursorPosition(nglm::vec2(5, 4)));
+		let recording = recorder.finish();
+
+		assert_eq!(recording.frames.len(), 2);
+		assert!(recording.frames[0].inputs.is_empty());
+		assert_eq!(recording.frames[1].inputs.len(), 2);
+		assert_eq!(recording.frames[1].delta_time(), frame_time());
+	}
+
+	#[test]
+	fn json_round_trip() {
+		let recording = InputRecording {
+			frames: vec![RecordedFrame {
+				delta_micros: 16_667,
+				inputs: vec![
+					UserInput::Keyboard(KeyCodeState {
+						key: KeyCode::ArrowRight,
+						state: SwitchState::Pressed,
+					}),
+					UserInput::CursorPosition(nglm::vec2(-1, 2)),
+				],
+			}],
+		};
+
+		let json = recording.to_json();
+		assert!(json.contains(r#""ArrowRight""#));
+		assert_eq!(InputRecording::from_json(&json).unwrap(), recording);
+	}
+
+	#[test]
+	fn replay_yields_frames_in_order() {
+		let frames = (1..=3).map(|i| RecordedFrame { delta_micros: i, inputs: vec![] });
+		let mut replay = InputReplay::new(InputRecording { frames: frames.collect() });
+
+		let deltas: Vec<u64> =
+			std::iter::from_fn(|| replay.next_frame()).map(|f| f.delta_micros).collect();
+		assert_eq!(deltas, vec![1, 2, 3]);
+		assert!(replay.is_finished());
+	}
+}
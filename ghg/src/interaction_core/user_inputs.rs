@@ -1,76 +1,20 @@
 #![allow(dead_code)]
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum SwitchState {
 	Pressed,
 	Released,
 }
 
-// TODO: Incomplete
-// #[derive(Clone, Copy, Debug)]
-// pub enum VirtualKey {
-//     Unknown, /// Only exists because this is incomplete
-//
-//     _1,
-//     _2,
-//     _3,
-//     _4,
-//     _5,
-//     _6,
-//     _7,
-//     _8,
-//     _9,
-//     _0,
-//
-//     A,
-//     B,
-//     C,
-//     D,
-//     E,
-//     F,
-//     G,
-//     H,
-//     I,
-//     J,
-//     K,
-//     L,
-//     M,
-//     N,
-//     O,
-//     P,
-//     Q,
-//     R,
-//     S,
-//     T,
-//     U,
-//     V,
-//     W,
-//     X,
-//     Y,
-//     Z,
-//
-//     Escape,
-//
-//     Space,
-//
-//     LeftShift,
-//     LeftControl,
-//     LeftAlt,
-//     LeftMod, // e.g. the Windows key
-//
-//     RightShift,
-//     RightControl,
-//     RightAlt,
-//     RightMod, // e.g. the Windows key
-// }
-
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct KeyCodeState<T: Clone> {
 	pub key: T,
 	pub state: SwitchState,
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum MouseButton {
 	Left,
 	Right,
@@ -78,13 +22,13 @@ pub enum MouseButton {
 	Extra(u16),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MouseButtonState {
 	pub button: MouseButton,
 	pub state: SwitchState,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Scroll {
 	pub delta_x: f32,
 	pub delta_y: f32,
@@ -99,19 +43,19 @@ impl Scroll {
 
 pub type LogicalCursorPosition = nglm::I32Vec2;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct LogicalTouchPosition {
 	pub identifier: i32,
 	pub position: LogicalCursorPosition,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TouchState {
 	pub identifier: i32,
 	pub state: SwitchState,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum UserInput<T: Clone> {
 	Keyboard(KeyCodeState<T>),
 	MouseButton(MouseButtonState),
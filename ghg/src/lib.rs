@@ -15,6 +15,10 @@ use crate::utils::set_panic_hook;
 pub mod utils;
 mod application;
 mod interaction_core;
+// Renders without a browser, for the render_globe tool. None of it is any use
+// in the web app, so it's left out of the wasm build.
+#[cfg(not(target_arch = "wasm32"))]
+pub mod offline;
 mod render_core;
 pub mod request_data;
 
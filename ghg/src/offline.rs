@@ -0,0 +1,718 @@
+//! Renders the globe to images without a browser or a GPU, for gallery
+//! thumbnails and link previews. Drawing goes through the same meshes, camera
+//! and shading as the web app, on a `SoftwareBackend`.
+
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ghg_data_core::metadata::Metadata;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Luma, Rgb, RgbaImage};
+use serde::Deserialize;
+
+pub use crate::application::animation_loop::INITIAL_TERRAIN_SCALE;
+pub use crate::application::data::Colormap;
+use crate::application::data::{map_index, range_matrices, upload_data_map, FIRST_MAP_UNIT};
+use crate::application::lighting::{LightSource, LightingOptions};
+use crate::application::planet::{
+	PlanetFrame, PlanetRenderer, COLOR_MAP_PATH, COLOR_MAP_UNIT, HEIGHT_MAP_PATH, HEIGHT_MAP_UNIT,
+	NIGHT_MAP_PATH, NIGHT_MAP_UNIT,
+};
+use crate::application::planet_shading::add_planet_port;
+use crate::application::shaders::{get_planet_shaders, get_sky_shaders, PlanetShaders};
+use crate::application::sky_shading::add_sky_port;
+use crate::application::sphere::SphereTessellation;
+pub use crate::application::time_cursor::TimeCursor;
+use crate::render_core::camera::{Camera, ClipPlanes};
+use crate::render_core::culling::TerrainShell;
+use crate::render_core::image::load_into_texture;
+use crate::render_core::software_backend::SoftwareBackend;
+use crate::render_core::uniform;
+
+/// A year of monthly data, as produced by the data processing tools
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Dataset {
+	pub name: String,
+	/// Relative to the assets directory, like `images/earth_temp`
+	pub directory: PathBuf,
+	/// File stems of the year's three maps, in order. Each is a PNG with a
+	/// `.metadata` file beside it.
+	pub maps: Vec<String>,
+	#[serde(default)]
+	pub colormap: Colormap,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct CameraPose {
+	pub position: [f32; 3],
+	#[serde(default)]
+	pub target: [f32; 3],
+}
+
+/// One size and angle to render every dataset from
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct View {
+	pub name: String,
+	pub width: u32,
+	pub height: u32,
+	pub camera: CameraPose,
+	/// Light the globe from the sun at each time step, rather than from the
+	/// camera
+	#[serde(default)]
+	pub sunlight: bool,
+	#[serde(default = "initial_terrain_scale")]
+	pub terrain_scale: f32,
+}
+
+fn initial_terrain_scale() -> f32 { INITIAL_TERRAIN_SCALE }
+
+fn every_month() -> Vec<usize> { (0..12).collect() }
+
+/// Everything to render in a batch: each dataset, at each month, from each
+/// view
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Catalog {
+	pub datasets: Vec<Dataset>,
+	pub views: Vec<View>,
+	/// Zero-based, every month of the year if left out
+	#[serde(default = "every_month")]
+	pub months: Vec<usize>,
+}
+
+impl Catalog {
+	pub fn from_json(json: &str) -> Result<Self, String> {
+		serde_json::from_str(json).map_err(|e| format!("Invalid catalog: {e}"))
+	}
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+	fs::read(path).map_err(|e| format!("Couldn't read {path:?}: {e}"))
+}
+
+/// The planet's own textures, as PNG files. Night lights are optional, as in
+/// the web app.
+pub struct Terrain {
+	pub height_map: Vec<u8>,
+	pub color_map: Vec<u8>,
+	pub night_map: Option<Vec<u8>>,
+}
+
+impl Terrain {
+	/// Reads the textures the web app would fetch from `assets`
+	pub fn from_assets(assets: &Path) -> Result<Self, String> {
+		Ok(Self {
+			height_map: read(&assets.join(HEIGHT_MAP_PATH))?,
+			color_map: read(&assets.join(COLOR_MAP_PATH))?,
+			night_map: read(&assets.join(NIGHT_MAP_PATH)).ok(),
+		})
+	}
+}
+
+/// Draws the globe into images of any size, one at a time. Datasets are read
+/// from the assets directory, and kept loaded until a different one is
+/// rendered.
+pub struct OfflineRenderer {
+	backend: SoftwareBackend,
+	shaders: PlanetShaders<SoftwareBackend>,
+	planet: PlanetRenderer<SoftwareBackend>,
+	assets: PathBuf,
+	night_lights_available: bool,
+	loaded_dataset: Option<Dataset>,
+}
+
+impl OfflineRenderer {
+	pub fn new(assets: &Path, terrain: &Terrain) -> Result<Self, String> {
+		let backend = SoftwareBackend::new(1, 1);
+		add_planet_port(&backend);
+		add_sky_port(&backend);
+
+		let shaders = get_planet_shaders(&backend)?;
+		let planet = PlanetRenderer::new(
+			shaders.clone(),
+			get_sky_shaders(&backend)?,
+			&SphereTessellation::default(),
+		)?;
+
+		load_into_texture::<Luma<u8>, _>(&backend, &terrain.height_map, HEIGHT_MAP_UNIT)?;
+		load_into_texture::<Rgb<u8>, _>(&backend, &terrain.color_map, COLOR_MAP_UNIT)?;
+		if let Some(night_map) = &terrain.night_map {
+			load_into_texture::<Rgb<u8>, _>(&backend, night_map, NIGHT_MAP_UNIT)?;
+		}
+
+		Ok(Self {
+			backend,
+			shaders,
+			planet,
+			assets: assets.to_owned(),
+			night_lights_available: terrain.night_map.is_some(),
+			loaded_dataset: None,
+		})
+	}
+
+	pub fn render(
+		&mut self,
+		dataset: &Dataset,
+		time_cursor: TimeCursor,
+		view: &View,
+		colormap: Colormap,
+	) -> Result<RgbaImage, String> {
+		if time_cursor.month >= 12 {
+			return Err(format!("There's no month {}", time_cursor.month));
+		}
+		if view.width == 0 || view.height == 0 {
+			return Err(format!("View {} has no pixels", view.name));
+		}
+		let (position, target) = (view.camera.position.into(), view.camera.target.into());
+		if position == target {
+			return Err(format!("The camera of view {} isn't looking anywhere", view.name));
+		}
+
+		self.load_dataset(dataset)?;
+
+		let month = time_cursor.month as i32;
+		for shader in self.shaders.variants() {
+			shader.use_shader();
+			uniform::init_i32("s_dataMap", shader, FIRST_MAP_UNIT + map_index(month));
+			uniform::init_i32("u_dataMonth", shader, month);
+			uniform::init_i32("u_colormap", shader, colormap.uniform_value());
+		}
+
+		let mut camera = Camera::new(&position, &target);
+		camera.set_clip_planes(ClipPlanes::AroundShell(TerrainShell::from_terrain_scale(
+			view.terrain_scale,
+		)));
+
+		let source = if view.sunlight { LightSource::Sun } else { LightSource::Camera };
+		let frame = PlanetFrame {
+			width: view.width as i32,
+			height: view.height as i32,
+			delta_time: Duration::ZERO,
+			terrain_scale: view.terrain_scale,
+			time_cursor,
+			lighting_options: LightingOptions {
+				source,
+				night_lights_available: self.night_lights_available,
+				..LightingOptions::default()
+			},
+		};
+
+		self.backend.resize(view.width, view.height);
+		self.planet.draw_frame(&mut camera, &frame);
+		Ok(self.backend.snapshot())
+	}
+
+	fn load_dataset(&mut self, dataset: &Dataset) -> Result<(), String> {
+		if self.loaded_dataset.as_ref() == Some(dataset) {
+			return Ok(());
+		}
+		self.loaded_dataset = None;
+
+		let directory = self.assets.join(&dataset.directory);
+		let mut metadata = Vec::new();
+		for (i, map) in (0..).zip(&dataset.maps) {
+			let png = read(&directory.join(format!("{map}.png")))?;
+			upload_data_map(&self.backend, &png, (FIRST_MAP_UNIT + i) as u32)?;
+
+			let path = directory.join(format!("{map}.metadata"));
+			let map_metadata: Metadata = serde_json::from_slice(&read(&path)?)
+				.map_err(|e| format!("Invalid metadata in {path:?}: {e}"))?;
+			metadata.push(map_metadata);
+		}
+
+		let (min_values, max_values) = range_matrices(&metadata)?;
+		for shader in self.shaders.variants() {
+			shader.use_shader();
+			uniform::init_mat4x3("u_dataMinValues", shader, min_values);
+			uniform::init_mat4x3("u_dataMaxValues", shader, max_values);
+		}
+
+		self.loaded_dataset = Some(dataset.clone());
+		Ok(())
+	}
+}
+
+/// Where `render_catalog` writes an image, relative to its output directory
+pub fn catalog_image_path(dataset: &Dataset, view: &View, month: usize) -> PathBuf {
+	[dataset.name.as_str(), view.name.as_str(), &format!("{:0>2}.png", month + 1)].iter().collect()
+}
+
+/// Renders everything in `catalog` into `output`, laid out as
+/// `catalog_image_path` describes. `on_written` is told about each image as it
+/// lands. Returns how many were written.
+pub fn render_catalog(
+	renderer: &mut OfflineRenderer,
+	catalog: &Catalog,
+	output: &Path,
+	mut on_written: impl FnMut(&Path),
+) -> Result<usize, String> {
+	let mut written = 0;
+	for dataset in &catalog.datasets {
+		for view in &catalog.views {
+			for month in &catalog.months {
+				let time_cursor = TimeCursor { month: *month, ..TimeCursor::default() };
+				let image = renderer.render(dataset, time_cursor, view, dataset.colormap)?;
+
+				let path = output.join(catalog_image_path(dataset, view, *month));
+				if let Some(directory) = path.parent() {
+					fs::create_dir_all(directory)
+						.map_err(|e| format!("Couldn't create {directory:?}: {e}"))?;
+				}
+				image.save(&path).map_err(|e| format!("Couldn't write {path:?}: {e}"))?;
+
+				on_written(&path);
+				written += 1;
+			}
+		}
+	}
+	Ok(written)
+}
+
+/// The data export writes each year as three maps of four months,
+/// `<year>.01.04`, `<year>.05.08` and `<year>.09.12`
+fn year_maps(year: i32) -> Vec<String> {
+	(0..3).map(|map| format!("{year:0>4}.{:0>2}.{:0>2}", map * 4 + 1, map * 4 + 4)).collect()
+}
+
+/// How the camera moves over a time-lapse. It always looks at the centre of
+/// the planet.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CameraPath {
+	/// Stays at `position` throughout
+	Fixed { position: [f32; 3] },
+	/// Circles the planet's axis `distance` away from it and `height` above
+	/// the equator, starting on the +z axis, making `turns` turns in all
+	Orbit {
+		distance: f32,
+		#[serde(default)]
+		height: f32,
+		turns: f32,
+	},
+}
+
+impl CameraPath {
+	/// Where the camera is `progress` of the way through, from 0 to 1
+	pub fn pose(&self, progress: f32) -> CameraPose {
+		match *self {
+			CameraPath::Fixed { position } => CameraPose { position, target: [0.0; 3] },
+			CameraPath::Orbit { distance, height, turns } => {
+				let angle = progress * turns * 2.0 * std::f32::consts::PI;
+				CameraPose {
+					position: [distance * angle.sin(), height, distance * angle.cos()],
+					target: [0.0; 3],
+				}
+			}
+		}
+	}
+}
+
+fn one() -> u32 { 1 }
+
+fn default_frame_duration_ms() -> u16 { 100 }
+
+/// An animation through every month of a range of years. Each frame's time
+/// and camera depend only on its index, so exports can be repeated, split up
+/// or resumed and still match.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TimeLapse {
+	/// Relative to the assets directory, holding every year's maps as
+	/// `year_maps` names them
+	pub directory: PathBuf,
+	pub first_year: i32,
+	pub last_year: i32,
+	/// More than one spreads each month's frames over its first 28 days, which
+	/// only moves the sun
+	#[serde(default = "one")]
+	pub frames_per_month: u32,
+	pub width: u32,
+	pub height: u32,
+	pub camera: CameraPath,
+	#[serde(default)]
+	pub sunlight: bool,
+	#[serde(default)]
+	pub colormap: Colormap,
+	#[serde(default = "initial_terrain_scale")]
+	pub terrain_scale: f32,
+	/// How long each frame shows for in the animated image
+	#[serde(default = "default_frame_duration_ms")]
+	pub frame_duration_ms: u16,
+}
+
+impl TimeLapse {
+	pub fn from_json(json: &str) -> Result<Self, String> {
+		let time_lapse: Self =
+			serde_json::from_str(json).map_err(|e| format!("Invalid time-lapse: {e}"))?;
+		time_lapse.validate()?;
+		Ok(time_lapse)
+	}
+
+	/// Catches settings that would leave nothing to render, or divide by zero
+	/// when working out frames
+	pub fn validate(&self) -> Result<(), String> {
+		if self.frames_per_month == 0 {
+			return Err("Invalid time-lapse: frames_per_month must be at least 1".to_owned());
+		}
+		if self.last_year < self.first_year {
+			return Err(format!(
+				"Invalid time-lapse: last_year {} is before first_year {}",
+				self.last_year, self.first_year
+			));
+		}
+		if self.width == 0 || self.height == 0 {
+			return Err(format!(
+				"Invalid time-lapse: {}x{} frames have no pixels",
+				self.width, self.height
+			));
+		}
+		Ok(())
+	}
+
+	pub fn frame_count(&self) -> u32 {
+		let years = (self.last_year - self.first_year + 1).max(0) as u32;
+		years * 12 * self.frames_per_month
+	}
+
+	/// The moment and camera pose of frame `index`
+	pub fn frame(&self, index: u32) -> (TimeCursor, CameraPose) {
+		let month_index = index / self.frames_per_month;
+		let step_in_month = index % self.frames_per_month;
+		let time_cursor = TimeCursor {
+			year: self.first_year + (month_index / 12) as i32,
+			month: (month_index % 12) as usize,
+			day: 1 + step_in_month * 28 / self.frames_per_month,
+			..TimeCursor::default()
+		};
+
+		// Full turns of an orbit land back at the start on the frame after the
+		// last, so the animation loops smoothly
+		let progress = index as f32 / self.frame_count() as f32;
+		(time_cursor, self.camera.pose(progress))
+	}
+
+	fn dataset(&self, year: i32) -> Dataset {
+		Dataset {
+			name: year.to_string(),
+			directory: self.directory.clone(),
+			maps: year_maps(year),
+			colormap: self.colormap,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AnimationFormat {
+	Gif,
+	Apng,
+}
+
+impl AnimationFormat {
+	pub fn file_name(self) -> &'static str {
+		match self {
+			AnimationFormat::Gif => "time_lapse.gif",
+			AnimationFormat::Apng => "time_lapse.apng",
+		}
+	}
+}
+
+/// Writes an animated image a frame at a time, so a long time-lapse never has
+/// to be held in memory
+enum AnimationWriter {
+	Gif(GifEncoder<BufWriter<File>>),
+	Apng(png::Writer<BufWriter<File>>),
+}
+
+impl AnimationWriter {
+	fn create(
+		path: &Path,
+		format: AnimationFormat,
+		time_lapse: &TimeLapse,
+	) -> Result<Self, String> {
+		let file = File::create(path).map_err(|e| format!("Couldn't create {path:?}: {e}"))?;
+		let file = BufWriter::new(file);
+
+		match format {
+			AnimationFormat::Gif => {
+				let mut encoder = GifEncoder::new_with_speed(file, 10);
+				encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?;
+				Ok(AnimationWriter::Gif(encoder))
+			}
+			AnimationFormat::Apng => {
+				let mut encoder = png::Encoder::new(file, time_lapse.width, time_lapse.height);
+				encoder.set_color(png::ColorType::Rgba);
+				encoder.set_depth(png::BitDepth::Eight);
+				encoder.set_animated(time_lapse.frame_count(), 0).map_err(|e| e.to_string())?;
+				encoder
+					.set_frame_delay(time_lapse.frame_duration_ms, 1000)
+					.map_err(|e| e.to_string())?;
+				Ok(AnimationWriter::Apng(encoder.write_header().map_err(|e| e.to_string())?))
+			}
+		}
+	}
+
+	fn add(&mut self, image: RgbaImage, duration_ms: u16) -> Result<(), String> {
+		match self {
+			AnimationWriter::Gif(encoder) => {
+				let delay = Delay::from_numer_denom_ms(duration_ms as u32, 1);
+				encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))
+			}
+			.map_err(|e| e.to_string()),
+			AnimationWriter::Apng(writer) => {
+				writer.write_image_data(image.as_raw()).map_err(|e| e.to_string())
+			}
+		}
+	}
+
+	fn finish(self) -> Result<(), String> {
+		match self {
+			// The trailer is written when the encoder is dropped
+			AnimationWriter::Gif(_) => Ok(()),
+			AnimationWriter::Apng(writer) => writer.finish().map_err(|e| e.to_string()),
+		}
+	}
+}
+
+/// Where `render_time_lapse` writes frame `index`, relative to its output
+/// directory. Numbered from 1, ready for `ffmpeg -i frame_%05d.png` to turn
+/// into a video.
+pub fn time_lapse_frame_path(index: u32) -> PathBuf {
+	format!("frame_{:0>5}.png", index + 1).into()
+}
+
+/// Renders every frame of `time_lapse` into `output` as a numbered PNG, and
+/// into an animated image if there's an `animation` format. `on_written` is
+/// told about each frame as it lands. Returns how many frames were written.
+pub fn render_time_lapse(
+	renderer: &mut OfflineRenderer,
+	time_lapse: &TimeLapse,
+	output: &Path,
+	animation: Option<AnimationFormat>,
+	mut on_written: impl FnMut(&Path),
+) -> Result<u32, String> {
+	time_lapse.validate()?;
+	let frame_count = time_lapse.frame_count();
+	fs::create_dir_all(output).map_err(|e| format!("Couldn't create {output:?}: {e}"))?;
+
+	let mut animation = animation
+		.map(|format| AnimationWriter::create(&output.join(format.file_name()), format, time_lapse))
+		.transpose()?;
+
+	for index in 0..frame_count {
+		let (time_cursor, camera) = time_lapse.frame(index);
+		let view = View {
+			name: "time_lapse".to_owned(),
+			width: time_lapse.width,
+			height: time_lapse.height,
+			camera,
+			sunlight: time_lapse.sunlight,
+			terrain_scale: time_lapse.terrain_scale,
+		};
+		let dataset = time_lapse.dataset(time_cursor.year);
+		let image = renderer.render(&dataset, time_cursor, &view, time_lapse.colormap)?;
+
+		let path = output.join(time_lapse_frame_path(index));
+		image.save(&path).map_err(|e| format!("Couldn't write {path:?}: {e}"))?;
+		if let Some(animation) = &mut animation {
+			animation.add(image, time_lapse.frame_duration_ms)?;
+		}
+		on_written(&path);
+	}
+
+	if let Some(animation) = animation {
+		animation.finish()?;
+	}
+	Ok(frame_count)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use image::{DynamicImage, GrayImage, ImageOutputFormat, RgbImage};
+
+	use super::*;
+
+	fn png(image: DynamicImage) -> Vec<u8> {
+		let mut bytes = Cursor::new(Vec::new());
+		image.write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+		bytes.into_inner()
+	}
+
+	/// Flat, green terrain, as the real maps aren't kept in the repo
+	fn terrain() -> Terrain {
+		Terrain {
+			height_map: png(DynamicImage::ImageLuma8(GrayImage::from_pixel(8, 4, Luma([128])))),
+			color_map: png(DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 4, Rgb([40, 160, 60])))),
+			night_map: None,
+		}
+	}
+
+	fn renderer() -> OfflineRenderer {
+		OfflineRenderer::new(
+			Path::new(env!("CARGO_MANIFEST_DIR")).join("www").as_path(),
+			&terrain(),
+		)
+		.unwrap()
+	}
+
+	fn catalog() -> Catalog { Catalog::from_json(include_str!("../render_catalog.json")).unwrap() }
+
+	fn small_view() -> View {
+		View {
+			name: "small".to_owned(),
+			width: 48,
+			height: 32,
+			camera: CameraPose { position: [0.0, 0.0, 3.0], target: [0.0; 3] },
+			sunlight: false,
+			terrain_scale: INITIAL_TERRAIN_SCALE,
+		}
+	}
+
+	#[test]
+	fn reads_the_catalog() {
+		let catalog = catalog();
+		assert_eq!(catalog.datasets[0].name, "earth_temp");
+		assert_eq!(catalog.datasets[0].maps.len(), 3);
+		assert_eq!(catalog.months, every_month());
+		assert!(catalog.views.iter().all(|view| view.terrain_scale == INITIAL_TERRAIN_SCALE));
+	}
+
+	#[test]
+	fn renders_at_any_size() {
+		let mut renderer = renderer();
+		let dataset = &catalog().datasets[0];
+		let view = small_view();
+
+		let hue = renderer.render(dataset, TimeCursor::default(), &view, Colormap::Hue).unwrap();
+		assert_eq!(hue.dimensions(), (48, 32));
+		let background = *hue.get_pixel(0, 0);
+		assert_ne!(*hue.get_pixel(24, 16), background);
+
+		let wide = View { width: 96, ..view.clone() };
+		let image = renderer.render(dataset, TimeCursor::default(), &wide, Colormap::Hue).unwrap();
+		assert_eq!(image.dimensions(), (96, 32));
+
+		// Colormaps only change the data layer
+		let grayscale =
+			renderer.render(dataset, TimeCursor::default(), &view, Colormap::Grayscale).unwrap();
+		assert_eq!(grayscale.get_pixel(0, 0), &background);
+		assert_ne!(grayscale.get_pixel(24, 16), hue.get_pixel(24, 16));
+
+		let december = TimeCursor { month: 12, ..TimeCursor::default() };
+		assert!(renderer.render(dataset, december, &view, Colormap::Hue).is_err());
+	}
+
+	#[test]
+	fn writes_every_image_in_the_catalog() {
+		let catalog = Catalog { views: vec![small_view()], months: vec![0, 7], ..catalog() };
+		let output = std::env::temp_dir().join(format!("ghg_catalog_{}", std::process::id()));
+
+		let mut written = Vec::new();
+		let count = render_catalog(&mut renderer(), &catalog, &output, |path| {
+			written.push(path.to_owned())
+		})
+		.unwrap();
+
+		assert_eq!(count, 2);
+		assert_eq!(
+			written,
+			vec![output.join("earth_temp/small/01.png"), output.join("earth_temp/small/08.png")]
+		);
+		assert!(written.iter().all(|path| path.exists()));
+		fs::remove_dir_all(output).unwrap();
+	}
+
+	fn time_lapse() -> TimeLapse {
+		TimeLapse::from_json(include_str!("../timelapse_1980_2021.json")).unwrap()
+	}
+
+	#[test]
+	fn steps_through_every_month_deterministically() {
+		let time_lapse = TimeLapse { frames_per_month: 2, ..time_lapse() };
+		assert_eq!(time_lapse.frame_count(), 42 * 12 * 2);
+
+		let (first, _) = time_lapse.frame(0);
+		assert_eq!((first.year, first.month, first.day), (1980, 0, 1));
+		let (second, _) = time_lapse.frame(1);
+		assert_eq!((second.year, second.month, second.day), (1980, 0, 15));
+		let (last, _) = time_lapse.frame(time_lapse.frame_count() - 1);
+		assert_eq!((last.year, last.month, last.day), (2021, 11, 15));
+
+		assert_eq!(time_lapse.frame(700), time_lapse.frame(700));
+		assert_eq!(time_lapse.dataset(1995).maps, ["1995.01.04", "1995.05.08", "1995.09.12"]);
+	}
+
+	#[test]
+	fn rejects_time_lapses_without_frames() {
+		assert!(time_lapse().validate().is_ok());
+
+		let json: serde_json::Value =
+			serde_json::from_str(include_str!("../timelapse_1980_2021.json")).unwrap();
+		let with = |key: &str, value: serde_json::Value| {
+			let mut json = json.clone();
+			json[key] = value;
+			TimeLapse::from_json(&json.to_string())
+		};
+		for (key, value) in [
+			("frames_per_month", 0.into()),
+			("last_year", 1979.into()),
+			("width", 0.into()),
+			("height", 0.into()),
+		] {
+			let error = with(key, value).unwrap_err();
+			assert!(error.starts_with("Invalid time-lapse"), "{error}");
+		}
+		assert!(with("last_year", 1980.into()).is_ok());
+	}
+
+	#[test]
+	fn orbits_around_the_planet() {
+		let orbit = CameraPath::Orbit { distance: 3.0, height: 0.5, turns: 1.0 };
+		let start = orbit.pose(0.0).position;
+		assert_eq!(start, [0.0, 0.5, 3.0]);
+
+		let quarter = orbit.pose(0.25).position;
+		assert!((quarter[0] - 3.0).abs() < 1e-5 && quarter[2].abs() < 1e-5);
+		let end = orbit.pose(1.0).position;
+		assert!(start.iter().zip(end).all(|(a, b)| (a - b).abs() < 1e-5));
+		assert!(orbit.pose(0.6).target == [0.0; 3]);
+	}
+
+	#[test]
+	fn writes_frames_and_animations() {
+		let time_lapse = TimeLapse { first_year: 2021, width: 24, height: 16, ..time_lapse() };
+		let output = std::env::temp_dir().join(format!("ghg_time_lapse_{}", std::process::id()));
+		let mut renderer = renderer();
+
+		let mut written = Vec::new();
+		let count = render_time_lapse(
+			&mut renderer,
+			&time_lapse,
+			&output,
+			Some(AnimationFormat::Gif),
+			|path| written.push(path.to_owned()),
+		)
+		.unwrap();
+		assert_eq!(count, 12);
+		assert_eq!(written.first(), Some(&output.join("frame_00001.png")));
+		assert_eq!(written.last(), Some(&output.join("frame_00012.png")));
+		assert!(written.iter().all(|path| path.exists()));
+
+		let gif = File::open(output.join("time_lapse.gif")).unwrap();
+		let frames = image::codecs::gif::GifDecoder::new(gif).unwrap();
+		assert_eq!(image::AnimationDecoder::into_frames(frames).count(), 12);
+
+		render_time_lapse(&mut renderer, &time_lapse, &output, Some(AnimationFormat::Apng), |_| {})
+			.unwrap();
+		let apng = png::Decoder::new(File::open(output.join("time_lapse.apng")).unwrap());
+		let info = apng.read_info().unwrap().info().animation_control.unwrap();
+		assert_eq!((info.num_frames, info.num_plays), (12, 0));
+
+		// Years without data fail rather than leaving gaps
+		let missing = TimeLapse { first_year: 1980, last_year: 1980, ..time_lapse };
+		assert!(render_time_lapse(&mut renderer, &missing, &output, None, |_| {}).is_err());
+		fs::remove_dir_all(output).unwrap();
+	}
+}
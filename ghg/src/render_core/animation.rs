@@ -9,9 +9,21 @@ use crate::render_core::animation_params::AnimationParams;
 use crate::utils::prelude::*;
 use crate::Viewport;
 
-pub type AnimationFn = Box<dyn FnMut(AnimationParams)>;
+/// Whether the animation body started a frame. A held frame leaves its time to
+/// the next one that starts, so `delta_time` still covers the whole gap.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameStatus {
+	Started,
+	Held,
+}
+
+pub type AnimationFn = Box<dyn FnMut(AnimationParams) -> FrameStatus>;
 
-pub fn wrap_animation_body<F: 'static + FnMut(AnimationParams)>(f: F) -> AnimationFn { Box::new(f) }
+pub fn wrap_animation_body<F: 'static + FnMut(AnimationParams) -> FrameStatus>(
+	f: F,
+) -> AnimationFn {
+	Box::new(f)
+}
 
 fn window() -> web_sys::Window { web_sys::window().expect("no global `window` exists") }
 
@@ -33,13 +45,15 @@ pub fn run_animation_loop(viewport: Viewport, mut animation_body: AnimationFn) {
 		let this_frame_time = performance.now();
 		let duration_millis: f64 = this_frame_time - last_frame_time.borrow().clone();
 		let duration = Duration::from_micros((duration_millis * 1000.0) as u64);
-		last_frame_time.replace(this_frame_time);
 
 		viewport.on_frame();
-		animation_body.deref_mut()(AnimationParams {
+		let status = animation_body.deref_mut()(AnimationParams {
 			viewport: viewport.clone(),
 			delta_time: duration,
 		});
+		if status == FrameStatus::Started {
+			last_frame_time.replace(this_frame_time);
+		}
 		request_animation_frame(next_frame.borrow().as_ref().unwrap());
 	}) as Box<dyn FnMut()>));
 
@@ -0,0 +1,83 @@
+//! Rotations for the arcball camera mode. Unlike orbiting around a fixed world
+//! up, these never need to be clamped near the poles, because the camera's own
+//! up vector is rotated along with it.
+
+/// Rotation for a drag of `movement` radians. Horizontal movement turns around
+/// `up`, or the planet's axis unless `free_rotation` is set, and vertical
+/// movement turns around `right`.
+pub fn drag_rotation(
+	movement: &nglm::Vec2,
+	right: &nglm::Vec3,
+	up: &nglm::Vec3,
+	free_rotation: bool,
+) -> nglm::Quat {
+	let horizontal_axis = if free_rotation { *up } else { nglm::Vec3::y() };
+
+	let horizontal = nglm::quat_angle_axis(movement.x, &horizontal_axis);
+	let vertical = nglm::quat_angle_axis(movement.y, right);
+	horizontal * vertical
+}
+
+pub fn rotate_about(point: &nglm::Vec3, center: &nglm::Vec3, rotation: &nglm::Quat) -> nglm::Vec3 {
+	center + nglm::quat_rotate_vec3(rotation, &(point - center))
+}
+
+/// Makes `up` perpendicular to `forward`. When they're parallel, any
+/// perpendicular vector will do.
+pub fn orthonormal_up(forward: &nglm::Vec3, up: &nglm::Vec3) -> nglm::Vec3 {
+	const PARALLEL_EPSILON: f32 = 1.0e-6;
+
+	let perpendicular = up - forward * forward.dot(up);
+	if perpendicular.magnitude() > PARALLEL_EPSILON {
+		return perpendicular.normalize();
+	}
+
+	let fallback = if forward.y.abs() < 0.9 { nglm::Vec3::y() } else { nglm::Vec3::z() };
+	(fallback - forward * forward.dot(&fallback)).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const EPSILON: f32 = 1.0e-5;
+
+	#[test]
+	fn opposite_drags_cancel() {
+		let right = nglm::Vec3::x();
+		let up = nglm::Vec3::y();
+		let point = nglm::vec3(0.3, -0.2, 2.0);
+
+		for free_rotation in [false, true] {
+			let there = drag_rotation(&nglm::vec2(0.0, 0.7), &right, &up, free_rotation);
+			let back = drag_rotation(&nglm::vec2(0.0, -0.7), &right, &up, free_rotation);
+
+			let moved = rotate_about(&point, &nglm::Vec3::zeros(), &there);
+			let returned = rotate_about(&moved, &nglm::Vec3::zeros(), &back);
+			assert!(returned.metric_distance(&point) < EPSILON);
+		}
+	}
+
+	#[test]
+	fn rotation_preserves_distance() {
+		let rotation =
+			drag_rotation(&nglm::vec2(1.3, -2.1), &nglm::Vec3::x(), &nglm::Vec3::y(), true);
+		let center = nglm::vec3(1.0, 2.0, 3.0);
+		let point = nglm::vec3(4.0, 0.0, -1.0);
+
+		let rotated = rotate_about(&point, &center, &rotation);
+		assert!((rotated.metric_distance(&center) - point.metric_distance(&center)).abs() < 1.0e-4);
+	}
+
+	#[test]
+	fn up_is_made_perpendicular() {
+		let forward = nglm::vec3(0.0, 0.0, -1.0);
+
+		let up = orthonormal_up(&forward, &nglm::vec3(0.0, 1.0, 0.5));
+		assert!(up.metric_distance(&nglm::Vec3::y()) < EPSILON);
+
+		let fallback = orthonormal_up(&nglm::Vec3::y(), &nglm::Vec3::y());
+		assert!(fallback.dot(&nglm::Vec3::y()).abs() < EPSILON);
+		assert!((fallback.magnitude() - 1.0).abs() < EPSILON);
+	}
+}
@@ -0,0 +1,410 @@
+use std::fmt::Debug;
+
+use web_sys::{
+	WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlShader, WebGlTexture,
+	WebGlUniformLocation, WebGlVertexArrayObject,
+};
+
+/// The GPU calls rendering is built from. `WebGl2RenderingContext` is the
+/// real thing; `RecordingBackend` stands in for it where there's no browser,
+/// so rendering code can be tested natively.
+pub trait GraphicsBackend: Clone + Debug + 'static {
+	type Buffer: Clone + Debug;
+	type VertexArray: Clone + Debug;
+	type Texture: Clone + Debug;
+	type Shader: Clone + Debug;
+	type Program: Clone + Debug;
+	type UniformLocation: Clone + Debug;
+
+	fn create_buffer(&self) -> Option<Self::Buffer>;
+	fn bind_buffer(&self, target: BufferTarget, buffer: Option<&Self::Buffer>);
+	/// Uploads to whichever buffer is bound to `target`
+	fn buffer_data_f32(&self, target: BufferTarget, data: &[f32], usage: BufferUsage);
+	fn buffer_data_u32(&self, target: BufferTarget, data: &[u32], usage: BufferUsage);
+
+	fn create_vertex_array(&self) -> Option<Self::VertexArray>;
+	fn bind_vertex_array(&self, vertex_array: Option<&Self::VertexArray>);
+	/// Enables the attribute at `location` and points it at `size` floats per
+	/// vertex of the bound array buffer. `stride` and `offset` are in bytes.
+	fn vertex_attribute_f32(&self, location: u32, size: i32, stride: i32, offset: i32);
+
+	fn create_texture(&self) -> Option<Self::Texture>;
+	/// Counts from zero, rather than from `TEXTURE0`
+	fn active_texture(&self, unit: u32);
+	fn bind_texture(&self, texture: Option<&Self::Texture>);
+	/// Sets the filters of the bound texture, clamping it at the edges
+	fn texture_sampling(&self, min_filter: TextureFilter, mag_filter: TextureFilter);
+	/// Fills the bound texture with tightly packed 8-bit pixels
+	fn texture_image(
+		&self,
+		format: TextureFormat,
+		width: u32,
+		height: u32,
+		pixels: &[u8],
+	) -> Result<(), String>;
+
+	fn compile_shader(&self, kind: ShaderKind, source: &str) -> Result<Self::Shader, String>;
+	fn link_program(
+		&self,
+		vertex: &Self::Shader,
+		fragment: &Self::Shader,
+	) -> Result<Self::Program, String>;
+	fn use_program(&self, program: Option<&Self::Program>);
+	fn attribute_location(&self, program: &Self::Program, name: &str) -> Option<u32>;
+	/// `None` for uniforms the program doesn't have, including ones the shader
+	/// compiler removed for being unused
+	fn uniform_location(
+		&self,
+		program: &Self::Program,
+		name: &str,
+	) -> Option<Self::UniformLocation>;
+	/// Writes to the program in use. Writing to a `None` location does nothing.
+	fn write_uniform(&self, location: Option<&Self::UniformLocation>, value: UniformData);
+
+	/// Clears both the color and depth buffers
+	fn clear(&self, color: &nglm::Vec3);
+	fn set_depth(&self, function: DepthFunction, write: bool);
+	/// Draws `count` `u32` indices from the bound element array buffer
+	fn draw_elements(&self, primitive: Primitive, count: i32);
+	fn draw_arrays(&self, primitive: Primitive, first: i32, count: i32);
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BufferTarget {
+	Array,
+	ElementArray,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BufferUsage {
+	Static,
+	Dynamic,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextureFilter {
+	Linear,
+	Nearest,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextureFormat {
+	Luminance,
+	Rgb,
+	Rgba,
+}
+
+impl TextureFormat {
+	#[allow(dead_code)]
+	pub fn bytes_per_pixel(self) -> usize {
+		match self {
+			TextureFormat::Luminance => 1,
+			TextureFormat::Rgb => 3,
+			TextureFormat::Rgba => 4,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ShaderKind {
+	Vertex,
+	Fragment,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DepthFunction {
+	Less,
+	LessOrEqual,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Primitive {
+	Triangles,
+	LineStrip,
+	Points,
+}
+
+/// A value for a uniform, typed so a backend can check it against what the
+/// shader declared
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UniformData {
+	Int(i32),
+	Float(f32),
+	Vec3(nglm::Vec3),
+	Vec4(nglm::Vec4),
+	Mat4(nglm::Mat4),
+	/// Declared as `mat3x4` in GLSL, which counts columns first
+	Mat4x3(nglm::Mat4x3),
+}
+
+impl UniformData {
+	/// Whether this can be written to a uniform declared as `glsl_type`
+	#[allow(dead_code)]
+	pub fn fits(&self, glsl_type: &str) -> bool {
+		match self {
+			UniformData::Int(_) => matches!(glsl_type, "int" | "bool" | "sampler2D"),
+			UniformData::Float(_) => glsl_type == "float",
+			UniformData::Vec3(_) => glsl_type == "vec3",
+			UniformData::Vec4(_) => glsl_type == "vec4",
+			UniformData::Mat4(_) => glsl_type == "mat4",
+			UniformData::Mat4x3(_) => glsl_type == "mat3x4",
+		}
+	}
+}
+
+impl GraphicsBackend for WebGl2RenderingContext {
+	type Buffer = WebGlBuffer;
+	type Program = WebGlProgram;
+	type Shader = WebGlShader;
+	type Texture = WebGlTexture;
+	type UniformLocation = WebGlUniformLocation;
+	type VertexArray = WebGlVertexArrayObject;
+
+	fn create_buffer(&self) -> Option<Self::Buffer> { WebGl2RenderingContext::create_buffer(self) }
+
+	fn bind_buffer(&self, target: BufferTarget, buffer: Option<&Self::Buffer>) {
+		WebGl2RenderingContext::bind_buffer(self, gl_buffer_target(target), buffer);
+	}
+
+	fn buffer_data_f32(&self, target: BufferTarget, data: &[f32], usage: BufferUsage) {
+		// Safe as long as nothing allocates before the view is handed over
+		unsafe {
+			let view = js_sys::Float32Array::view(data);
+			self.buffer_data_with_array_buffer_view(
+				gl_buffer_target(target),
+				&view,
+				gl_buffer_usage(usage),
+			);
+		}
+	}
+
+	fn buffer_data_u32(&self, target: BufferTarget, data: &[u32], usage: BufferUsage) {
+		unsafe {
+			let view = js_sys::Uint32Array::view(data);
+			self.buffer_data_with_array_buffer_view(
+				gl_buffer_target(target),
+				&view,
+				gl_buffer_usage(usage),
+			);
+		}
+	}
+
+	fn create_vertex_array(&self) -> Option<Self::VertexArray> {
+		WebGl2RenderingContext::create_vertex_array(self)
+	}
+
+	fn bind_vertex_array(&self, vertex_array: Option<&Self::VertexArray>) {
+		WebGl2RenderingContext::bind_vertex_array(self, vertex_array);
+	}
+
+	fn vertex_attribute_f32(&self, location: u32, size: i32, stride: i32, offset: i32) {
+		self.enable_vertex_attrib_array(location);
+		self.vertex_attrib_pointer_with_i32(
+			location,
+			size,
+			WebGl2RenderingContext::FLOAT,
+			false,
+			stride,
+			offset,
+		);
+	}
+
+	fn create_texture(&self) -> Option<Self::Texture> {
+		WebGl2RenderingContext::create_texture(self)
+	}
+
+	fn active_texture(&self, unit: u32) {
+		WebGl2RenderingContext::active_texture(self, WebGl2RenderingContext::TEXTURE0 + unit);
+	}
+
+	fn bind_texture(&self, texture: Option<&Self::Texture>) {
+		WebGl2RenderingContext::bind_texture(self, WebGl2RenderingContext::TEXTURE_2D, texture);
+	}
+
+	fn texture_sampling(&self, min_filter: TextureFilter, mag_filter: TextureFilter) {
+		let parameters = [
+			(WebGl2RenderingContext::TEXTURE_MIN_FILTER, gl_texture_filter(min_filter)),
+			(WebGl2RenderingContext::TEXTURE_MAG_FILTER, gl_texture_filter(mag_filter)),
+			(WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE),
+			(WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE),
+		];
+		for (parameter, value) in parameters {
+			self.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, parameter, value as i32);
+		}
+	}
+
+	fn texture_image(
+		&self,
+		format: TextureFormat,
+		width: u32,
+		height: u32,
+		pixels: &[u8],
+	) -> Result<(), String> {
+		let format = gl_texture_format(format);
+
+		// Lol. This should just be a builder.
+		self.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+			WebGl2RenderingContext::TEXTURE_2D,
+			0,
+			format as i32,
+			width as i32,
+			height as i32,
+			0,
+			format,
+			WebGl2RenderingContext::UNSIGNED_BYTE,
+			Some(pixels),
+		)
+		.map_err(|e| format!("{e:?}"))
+	}
+
+	fn compile_shader(&self, kind: ShaderKind, source: &str) -> Result<Self::Shader, String> {
+		let shader_type = match kind {
+			ShaderKind::Vertex => WebGl2RenderingContext::VERTEX_SHADER,
+			ShaderKind::Fragment => WebGl2RenderingContext::FRAGMENT_SHADER,
+		};
+		let shader = self
+			.create_shader(shader_type)
+			.ok_or_else(|| String::from("Unable to create shader object"))?;
+
+		self.shader_source(&shader, source);
+		WebGl2RenderingContext::compile_shader(self, &shader);
+
+		if self
+			.get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
+			.as_bool()
+			.unwrap_or(false)
+		{
+			Ok(shader)
+		} else {
+			Err(self
+				.get_shader_info_log(&shader)
+				.unwrap_or_else(|| String::from("Unknown error creating shader")))
+		}
+	}
+
+	fn link_program(
+		&self,
+		vertex: &Self::Shader,
+		fragment: &Self::Shader,
+	) -> Result<Self::Program, String> {
+		let program =
+			self.create_program().ok_or_else(|| String::from("Unable to create shader object"))?;
+
+		self.attach_shader(&program, vertex);
+		self.attach_shader(&program, fragment);
+		WebGl2RenderingContext::link_program(self, &program);
+
+		if self
+			.get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
+			.as_bool()
+			.unwrap_or(false)
+		{
+			Ok(program)
+		} else {
+			Err(self
+				.get_program_info_log(&program)
+				.unwrap_or_else(|| String::from("Unknown error creating program object")))
+		}
+	}
+
+	fn use_program(&self, program: Option<&Self::Program>) {
+		WebGl2RenderingContext::use_program(self, program);
+	}
+
+	fn attribute_location(&self, program: &Self::Program, name: &str) -> Option<u32> {
+		u32::try_from(self.get_attrib_location(program, name)).ok()
+	}
+
+	fn uniform_location(
+		&self,
+		program: &Self::Program,
+		name: &str,
+	) -> Option<Self::UniformLocation> {
+		self.get_uniform_location(program, name)
+	}
+
+	fn write_uniform(&self, location: Option<&Self::UniformLocation>, value: UniformData) {
+		match value {
+			UniformData::Int(value) => self.uniform1i(location, value),
+			UniformData::Float(value) => self.uniform1f(location, value),
+			UniformData::Vec3(value) => self.uniform3f(location, value.x, value.y, value.z),
+			UniformData::Vec4(value) => {
+				self.uniform4f(location, value.x, value.y, value.z, value.w)
+			}
+			UniformData::Mat4(value) => {
+				self.uniform_matrix4fv_with_f32_array(location, false, value.as_slice())
+			}
+			// TODO: Uh... This switches column/row. Is that expected?
+			UniformData::Mat4x3(value) => {
+				self.uniform_matrix3x4fv_with_f32_array(location, false, value.as_slice())
+			}
+		}
+	}
+
+	fn clear(&self, color: &nglm::Vec3) {
+		self.clear_color(color.x, color.y, color.z, 1.0);
+		WebGl2RenderingContext::clear(
+			self,
+			WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT,
+		);
+	}
+
+	fn set_depth(&self, function: DepthFunction, write: bool) {
+		self.depth_func(match function {
+			DepthFunction::Less => WebGl2RenderingContext::LESS,
+			DepthFunction::LessOrEqual => WebGl2RenderingContext::LEQUAL,
+		});
+		self.depth_mask(write);
+	}
+
+	fn draw_elements(&self, primitive: Primitive, count: i32) {
+		self.draw_elements_with_i32(
+			gl_primitive(primitive),
+			count,
+			WebGl2RenderingContext::UNSIGNED_INT,
+			0,
+		);
+	}
+
+	fn draw_arrays(&self, primitive: Primitive, first: i32, count: i32) {
+		WebGl2RenderingContext::draw_arrays(self, gl_primitive(primitive), first, count);
+	}
+}
+
+fn gl_buffer_target(target: BufferTarget) -> u32 {
+	match target {
+		BufferTarget::Array => WebGl2RenderingContext::ARRAY_BUFFER,
+		BufferTarget::ElementArray => WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+	}
+}
+
+fn gl_buffer_usage(usage: BufferUsage) -> u32 {
+	match usage {
+		BufferUsage::Static => WebGl2RenderingContext::STATIC_DRAW,
+		BufferUsage::Dynamic => WebGl2RenderingContext::DYNAMIC_DRAW,
+	}
+}
+
+fn gl_texture_filter(filter: TextureFilter) -> u32 {
+	match filter {
+		TextureFilter::Linear => WebGl2RenderingContext::LINEAR,
+		TextureFilter::Nearest => WebGl2RenderingContext::NEAREST,
+	}
+}
+
+// Combinations: https://registry.khronos.org/webgl/specs/latest/2.0/#TEXTURE_TYPES_FORMATS_FROM_DOM_ELEMENTS_TABLE
+fn gl_texture_format(format: TextureFormat) -> u32 {
+	match format {
+		TextureFormat::Luminance => WebGl2RenderingContext::LUMINANCE,
+		TextureFormat::Rgb => WebGl2RenderingContext::RGB,
+		TextureFormat::Rgba => WebGl2RenderingContext::RGBA,
+	}
+}
+
+fn gl_primitive(primitive: Primitive) -> u32 {
+	match primitive {
+		Primitive::Triangles => WebGl2RenderingContext::TRIANGLES,
+		Primitive::LineStrip => WebGl2RenderingContext::LINE_STRIP,
+		Primitive::Points => WebGl2RenderingContext::POINTS,
+	}
+}
@@ -1,36 +1,183 @@
 #![allow(dead_code)]
 
+use crate::render_core::arcball;
+use crate::render_core::culling::TerrainShell;
+
+/// Used by `ClipPlanes::AroundShell`. Keeping the near plane at a fraction of
+/// the altitude keeps the ratio of far to near, and so the depth precision,
+/// roughly constant.
+const NEAR_ALTITUDE_FRACTION: f32 = 0.5;
+const MIN_NEAR: f32 = 1.0e-5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+	/// Orbits with a fixed world up, and stops just short of the poles
+	Orbit,
+	/// Carries its own up vector, so it can go over the poles. With
+	/// `free_rotation`, horizontal drags turn around the camera's up rather
+	/// than the planet's axis, so the globe can end up at any angle.
+	Arcball { free_rotation: bool },
+}
+
+impl CameraMode {
+	/// Cycles through every mode
+	pub fn next(self) -> Self {
+		match self {
+			CameraMode::Orbit => CameraMode::Arcball { free_rotation: false },
+			CameraMode::Arcball { free_rotation: false } => {
+				CameraMode::Arcball { free_rotation: true }
+			}
+			CameraMode::Arcball { free_rotation: true } => CameraMode::Orbit,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ClipPlanes {
+	Fixed {
+		near: f32,
+		far: f32,
+	},
+	/// Fits the planes around a planet's terrain, so the near plane gets closer
+	/// as the camera gets lower, and the far plane reaches just past the
+	/// horizon
+	AroundShell(TerrainShell),
+}
+
+impl ClipPlanes {
+	/// Returns (near, far) for a camera at `position`
+	pub fn distances(&self, position: &nglm::Vec3) -> (f32, f32) {
+		match self {
+			ClipPlanes::Fixed { near, far } => (*near, *far),
+			ClipPlanes::AroundShell(shell) => {
+				let distance = position.magnitude();
+				let altitude = distance - shell.outer_radius;
+				let near = (altitude * NEAR_ALTITUDE_FRACTION).max(MIN_NEAR);
+
+				// Past the tangent point to the lowest surface, nothing can be seen
+				// except terrain rising up to the outer radius
+				let far = if distance > shell.inner_radius {
+					(distance * distance - shell.inner_radius * shell.inner_radius).sqrt()
+						+ (shell.outer_radius * shell.outer_radius
+							- shell.inner_radius * shell.inner_radius)
+							.sqrt()
+				} else {
+					distance + shell.outer_radius
+				};
+
+				(near, far.max(near * 2.0))
+			}
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Projection {
+	/// Vertical field of view
+	pub field_of_view: f32,
+	pub clip_planes: ClipPlanes,
+	/// Whether shaders should write logarithmic depth, see
+	/// `log_depth_coefficient`
+	pub logarithmic_depth: bool,
+}
+
+impl Default for Projection {
+	fn default() -> Self {
+		Self {
+			field_of_view: nglm::quarter_pi(),
+			clip_planes: ClipPlanes::Fixed { near: 0.1, far: 10.0 },
+			logarithmic_depth: false,
+		}
+	}
+}
+
+/// Scale for `log2(1 + w)` that maps the far plane to a depth of 1. Matches
+/// `logarithmicDepth` in `depth.glsl`.
+pub fn log_depth_coefficient(far: f32) -> f32 { 2.0 / (far + 1.0).log2() }
+
 pub struct Camera {
 	position: nglm::Vec3,
 	forward: nglm::Vec3,
 	world_up: nglm::Vec3,
+	mode: CameraMode,
+	/// Only kept up to date in arcball mode
+	arcball_up: nglm::Vec3,
+	projection: Projection,
 }
 
 pub struct MvpMatrices {
 	pub model: nglm::Mat4,
 	pub view: nglm::Mat4,
 	pub projection: nglm::Mat4,
+	pub near: f32,
+	pub far: f32,
 }
 
 impl Camera {
 	pub fn new(position: &nglm::Vec3, target: &nglm::Vec3) -> Self {
+		let forward = nglm::normalize(&(target - position));
+		let world_up = nglm::Vec3::y();
 		Self {
 			position: position.clone(),
-			forward: nglm::normalize(&(target - position)),
-			world_up: nglm::Vec3::y(),
+			forward,
+			world_up,
+			mode: CameraMode::Orbit,
+			arcball_up: arcball::orthonormal_up(&forward, &world_up),
+			projection: Projection::default(),
 		}
 	}
 
+	pub fn projection(&self) -> &Projection { &self.projection }
+
+	pub fn set_projection(&mut self, projection: Projection) { self.projection = projection; }
+
+	pub fn set_field_of_view(&mut self, field_of_view: f32) {
+		self.projection.field_of_view = field_of_view;
+	}
+
+	pub fn set_clip_planes(&mut self, clip_planes: ClipPlanes) {
+		self.projection.clip_planes = clip_planes;
+	}
+
+	pub fn mode(&self) -> CameraMode { self.mode }
+
+	/// Entering arcball mode keeps the view as it is. Leaving it keeps the
+	/// position and view direction, but orbit mode always takes its up from
+	/// the world, so a view that was rolled (by free rotation, or by crossing a
+	/// pole) turns back upright. Right over a pole, the camera is also nudged
+	/// off it so that orbiting has a defined up.
+	pub fn set_mode(&mut self, mode: CameraMode) {
+		const POLE_EPSILON: f32 = 1.0e-3;
+		const POLE_NUDGE_RADIANS: f32 = 1.0e-2;
+
+		match (self.mode, mode) {
+			(CameraMode::Orbit, CameraMode::Arcball { .. }) => {
+				self.arcball_up = self.up();
+			}
+			(CameraMode::Arcball { .. }, CameraMode::Orbit)
+				if self.forward.cross(&self.world_up).magnitude() < POLE_EPSILON =>
+			{
+				let nudge = nglm::quat_angle_axis(POLE_NUDGE_RADIANS, &self.right());
+				self.position = arcball::rotate_about(&self.position, &self.target(), &nudge);
+				self.forward = nglm::quat_rotate_vec3(&nudge, &self.forward);
+			}
+			_ => {}
+		}
+
+		self.mode = mode;
+	}
+
 	pub fn get_perspective_matrices(&self, screen_width: i32, screen_height: i32) -> MvpMatrices {
 		let aspect_ratio = (screen_width as f32) / (screen_height as f32);
-		let fov_radians = nglm::quarter_pi::<f32>();
+		let fov_radians = self.projection.field_of_view;
+		let (near, far) = self.projection.clip_planes.distances(&self.position);
 		let model: nglm::Mat4 = nglm::identity();
 		let view = nglm::look_at(&self.position(), &self.target(), &self.up());
-		let mut projection = nglm::perspective(aspect_ratio, fov_radians, 0.1, 10.0);
+		let mut projection = nglm::perspective(aspect_ratio, fov_radians, near, far);
 
 		projection.data.0[1][1] *= -1.0; // Flip so y points upwards
 
-		MvpMatrices { model, view, projection }
+		MvpMatrices { model, view, projection, near, far }
 	}
 
 	pub fn position(&self) -> nglm::Vec3 { self.position }
@@ -41,6 +188,9 @@ impl Camera {
 
 	pub fn set_target(&mut self, target: nglm::Vec3) {
 		self.forward = nglm::normalize(&(target - self.position));
+		if let CameraMode::Arcball { .. } = self.mode {
+			self.arcball_up = arcball::orthonormal_up(&self.forward, &self.arcball_up);
+		}
 	}
 
 	/// Nothing happens if any component of translation is NaN
@@ -71,6 +221,15 @@ impl Camera {
 			nglm::vec2(sphere_movement[0].to_radians(), sphere_movement[1].to_radians());
 		let sized_movement = radians_movement * sensitivity;
 
+		if let CameraMode::Arcball { free_rotation } = self.mode {
+			let rotation =
+				arcball::drag_rotation(&sized_movement, &self.right(), &self.up(), free_rotation);
+			self.position = arcball::rotate_about(&self.position, target, &rotation);
+			self.arcball_up = nglm::quat_rotate_vec3(&rotation, &self.arcball_up);
+			self.set_target(*target);
+			return;
+		}
+
 		// XZ
 		let flat_rotation = nglm::rotate(&nglm::identity(), sized_movement.x, &nglm::Vec3::y());
 		let mut position4 = nglm::vec3_to_vec4(&self.position());
@@ -89,10 +248,22 @@ impl Camera {
 	pub fn forward(&self) -> nglm::Vec3 { self.forward }
 
 	pub fn right(&self) -> nglm::Vec3 {
-		-1.0 * nglm::normalize(&nglm::cross(&self.world_up, &self.forward))
+		match self.mode {
+			CameraMode::Orbit => {
+				-1.0 * nglm::normalize(&nglm::cross(&self.world_up, &self.forward))
+			}
+			CameraMode::Arcball { .. } => {
+				nglm::normalize(&nglm::cross(&self.forward, &self.arcball_up))
+			}
+		}
 	}
 
-	pub fn up(&self) -> nglm::Vec3 { -1.0 * nglm::cross(&self.forward, &self.right()) }
+	pub fn up(&self) -> nglm::Vec3 {
+		match self.mode {
+			CameraMode::Orbit => -1.0 * nglm::cross(&self.forward, &self.right()),
+			CameraMode::Arcball { .. } => self.arcball_up,
+		}
+	}
 
 	fn forward_xz_plane(&self) -> nglm::Vec2 { nglm::vec2(self.forward[0], self.forward[2]) }
 
@@ -130,3 +301,173 @@ fn clamp_vertically(position: &nglm::Vec3, vertical_angle_delta: f32) -> f32 {
 	}
 	vertical_angle_delta
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const EPSILON: f32 = 1.0e-4;
+
+	fn view_matrix(camera: &Camera) -> nglm::Mat4 { camera.get_perspective_matrices(800, 600).view }
+
+	/// Fraction of the way from the equator to a pole
+	fn polar_proximity(camera: &Camera) -> f32 { camera.position().normalize().y.abs() }
+
+	fn shell() -> TerrainShell { TerrainShell { inner_radius: 0.98, outer_radius: 1.02 } }
+
+	#[test]
+	fn fixed_clip_planes() {
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::Vec3::zeros());
+		let mvp = camera.get_perspective_matrices(800, 600);
+		assert_eq!((mvp.near, mvp.far), (0.1, 10.0));
+	}
+
+	#[test]
+	fn near_plane_follows_altitude() {
+		let clip_planes = ClipPlanes::AroundShell(shell());
+
+		let (high_near, _) = clip_planes.distances(&nglm::vec3(0.0, 0.0, 3.0));
+		let (low_near, _) = clip_planes.distances(&nglm::vec3(0.0, 0.0, 1.03));
+		assert!((high_near - (3.0 - 1.02) * NEAR_ALTITUDE_FRACTION).abs() < EPSILON);
+		assert!((low_near - 0.01 * NEAR_ALTITUDE_FRACTION).abs() < EPSILON);
+
+		// Never zero or negative, even below the highest terrain
+		let (inside_near, inside_far) = clip_planes.distances(&nglm::vec3(0.0, 0.0, 1.0));
+		assert!(inside_near > 0.0);
+		assert!(inside_far > inside_near);
+	}
+
+	#[test]
+	fn far_plane_reaches_the_horizon() {
+		let shell = shell();
+		let position = nglm::vec3(0.0, 0.0, 1.5);
+		let (_, far) = ClipPlanes::AroundShell(shell).distances(&position);
+
+		// The tangent point on the lowest terrain, and the highest terrain behind it
+		let tangent_distance = (1.5f32 * 1.5 - shell.inner_radius * shell.inner_radius).sqrt();
+		assert!(far >= tangent_distance);
+		assert!(far < 1.5 + shell.outer_radius);
+
+		// The near plane stays in front of the closest surface
+		let (near, _) = ClipPlanes::AroundShell(shell).distances(&position);
+		assert!(near < 1.5 - shell.outer_radius);
+	}
+
+	#[test]
+	fn field_of_view_is_used() {
+		let mut camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::Vec3::zeros());
+		let wide = camera.get_perspective_matrices(800, 600).projection;
+		camera.set_field_of_view(nglm::quarter_pi::<f32>() / 2.0);
+		let narrow = camera.get_perspective_matrices(800, 600).projection;
+
+		// Focal length grows as the view narrows
+		assert!(narrow[(0, 0)].abs() > wide[(0, 0)].abs());
+	}
+
+	#[test]
+	fn log_depth_maps_far_plane_to_one() {
+		// depth.glsl's logDepthInput and logarithmicDepth
+		let depth = |w: f32, far: f32| (1.0 + w).log2() * log_depth_coefficient(far) * 0.5;
+
+		for far in [1.5f32, 7.5, 1000.0] {
+			assert!(depth(0.0, far).abs() < EPSILON);
+			assert!((depth(far, far) - 1.0).abs() < EPSILON);
+			assert!(depth(far / 2.0, far) > 0.0 && depth(far / 2.0, far) < 1.0);
+		}
+	}
+
+	#[test]
+	fn switching_modes_keeps_the_view() {
+		let mut camera = Camera::new(&nglm::vec3(1.0, 2.0, 2.5), &nglm::Vec3::zeros());
+		let original = view_matrix(&camera);
+
+		camera.set_mode(CameraMode::Arcball { free_rotation: false });
+		assert!((view_matrix(&camera) - original).abs().max() < EPSILON);
+
+		camera.set_mode(CameraMode::Arcball { free_rotation: true });
+		assert!((view_matrix(&camera) - original).abs().max() < EPSILON);
+
+		camera.set_mode(CameraMode::Orbit);
+		assert!((view_matrix(&camera) - original).abs().max() < EPSILON);
+	}
+
+	#[test]
+	fn arcball_drags_round_trip() {
+		for free_rotation in [false, true] {
+			let mut camera = Camera::new(&nglm::vec3(0.0, 0.5, 3.0), &nglm::Vec3::zeros());
+			camera.set_mode(CameraMode::Arcball { free_rotation });
+			let original = view_matrix(&camera);
+
+			camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(0.0, 150.0), 1.0);
+			camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(0.0, -150.0), 1.0);
+			camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(40.0, 0.0), 1.0);
+			camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(-40.0, 0.0), 1.0);
+
+			assert!((view_matrix(&camera) - original).abs().max() < 1.0e-3);
+		}
+	}
+
+	#[test]
+	fn arcball_crosses_poles_continuously() {
+		for free_rotation in [false, true] {
+			let mut camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::Vec3::zeros());
+			camera.set_mode(CameraMode::Arcball { free_rotation });
+
+			let mut closest_to_pole = 0.0f32;
+			for _ in 0..120 {
+				let last_position = camera.position();
+				let last_up = camera.up();
+
+				camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(0.0, 2.0), 1.0);
+
+				// 2 degrees of arc at a distance of 3
+				assert!(camera.position().metric_distance(&last_position) < 0.11);
+				assert!(camera.up().dot(&last_up) > 0.99, "Up vector flipped");
+				assert!(view_matrix(&camera).iter().all(|v| v.is_finite()));
+
+				closest_to_pole = closest_to_pole.max(polar_proximity(&camera));
+			}
+
+			assert!(closest_to_pole > 0.999);
+			assert!((camera.position().magnitude() - 3.0).abs() < EPSILON);
+		}
+	}
+
+	#[test]
+	fn orbit_stops_short_of_poles() {
+		let mut camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::Vec3::zeros());
+		for _ in 0..120 {
+			camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(0.0, 2.0), 1.0);
+		}
+		assert!(polar_proximity(&camera) < 1.0);
+		assert!(view_matrix(&camera).iter().all(|v| v.is_finite()));
+	}
+
+	#[test]
+	fn leaving_arcball_levels_a_rolled_view() {
+		let mut camera = Camera::new(&nglm::vec3(0.0, 0.5, 3.0), &nglm::Vec3::zeros());
+		camera.set_mode(CameraMode::Arcball { free_rotation: true });
+		camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(40.0, 0.0), 1.0);
+		camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(0.0, 30.0), 1.0);
+		let (position, forward) = (camera.position(), camera.forward());
+		assert!(camera.right().y.abs() > 0.1, "Not rolled");
+
+		camera.set_mode(CameraMode::Orbit);
+		assert!(camera.position().metric_distance(&position) < EPSILON);
+		assert!(camera.forward().metric_distance(&forward) < EPSILON);
+		assert!(camera.right().y.abs() < EPSILON);
+		assert!(camera.up().y > 0.0);
+	}
+
+	#[test]
+	fn leaving_arcball_over_a_pole() {
+		let mut camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::Vec3::zeros());
+		camera.set_mode(CameraMode::Arcball { free_rotation: false });
+		camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(0.0, -90.0), 1.0);
+		assert!(polar_proximity(&camera) > 0.9999);
+
+		camera.set_mode(CameraMode::Orbit);
+		camera.orbit_around_target(&nglm::Vec3::zeros(), &nglm::vec2(5.0, 0.0), 1.0);
+		assert!(view_matrix(&camera).iter().all(|v| v.is_finite()));
+	}
+}
@@ -1,9 +1,6 @@
-use utils::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
 
-use crate::utils;
-
 pub fn get_webgl2_canvas() -> Option<(HtmlCanvasElement, WebGl2RenderingContext)> {
 	let document = web_sys::window().unwrap().document().unwrap();
 	let canvas = document.get_element_by_id("render_canvas").unwrap();
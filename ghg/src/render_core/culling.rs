@@ -0,0 +1,341 @@
+use std::cell::Cell;
+
+use crate::render_core::camera::{Camera, MvpMatrices};
+
+/// The region a planet's surface can occupy once its terrain is displaced.
+/// Meshes are assumed to be built around a unit sphere at the origin, and
+/// every position is scaled by somewhere between `inner_radius` and
+/// `outer_radius`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TerrainShell {
+	pub inner_radius: f32,
+	pub outer_radius: f32,
+}
+
+impl TerrainShell {
+	/// Matches `terrainRadius` in `terrain.glsl`
+	pub fn from_terrain_scale(terrain_scale: f32) -> Self {
+		Self { inner_radius: 1.0 - terrain_scale / 2.0, outer_radius: 1.0 + terrain_scale / 2.0 }
+	}
+
+	/// Scaling each coordinate is monotonic, so the displaced box is spanned by
+	/// the box scaled to the inner and outer radii.
+	fn displace(&self, bounds: &nglm::Mat3x2) -> nglm::Mat3x2 {
+		let (min, max) = (bounds.column(0), bounds.column(1));
+		let (inner_min, inner_max) = (min * self.inner_radius, max * self.inner_radius);
+		let (outer_min, outer_max) = (min * self.outer_radius, max * self.outer_radius);
+
+		let mut displaced: nglm::Mat3x2 = nglm::zero();
+		displaced.set_column(0, &inner_min.inf(&inner_max).inf(&outer_min).inf(&outer_max));
+		displaced.set_column(1, &inner_min.sup(&inner_max).sup(&outer_min).sup(&outer_max));
+		displaced
+	}
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Visibility {
+	Visible,
+	OutsideFrustum,
+	BeyondHorizon,
+	/// The mesh has no bounds to test, e.g. because it has no vertices
+	Invalid,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct CullingStatistics {
+	pub tested: usize,
+	pub visible: usize,
+	pub outside_frustum: usize,
+	pub beyond_horizon: usize,
+	pub invalid: usize,
+}
+
+impl CullingStatistics {
+	fn record(&mut self, visibility: Visibility) {
+		self.tested += 1;
+		match visibility {
+			Visibility::Visible => self.visible += 1,
+			Visibility::OutsideFrustum => self.outside_frustum += 1,
+			Visibility::BeyondHorizon => self.beyond_horizon += 1,
+			Visibility::Invalid => self.invalid += 1,
+		}
+	}
+}
+
+/// The six clip planes of a view-projection matrix, pointing inwards.
+/// Extracted as described by Gribb & Hartmann: https://www.gamedevs.org/uploads/fast-extraction-viewing-frustum-planes-from-world-view-projection-matrix.pdf
+#[derive(Clone, Debug)]
+pub struct Frustum {
+	planes: [nglm::Vec4; 6],
+}
+
+impl Frustum {
+	pub fn from_matrix(clip_from_world: &nglm::Mat4) -> Self {
+		let row = |i: usize| clip_from_world.row(i).transpose();
+		let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+		Self { planes: [w + x, w - x, w + y, w - y, w + z, w - z] }
+	}
+
+	/// Conservative: boxes that straddle the corner of two planes may pass even
+	/// though they're outside.
+	pub fn intersects_box(&self, bounds: &nglm::Mat3x2) -> bool {
+		let (min, max) = (bounds.column(0), bounds.column(1));
+
+		self.planes.iter().all(|plane| {
+			// The corner furthest along the plane's normal
+			let corner = nglm::vec3(
+				if plane.x >= 0.0 { max.x } else { min.x },
+				if plane.y >= 0.0 { max.y } else { min.y },
+				if plane.z >= 0.0 { max.z } else { min.z },
+			);
+			plane.xyz().dot(&corner) + plane.w >= 0.0
+		})
+	}
+}
+
+/// Everything hidden behind a sphere at the origin, as seen from the camera.
+/// That region is the cone from the camera which wraps the sphere, beyond the
+/// plane through the sphere's horizon circle. Both are convex, so a box is
+/// hidden exactly when all of its corners are.
+#[derive(Clone, Debug)]
+struct Horizon {
+	camera_position: nglm::Vec3,
+	/// Unit vector from the origin towards the camera
+	direction_to_camera: nglm::Vec3,
+	/// Distance of the horizon plane from the origin, along
+	/// `direction_to_camera`
+	plane_distance: f32,
+	cos_cone_half_angle: f32,
+}
+
+impl Horizon {
+	/// None when the camera is inside the sphere, since nothing is hidden then
+	fn new(camera_position: &nglm::Vec3, radius: f32) -> Option<Self> {
+		let camera_distance = camera_position.magnitude();
+		if camera_distance <= radius {
+			return None;
+		}
+
+		let sin_half_angle = radius / camera_distance;
+		Some(Self {
+			camera_position: *camera_position,
+			direction_to_camera: camera_position / camera_distance,
+			plane_distance: radius * sin_half_angle,
+			cos_cone_half_angle: (1.0 - sin_half_angle * sin_half_angle).sqrt(),
+		})
+	}
+
+	fn hides_point(&self, point: &nglm::Vec3) -> bool {
+		if point.dot(&self.direction_to_camera) >= self.plane_distance {
+			return false;
+		}
+
+		let from_camera = point - self.camera_position;
+		let distance = from_camera.magnitude();
+		distance > 0.0
+			&& -from_camera.dot(&self.direction_to_camera) >= self.cos_cone_half_angle * distance
+	}
+
+	fn hides_box(&self, bounds: &nglm::Mat3x2) -> bool {
+		let (min, max) = (bounds.column(0), bounds.column(1));
+
+		(0..8).all(|corner| {
+			let point = nglm::vec3(
+				if corner & 1 == 0 { min.x } else { max.x },
+				if corner & 2 == 0 { min.y } else { max.y },
+				if corner & 4 == 0 { min.z } else { max.z },
+			);
+			self.hides_point(&point)
+		})
+	}
+}
+
+/// Decides whether meshes need to be drawn this frame, and keeps count of the
+/// results. Create one per frame (and per camera).
+pub struct Culler {
+	frustum: Frustum,
+	terrain: Option<TerrainShell>,
+	horizon: Option<Horizon>,
+	statistics: Cell<CullingStatistics>,
+}
+
+impl Culler {
+	/// Without a `terrain` shell, only frustum culling is done.
+	pub fn new(camera: &Camera, matrices: &MvpMatrices, terrain: Option<TerrainShell>) -> Self {
+		let clip_from_world = matrices.projection * matrices.view * matrices.model;
+
+		Self {
+			frustum: Frustum::from_matrix(&clip_from_world),
+			terrain,
+			horizon: terrain.and_then(|t| Horizon::new(&camera.position(), t.inner_radius)),
+			statistics: Cell::new(CullingStatistics::default()),
+		}
+	}
+
+	/// `bounds` is the undisplaced bounding box, with the minimum in column 0
+	/// and the maximum in column 1.
+	pub fn test_bounds(&self, bounds: &nglm::Mat3x2) -> Visibility {
+		let displaced = match &self.terrain {
+			Some(terrain) => terrain.displace(bounds),
+			None => *bounds,
+		};
+
+		let visibility = if !self.frustum.intersects_box(&displaced) {
+			Visibility::OutsideFrustum
+		} else if self.horizon.as_ref().is_some_and(|h| h.hides_box(&displaced)) {
+			Visibility::BeyondHorizon
+		} else {
+			Visibility::Visible
+		};
+
+		self.record(visibility)
+	}
+
+	pub fn record(&self, visibility: Visibility) -> Visibility {
+		let mut statistics = self.statistics.get();
+		statistics.record(visibility);
+		self.statistics.set(statistics);
+		visibility
+	}
+
+	pub fn statistics(&self) -> CullingStatistics { self.statistics.get() }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const WIDTH: i32 = 800;
+	const HEIGHT: i32 = 600;
+
+	fn culler_for(camera: &Camera, terrain: Option<TerrainShell>) -> Culler {
+		Culler::new(camera, &camera.get_perspective_matrices(WIDTH, HEIGHT), terrain)
+	}
+
+	fn small_box_at(center: nglm::Vec3) -> nglm::Mat3x2 {
+		let half_size = nglm::vec3(0.001, 0.001, 0.001);
+		let mut bounds: nglm::Mat3x2 = nglm::zero();
+		bounds.set_column(0, &(center - half_size));
+		bounds.set_column(1, &(center + half_size));
+		bounds
+	}
+
+	fn point_at_angle_from_z(degrees: f32) -> nglm::Vec3 {
+		let radians = degrees.to_radians();
+		nglm::vec3(radians.sin(), 0.0, radians.cos())
+	}
+
+	#[test]
+	fn facing_patch_is_visible() {
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::zero());
+		let culler = culler_for(&camera, Some(TerrainShell::from_terrain_scale(0.0)));
+
+		assert_eq!(
+			culler.test_bounds(&small_box_at(nglm::vec3(0.0, 0.0, 1.0))),
+			Visibility::Visible
+		);
+	}
+
+	#[test]
+	fn far_side_is_beyond_horizon() {
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::zero());
+		let culler = culler_for(&camera, Some(TerrainShell::from_terrain_scale(0.0)));
+
+		assert_eq!(
+			culler.test_bounds(&small_box_at(nglm::vec3(0.0, 0.0, -1.0))),
+			Visibility::BeyondHorizon
+		);
+		assert_eq!(
+			culler.test_bounds(&small_box_at(point_at_angle_from_z(80.0))),
+			Visibility::BeyondHorizon
+		);
+	}
+
+	#[test]
+	fn grazing_limb_is_visible() {
+		// From a distance of 3, the horizon of the unit sphere is at acos(1/3) ~= 70.5
+		// degrees. The previous distance check culled anything past ~56 degrees.
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::zero());
+		let culler = culler_for(&camera, Some(TerrainShell::from_terrain_scale(0.0)));
+
+		for degrees in [60.0, 65.0, 70.0] {
+			assert_eq!(
+				culler.test_bounds(&small_box_at(point_at_angle_from_z(degrees))),
+				Visibility::Visible,
+				"{degrees} degrees"
+			);
+		}
+	}
+
+	#[test]
+	fn displaced_terrain_peeks_over_horizon() {
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::zero());
+		let behind_flat_horizon = small_box_at(point_at_angle_from_z(80.0));
+
+		let flat = culler_for(&camera, Some(TerrainShell::from_terrain_scale(0.0)));
+		assert_eq!(flat.test_bounds(&behind_flat_horizon), Visibility::BeyondHorizon);
+
+		let mountainous = culler_for(&camera, Some(TerrainShell::from_terrain_scale(0.1)));
+		assert_eq!(mountainous.test_bounds(&behind_flat_horizon), Visibility::Visible);
+	}
+
+	#[test]
+	fn behind_camera_is_outside_frustum() {
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::vec3(0.0, 0.0, 6.0));
+		let culler = culler_for(&camera, None);
+
+		assert_eq!(
+			culler.test_bounds(&small_box_at(nglm::vec3(0.0, 0.0, 1.0))),
+			Visibility::OutsideFrustum
+		);
+	}
+
+	#[test]
+	fn close_up_limb_is_outside_frustum() {
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 1.2), &nglm::zero());
+		let culler = culler_for(&camera, Some(TerrainShell::from_terrain_scale(0.0)));
+
+		assert_eq!(
+			culler.test_bounds(&small_box_at(nglm::vec3(0.0, 0.0, 1.0))),
+			Visibility::Visible
+		);
+		assert_eq!(
+			culler.test_bounds(&small_box_at(point_at_angle_from_z(30.0))),
+			Visibility::OutsideFrustum
+		);
+	}
+
+	#[test]
+	fn camera_inside_sphere_skips_horizon() {
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 0.5), &nglm::vec3(0.0, 0.0, -1.0));
+		let culler = culler_for(&camera, Some(TerrainShell::from_terrain_scale(0.0)));
+
+		assert_eq!(
+			culler.test_bounds(&small_box_at(nglm::vec3(0.0, 0.0, -1.0))),
+			Visibility::Visible
+		);
+	}
+
+	#[test]
+	fn statistics_are_counted() {
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::zero());
+		let culler = culler_for(&camera, Some(TerrainShell::from_terrain_scale(0.0)));
+
+		culler.test_bounds(&small_box_at(nglm::vec3(0.0, 0.0, 1.0)));
+		culler.test_bounds(&small_box_at(nglm::vec3(0.0, 0.0, -1.0)));
+		culler.test_bounds(&small_box_at(nglm::vec3(0.0, 0.0, 10.0)));
+		culler.record(Visibility::Invalid);
+
+		assert_eq!(
+			culler.statistics(),
+			CullingStatistics {
+				tested: 4,
+				visible: 1,
+				outside_frustum: 1,
+				beyond_horizon: 1,
+				invalid: 1,
+			}
+		);
+	}
+}
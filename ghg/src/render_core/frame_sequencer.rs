@@ -1,16 +1,24 @@
 use std::borrow::Borrow;
 use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::future::Future;
 use std::ops::Deref;
 use std::pin::Pin;
 use std::rc::Rc;
 use std::task::{Context, Poll, Waker};
 
-use crate::utils::prelude::*;
-
 pub trait FrameParams = Clone;
 
+/// Frames are numbered from 1, in the order `FrameMarker` marks them
+pub type FrameId = u64;
+
+/// A gate's parameters for the frame it was last given, until its task is done
+/// with that frame
+type SharedParams<T> = Rc<RefCell<Option<(FrameId, T)>>>;
+
+/// How many finished frames `FrameSequencer::frame_done` remembers
+const FRAME_HISTORY: usize = 64;
+
 /// This acts as the single-frame context. When this object is destroyed, it
 /// marks that the current task has reached the end of its frame, so it can be
 /// queued for the next one.
@@ -18,18 +26,30 @@ pub trait FrameParams = Clone;
 /// It provides automatic dereferencing to this frame's parameter values
 #[must_use]
 pub struct FrameContext<T: FrameParams> {
+	frame_id: FrameId,
 	current_params: T,
-	shared_params: Rc<RefCell<Option<T>>>,
+	shared_params: SharedParams<T>,
+	sequencer: Rc<FrameSequencer<T>>,
+	gate_id: usize,
 }
 
 impl<T: FrameParams> FrameContext<T> {
-	fn new(shared_params: Rc<RefCell<Option<T>>>) -> Self {
-		let cell: &RefCell<Option<T>> = shared_params.borrow();
+	fn new(gate: &FrameGate<T>) -> Self {
+		let cell: &RefCell<Option<(FrameId, T)>> = gate.params.borrow();
 		let current_value = cell.borrow().clone();
-		let current_params =
+		let (frame_id, current_params) =
 			current_value.expect("Unable to create ParamContext with empty params");
-		Self { current_params, shared_params }
+		Self {
+			frame_id,
+			current_params,
+			shared_params: gate.params.clone(),
+			sequencer: gate.sequencer.clone(),
+			gate_id: gate.id,
+		}
 	}
+
+	/// The frame these parameters were marked for
+	pub fn frame_id(&self) -> FrameId { self.frame_id }
 }
 
 impl<T: FrameParams> Deref for FrameContext<T> {
@@ -40,69 +60,440 @@ impl<T: FrameParams> Deref for FrameContext<T> {
 
 impl<T: FrameParams> Drop for FrameContext<T> {
 	fn drop(&mut self) {
-		let cell: &RefCell<Option<T>> = self.shared_params.borrow();
-		assert!(cell.borrow().is_some());
-		cell.replace(None);
+		// A newer frame may have been handed out while this one ran, and that
+		// one still has to run
+		let cell: &RefCell<Option<(FrameId, T)>> = self.shared_params.borrow();
+		let own_frame = matches!(*cell.borrow(), Some((frame_id, _)) if frame_id == self.frame_id);
+		if own_frame {
+			cell.replace(None);
+		}
+		self.sequencer.finish_gate(self.gate_id, self.frame_id);
 	}
 }
 
+/// The parts of a frame, in the order they run. Every gate of a phase that's
+/// waiting for the frame finishes it before any gate of a later phase starts,
+/// so for example input is always applied before the draw that shows it.
+/// Gates whose tasks are still busy when their phase starts aren't waited
+/// for, and run the frame whenever they next ask for one.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum FramePhase {
+	Input,
+	Update,
+	Upload,
+	Render,
+	Post,
+}
+
+impl FramePhase {
+	const ALL: [FramePhase; 5] = [
+		FramePhase::Input,
+		FramePhase::Update,
+		FramePhase::Upload,
+		FramePhase::Render,
+		FramePhase::Post,
+	];
+
+	fn next(self) -> Option<FramePhase> { Self::ALL.into_iter().find(|phase| *phase > self) }
+}
+
+/// How a frame ended, as reported by `FrameSequencer::frame_done`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameOutcome {
+	/// Every phase ran, and every gate waiting for the frame finished it
+	Completed,
+	/// The next frame was marked before this one got through its phases
+	Abandoned,
+}
+
+/// Whether `FrameMarker` waits for slow gates before marking another frame
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BackPressure {
+	/// Every frame is marked as it comes
+	Off,
+	/// Frames are held back while a critical gate is still working on the last
+	/// one, but never more than `max_held_frames` in a row, so a stuck gate
+	/// can't stop the animation
+	WaitForCritical { max_held_frames: u32 },
+}
+
+/// How a gate's task has been keeping up, counted in frames marked by
+/// `FrameMarker`
+#[derive(Clone, Debug, PartialEq)]
+pub struct GateStatistics {
+	pub name: String,
+	pub phase: FramePhase,
+	pub frames_completed: u64,
+	/// The frame the gate was last given parameters for
+	pub last_woken_frame: Option<FrameId>,
+	/// Frames marked since the gate last finished one. Anything above zero
+	/// means its task is still busy with, or stuck in, an earlier frame. Not
+	/// counted until the task first asks for a frame.
+	pub frames_behind: u64,
+	/// Frames the gate was given but never started, because a newer frame
+	/// replaced them first
+	pub frames_skipped: u64,
+}
+
+struct GateState<T: FrameParams> {
+	params: SharedParams<T>,
+	waker: Option<Waker>,
+	/// Whether the gate's task is waiting on it for a frame
+	parked: bool,
+	/// Whether the gate's task has ever asked for a frame. Until then it's busy
+	/// with something else, like loading, rather than behind.
+	asked: bool,
+	/// Whether the gate has finished the last frame it was given
+	finished: bool,
+	/// The last frame the gate's task started, so it never runs one twice
+	started: Option<FrameId>,
+	/// Whether `BackPressure::WaitForCritical` waits for this gate
+	critical: bool,
+	statistics: GateStatistics,
+}
+
+/// Hands each frame's parameters to the gates one phase at a time, in the
+/// order the gates were created within a phase
 pub struct FrameSequencer<T: FrameParams> {
-	running_gates: RefCell<HashMap<usize, (Rc<RefCell<Option<T>>>, Option<Waker>)>>,
+	running_gates: RefCell<BTreeMap<usize, GateState<T>>>,
 	next_id: Cell<usize>,
+	frame: Cell<FrameId>,
+	/// Parameters for the frame being run, until its last phase has started
+	pending_params: RefCell<Option<T>>,
+	/// The phase of the frame being run, and the gates it's still waiting for
+	current_phase: Cell<Option<FramePhase>>,
+	waiting_for: RefCell<BTreeSet<usize>>,
+	/// How the most recently finished frames ended, oldest first
+	outcomes: RefCell<VecDeque<(FrameId, FrameOutcome)>>,
+	frame_waiters: RefCell<Vec<(FrameId, Waker)>>,
+	frames_abandoned: Cell<u64>,
+	back_pressure: Cell<BackPressure>,
+	/// Frames held back since one was last marked, and in total
+	held_in_a_row: Cell<u32>,
+	frames_held: Cell<u64>,
+	/// Gates this many frames behind are reported by `FrameMarker`
+	stall_threshold: Cell<Option<u64>>,
 }
 
 impl<T: FrameParams> FrameSequencer<T> {
 	pub fn new() -> Self {
-		Self { running_gates: RefCell::new(HashMap::default()), next_id: Cell::new(0) }
+		Self {
+			running_gates: RefCell::new(BTreeMap::default()),
+			next_id: Cell::new(0),
+			frame: Cell::new(0),
+			pending_params: RefCell::new(None),
+			current_phase: Cell::new(None),
+			waiting_for: RefCell::new(BTreeSet::default()),
+			outcomes: RefCell::new(VecDeque::with_capacity(FRAME_HISTORY)),
+			frame_waiters: RefCell::new(Vec::new()),
+			frames_abandoned: Cell::new(0),
+			back_pressure: Cell::new(BackPressure::Off),
+			held_in_a_row: Cell::new(0),
+			frames_held: Cell::new(0),
+			stall_threshold: Cell::new(None),
+		}
+	}
+
+	/// Warns once a gate hasn't finished a frame in `frames` frames, or never
+	/// if `None`
+	pub fn set_stall_threshold(&self, frames: Option<u64>) { self.stall_threshold.set(frames) }
+
+	pub fn set_back_pressure(&self, back_pressure: BackPressure) {
+		self.back_pressure.set(back_pressure);
+		self.held_in_a_row.set(0);
 	}
 
-	fn register(self: &Rc<FrameSequencer<T>>) -> (usize, Rc<RefCell<Option<T>>>) {
+	/// The number of frames marked so far, which is also the ID of the last one
+	pub fn current_frame(&self) -> FrameId { self.frame.get() }
+
+	/// Frames that were still running their phases when the next was marked
+	pub fn frames_abandoned(&self) -> u64 { self.frames_abandoned.get() }
+
+	/// Frames `FrameMarker` held back for critical gates to catch up
+	pub fn frames_held(&self) -> u64 { self.frames_held.get() }
+
+	/// Every gate, in the order they were created
+	pub fn statistics(&self) -> Vec<GateStatistics> {
+		self.running_gates.borrow().values().map(|gate| gate.statistics.clone()).collect()
+	}
+
+	/// Resolves once `frame` has been completed or abandoned, or to `None` for
+	/// frames too old to remember
+	pub fn frame_done(self: &Rc<FrameSequencer<T>>, frame: FrameId) -> FrameDone<T> {
+		FrameDone { sequencer: self.clone(), frame }
+	}
+
+	fn register(
+		self: &Rc<FrameSequencer<T>>,
+		phase: FramePhase,
+		name: &str,
+	) -> (usize, SharedParams<T>) {
 		let next_id = self.next_id.get();
 		self.next_id.replace(next_id + 1);
 
-		self.running_gates.borrow_mut().insert(next_id, (Rc::new(RefCell::new(None)), None));
-		(next_id, self.running_gates.borrow().get(&next_id).unwrap().0.clone())
+		let params = Rc::new(RefCell::new(None));
+		let statistics = GateStatistics {
+			name: name.to_owned(),
+			phase,
+			frames_completed: 0,
+			last_woken_frame: None,
+			frames_behind: 0,
+			frames_skipped: 0,
+		};
+		self.running_gates.borrow_mut().insert(
+			next_id,
+			GateState {
+				params: params.clone(),
+				waker: None,
+				parked: false,
+				asked: false,
+				finished: true,
+				started: None,
+				critical: false,
+				statistics,
+			},
+		);
+		(next_id, params)
 	}
 
-	fn mark_all_running(self: &Rc<FrameSequencer<T>>, params: T) {
-		for (_id, (running, maybe_waker)) in self.running_gates.borrow().iter() {
-			let running_params: &RefCell<Option<T>> = running.borrow();
-			running_params.replace(Some(params.clone()));
+	/// Whether `FrameMarker` should skip marking this frame, counting it if so
+	fn hold_frame(&self) -> bool {
+		let BackPressure::WaitForCritical { max_held_frames } = self.back_pressure.get() else {
+			return false;
+		};
 
-			if maybe_waker.is_some() {
-				maybe_waker.as_ref().unwrap().clone().wake();
+		let critical_busy =
+			self.running_gates.borrow().values().any(|gate| gate.critical && !gate.finished);
+		if critical_busy && self.held_in_a_row.get() < max_held_frames {
+			self.held_in_a_row.set(self.held_in_a_row.get() + 1);
+			self.frames_held.set(self.frames_held.get() + 1);
+			true
+		} else {
+			self.held_in_a_row.set(0);
+			false
+		}
+	}
+
+	/// Starts a new frame with its first phase, abandoning whatever's left of
+	/// the previous one. Returns the gates that have just reached the stall
+	/// threshold.
+	fn mark_all_running(self: &Rc<FrameSequencer<T>>, params: T) -> Vec<GateStatistics> {
+		if self.current_phase.get().is_some() {
+			self.frames_abandoned.set(self.frames_abandoned.get() + 1);
+			self.finish_frame(FrameOutcome::Abandoned);
+		}
+
+		let frame = self.frame.get() + 1;
+		self.frame.set(frame);
+
+		let mut stalled = Vec::new();
+		for gate in self.running_gates.borrow_mut().values_mut() {
+			if gate.asked && !gate.finished {
+				gate.statistics.frames_behind += 1;
+				if Some(gate.statistics.frames_behind) == self.stall_threshold.get() {
+					stalled.push(gate.statistics.clone());
+				}
+			}
+		}
+
+		self.pending_params.replace(Some(params));
+		self.waiting_for.borrow_mut().clear();
+		self.start_phase(FramePhase::ALL[0]);
+		stalled
+	}
+
+	/// Wakes the gates of `phase`, moving on to later phases straight away if
+	/// none of them are waiting for a frame
+	fn start_phase(self: &Rc<FrameSequencer<T>>, phase: FramePhase) {
+		let mut phase = Some(phase);
+		while let Some(current) = phase {
+			self.current_phase.set(Some(current));
+			let params = self.pending_params.borrow().clone().expect("No frame to start");
+			let frame = self.frame.get();
+
+			let mut waiting_for = self.waiting_for.borrow_mut();
+			for (id, gate) in self.running_gates.borrow_mut().iter_mut() {
+				if gate.statistics.phase != current {
+					continue;
+				}
+
+				let running_params: &RefCell<Option<(FrameId, T)>> = gate.params.borrow();
+				let replaced = running_params.replace(Some((frame, params.clone())));
+				if replaced.is_some_and(|(replaced, _)| gate.started != Some(replaced)) {
+					gate.statistics.frames_skipped += 1;
+				}
+				gate.finished = false;
+				gate.statistics.last_woken_frame = Some(frame);
+
+				// Gates whose tasks are busy with something else get the frame when
+				// they next ask, without holding up the phases after theirs
+				if gate.parked {
+					waiting_for.insert(*id);
+				}
+				if let Some(waker) = &gate.waker {
+					waker.wake_by_ref();
+				}
 			}
+
+			if !waiting_for.is_empty() {
+				return;
+			}
+			phase = current.next();
+		}
+
+		self.finish_frame(FrameOutcome::Completed);
+	}
+
+	/// Records how the current frame ended and wakes whoever is waiting on it
+	fn finish_frame(&self, outcome: FrameOutcome) {
+		self.current_phase.set(None);
+		self.pending_params.replace(None);
+
+		let frame = self.frame.get();
+		{
+			let mut outcomes = self.outcomes.borrow_mut();
+			if outcomes.len() == FRAME_HISTORY {
+				outcomes.pop_front();
+			}
+			outcomes.push_back((frame, outcome));
+		}
+
+		let mut woken = Vec::new();
+		self.frame_waiters.borrow_mut().retain(|(waiting_for, waker)| {
+			let done = *waiting_for <= frame;
+			if done {
+				woken.push(waker.clone());
+			}
+			!done
+		});
+		woken.into_iter().for_each(Waker::wake);
+	}
+
+	/// How `frame` ended, or `None` while it's yet to end
+	fn frame_outcome(&self, frame: FrameId) -> Option<Option<FrameOutcome>> {
+		let last_finished = match self.current_phase.get() {
+			Some(_) => self.frame.get() - 1,
+			None => self.frame.get(),
+		};
+		if frame > last_finished {
+			return None;
+		}
+
+		let outcomes = self.outcomes.borrow();
+		Some(outcomes.iter().find(|(id, _)| *id == frame).map(|(_, outcome)| *outcome))
+	}
+
+	/// Claims `frame` for the gate's task, unless it's already been started
+	fn start_gate(&self, gate_id: usize, frame: FrameId) -> bool {
+		let mut gates = self.running_gates.borrow_mut();
+		let entry =
+			gates.get_mut(&gate_id).unwrap_or_else(|| panic!("Could not find gate id {gate_id}"));
+		if entry.started == Some(frame) {
+			return false;
+		}
+
+		entry.started = Some(frame);
+		entry.parked = false;
+		entry.asked = true;
+		entry.waker = None;
+		true
+	}
+
+	fn finish_gate(self: &Rc<FrameSequencer<T>>, gate_id: usize, frame: FrameId) {
+		if let Some(gate) = self.running_gates.borrow_mut().get_mut(&gate_id) {
+			// Finishing an older frame still counts as progress, but the gate has
+			// yet to run the newer one
+			gate.finished = gate.statistics.last_woken_frame == Some(frame);
+			gate.statistics.frames_completed += 1;
+			gate.statistics.frames_behind = 0;
+		}
+		if frame == self.frame.get() {
+			self.stop_waiting_for(gate_id);
+		}
+	}
+
+	/// Starts the next phase once the current one has nothing left to wait for
+	fn stop_waiting_for(self: &Rc<FrameSequencer<T>>, gate_id: usize) {
+		let phase_done = {
+			let mut waiting_for = self.waiting_for.borrow_mut();
+			waiting_for.remove(&gate_id) && waiting_for.is_empty()
+		};
+		if !phase_done {
+			return;
+		}
+
+		match self.current_phase.get().and_then(FramePhase::next) {
+			Some(next) => self.start_phase(next),
+			None => self.finish_frame(FrameOutcome::Completed),
 		}
 	}
 
-	fn update_waker(self: &Rc<FrameSequencer<T>>, gate_id: usize, waker: Waker) {
+	fn park(self: &Rc<FrameSequencer<T>>, gate_id: usize, waker: Waker) {
 		let mut gates = self.running_gates.borrow_mut();
 		let entry =
 			gates.get_mut(&gate_id).expect(format!("Could not find gate id {gate_id}").as_str());
 
-		(*entry).1 = Some(waker);
+		entry.parked = true;
+		entry.asked = true;
+		entry.waker = Some(waker);
+	}
+
+	fn set_critical(&self, gate_id: usize) {
+		if let Some(gate) = self.running_gates.borrow_mut().get_mut(&gate_id) {
+			gate.critical = true;
+		}
 	}
 
 	fn remove_gate(self: &Rc<FrameSequencer<T>>, gate_id: usize) {
-		let mut gates = self.running_gates.borrow_mut();
-		gates.remove(&gate_id).expect(format!("Unable to find gate ID {gate_id}").as_str());
-		ghg_log!("Removed gate ID {gate_id}");
+		self.running_gates
+			.borrow_mut()
+			.remove(&gate_id)
+			.unwrap_or_else(|| panic!("Unable to find gate ID {gate_id}"));
+		self.stop_waiting_for(gate_id);
+	}
+}
+
+/// Waits for a frame to be completed or abandoned
+pub struct FrameDone<T: FrameParams> {
+	sequencer: Rc<FrameSequencer<T>>,
+	frame: FrameId,
+}
+
+impl<T: FrameParams> Future for FrameDone<T> {
+	type Output = Option<FrameOutcome>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		match self.sequencer.frame_outcome(self.frame) {
+			Some(outcome) => Poll::Ready(outcome),
+			None => {
+				let waiter = (self.frame, cx.waker().clone());
+				self.sequencer.frame_waiters.borrow_mut().push(waiter);
+				Poll::Pending
+			}
+		}
 	}
 }
 
 pub struct FrameGate<T: FrameParams> {
 	sequencer: Rc<FrameSequencer<T>>,
 	id: usize,
-	pub params: Rc<RefCell<Option<T>>>,
+	pub params: SharedParams<T>,
 	pub name: String,
 	frame_waker: Cell<Option<Waker>>,
 }
 
 impl<T: FrameParams> FrameGate<T> {
-	pub fn new(sequencer: Rc<FrameSequencer<T>>, name: String) -> Self {
-		let (id, params) = sequencer.register();
+	pub fn new(sequencer: Rc<FrameSequencer<T>>, phase: FramePhase, name: String) -> Self {
+		let (id, params) = sequencer.register(phase, &name);
 		Self { sequencer, id, params, name, frame_waker: Cell::new(None) }
 	}
+
+	/// Makes `BackPressure::WaitForCritical` hold frames back until this gate
+	/// has finished the last one
+	pub fn critical(self) -> Self {
+		self.sequencer.set_critical(self.id);
+		self
+	}
 }
 
 impl<T: FrameParams> Future for &FrameGate<T> {
@@ -110,24 +501,20 @@ impl<T: FrameParams> Future for &FrameGate<T> {
 
 	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
 		self.as_mut().frame_waker.replace(Some(cx.waker().clone()));
-		self.sequencer.update_waker(self.id, cx.waker().clone());
 
-		let running_params: &RefCell<Option<T>> = self.params.borrow();
-		if running_params.borrow().is_some() {
-			// let params = running_params.replace(None);
-			// assert!(params.is_some());
-			Poll::Ready(FrameContext::new(self.params.clone()))
+		let running_params: &RefCell<Option<(FrameId, T)>> = self.params.borrow();
+		let frame = running_params.borrow().as_ref().map(|(frame, _)| *frame);
+		if frame.is_some_and(|frame| self.sequencer.start_gate(self.id, frame)) {
+			Poll::Ready(FrameContext::new(*self))
 		} else {
+			self.sequencer.park(self.id, cx.waker().clone());
 			Poll::Pending
 		}
 	}
 }
 
 impl<T: FrameParams> Drop for FrameGate<T> {
-	fn drop(&mut self) {
-		ghg_log!("Removing gate {} (id={})", self.name, self.id);
-		self.sequencer.remove_gate(self.id);
-	}
+	fn drop(&mut self) { self.sequencer.remove_gate(self.id); }
 }
 
 pub struct FrameMarker<T: FrameParams> {
@@ -137,5 +524,296 @@ pub struct FrameMarker<T: FrameParams> {
 impl<T: FrameParams> FrameMarker<T> {
 	pub fn new(sequencer: Rc<FrameSequencer<T>>) -> Self { Self { sequencer } }
 
-	pub fn frame(&self, params: T) { self.sequencer.mark_all_running(params) }
+	/// Marks a new frame and returns its ID, or `None` if back-pressure held it
+	/// back. `params` is only called for frames that aren't held back, so
+	/// making them can have side effects.
+	pub fn frame(&self, params: impl FnOnce() -> T) -> Option<FrameId> {
+		if self.sequencer.hold_frame() {
+			return None;
+		}
+
+		for gate in self.sequencer.mark_all_running(params()) {
+			ghg_log!(
+				"Frame gate {} hasn't finished a frame in {} frames",
+				gate.name,
+				gate.frames_behind
+			);
+		}
+		Some(self.sequencer.current_frame())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use single_thread_executor::{
+		new_executor_and_spawner_with_clock, sleep, ManualClock, Spawner,
+	};
+
+	use super::*;
+
+	type Log = Rc<RefCell<Vec<String>>>;
+
+	/// Spawns a task that logs each frame it runs, as `name` followed by the
+	/// frame's parameter
+	fn logging_task(
+		spawner: &Spawner,
+		sequencer: &Rc<FrameSequencer<u32>>,
+		phase: FramePhase,
+		name: &'static str,
+		log: &Log,
+	) {
+		let gate = FrameGate::new(sequencer.clone(), phase, name.to_owned());
+		let log = log.clone();
+		spawner.spawn(async move {
+			loop {
+				let params = (&gate).await;
+				log.borrow_mut().push(format!("{name} {}", *params));
+			}
+		});
+	}
+
+	#[test]
+	fn phases_run_in_order() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let sequencer = Rc::new(FrameSequencer::<u32>::new());
+		let log = Log::default();
+
+		// Created backwards, so creation order can't be what puts them in order
+		logging_task(&spawner, &sequencer, FramePhase::Post, "post", &log);
+		logging_task(&spawner, &sequencer, FramePhase::Render, "render", &log);
+		logging_task(&spawner, &sequencer, FramePhase::Upload, "upload", &log);
+
+		// Input takes a while, and everything else has to wait for it
+		let input = FrameGate::new(sequencer.clone(), FramePhase::Input, "input".to_owned());
+		let input_log = log.clone();
+		spawner.spawn(async move {
+			loop {
+				let params = (&input).await;
+				sleep(std::time::Duration::from_millis(5)).await;
+				input_log.borrow_mut().push(format!("input {}", *params));
+			}
+		});
+
+		executor.run_until_stalled();
+		for frame in 1..=2 {
+			sequencer.mark_all_running(frame);
+			executor.run_until_stalled();
+			clock.advance(std::time::Duration::from_millis(5));
+			executor.run_until_stalled();
+		}
+
+		assert_eq!(
+			log.take(),
+			vec![
+				"input 1", "upload 1", "render 1", "post 1", "input 2", "upload 2", "render 2",
+				"post 2"
+			]
+		);
+	}
+
+	#[test]
+	fn busy_gates_do_not_hold_up_later_phases() {
+		let (executor, spawner) = new_executor_and_spawner_with_clock(ManualClock::new());
+		let sequencer = Rc::new(FrameSequencer::<u32>::new());
+		let log = Log::default();
+
+		// Like texture loading, which only asks for frames once it's done
+		let _loading = FrameGate::new(sequencer.clone(), FramePhase::Update, "loading".to_owned());
+		logging_task(&spawner, &sequencer, FramePhase::Render, "render", &log);
+
+		executor.run_until_stalled();
+		sequencer.mark_all_running(1);
+		executor.run_until_stalled();
+
+		assert_eq!(log.take(), vec!["render 1"]);
+	}
+
+	#[test]
+	fn gates_are_not_behind_before_they_ask_for_a_frame() {
+		let (executor, spawner) = new_executor_and_spawner_with_clock(ManualClock::new());
+		let sequencer = Rc::new(FrameSequencer::<u32>::new());
+		sequencer.set_stall_threshold(Some(2));
+
+		let loading = FrameGate::new(sequencer.clone(), FramePhase::Update, "loading".to_owned());
+		let stalled: Vec<_> = (1..=3).flat_map(|frame| sequencer.mark_all_running(frame)).collect();
+		assert!(stalled.is_empty());
+		assert_eq!(sequencer.statistics()[0].frames_behind, 0);
+
+		// Done loading, so it's counted from here on
+		spawner.spawn(async move {
+			let _params = (&loading).await;
+			std::future::pending::<()>().await
+		});
+		executor.run_until_stalled();
+		sequencer.mark_all_running(4);
+		sequencer.mark_all_running(5);
+		assert_eq!(sequencer.statistics()[0].frames_behind, 2);
+	}
+
+	#[test]
+	fn reports_gates_that_stop_finishing_frames() {
+		let (executor, spawner) = new_executor_and_spawner_with_clock(ManualClock::new());
+		let sequencer = Rc::new(FrameSequencer::<u32>::new());
+		sequencer.set_stall_threshold(Some(3));
+
+		let fast = FrameGate::new(sequencer.clone(), FramePhase::Update, "Fast".to_owned());
+		spawner.spawn(async move {
+			loop {
+				let _params = (&fast).await;
+			}
+		});
+		let stuck = FrameGate::new(sequencer.clone(), FramePhase::Update, "Stuck".to_owned());
+		spawner.spawn(async move {
+			let _params = (&stuck).await;
+			std::future::pending::<()>().await
+		});
+
+		let stalled: Vec<Vec<String>> = (0..5)
+			.map(|frame| {
+				let stalled = sequencer.mark_all_running(frame);
+				executor.run_until_stalled();
+				stalled.into_iter().map(|gate| gate.name).collect()
+			})
+			.collect();
+		assert_eq!(stalled, vec![vec![], vec![], vec![], vec!["Stuck".to_owned()], vec![]]);
+
+		assert_eq!(sequencer.current_frame(), 5);
+		assert_eq!(
+			sequencer.statistics(),
+			vec![
+				GateStatistics {
+					name: "Fast".to_owned(),
+					phase: FramePhase::Update,
+					frames_completed: 5,
+					last_woken_frame: Some(5),
+					frames_behind: 0,
+					frames_skipped: 0,
+				},
+				GateStatistics {
+					name: "Stuck".to_owned(),
+					phase: FramePhase::Update,
+					frames_completed: 0,
+					last_woken_frame: Some(5),
+					frames_behind: 4,
+					frames_skipped: 3,
+				},
+			]
+		);
+
+		drop(executor);
+		assert!(sequencer.statistics().is_empty());
+	}
+
+	/// Spawns a task that holds on to each frame for 5ms, logging the frames it
+	/// runs by ID
+	fn slow_task(spawner: &Spawner, gate: FrameGate<u32>) -> Rc<RefCell<Vec<FrameId>>> {
+		let frames = Rc::new(RefCell::new(Vec::new()));
+		let task_frames = frames.clone();
+		spawner.spawn(async move {
+			loop {
+				let params = (&gate).await;
+				sleep(std::time::Duration::from_millis(5)).await;
+				task_frames.borrow_mut().push(params.frame_id());
+			}
+		});
+		frames
+	}
+
+	#[test]
+	fn slow_gates_skip_frames_instead_of_running_them_twice() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let sequencer = Rc::new(FrameSequencer::<u32>::new());
+		let gate = FrameGate::new(sequencer.clone(), FramePhase::Update, "slow".to_owned());
+		let frames = slow_task(&spawner, gate);
+
+		executor.run_until_stalled();
+		for frame in 1..=3 {
+			sequencer.mark_all_running(frame);
+			executor.run_until_stalled();
+		}
+		for _ in 0..3 {
+			clock.advance(std::time::Duration::from_millis(5));
+			executor.run_until_stalled();
+		}
+
+		assert_eq!(frames.take(), vec![1, 3]);
+		let statistics = sequencer.statistics();
+		assert_eq!(statistics[0].frames_completed, 2);
+		assert_eq!(statistics[0].frames_skipped, 1);
+	}
+
+	#[test]
+	fn frame_done_reports_how_frames_ended() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let sequencer = Rc::new(FrameSequencer::<u32>::new());
+		let gate = FrameGate::new(sequencer.clone(), FramePhase::Input, "slow".to_owned());
+		let _frames = slow_task(&spawner, gate);
+
+		let outcomes = Rc::new(RefCell::new(Vec::new()));
+		for frame in [1, 2, 3] {
+			let (sequencer, outcomes) = (sequencer.clone(), outcomes.clone());
+			spawner.spawn(async move {
+				let outcome = sequencer.frame_done(frame).await;
+				outcomes.borrow_mut().push((frame, outcome));
+			});
+		}
+		executor.run_until_stalled();
+
+		// Frame 1 gets to finish, frame 2 is cut short by frame 3, and frame 3
+		// doesn't wait for a gate that's still busy with frame 2
+		sequencer.mark_all_running(1);
+		executor.run_until_stalled();
+		clock.advance(std::time::Duration::from_millis(5));
+		executor.run_until_stalled();
+		sequencer.mark_all_running(2);
+		executor.run_until_stalled();
+		sequencer.mark_all_running(3);
+		executor.run_until_stalled();
+
+		assert_eq!(
+			outcomes.take(),
+			vec![
+				(1, Some(FrameOutcome::Completed)),
+				(2, Some(FrameOutcome::Abandoned)),
+				(3, Some(FrameOutcome::Completed)),
+			]
+		);
+		assert_eq!(sequencer.frames_abandoned(), 1);
+	}
+
+	#[test]
+	fn back_pressure_holds_frames_for_critical_gates() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let sequencer = Rc::new(FrameSequencer::<u32>::new());
+		sequencer.set_back_pressure(BackPressure::WaitForCritical { max_held_frames: 1 });
+		let gate =
+			FrameGate::new(sequencer.clone(), FramePhase::Render, "critical".to_owned()).critical();
+		let _frames = slow_task(&spawner, gate);
+		let marker = FrameMarker::new(sequencer.clone());
+		executor.run_until_stalled();
+
+		let mut marked = Vec::new();
+		let mut made = Vec::new();
+		for step in 0..5 {
+			marked.push(marker.frame(|| {
+				made.push(step);
+				step
+			}));
+			executor.run_until_stalled();
+			if step >= 2 {
+				clock.advance(std::time::Duration::from_millis(5));
+				executor.run_until_stalled();
+			}
+		}
+
+		// Held until the gate catches up, but only once in a row, so a slow gate
+		// can't stop every frame
+		assert_eq!(marked, vec![Some(1), None, Some(2), None, Some(3)]);
+		assert_eq!(made, vec![0, 2, 4], "Held frames don't make parameters");
+		assert_eq!(sequencer.frames_held(), 2);
+	}
 }
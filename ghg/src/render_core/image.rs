@@ -2,18 +2,15 @@ use image::{
 	DynamicImage, EncodableLayout, GenericImageView, GrayImage, Luma, Rgb, RgbImage, Rgba,
 	RgbaImage,
 };
-use wasm_bindgen::JsValue;
-use web_sys::WebGl2RenderingContext;
+
+use crate::render_core::backend::{GraphicsBackend, TextureFilter, TextureFormat};
 
 /// This feels like it probably duplicates something that can be done in the
 /// image library already.
 pub trait LoadableImageType {
 	type ImageType: GenericImageView;
 
-	// Combinations: https://registry.khronos.org/webgl/specs/latest/2.0/#TEXTURE_TYPES_FORMATS_FROM_DOM_ELEMENTS_TABLE
-	fn texture_internal_format() -> u32;
-	fn texture_format() -> u32;
-	fn texture_type() -> u32;
+	fn texture_format() -> TextureFormat;
 
 	fn cast_to(dynamic: &DynamicImage) -> Option<&Self::ImageType>;
 	fn copy_to(dynamic: &DynamicImage) -> Self::ImageType;
@@ -25,11 +22,7 @@ pub trait LoadableImageType {
 impl LoadableImageType for Luma<u8> {
 	type ImageType = GrayImage;
 
-	fn texture_internal_format() -> u32 { WebGl2RenderingContext::LUMINANCE }
-
-	fn texture_format() -> u32 { WebGl2RenderingContext::LUMINANCE }
-
-	fn texture_type() -> u32 { WebGl2RenderingContext::UNSIGNED_BYTE }
+	fn texture_format() -> TextureFormat { TextureFormat::Luminance }
 
 	fn cast_to(dynamic: &DynamicImage) -> Option<&Self::ImageType> { dynamic.as_luma8() }
 
@@ -43,11 +36,7 @@ impl LoadableImageType for Luma<u8> {
 impl LoadableImageType for Rgb<u8> {
 	type ImageType = RgbImage;
 
-	fn texture_internal_format() -> u32 { WebGl2RenderingContext::RGB }
-
-	fn texture_format() -> u32 { WebGl2RenderingContext::RGB }
-
-	fn texture_type() -> u32 { WebGl2RenderingContext::UNSIGNED_BYTE }
+	fn texture_format() -> TextureFormat { TextureFormat::Rgb }
 
 	fn cast_to(dynamic: &DynamicImage) -> Option<&Self::ImageType> { dynamic.as_rgb8() }
 
@@ -61,11 +50,7 @@ impl LoadableImageType for Rgb<u8> {
 impl LoadableImageType for Rgba<u8> {
 	type ImageType = RgbaImage;
 
-	fn texture_internal_format() -> u32 { WebGl2RenderingContext::RGBA }
-
-	fn texture_format() -> u32 { WebGl2RenderingContext::RGBA }
-
-	fn texture_type() -> u32 { WebGl2RenderingContext::UNSIGNED_BYTE }
+	fn texture_format() -> TextureFormat { TextureFormat::Rgba }
 
 	fn cast_to(dynamic: &DynamicImage) -> Option<&Self::ImageType> { dynamic.as_rgba8() }
 
@@ -76,27 +61,28 @@ impl LoadableImageType for Rgba<u8> {
 	fn name() -> String { "Rgba8".to_owned() }
 }
 
-pub fn load_into_texture<T: LoadableImageType>(
-	context: WebGl2RenderingContext,
+/// `texture_unit` counts from zero
+pub fn load_into_texture<T: LoadableImageType, B: GraphicsBackend>(
+	context: &B,
 	png_bytes: &[u8],
-	texture_number: u32,
-) -> Result<(), JsValue> {
-	load_into_texture_with_filters::<T>(
+	texture_unit: u32,
+) -> Result<(), String> {
+	load_into_texture_with_filters::<T, B>(
 		context,
 		png_bytes,
-		texture_number,
-		WebGl2RenderingContext::LINEAR,
-		WebGl2RenderingContext::LINEAR,
+		texture_unit,
+		TextureFilter::Linear,
+		TextureFilter::Linear,
 	)
 }
 
-pub fn load_into_texture_with_filters<T: LoadableImageType>(
-	context: WebGl2RenderingContext,
+pub fn load_into_texture_with_filters<T: LoadableImageType, B: GraphicsBackend>(
+	context: &B,
 	png_bytes: &[u8],
-	texture_number: u32,
-	min_filter: u32,
-	mag_filter: u32,
-) -> Result<(), JsValue> {
+	texture_unit: u32,
+	min_filter: TextureFilter,
+	mag_filter: TextureFilter,
+) -> Result<(), String> {
 	let decoder = png::Decoder::new(png_bytes);
 	let mut reader = decoder.read_info().map_err(|s| s.to_string())?;
 	let mut buf = vec![0; reader.output_buffer_size()];
@@ -117,43 +103,8 @@ pub fn load_into_texture_with_filters<T: LoadableImageType>(
 
 	let texture = context.create_texture().ok_or("no texture")?;
 
-	context.active_texture(texture_number);
-	context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
-
-	context.tex_parameteri(
-		WebGl2RenderingContext::TEXTURE_2D,
-		WebGl2RenderingContext::TEXTURE_MIN_FILTER,
-		min_filter as i32,
-	);
-	context.tex_parameteri(
-		WebGl2RenderingContext::TEXTURE_2D,
-		WebGl2RenderingContext::TEXTURE_MAG_FILTER,
-		mag_filter as i32,
-	);
-
-	context.tex_parameteri(
-		WebGl2RenderingContext::TEXTURE_2D,
-		WebGl2RenderingContext::TEXTURE_WRAP_S,
-		WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
-	);
-	context.tex_parameteri(
-		WebGl2RenderingContext::TEXTURE_2D,
-		WebGl2RenderingContext::TEXTURE_WRAP_T,
-		WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
-	);
-
-	// Lol. This should just be a builder.
-	context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
-		WebGl2RenderingContext::TEXTURE_2D,
-		0,
-		T::texture_internal_format() as i32,
-		dimensions.0 as i32,
-		dimensions.1 as i32,
-		0,
-		T::texture_format(),
-		T::texture_type(),
-		Some(bytes),
-	)?;
-
-	Ok(())
+	context.active_texture(texture_unit);
+	context.bind_texture(Some(&texture));
+	context.texture_sampling(min_filter, mag_filter);
+	context.texture_image(T::texture_format(), dimensions.0, dimensions.1, bytes)
 }
@@ -1,11 +1,8 @@
 use std::convert::TryInto;
 
-use wasm_bindgen::JsValue;
-use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlVertexArrayObject};
-
 use crate::application::shaders::ShaderContext;
-use crate::application::vertex::BasicMesh;
-use crate::render_core::camera::Camera;
+use crate::render_core::backend::{BufferTarget, BufferUsage, GraphicsBackend, Primitive};
+use crate::render_core::culling::{Culler, Visibility};
 #[allow(unused_imports)]
 use crate::utils::prelude::*;
 
@@ -32,7 +29,14 @@ pub trait ToMesh {
 	fn get_bounding_box(&self) -> Option<nglm::Mat3x2>;
 	fn get_center(&self) -> Option<nglm::Vec3>;
 
-	fn is_visible(&self, camera: &Camera) -> bool;
+	fn visibility(&self, culler: &Culler) -> Visibility {
+		match self.get_bounding_box() {
+			Some(bounds) => culler.test_bounds(&bounds),
+			None => culler.record(Visibility::Invalid),
+		}
+	}
+
+	fn is_visible(&self, culler: &Culler) -> bool { self.visibility(culler) == Visibility::Visible }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -42,10 +46,10 @@ struct VertexForDisplay {
 	offset: i32,
 }
 
-pub struct DrawBuffers {
-	pub vertex_buffer: WebGlBuffer,
-	pub vertex_array_object: WebGlVertexArrayObject,
-	pub index_buffer: WebGlBuffer,
+pub struct DrawBuffers<B: GraphicsBackend> {
+	pub vertex_buffer: B::Buffer,
+	pub vertex_array_object: B::VertexArray,
+	pub index_buffer: B::Buffer,
 
 	#[allow(dead_code)]
 	num_vertices: u32,
@@ -58,81 +62,53 @@ pub enum MeshMode {
 	Dynamic,
 }
 
-pub fn add_mesh<T: ToMesh>(
-	shader_context: &ShaderContext,
+pub fn add_mesh<T: ToMesh, B: GraphicsBackend>(
+	shader_context: &ShaderContext<B>,
 	mesh: &T,
 	mode: MeshMode,
-) -> Result<DrawBuffers, JsValue> {
+) -> Result<DrawBuffers<B>, String> {
+	let context = &shader_context.context;
 	let vertex_attribute_tags = mesh.get_attributes();
 
-	let vertex_attributes = vertex_attribute_tags.iter().map(|a| {
-		let a_name: &str = &a.name[..];
-		let location = shader_context.context.get_attrib_location(&shader_context.program, a_name);
-		VertexForDisplay {
-			location: if location != -1 { Some(location as u32) } else { None },
-			size: a.size as i32,
-			offset: a.offset as i32,
-		}
+	let vertex_attributes = vertex_attribute_tags.iter().map(|a| VertexForDisplay {
+		location: context.attribute_location(&shader_context.program, &a.name),
+		size: a.size as i32,
+		offset: a.offset as i32,
 	});
 
-	let vertex_buffer =
-		shader_context.context.create_buffer().ok_or("Failed to create vertex buffer")?;
-	shader_context.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&vertex_buffer));
+	let vertex_buffer = context.create_buffer().ok_or("Failed to create vertex buffer")?;
+	context.bind_buffer(BufferTarget::Array, Some(&vertex_buffer));
 
 	let draw_mode = match mode {
-		MeshMode::Static => WebGl2RenderingContext::STATIC_DRAW,
-		MeshMode::Dynamic => WebGl2RenderingContext::DYNAMIC_DRAW,
+		MeshMode::Static => BufferUsage::Static,
+		MeshMode::Dynamic => BufferUsage::Dynamic,
 	};
 
 	let vertices = mesh.get_flat_vertex_buffer();
 	let num_vertices: u32 = vertices.len() as u32;
-	unsafe {
-		let vert_array_buffer_view = js_sys::Float32Array::view(vertices);
+	context.buffer_data_f32(BufferTarget::Array, vertices, draw_mode);
 
-		shader_context.context.buffer_data_with_array_buffer_view(
-			WebGl2RenderingContext::ARRAY_BUFFER,
-			&vert_array_buffer_view,
-			draw_mode,
-		);
-	}
-
-	let vertex_array_object = shader_context
-		.context
-		.create_vertex_array()
-		.ok_or("Could not create vertex array object")?;
-	shader_context.context.bind_vertex_array(Some(&vertex_array_object));
+	let vertex_array_object =
+		context.create_vertex_array().ok_or("Could not create vertex array object")?;
+	context.bind_vertex_array(Some(&vertex_array_object));
 
 	vertex_attributes.for_each(|a| {
-		if a.location.is_some() {
-			shader_context.context.enable_vertex_attrib_array(a.location.unwrap());
-			shader_context.context.vertex_attrib_pointer_with_i32(
-				a.location.unwrap(),
+		if let Some(location) = a.location {
+			context.vertex_attribute_f32(
+				location,
 				a.size,
-				WebGl2RenderingContext::FLOAT,
-				false,
 				std::mem::size_of::<T::Vertex>() as i32,
 				a.offset,
 			);
 		}
 	});
 
-	let index_buffer =
-		shader_context.context.create_buffer().ok_or("Failed to create index buffer")?;
-	shader_context
-		.context
-		.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, Some(&index_buffer));
+	let index_buffer = context.create_buffer().ok_or("Failed to create index buffer")?;
+	context.bind_buffer(BufferTarget::ElementArray, Some(&index_buffer));
 
 	let indices = mesh.get_flat_index_buffer();
 	let num_indices = indices.len() as u32;
-	unsafe {
-		let index_array_buffer_view = js_sys::Uint32Array::view(indices);
-
-		shader_context.context.buffer_data_with_array_buffer_view(
-			WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
-			&index_array_buffer_view,
-			draw_mode,
-		);
-	}
+	context.buffer_data_u32(BufferTarget::ElementArray, indices, draw_mode);
 
 	Ok(DrawBuffers { vertex_buffer, vertex_array_object, index_buffer, num_vertices, num_indices })
 }
@@ -144,55 +120,56 @@ pub enum DrawMode {
 	Points,
 }
 
-pub fn clear_frame(context: &WebGl2RenderingContext) {
-	context.clear_color(0.45, 0.67, 0.93, 1.0);
-	context
-		.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT);
-}
+pub fn clear_frame<B: GraphicsBackend>(context: &B, color: &nglm::Vec3) { context.clear(color); }
 
-pub fn draw_meshes(
-	context: &WebGl2RenderingContext,
-	camera: &Camera,
-	buffers: &Vec<(BasicMesh, DrawBuffers)>,
-	draw_mode: DrawMode,
+pub fn draw_buffers<B: GraphicsBackend>(
+	context: &B,
+	buffers: &DrawBuffers<B>,
+	draw_mode: &DrawMode,
 ) {
-	buffers.iter().for_each(|(m, b)| {
-		if m.is_visible(camera) {
-			context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&b.vertex_buffer));
-			context.bind_vertex_array(Some(&b.vertex_array_object));
-
-			let mode: u32 = match draw_mode {
-				DrawMode::Surface => WebGl2RenderingContext::TRIANGLES,
-				DrawMode::Wireframe => WebGl2RenderingContext::LINE_STRIP,
-				DrawMode::Points => WebGl2RenderingContext::POINTS,
-			};
-			context
-				.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, Some(&b.index_buffer));
-			context.draw_elements_with_i32(
-				mode,
-				b.num_indices.try_into().unwrap(),
-				WebGl2RenderingContext::UNSIGNED_INT,
-				0,
-			);
-		}
-	});
+	context.bind_buffer(BufferTarget::Array, Some(&buffers.vertex_buffer));
+	context.bind_vertex_array(Some(&buffers.vertex_array_object));
+
+	let mode = match draw_mode {
+		DrawMode::Surface => Primitive::Triangles,
+		DrawMode::Wireframe => Primitive::LineStrip,
+		DrawMode::Points => Primitive::Points,
+	};
+	context.bind_buffer(BufferTarget::ElementArray, Some(&buffers.index_buffer));
+	context.draw_elements(mode, buffers.num_indices.try_into().unwrap());
 }
 
-// pub fn draw_buffers(context: &WebGl2RenderingContext, buffers:
-// &Vec<DrawBuffers>, draw_mode: DrawMode) {     buffers.iter()
-//         .for_each(|b| {
-//             context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER,
-// Some(&b.vertex_buffer));
-// context.bind_vertex_array(Some(&b.vertex_array_object));
-//
-//             let mode: u32 = match draw_mode {
-//                 DrawMode::Surface => WebGl2RenderingContext::TRIANGLES,
-//                 DrawMode::Wireframe => WebGl2RenderingContext::LINE_STRIP,
-//             };
-//             context.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
-// Some(&b.index_buffer));             context.draw_elements_with_i32(mode,
-//                                            b.num_indices.try_into().unwrap(),
-//
-// WebGl2RenderingContext::UNSIGNED_INT,
-// 0);         });
-// }
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::application::shaders::get_planet_shaders;
+	use crate::application::vertex::{BasicMesh, Vertex};
+	use crate::render_core::recording_backend::{Command, RecordingBackend};
+
+	#[test]
+	fn uploads_and_draws_a_mesh() {
+		let backend = RecordingBackend::new();
+		let shader = get_planet_shaders(&backend).unwrap().standard;
+		shader.use_shader();
+
+		let vertex = Vertex::from_vecs(nglm::zero(), nglm::zero(), nglm::zero());
+		let mesh = BasicMesh::with_contents(vec![vertex; 3], vec![0, 1, 2]);
+		let buffers = add_mesh(&shader, &mesh, MeshMode::Static).unwrap();
+		draw_buffers(&backend, &buffers, &DrawMode::Surface);
+
+		assert_eq!(backend.errors(), Vec::<String>::new());
+		let commands = backend.commands();
+		let attributes: Vec<u32> = commands
+			.iter()
+			.filter_map(|command| match command {
+				Command::VertexAttribute { location, .. } => Some(*location),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(attributes, vec![0, 1, 2]);
+		assert_eq!(
+			commands.last(),
+			Some(&Command::DrawElements { primitive: Primitive::Triangles, count: 3 })
+		);
+	}
+}
@@ -1,11 +1,18 @@
 pub mod animation;
 pub mod animation_params;
+pub mod arcball;
+pub mod backend;
 pub mod camera;
 pub mod canvas;
+pub mod culling;
 pub mod frame_sequencer;
 pub mod image;
 pub mod mesh;
+#[cfg(test)]
+pub mod recording_backend;
 /// This module provides the key ingredients to rendering in a WebGL2 context.
 pub mod shader;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod software_backend;
 pub mod uniform;
 pub mod viewport;
@@ -0,0 +1,536 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::render_core::backend::{
+	BufferTarget, BufferUsage, DepthFunction, GraphicsBackend, Primitive, ShaderKind,
+	TextureFilter, TextureFormat, UniformData,
+};
+
+/// Stands in for every kind of GPU object. Handles are never reused, so one
+/// kind of handle passed where another is expected is caught as unknown.
+pub type Handle = u32;
+
+/// One call made to a `RecordingBackend`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+	CreateBuffer(Handle),
+	BindBuffer(BufferTarget, Option<Handle>),
+	/// `len` counts elements, not bytes
+	BufferData {
+		target: BufferTarget,
+		len: usize,
+		usage: BufferUsage,
+	},
+	CreateVertexArray(Handle),
+	BindVertexArray(Option<Handle>),
+	VertexAttribute {
+		location: u32,
+		size: i32,
+		stride: i32,
+		offset: i32,
+	},
+	CreateTexture(Handle),
+	ActiveTexture(u32),
+	BindTexture(Option<Handle>),
+	TextureSampling {
+		min_filter: TextureFilter,
+		mag_filter: TextureFilter,
+	},
+	TextureImage {
+		format: TextureFormat,
+		width: u32,
+		height: u32,
+	},
+	CompileShader(ShaderKind, Handle),
+	LinkProgram(Handle),
+	UseProgram(Option<Handle>),
+	Uniform {
+		name: String,
+		value: UniformData,
+	},
+	Clear(nglm::Vec3),
+	SetDepth(DepthFunction, bool),
+	DrawElements {
+		primitive: Primitive,
+		count: i32,
+	},
+	DrawArrays {
+		primitive: Primitive,
+		first: i32,
+		count: i32,
+	},
+}
+
+/// What a shader declared, read from its source
+#[derive(Clone, Debug, Default)]
+struct Declarations {
+	/// Uniform names and their GLSL types
+	uniforms: HashMap<String, String>,
+	/// Vertex inputs and their `layout` locations
+	attributes: HashMap<String, u32>,
+}
+
+struct UniformLocation {
+	program: Handle,
+	name: String,
+	glsl_type: String,
+}
+
+#[derive(Default)]
+struct State {
+	commands: Vec<Command>,
+	errors: Vec<String>,
+	next_handle: Handle,
+
+	/// Elements uploaded to each buffer
+	buffers: HashMap<Handle, usize>,
+	vertex_arrays: Vec<Handle>,
+	textures: Vec<Handle>,
+	shaders: HashMap<Handle, (ShaderKind, Declarations)>,
+	programs: HashMap<Handle, Declarations>,
+	uniform_locations: HashMap<Handle, UniformLocation>,
+
+	bound_buffers: HashMap<BufferTarget, Handle>,
+	bound_vertex_array: Option<Handle>,
+	active_texture: u32,
+	/// The texture bound to each texture unit
+	bound_textures: HashMap<u32, Handle>,
+	current_program: Option<Handle>,
+}
+
+impl State {
+	fn new_handle(&mut self) -> Handle {
+		self.next_handle += 1;
+		self.next_handle
+	}
+
+	fn error(&mut self, error: String) { self.errors.push(error); }
+
+	fn require(&mut self, condition: bool, error: impl FnOnce() -> String) {
+		if !condition {
+			self.error(error());
+		}
+	}
+}
+
+/// A `GraphicsBackend` that draws nothing, but keeps every call it's given
+/// and checks each one against the GPU state the calls before it set up.
+/// Misuse, like uploading to an unbound buffer or writing a `float` to a
+/// `mat4` uniform, is collected in `errors` rather than failing straight away.
+///
+/// Shaders are read for their `uniform` and `layout (location = N) in`
+/// declarations instead of being compiled, which is just enough to check
+/// uniform types and hand out attribute locations.
+#[derive(Clone, Default)]
+pub struct RecordingBackend {
+	state: Rc<RefCell<State>>,
+}
+
+impl std::fmt::Debug for RecordingBackend {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let state = self.state.borrow();
+		f.debug_struct("RecordingBackend")
+			.field("commands", &state.commands.len())
+			.field("errors", &state.errors)
+			.finish()
+	}
+}
+
+impl RecordingBackend {
+	pub fn new() -> Self { Self::default() }
+
+	pub fn commands(&self) -> Vec<Command> { self.state.borrow().commands.clone() }
+
+	/// Hands back the commands recorded so far, starting a fresh list
+	pub fn take_commands(&self) -> Vec<Command> {
+		std::mem::take(&mut self.state.borrow_mut().commands)
+	}
+
+	pub fn errors(&self) -> Vec<String> { self.state.borrow().errors.clone() }
+
+	fn record(&self, command: Command) { self.state.borrow_mut().commands.push(command); }
+}
+
+impl GraphicsBackend for RecordingBackend {
+	type Buffer = Handle;
+	type Program = Handle;
+	type Shader = Handle;
+	type Texture = Handle;
+	type UniformLocation = Handle;
+	type VertexArray = Handle;
+
+	fn create_buffer(&self) -> Option<Self::Buffer> {
+		let mut state = self.state.borrow_mut();
+		let buffer = state.new_handle();
+		state.buffers.insert(buffer, 0);
+		state.commands.push(Command::CreateBuffer(buffer));
+		Some(buffer)
+	}
+
+	fn bind_buffer(&self, target: BufferTarget, buffer: Option<&Self::Buffer>) {
+		let mut state = self.state.borrow_mut();
+		match buffer {
+			Some(buffer) => {
+				let known = state.buffers.contains_key(buffer);
+				state.require(known, || format!("Bound unknown buffer {buffer}"));
+				state.bound_buffers.insert(target, *buffer);
+			}
+			None => {
+				state.bound_buffers.remove(&target);
+			}
+		}
+		state.commands.push(Command::BindBuffer(target, buffer.copied()));
+	}
+
+	fn buffer_data_f32(&self, target: BufferTarget, data: &[f32], usage: BufferUsage) {
+		self.buffer_data(target, data.len(), usage);
+	}
+
+	fn buffer_data_u32(&self, target: BufferTarget, data: &[u32], usage: BufferUsage) {
+		self.buffer_data(target, data.len(), usage);
+	}
+
+	fn create_vertex_array(&self) -> Option<Self::VertexArray> {
+		let mut state = self.state.borrow_mut();
+		let vertex_array = state.new_handle();
+		state.vertex_arrays.push(vertex_array);
+		state.commands.push(Command::CreateVertexArray(vertex_array));
+		Some(vertex_array)
+	}
+
+	fn bind_vertex_array(&self, vertex_array: Option<&Self::VertexArray>) {
+		let mut state = self.state.borrow_mut();
+		if let Some(vertex_array) = vertex_array {
+			let known = state.vertex_arrays.contains(vertex_array);
+			state.require(known, || format!("Bound unknown vertex array {vertex_array}"));
+		}
+		state.bound_vertex_array = vertex_array.copied();
+		state.commands.push(Command::BindVertexArray(vertex_array.copied()));
+	}
+
+	fn vertex_attribute_f32(&self, location: u32, size: i32, stride: i32, offset: i32) {
+		let mut state = self.state.borrow_mut();
+		let ready = state.bound_vertex_array.is_some()
+			&& state.bound_buffers.contains_key(&BufferTarget::Array);
+		state.require(ready, || {
+			format!("Vertex attribute {location} set without a vertex array and array buffer bound")
+		});
+		state.commands.push(Command::VertexAttribute { location, size, stride, offset });
+	}
+
+	fn create_texture(&self) -> Option<Self::Texture> {
+		let mut state = self.state.borrow_mut();
+		let texture = state.new_handle();
+		state.textures.push(texture);
+		state.commands.push(Command::CreateTexture(texture));
+		Some(texture)
+	}
+
+	fn active_texture(&self, unit: u32) {
+		let mut state = self.state.borrow_mut();
+		state.active_texture = unit;
+		state.commands.push(Command::ActiveTexture(unit));
+	}
+
+	fn bind_texture(&self, texture: Option<&Self::Texture>) {
+		let mut state = self.state.borrow_mut();
+		let unit = state.active_texture;
+		match texture {
+			Some(texture) => {
+				let known = state.textures.contains(texture);
+				state.require(known, || format!("Bound unknown texture {texture}"));
+				state.bound_textures.insert(unit, *texture);
+			}
+			None => {
+				state.bound_textures.remove(&unit);
+			}
+		}
+		state.commands.push(Command::BindTexture(texture.copied()));
+	}
+
+	fn texture_sampling(&self, min_filter: TextureFilter, mag_filter: TextureFilter) {
+		self.require_bound_texture("Texture sampling set");
+		self.record(Command::TextureSampling { min_filter, mag_filter });
+	}
+
+	fn texture_image(
+		&self,
+		format: TextureFormat,
+		width: u32,
+		height: u32,
+		pixels: &[u8],
+	) -> Result<(), String> {
+		self.require_bound_texture("Texture image uploaded");
+		self.record(Command::TextureImage { format, width, height });
+
+		let expected = width as usize * height as usize * format.bytes_per_pixel();
+		if pixels.len() == expected {
+			Ok(())
+		} else {
+			Err(format!(
+				"{width}x{height} {format:?} image needs {expected} bytes, not {}",
+				pixels.len()
+			))
+		}
+	}
+
+	fn compile_shader(&self, kind: ShaderKind, source: &str) -> Result<Self::Shader, String> {
+		if source.contains("#include") {
+			return Err("Shader has includes left in it".to_owned());
+		}
+
+		let declarations = read_declarations(source)?;
+		let mut state = self.state.borrow_mut();
+		let shader = state.new_handle();
+		state.shaders.insert(shader, (kind, declarations));
+		state.commands.push(Command::CompileShader(kind, shader));
+		Ok(shader)
+	}
+
+	fn link_program(
+		&self,
+		vertex: &Self::Shader,
+		fragment: &Self::Shader,
+	) -> Result<Self::Program, String> {
+		let mut state = self.state.borrow_mut();
+		let (vertex, fragment) = match (state.shaders.get(vertex), state.shaders.get(fragment)) {
+			(Some((ShaderKind::Vertex, vertex)), Some((ShaderKind::Fragment, fragment))) => {
+				(vertex.clone(), fragment.clone())
+			}
+			_ => return Err("Programs need a vertex and a fragment shader".to_owned()),
+		};
+
+		let mut uniforms = vertex.uniforms;
+		for (name, glsl_type) in fragment.uniforms {
+			match uniforms.get(&name) {
+				Some(declared) if *declared != glsl_type => {
+					return Err(format!(
+						"Uniform {name} is a {declared} in the vertex shader but a {glsl_type} in the \
+						 fragment shader"
+					));
+				}
+				_ => {
+					uniforms.insert(name, glsl_type);
+				}
+			}
+		}
+
+		let program = state.new_handle();
+		state.programs.insert(program, Declarations { uniforms, attributes: vertex.attributes });
+		state.commands.push(Command::LinkProgram(program));
+		Ok(program)
+	}
+
+	fn use_program(&self, program: Option<&Self::Program>) {
+		let mut state = self.state.borrow_mut();
+		if let Some(program) = program {
+			let known = state.programs.contains_key(program);
+			state.require(known, || format!("Used unknown program {program}"));
+		}
+		state.current_program = program.copied();
+		state.commands.push(Command::UseProgram(program.copied()));
+	}
+
+	fn attribute_location(&self, program: &Self::Program, name: &str) -> Option<u32> {
+		let state = self.state.borrow();
+		state.programs.get(program)?.attributes.get(name).copied()
+	}
+
+	fn uniform_location(
+		&self,
+		program: &Self::Program,
+		name: &str,
+	) -> Option<Self::UniformLocation> {
+		let mut state = self.state.borrow_mut();
+		let glsl_type = state.programs.get(program)?.uniforms.get(name)?.clone();
+
+		let location = state.new_handle();
+		let uniform = UniformLocation { program: *program, name: name.to_owned(), glsl_type };
+		state.uniform_locations.insert(location, uniform);
+		Some(location)
+	}
+
+	fn write_uniform(&self, location: Option<&Self::UniformLocation>, value: UniformData) {
+		let Some(location) = location else {
+			return;
+		};
+
+		let mut state = self.state.borrow_mut();
+		let Some(uniform) = state.uniform_locations.get(location) else {
+			state.error(format!("Wrote to unknown uniform location {location}"));
+			return;
+		};
+
+		let name = uniform.name.clone();
+		let mut errors = Vec::new();
+		if state.current_program != Some(uniform.program) {
+			errors.push(format!(
+				"Uniform {name} belongs to program {}, but {:?} is in use",
+				uniform.program, state.current_program
+			));
+		}
+		if !value.fits(&uniform.glsl_type) {
+			errors.push(format!(
+				"Uniform {name} is a {}, but was given {value:?}",
+				uniform.glsl_type
+			));
+		}
+
+		state.errors.extend(errors);
+		state.commands.push(Command::Uniform { name, value });
+	}
+
+	fn clear(&self, color: &nglm::Vec3) { self.record(Command::Clear(*color)); }
+
+	fn set_depth(&self, function: DepthFunction, write: bool) {
+		self.record(Command::SetDepth(function, write));
+	}
+
+	fn draw_elements(&self, primitive: Primitive, count: i32) {
+		self.require_draw_state("Elements drawn");
+
+		let mut state = self.state.borrow_mut();
+		let indices = state
+			.bound_buffers
+			.get(&BufferTarget::ElementArray)
+			.and_then(|buffer| state.buffers.get(buffer))
+			.copied();
+		match indices {
+			Some(indices) => state.require(count as usize <= indices, || {
+				format!("Drew {count} indices from an element array buffer of {indices}")
+			}),
+			None => state.error("Elements drawn without an element array buffer bound".to_owned()),
+		}
+		state.commands.push(Command::DrawElements { primitive, count });
+	}
+
+	fn draw_arrays(&self, primitive: Primitive, first: i32, count: i32) {
+		self.require_draw_state("Arrays drawn");
+		self.record(Command::DrawArrays { primitive, first, count });
+	}
+}
+
+impl RecordingBackend {
+	fn buffer_data(&self, target: BufferTarget, len: usize, usage: BufferUsage) {
+		let mut state = self.state.borrow_mut();
+		match state.bound_buffers.get(&target).copied() {
+			Some(buffer) => {
+				state.buffers.insert(buffer, len);
+			}
+			None => state.error(format!("Uploaded to {target:?} with no buffer bound")),
+		}
+		state.commands.push(Command::BufferData { target, len, usage });
+	}
+
+	fn require_bound_texture(&self, action: &str) {
+		let mut state = self.state.borrow_mut();
+		let unit = state.active_texture;
+		let bound = state.bound_textures.contains_key(&unit);
+		state.require(bound, || format!("{action} with no texture bound to unit {unit}"));
+	}
+
+	fn require_draw_state(&self, action: &str) {
+		let mut state = self.state.borrow_mut();
+		let has_program = state.current_program.is_some();
+		state.require(has_program, || format!("{action} with no program in use"));
+		let has_vertex_array = state.bound_vertex_array.is_some();
+		state.require(has_vertex_array, || format!("{action} with no vertex array bound"));
+	}
+}
+
+/// Picks out `uniform` declarations and `layout (location = N) in` vertex
+/// inputs, one per line
+fn read_declarations(source: &str) -> Result<Declarations, String> {
+	let uniform = regex::Regex::new(r"^uniform\s+(?:(?:lowp|mediump|highp)\s+)?(\w+)\s+(\w+)\s*;")
+		.map_err(|e| e.to_string())?;
+	let attribute =
+		regex::Regex::new(r"^layout\s*\(\s*location\s*=\s*(\d+)\s*\)\s*in\s+\w+\s+(\w+)\s*;")
+			.map_err(|e| e.to_string())?;
+
+	let mut declarations = Declarations::default();
+	for line in source.lines().map(str::trim) {
+		if let Some(captures) = uniform.captures(line) {
+			declarations.uniforms.insert(captures[2].to_owned(), captures[1].to_owned());
+		} else if let Some(captures) = attribute.captures(line) {
+			let location = captures[1].parse().map_err(|_| format!("Bad location in {line}"))?;
+			declarations.attributes.insert(captures[2].to_owned(), location);
+		}
+	}
+	Ok(declarations)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const VERTEX: &str = "
+		layout (location = 0) in vec3 position;
+		uniform mat4 u_view;
+		uniform highp float u_scale;
+	";
+	const FRAGMENT: &str = "
+		uniform vec3 u_color;
+		uniform float u_scale; // Shared with the vertex shader
+	";
+
+	fn program(backend: &RecordingBackend) -> Handle {
+		let vertex = backend.compile_shader(ShaderKind::Vertex, VERTEX).unwrap();
+		let fragment = backend.compile_shader(ShaderKind::Fragment, FRAGMENT).unwrap();
+		backend.link_program(&vertex, &fragment).unwrap()
+	}
+
+	#[test]
+	fn reads_declarations_from_both_shaders() {
+		let backend = RecordingBackend::new();
+		let program = program(&backend);
+
+		assert_eq!(backend.attribute_location(&program, "position"), Some(0));
+		assert_eq!(backend.attribute_location(&program, "normal"), None);
+		for name in ["u_view", "u_scale", "u_color"] {
+			assert!(backend.uniform_location(&program, name).is_some(), "{name}");
+		}
+		assert!(backend.uniform_location(&program, "u_missing").is_none());
+	}
+
+	#[test]
+	fn checks_uniform_types_and_program() {
+		let backend = RecordingBackend::new();
+		let program = program(&backend);
+		let scale = backend.uniform_location(&program, "u_scale");
+
+		backend.write_uniform(scale.as_ref(), UniformData::Float(1.0));
+		backend.use_program(Some(&program));
+		backend.write_uniform(scale.as_ref(), UniformData::Float(2.0));
+		backend.write_uniform(scale.as_ref(), UniformData::Int(3));
+
+		assert_eq!(
+			backend.errors(),
+			vec![
+				format!("Uniform u_scale belongs to program {program}, but None is in use"),
+				"Uniform u_scale is a float, but was given Int(3)".to_owned(),
+			]
+		);
+	}
+
+	#[test]
+	fn checks_binding_before_use() {
+		let backend = RecordingBackend::new();
+		backend.buffer_data_f32(BufferTarget::Array, &[0.0; 3], BufferUsage::Static);
+
+		let buffer = backend.create_buffer().unwrap();
+		backend.bind_buffer(BufferTarget::ElementArray, Some(&buffer));
+		backend.buffer_data_u32(BufferTarget::ElementArray, &[0, 1, 2], BufferUsage::Static);
+		backend.draw_elements(Primitive::Triangles, 6);
+
+		assert_eq!(
+			backend.errors(),
+			vec![
+				"Uploaded to Array with no buffer bound",
+				"Elements drawn with no program in use",
+				"Elements drawn with no vertex array bound",
+				"Drew 6 indices from an element array buffer of 3",
+			]
+		);
+	}
+}
@@ -2,66 +2,33 @@ use std::ops::Index;
 
 use phf::{phf_map, Map};
 use regex;
-use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader};
+
+use crate::render_core::backend::{GraphicsBackend, ShaderKind};
 
 /// Note: `shader_path` must be relative to this crate's `src` directory
-pub fn preprocess_and_compile_shader(
-	context: &WebGl2RenderingContext,
-	shader_type: u32,
+pub fn preprocess_and_compile_shader<B: GraphicsBackend>(
+	context: &B,
+	shader_kind: ShaderKind,
 	shader_source: &str,
-) -> Result<WebGlShader, String> {
+) -> Result<B::Shader, String> {
 	let preprocessed = preprocess_shader(shader_source);
-	compile_shader(context, shader_type, preprocessed.as_str())
+	context.compile_shader(shader_kind, preprocessed.as_str())
 }
 
-fn compile_shader(
-	context: &WebGl2RenderingContext,
-	shader_type: u32,
-	shader_source: &str,
-) -> Result<WebGlShader, String> {
-	let shader = context
-		.create_shader(shader_type)
-		.ok_or_else(|| String::from("Unable to create shader object"))?;
-
-	context.shader_source(&shader, shader_source);
-	context.compile_shader(&shader);
-
-	if context
-		.get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
-		.as_bool()
-		.unwrap_or(false)
-	{
-		Ok(shader)
-	} else {
-		Err(context
-			.get_shader_info_log(&shader)
-			.unwrap_or_else(|| String::from("Unknown error creating shader")))
-	}
+pub fn link_program<B: GraphicsBackend>(
+	context: &B,
+	vert_shader: &B::Shader,
+	frag_shader: &B::Shader,
+) -> Result<B::Program, String> {
+	context.link_program(vert_shader, frag_shader)
 }
 
-pub fn link_program(
-	context: &WebGl2RenderingContext,
-	vert_shader: &WebGlShader,
-	frag_shader: &WebGlShader,
-) -> Result<WebGlProgram, String> {
-	let program =
-		context.create_program().ok_or_else(|| String::from("Unable to create shader object"))?;
-
-	context.attach_shader(&program, vert_shader);
-	context.attach_shader(&program, frag_shader);
-	context.link_program(&program);
-
-	if context
-		.get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
-		.as_bool()
-		.unwrap_or(false)
-	{
-		Ok(program)
-	} else {
-		Err(context
-			.get_program_info_log(&program)
-			.unwrap_or_else(|| String::from("Unknown error creating program object")))
-	}
+/// Adds a `#define` for each of `defines`, just after the `#version` line that
+/// has to come first
+pub fn with_defines(shader_source: &str, defines: &[&str]) -> String {
+	let (version, rest) = shader_source.split_once('\n').unwrap_or((shader_source, ""));
+	let defines: String = defines.iter().map(|define| format!("#define {define}\n")).collect();
+	format!("{version}\n{defines}{rest}")
 }
 
 const INCLUDE_STRING_MATCH: &str = r#"#include <([a-zA-Z0-9\.\-\_/]+)>"#;
@@ -79,8 +46,11 @@ macro_rules! include_strs {
 const PREPROCESSABLE_SHADERS: Map<&str, &str> = include_strs![
 	"application/shaders/channels.glsl",
 	"application/shaders/color.glsl",
+	"application/shaders/colormaps.glsl",
+	"application/shaders/depth.glsl",
 	"application/shaders/pointmapping.glsl",
 	"application/shaders/math.glsl",
+	"application/shaders/terrain.glsl",
 ];
 
 fn load_shader(source_path: &str) -> &str {
@@ -89,7 +59,7 @@ fn load_shader(source_path: &str) -> &str {
 		.expect(format!("Shader {} was not listed for preprocessing", source_path).as_str())
 }
 
-fn preprocess_shader(shader_source: &str) -> String {
+pub(crate) fn preprocess_shader(shader_source: &str) -> String {
 	let with_includes = fill_includes(shader_source);
 	with_includes
 }
@@ -145,6 +115,36 @@ mod tests {
 		println!("{}", result);
 	}
 
+	#[test]
+	fn planet_includes() {
+		let result = preprocess_shader(include_str!("../application/shaders/planet.vert"));
+		assert!(!result.contains("#include"));
+		assert!(result.contains("vec3 terrainNormal("));
+		assert!(result.contains("float logDepthInput("));
+
+		let fragment = preprocess_shader(include_str!("../application/shaders/planet.frag"));
+		assert!(!fragment.contains("#include"));
+		assert!(fragment.contains("float logarithmicDepth("));
+	}
+
+	#[test]
+	fn defines_follow_the_version() {
+		let source = "#version 300 es\n\nvoid main() {}\n";
+		assert_eq!(with_defines(source, &[]), source);
+		assert_eq!(
+			with_defines(source, &["LOG_DEPTH", "DEBUG"]),
+			"#version 300 es\n#define LOG_DEPTH\n#define DEBUG\n\nvoid main() {}\n"
+		);
+	}
+
+	#[test]
+	fn sky_includes() {
+		let result = preprocess_shader(include_str!("../application/shaders/sky.frag"));
+		assert!(!result.contains("#include"));
+		assert!(result.contains("float max3("));
+		assert!(result.contains("#define M_PI"));
+	}
+
 	#[test]
 	fn shader_map() {
 		let shader_map = include_strs!("application/shaders/pointmapping.glsl");
@@ -0,0 +1,878 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use image::{Rgba, RgbaImage};
+
+use crate::render_core::backend::{
+	BufferTarget, BufferUsage, DepthFunction, GraphicsBackend, Primitive, ShaderKind,
+	TextureFilter, TextureFormat, UniformData,
+};
+use crate::render_core::shader::preprocess_shader;
+
+/// Stands in for every kind of GPU object, as in `RecordingBackend`
+pub type Handle = u32;
+
+/// A GLSL program rewritten in Rust, for `SoftwareBackend` to run in place of
+/// the real one. Only what's needed to draw triangles is ported: no
+/// derivatives and no `discard`.
+pub trait ShaderPort {
+	/// The `layout (location = N) in` declarations of the vertex shader
+	fn attributes(&self) -> &[(&'static str, u32)];
+
+	/// How many floats `vertex` hands on to `fragment`
+	fn varyings(&self) -> usize;
+
+	/// Runs once per vertex, with its attributes indexed by location and its
+	/// index as `vertex_id`. Fills in `varyings` and returns `gl_Position`.
+	fn vertex(
+		&self,
+		vertex_id: u32,
+		attributes: &[nglm::Vec4],
+		inputs: &ShaderInputs,
+		varyings: &mut [f32],
+	) -> nglm::Vec4;
+
+	/// Runs once per covered pixel, with perspective-correct `varyings`.
+	/// `window_depth` is `gl_FragCoord.z`. Returns the color and
+	/// `gl_FragDepth`.
+	fn fragment(
+		&self,
+		varyings: &[f32],
+		window_depth: f32,
+		inputs: &ShaderInputs,
+	) -> (nglm::Vec4, f32);
+}
+
+#[derive(Clone, Debug)]
+struct Texture {
+	format: TextureFormat,
+	width: u32,
+	height: u32,
+	pixels: Vec<u8>,
+	mag_filter: TextureFilter,
+}
+
+impl Default for Texture {
+	fn default() -> Self {
+		Self {
+			format: TextureFormat::Rgba,
+			width: 0,
+			height: 0,
+			pixels: Vec::new(),
+			mag_filter: TextureFilter::Linear,
+		}
+	}
+}
+
+impl Texture {
+	fn texel(&self, x: i64, y: i64) -> nglm::Vec4 {
+		let x = x.clamp(0, self.width as i64 - 1) as usize;
+		let y = y.clamp(0, self.height as i64 - 1) as usize;
+		let bytes = self.format.bytes_per_pixel();
+		let start = (y * self.width as usize + x) * bytes;
+		let channel = |i: usize| self.pixels[start + i] as f32 / 255.0;
+
+		match self.format {
+			TextureFormat::Luminance => nglm::vec4(channel(0), channel(0), channel(0), 1.0),
+			TextureFormat::Rgb => nglm::vec4(channel(0), channel(1), channel(2), 1.0),
+			TextureFormat::Rgba => nglm::vec4(channel(0), channel(1), channel(2), channel(3)),
+		}
+	}
+}
+
+/// A texture unit, as a shader port sees it
+#[derive(Clone, Copy)]
+pub struct Sampler<'a> {
+	texture: Option<&'a Texture>,
+}
+
+impl<'a> Sampler<'a> {
+	/// Like `textureSize(sampler, 0)`
+	pub fn size(&self) -> (u32, u32) {
+		self.texture.map_or((0, 0), |texture| (texture.width, texture.height))
+	}
+
+	/// Like `texture(sampler, uv)`, clamped at the edges. Without derivatives
+	/// there's no telling minification from magnification, so the
+	/// magnification filter is always used. Units with nothing bound read as
+	/// opaque black, as incomplete textures do in WebGL.
+	pub fn sample(&self, uv: &nglm::Vec2) -> nglm::Vec4 {
+		let texture = match self.texture {
+			Some(texture) if texture.width > 0 && texture.height > 0 => texture,
+			_ => return nglm::vec4(0.0, 0.0, 0.0, 1.0),
+		};
+
+		let x = uv.x * texture.width as f32;
+		let y = uv.y * texture.height as f32;
+		match texture.mag_filter {
+			TextureFilter::Nearest => texture.texel(x.floor() as i64, y.floor() as i64),
+			TextureFilter::Linear => {
+				let (x, y) = (x - 0.5, y - 0.5);
+				let (left, top) = (x.floor(), y.floor());
+				let (fx, fy) = (x - left, y - top);
+				let (left, top) = (left as i64, top as i64);
+
+				let upper =
+					nglm::lerp(&texture.texel(left, top), &texture.texel(left + 1, top), fx);
+				let lower = nglm::lerp(
+					&texture.texel(left, top + 1),
+					&texture.texel(left + 1, top + 1),
+					fx,
+				);
+				nglm::lerp(&upper, &lower, fy)
+			}
+		}
+	}
+}
+
+/// Uniforms and textures of the program being drawn with. Reading a uniform
+/// that was never written, or as the wrong type, gives zeros, like a freshly
+/// linked program.
+pub struct ShaderInputs<'a> {
+	uniforms: &'a HashMap<String, UniformData>,
+	textures: &'a HashMap<Handle, Texture>,
+	bound_textures: &'a HashMap<u32, Handle>,
+}
+
+impl<'a> ShaderInputs<'a> {
+	pub fn int(&self, name: &str) -> i32 {
+		match self.uniforms.get(name) {
+			Some(UniformData::Int(value)) => *value,
+			_ => 0,
+		}
+	}
+
+	pub fn bool(&self, name: &str) -> bool { self.int(name) != 0 }
+
+	pub fn float(&self, name: &str) -> f32 {
+		match self.uniforms.get(name) {
+			Some(UniformData::Float(value)) => *value,
+			_ => 0.0,
+		}
+	}
+
+	pub fn vec3(&self, name: &str) -> nglm::Vec3 {
+		match self.uniforms.get(name) {
+			Some(UniformData::Vec3(value)) => *value,
+			_ => nglm::zero(),
+		}
+	}
+
+	pub fn mat4(&self, name: &str) -> nglm::Mat4 {
+		match self.uniforms.get(name) {
+			Some(UniformData::Mat4(value)) => *value,
+			_ => nglm::zero(),
+		}
+	}
+
+	/// A `mat3x4`, whose columns are `vec4`s
+	pub fn mat4x3(&self, name: &str) -> nglm::Mat4x3 {
+		match self.uniforms.get(name) {
+			Some(UniformData::Mat4x3(value)) => *value,
+			_ => nglm::zero(),
+		}
+	}
+
+	/// The texture bound to the unit a `sampler2D` uniform names
+	pub fn sampler(&self, name: &str) -> Sampler<'a> {
+		let texture = self
+			.bound_textures
+			.get(&(self.int(name) as u32))
+			.and_then(|texture| self.textures.get(texture));
+		Sampler { texture }
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+struct AttributePointer {
+	buffer: Handle,
+	size: usize,
+	/// In floats, already accounting for tightly packed attributes
+	stride: usize,
+	offset: usize,
+}
+
+struct Program {
+	port: Option<Rc<dyn ShaderPort>>,
+	uniforms: HashMap<String, UniformData>,
+}
+
+#[derive(Default)]
+struct Objects {
+	next_handle: Handle,
+	f32_buffers: HashMap<Handle, Vec<f32>>,
+	u32_buffers: HashMap<Handle, Vec<u32>>,
+	/// Attribute pointers of each vertex array, by location
+	vertex_arrays: HashMap<Handle, HashMap<u32, AttributePointer>>,
+	textures: HashMap<Handle, Texture>,
+	/// Preprocessed source of each shader
+	shaders: HashMap<Handle, (ShaderKind, String)>,
+	programs: HashMap<Handle, Program>,
+	uniform_locations: HashMap<Handle, (Handle, String)>,
+	/// Ports by their preprocessed vertex and fragment sources
+	ports: Vec<(String, String, Rc<dyn ShaderPort>)>,
+
+	bound_buffers: HashMap<BufferTarget, Handle>,
+	bound_vertex_array: Option<Handle>,
+	active_texture: u32,
+	bound_textures: HashMap<u32, Handle>,
+	current_program: Option<Handle>,
+}
+
+impl Objects {
+	fn new_handle(&mut self) -> Handle {
+		self.next_handle += 1;
+		self.next_handle
+	}
+
+	fn bound_texture_mut(&mut self) -> Option<&mut Texture> {
+		let texture = self.bound_textures.get(&self.active_texture)?;
+		self.textures.get_mut(texture)
+	}
+}
+
+/// Row 0 is the top of the picture
+struct Framebuffer {
+	color: RgbaImage,
+	depth: Vec<f32>,
+	depth_function: DepthFunction,
+	depth_write: bool,
+}
+
+impl Framebuffer {
+	fn new(width: u32, height: u32) -> Self {
+		Self {
+			color: RgbaImage::new(width, height),
+			depth: vec![1.0; width as usize * height as usize],
+			depth_function: DepthFunction::Less,
+			depth_write: true,
+		}
+	}
+
+	/// Depth tests the fragment at `(x, y)` and keeps it if it passes
+	fn write(&mut self, x: u32, y: u32, color: &nglm::Vec4, depth: f32) {
+		let index = (y * self.color.width() + x) as usize;
+		let passes = match self.depth_function {
+			DepthFunction::Less => depth < self.depth[index],
+			DepthFunction::LessOrEqual => depth <= self.depth[index],
+		};
+		if !passes {
+			return;
+		}
+
+		if self.depth_write {
+			self.depth[index] = depth;
+		}
+		let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+		self.color.put_pixel(
+			x,
+			y,
+			Rgba([channel(color.x), channel(color.y), channel(color.z), channel(color.w)]),
+		);
+	}
+}
+
+/// A vertex after the vertex shader and the viewport transform
+struct ScreenVertex {
+	x: f32,
+	y: f32,
+	z: f32,
+	inverse_w: f32,
+	/// Divided by w, ready to be interpolated linearly on screen
+	varyings: Vec<f32>,
+}
+
+/// A `GraphicsBackend` that draws on the CPU, into an image that can be
+/// compared against a reference. Shaders can't be run as written: each program
+/// is drawn with the `ShaderPort` registered for its sources, and programs
+/// without one draw nothing.
+///
+/// Only filled triangles are drawn, with a depth test but no blending or face
+/// culling. Triangles that reach behind the near plane are dropped rather than
+/// clipped, which is fine for views that don't cut into the geometry.
+#[derive(Clone)]
+pub struct SoftwareBackend {
+	objects: Rc<RefCell<Objects>>,
+	framebuffer: Rc<RefCell<Framebuffer>>,
+}
+
+impl std::fmt::Debug for SoftwareBackend {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let framebuffer = self.framebuffer.borrow();
+		f.debug_struct("SoftwareBackend")
+			.field("width", &framebuffer.color.width())
+			.field("height", &framebuffer.color.height())
+			.field("ports", &self.objects.borrow().ports.len())
+			.finish()
+	}
+}
+
+impl SoftwareBackend {
+	pub fn new(width: u32, height: u32) -> Self {
+		Self {
+			objects: Rc::new(RefCell::new(Objects::default())),
+			framebuffer: Rc::new(RefCell::new(Framebuffer::new(width, height))),
+		}
+	}
+
+	/// Draws programs linked from these sources with `port`. The sources are
+	/// given as written, before includes are filled in.
+	pub fn add_port(&self, vertex_source: &str, fragment_source: &str, port: Rc<dyn ShaderPort>) {
+		self.objects.borrow_mut().ports.push((
+			preprocess_shader(vertex_source),
+			preprocess_shader(fragment_source),
+			port,
+		));
+	}
+
+	/// Starts a new, uncleared picture at another size, like resizing a canvas
+	pub fn resize(&self, width: u32, height: u32) {
+		let mut framebuffer = self.framebuffer.borrow_mut();
+		*framebuffer = Framebuffer {
+			depth_function: framebuffer.depth_function,
+			depth_write: framebuffer.depth_write,
+			..Framebuffer::new(width, height)
+		};
+	}
+
+	/// What's been drawn so far
+	pub fn snapshot(&self) -> RgbaImage { self.framebuffer.borrow().color.clone() }
+
+	fn buffer_data<T: Clone>(
+		&self,
+		target: BufferTarget,
+		data: &[T],
+		buffers: impl FnOnce(&mut Objects) -> &mut HashMap<Handle, Vec<T>>,
+	) {
+		let mut objects = self.objects.borrow_mut();
+		if let Some(buffer) = objects.bound_buffers.get(&target).copied() {
+			buffers(&mut objects).insert(buffer, data.to_vec());
+		}
+	}
+
+	/// Runs the current program over each triangle of `indices`
+	fn draw_triangles(&self, indices: impl Iterator<Item = u32>) {
+		let objects = self.objects.borrow();
+		let Some(program) = objects.current_program.and_then(|p| objects.programs.get(&p)) else {
+			return;
+		};
+		let Some(port) = &program.port else {
+			return;
+		};
+		let Some(pointers) = objects.bound_vertex_array.and_then(|v| objects.vertex_arrays.get(&v))
+		else {
+			return;
+		};
+
+		let inputs = ShaderInputs {
+			uniforms: &program.uniforms,
+			textures: &objects.textures,
+			bound_textures: &objects.bound_textures,
+		};
+		let mut framebuffer = self.framebuffer.borrow_mut();
+		let (width, height) = framebuffer.color.dimensions();
+
+		let locations = pointers.keys().max().map_or(0, |max| *max as usize + 1);
+		let mut shaded: HashMap<u32, Option<Rc<ScreenVertex>>> = HashMap::new();
+		let shade = |index: u32| -> Option<Rc<ScreenVertex>> {
+			let mut attributes = vec![nglm::vec4(0.0, 0.0, 0.0, 1.0); locations];
+			for (location, pointer) in pointers {
+				let data = objects.f32_buffers.get(&pointer.buffer)?;
+				let start = pointer.offset + index as usize * pointer.stride;
+				let attribute = &mut attributes[*location as usize];
+				for component in 0..pointer.size {
+					attribute[component] = *data.get(start + component)?;
+				}
+			}
+
+			let mut varyings = vec![0.0; port.varyings()];
+			let clip = port.vertex(index, &attributes, &inputs, &mut varyings);
+
+			// Behind the near plane
+			if clip.w <= 0.0 || clip.z < -clip.w {
+				return None;
+			}
+
+			let inverse_w = 1.0 / clip.w;
+			varyings.iter_mut().for_each(|varying| *varying *= inverse_w);
+			Some(Rc::new(ScreenVertex {
+				x: (clip.x * inverse_w + 1.0) * 0.5 * width as f32,
+				y: (1.0 - clip.y * inverse_w) * 0.5 * height as f32,
+				z: (clip.z * inverse_w + 1.0) * 0.5,
+				inverse_w,
+				varyings,
+			}))
+		};
+
+		let indices: Vec<u32> = indices.collect();
+		for triangle in indices.chunks_exact(3) {
+			let vertices: Option<Vec<Rc<ScreenVertex>>> = triangle
+				.iter()
+				.map(|index| shaded.entry(*index).or_insert_with(|| shade(*index)).clone())
+				.collect();
+			if let Some(vertices) = vertices {
+				rasterize(&vertices, port.as_ref(), &inputs, &mut framebuffer);
+			}
+		}
+	}
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `p`
+fn edge(a: (f32, f32), b: (f32, f32), p: (f32, f32)) -> f32 {
+	(b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+/// Shades every pixel whose center falls inside the triangle
+fn rasterize(
+	vertices: &[Rc<ScreenVertex>],
+	port: &dyn ShaderPort,
+	inputs: &ShaderInputs,
+	framebuffer: &mut Framebuffer,
+) {
+	let points: Vec<(f32, f32)> = vertices.iter().map(|v| (v.x, v.y)).collect();
+	let area = edge(points[0], points[1], points[2]);
+	if area == 0.0 || !area.is_finite() {
+		return;
+	}
+
+	let (width, height) = framebuffer.color.dimensions();
+	let bound = |axis: fn(&(f32, f32)) -> f32, limit: u32| {
+		let values = points.iter().map(axis);
+		let min = values.clone().fold(f32::INFINITY, f32::min).floor().max(0.0) as u32;
+		let max = values.fold(f32::NEG_INFINITY, f32::max).ceil().min(limit as f32) as u32;
+		(min, max)
+	};
+	let (min_x, max_x) = bound(|p| p.0, width);
+	let (min_y, max_y) = bound(|p| p.1, height);
+
+	let mut varyings = vec![0.0; vertices[0].varyings.len()];
+	for y in min_y..max_y {
+		for x in min_x..max_x {
+			let center = (x as f32 + 0.5, y as f32 + 0.5);
+			let weights = [
+				edge(points[1], points[2], center) / area,
+				edge(points[2], points[0], center) / area,
+				edge(points[0], points[1], center) / area,
+			];
+			if weights.iter().any(|weight| *weight < 0.0) {
+				continue;
+			}
+
+			let interpolate = |value: fn(&ScreenVertex) -> f32| -> f32 {
+				weights.iter().zip(vertices).map(|(weight, v)| weight * value(v)).sum()
+			};
+			let window_depth = interpolate(|v| v.z);
+			let inverse_w = interpolate(|v| v.inverse_w);
+			for (i, varying) in varyings.iter_mut().enumerate() {
+				let over_w: f32 =
+					weights.iter().zip(vertices).map(|(weight, v)| weight * v.varyings[i]).sum();
+				*varying = over_w / inverse_w;
+			}
+
+			let (color, depth) = port.fragment(&varyings, window_depth, inputs);
+			framebuffer.write(x, y, &color, depth.clamp(0.0, 1.0));
+		}
+	}
+}
+
+impl GraphicsBackend for SoftwareBackend {
+	type Buffer = Handle;
+	type Program = Handle;
+	type Shader = Handle;
+	type Texture = Handle;
+	type UniformLocation = Handle;
+	type VertexArray = Handle;
+
+	fn create_buffer(&self) -> Option<Self::Buffer> { Some(self.objects.borrow_mut().new_handle()) }
+
+	fn bind_buffer(&self, target: BufferTarget, buffer: Option<&Self::Buffer>) {
+		let mut objects = self.objects.borrow_mut();
+		match buffer {
+			Some(buffer) => objects.bound_buffers.insert(target, *buffer),
+			None => objects.bound_buffers.remove(&target),
+		};
+	}
+
+	fn buffer_data_f32(&self, target: BufferTarget, data: &[f32], _usage: BufferUsage) {
+		self.buffer_data(target, data, |objects| &mut objects.f32_buffers);
+	}
+
+	fn buffer_data_u32(&self, target: BufferTarget, data: &[u32], _usage: BufferUsage) {
+		self.buffer_data(target, data, |objects| &mut objects.u32_buffers);
+	}
+
+	fn create_vertex_array(&self) -> Option<Self::VertexArray> {
+		let mut objects = self.objects.borrow_mut();
+		let vertex_array = objects.new_handle();
+		objects.vertex_arrays.insert(vertex_array, HashMap::new());
+		Some(vertex_array)
+	}
+
+	fn bind_vertex_array(&self, vertex_array: Option<&Self::VertexArray>) {
+		self.objects.borrow_mut().bound_vertex_array = vertex_array.copied();
+	}
+
+	fn vertex_attribute_f32(&self, location: u32, size: i32, stride: i32, offset: i32) {
+		let mut objects = self.objects.borrow_mut();
+		let Some(buffer) = objects.bound_buffers.get(&BufferTarget::Array).copied() else {
+			return;
+		};
+
+		let size = size as usize;
+		let float_size = std::mem::size_of::<f32>();
+		let stride = if stride == 0 { size } else { stride as usize / float_size };
+		let pointer =
+			AttributePointer { buffer, size, stride, offset: offset as usize / float_size };
+		if let Some(vertex_array) = objects.bound_vertex_array {
+			objects.vertex_arrays.entry(vertex_array).or_default().insert(location, pointer);
+		}
+	}
+
+	fn create_texture(&self) -> Option<Self::Texture> {
+		let mut objects = self.objects.borrow_mut();
+		let texture = objects.new_handle();
+		objects.textures.insert(texture, Texture::default());
+		Some(texture)
+	}
+
+	fn active_texture(&self, unit: u32) { self.objects.borrow_mut().active_texture = unit; }
+
+	fn bind_texture(&self, texture: Option<&Self::Texture>) {
+		let mut objects = self.objects.borrow_mut();
+		let unit = objects.active_texture;
+		match texture {
+			Some(texture) => objects.bound_textures.insert(unit, *texture),
+			None => objects.bound_textures.remove(&unit),
+		};
+	}
+
+	fn texture_sampling(&self, _min_filter: TextureFilter, mag_filter: TextureFilter) {
+		if let Some(texture) = self.objects.borrow_mut().bound_texture_mut() {
+			texture.mag_filter = mag_filter;
+		}
+	}
+
+	fn texture_image(
+		&self,
+		format: TextureFormat,
+		width: u32,
+		height: u32,
+		pixels: &[u8],
+	) -> Result<(), String> {
+		let expected = width as usize * height as usize * format.bytes_per_pixel();
+		if pixels.len() != expected {
+			return Err(format!(
+				"{width}x{height} {format:?} image needs {expected} bytes, not {}",
+				pixels.len()
+			));
+		}
+
+		let mut objects = self.objects.borrow_mut();
+		let texture = objects.bound_texture_mut().ok_or("No texture bound")?;
+		*texture = Texture { format, width, height, pixels: pixels.to_vec(), ..texture.clone() };
+		Ok(())
+	}
+
+	fn compile_shader(&self, kind: ShaderKind, source: &str) -> Result<Self::Shader, String> {
+		let mut objects = self.objects.borrow_mut();
+		let shader = objects.new_handle();
+		objects.shaders.insert(shader, (kind, source.to_owned()));
+		Ok(shader)
+	}
+
+	fn link_program(
+		&self,
+		vertex: &Self::Shader,
+		fragment: &Self::Shader,
+	) -> Result<Self::Program, String> {
+		let mut objects = self.objects.borrow_mut();
+		let (vertex, fragment) = match (objects.shaders.get(vertex), objects.shaders.get(fragment))
+		{
+			(Some((ShaderKind::Vertex, vertex)), Some((ShaderKind::Fragment, fragment))) => {
+				(vertex, fragment)
+			}
+			_ => return Err("Programs need a vertex and a fragment shader".to_owned()),
+		};
+
+		let port = objects
+			.ports
+			.iter()
+			.find(|(port_vertex, port_fragment, _)| {
+				port_vertex == vertex && port_fragment == fragment
+			})
+			.map(|(_, _, port)| port.clone());
+
+		let program = objects.new_handle();
+		objects.programs.insert(program, Program { port, uniforms: HashMap::new() });
+		Ok(program)
+	}
+
+	fn use_program(&self, program: Option<&Self::Program>) {
+		self.objects.borrow_mut().current_program = program.copied();
+	}
+
+	fn attribute_location(&self, program: &Self::Program, name: &str) -> Option<u32> {
+		let objects = self.objects.borrow();
+		let port = objects.programs.get(program)?.port.as_ref()?;
+		port.attributes().iter().find(|(attribute, _)| *attribute == name).map(|(_, l)| *l)
+	}
+
+	/// Every name has a location, as ports don't say which uniforms they read
+	fn uniform_location(
+		&self,
+		program: &Self::Program,
+		name: &str,
+	) -> Option<Self::UniformLocation> {
+		let mut objects = self.objects.borrow_mut();
+		if !objects.programs.contains_key(program) {
+			return None;
+		}
+
+		let location = objects.new_handle();
+		objects.uniform_locations.insert(location, (*program, name.to_owned()));
+		Some(location)
+	}
+
+	fn write_uniform(&self, location: Option<&Self::UniformLocation>, value: UniformData) {
+		let mut objects = self.objects.borrow_mut();
+		let Some((program, name)) = location.and_then(|l| objects.uniform_locations.get(l)) else {
+			return;
+		};
+
+		// Like WebGL, which only writes to the program in use
+		if objects.current_program == Some(*program) {
+			let (program, name) = (*program, name.clone());
+			if let Some(program) = objects.programs.get_mut(&program) {
+				program.uniforms.insert(name, value);
+			}
+		}
+	}
+
+	fn clear(&self, color: &nglm::Vec3) {
+		let mut framebuffer = self.framebuffer.borrow_mut();
+		let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+		let pixel = Rgba([channel(color.x), channel(color.y), channel(color.z), 255]);
+		framebuffer.color.pixels_mut().for_each(|p| *p = pixel);
+		framebuffer.depth.fill(1.0);
+	}
+
+	fn set_depth(&self, function: DepthFunction, write: bool) {
+		let mut framebuffer = self.framebuffer.borrow_mut();
+		framebuffer.depth_function = function;
+		framebuffer.depth_write = write;
+	}
+
+	fn draw_elements(&self, primitive: Primitive, count: i32) {
+		if primitive != Primitive::Triangles {
+			return;
+		}
+
+		let indices = {
+			let objects = self.objects.borrow();
+			let buffer = objects.bound_buffers.get(&BufferTarget::ElementArray);
+			match buffer.and_then(|buffer| objects.u32_buffers.get(buffer)) {
+				Some(indices) => indices[..(count as usize).min(indices.len())].to_vec(),
+				None => return,
+			}
+		};
+		self.draw_triangles(indices.into_iter());
+	}
+
+	fn draw_arrays(&self, primitive: Primitive, first: i32, count: i32) {
+		if primitive == Primitive::Triangles {
+			self.draw_triangles(first as u32..(first + count) as u32);
+		}
+	}
+}
+
+/// Comparing snapshots with reference images
+#[cfg(test)]
+pub mod golden {
+	use std::path::Path;
+
+	use image::RgbaImage;
+
+	/// How far a snapshot may stray from its reference image before a test
+	/// fails. Floating point results differ slightly between platforms, so an
+	/// exact match is too much to ask.
+	#[derive(Clone, Copy, Debug)]
+	pub struct GoldenTolerance {
+		/// Largest difference in any one channel for a pixel to still match
+		pub channel_difference: u8,
+		/// Fraction of pixels allowed not to match
+		pub mismatched_fraction: f32,
+	}
+
+	impl Default for GoldenTolerance {
+		fn default() -> Self { Self { channel_difference: 3, mismatched_fraction: 0.005 } }
+	}
+
+	/// Compares `image` with the PNG at `golden`. Running with
+	/// `UPDATE_GOLDENS=1` writes `image` there instead, for when a change to
+	/// the output is expected.
+	pub fn compare_with_golden(
+		image: &RgbaImage,
+		golden: &Path,
+		tolerance: GoldenTolerance,
+	) -> Result<(), String> {
+		if std::env::var_os("UPDATE_GOLDENS").is_some() {
+			return image.save(golden).map_err(|e| format!("Couldn't write {golden:?}: {e}"));
+		}
+
+		let expected = image::open(golden)
+			.map_err(|e| format!("Couldn't read {golden:?}, UPDATE_GOLDENS=1 creates it: {e}"))?
+			.to_rgba8();
+		if expected.dimensions() != image.dimensions() {
+			return Err(format!(
+				"{golden:?} is {:?}, but the snapshot is {:?}",
+				expected.dimensions(),
+				image.dimensions()
+			));
+		}
+
+		let mismatched = expected
+			.pixels()
+			.zip(image.pixels())
+			.filter(|(expected, actual)| {
+				expected
+					.0
+					.iter()
+					.zip(actual.0)
+					.any(|(e, a)| e.abs_diff(a) > tolerance.channel_difference)
+			})
+			.count();
+		let allowed = (tolerance.mismatched_fraction * image.pixels().len() as f32) as usize;
+		if mismatched > allowed {
+			return Err(format!(
+				"{mismatched} pixels differ from {golden:?}, more than the {allowed} allowed"
+			));
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Passes positions straight through, colored by a uniform
+	struct FlatColor;
+
+	impl ShaderPort for FlatColor {
+		fn attributes(&self) -> &[(&'static str, u32)] { &[("position", 0)] }
+
+		fn varyings(&self) -> usize { 0 }
+
+		fn vertex(
+			&self,
+			_: u32,
+			attributes: &[nglm::Vec4],
+			_: &ShaderInputs,
+			_: &mut [f32],
+		) -> nglm::Vec4 {
+			attributes[0]
+		}
+
+		fn fragment(&self, _: &[f32], depth: f32, inputs: &ShaderInputs) -> (nglm::Vec4, f32) {
+			(inputs.vec3("u_color").push(1.0), depth)
+		}
+	}
+
+	const VERTEX: &str = "layout (location = 0) in vec3 position;";
+	const FRAGMENT: &str = "uniform vec3 u_color;";
+
+	fn backend() -> (SoftwareBackend, Handle) {
+		let backend = SoftwareBackend::new(4, 4);
+		backend.add_port(VERTEX, FRAGMENT, Rc::new(FlatColor));
+		let vertex = backend.compile_shader(ShaderKind::Vertex, VERTEX).unwrap();
+		let fragment = backend.compile_shader(ShaderKind::Fragment, FRAGMENT).unwrap();
+		let program = backend.link_program(&vertex, &fragment).unwrap();
+		backend.use_program(Some(&program));
+		(backend, program)
+	}
+
+	/// Draws one triangle at depth `z`
+	fn triangle(backend: &SoftwareBackend, program: Handle, corners: [(f32, f32); 3], z: f32) {
+		let buffer = backend.create_buffer().unwrap();
+		backend.bind_buffer(BufferTarget::Array, Some(&buffer));
+		let vertices: Vec<f32> = corners.iter().flat_map(|(x, y)| [*x, *y, z]).collect();
+		backend.buffer_data_f32(BufferTarget::Array, &vertices, BufferUsage::Static);
+
+		let vertex_array = backend.create_vertex_array().unwrap();
+		backend.bind_vertex_array(Some(&vertex_array));
+		let location = backend.attribute_location(&program, "position").unwrap();
+		backend.vertex_attribute_f32(location, 3, 0, 0);
+		backend.draw_arrays(Primitive::Triangles, 0, 3);
+	}
+
+	fn color(backend: &SoftwareBackend, program: Handle, color: nglm::Vec3) {
+		let location = backend.uniform_location(&program, "u_color");
+		backend.write_uniform(location.as_ref(), UniformData::Vec3(color));
+	}
+
+	#[test]
+	fn fills_pixel_centers_inside_triangles() {
+		let (backend, program) = backend();
+		backend.clear(&nglm::zero());
+		color(&backend, program, nglm::vec3(1.0, 0.0, 0.0));
+
+		// The lower left half of the screen, with the diagonal through pixel centers
+		triangle(&backend, program, [(-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)], 0.0);
+
+		let covered: Vec<Vec<bool>> = (0..4)
+			.map(|y| (0..4).map(|x| backend.snapshot().get_pixel(x, y).0[0] == 255).collect())
+			.collect();
+		assert_eq!(
+			covered,
+			vec![
+				vec![true, false, false, false],
+				vec![true, true, false, false],
+				vec![true, true, true, false],
+				vec![true, true, true, true],
+			]
+		);
+	}
+
+	#[test]
+	fn keeps_the_nearest_fragment() {
+		let (backend, program) = backend();
+		backend.clear(&nglm::zero());
+		let whole_screen = [(-3.0, -1.0), (1.0, -1.0), (1.0, 3.0)];
+
+		color(&backend, program, nglm::vec3(0.0, 1.0, 0.0));
+		triangle(&backend, program, whole_screen, 0.5);
+		color(&backend, program, nglm::vec3(1.0, 0.0, 0.0));
+		triangle(&backend, program, whole_screen, 0.7);
+		assert_eq!(backend.snapshot().get_pixel(1, 1), &Rgba([0, 255, 0, 255]));
+
+		// Equal depths only pass with LessOrEqual, and without writes, the
+		// nearer triangle can still be replaced
+		backend.set_depth(DepthFunction::LessOrEqual, false);
+		color(&backend, program, nglm::vec3(0.0, 0.0, 1.0));
+		triangle(&backend, program, whole_screen, 0.5);
+		color(&backend, program, nglm::vec3(1.0, 1.0, 1.0));
+		triangle(&backend, program, whole_screen, 0.6);
+		assert_eq!(backend.snapshot().get_pixel(1, 1), &Rgba([0, 0, 255, 255]));
+	}
+
+	#[test]
+	fn samples_textures_clamped_at_the_edges() {
+		let texture = Texture {
+			format: TextureFormat::Luminance,
+			width: 2,
+			height: 1,
+			pixels: vec![0, 255],
+			mag_filter: TextureFilter::Linear,
+		};
+		let sampler = Sampler { texture: Some(&texture) };
+
+		assert_eq!(sampler.sample(&nglm::vec2(0.0, 0.5)).x, 0.0);
+		assert_eq!(sampler.sample(&nglm::vec2(0.5, 0.5)).x, 0.5);
+		assert_eq!(sampler.sample(&nglm::vec2(1.0, 0.5)).x, 1.0);
+		assert_eq!(
+			Sampler { texture: None }.sample(&nglm::vec2(0.5, 0.5)),
+			nglm::vec4(0.0, 0.0, 0.0, 1.0)
+		);
+	}
+}
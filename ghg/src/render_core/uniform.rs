@@ -2,9 +2,9 @@ use std::fmt::Debug;
 use std::marker::PhantomData;
 
 use paste::paste;
-use web_sys::{WebGl2RenderingContext, WebGlUniformLocation};
 
 use crate::application::shaders::ShaderContext;
+use crate::render_core::backend::{GraphicsBackend, UniformData};
 #[allow(unused_imports)]
 use crate::utils::prelude::*;
 
@@ -21,16 +21,16 @@ use crate::utils::prelude::*;
 /// problems. So it's not a complete solution yet.
 #[derive(Clone, Debug)]
 #[allow(dead_code)] // name isn't used, but it is useful. TODO: Remove it if not debug
-pub struct Uniform<T: Debug> {
+pub struct Uniform<T: Debug, B: GraphicsBackend> {
 	name: String,
-	context: WebGl2RenderingContext,
-	location: Option<WebGlUniformLocation>,
+	context: B,
+	location: Option<B::UniformLocation>,
 	phantom_value: PhantomData<T>, // Strongly-typed Uniforms are important
 }
 
-impl<T: Clone + Debug + PartialEq + UniformValue> Uniform<T> {
-	pub fn new(name: &str, shader_context: &ShaderContext) -> Self {
-		let location = shader_context.context.get_uniform_location(&shader_context.program, name);
+impl<T: Clone + Debug + PartialEq + UniformValue, B: GraphicsBackend> Uniform<T, B> {
+	pub fn new(name: &str, shader_context: &ShaderContext<B>) -> Self {
+		let location = shader_context.context.uniform_location(&shader_context.program, name);
 		Self {
 			context: shader_context.context.clone(),
 			name: name.to_owned(),
@@ -42,18 +42,18 @@ impl<T: Clone + Debug + PartialEq + UniformValue> Uniform<T> {
 	pub fn write_unchecked(&self, t: T) {
 		// let name = &self.name;
 		// ghg_log!("Writing uniform: {name} -> {t:?}");
-		t.write_to_program(&self.context, &self.location);
+		self.context.write_uniform(self.location.as_ref(), t.to_uniform_data());
 	}
 }
 
 #[derive(Debug)]
-pub struct SmartUniform<T: Debug> {
-	uniform: Uniform<T>,
+pub struct SmartUniform<T: Debug, B: GraphicsBackend> {
+	uniform: Uniform<T, B>,
 	last_value: Option<T>,
 }
 
-impl<T: Clone + Debug + PartialEq + UniformValue> SmartUniform<T> {
-	pub fn new(name: &str, shader_context: &ShaderContext) -> Self {
+impl<T: Clone + Debug + PartialEq + UniformValue, B: GraphicsBackend> SmartUniform<T, B> {
+	pub fn new(name: &str, shader_context: &ShaderContext<B>) -> Self {
 		Self { uniform: Uniform::new(name, shader_context), last_value: None }
 	}
 
@@ -70,12 +70,7 @@ impl<T: Clone + Debug + PartialEq + UniformValue> SmartUniform<T> {
 }
 
 pub trait UniformValue {
-	fn write_to_program(
-		self,
-		context: &WebGl2RenderingContext,
-		location: &Option<WebGlUniformLocation>,
-	) where
-		Self: Sized;
+	fn to_uniform_data(self) -> UniformData;
 }
 
 macro_rules! impl_uniform_creator_fns {
@@ -87,14 +82,14 @@ macro_rules! impl_uniform_creator_fns {
             #[allow(dead_code)]
             #[doc = "Creates a new `Uniform<" [< $short_name:upper _STR >] ">`."]
             /// Creates a new Uniform<> of the given type.
-            pub fn [< new_ $short_name >](name: &str, shader_context: &ShaderContext) -> Uniform<$type_name> {
+            pub fn [< new_ $short_name >]<B: GraphicsBackend>(name: &str, shader_context: &ShaderContext<B>) -> Uniform<$type_name, B> {
                 Uniform::new(name, shader_context)
             }
 
             #[allow(dead_code)]
             #[doc = "Creates and initializes a new `Uniform<" [< $short_name:upper _STR >] ">`."]
             /// Creates and initializes a new Uniform<> of the given type.
-            pub fn [< init_ $short_name >](name: &str, shader_context: &ShaderContext, value: $type_name) -> Uniform<$type_name> {
+            pub fn [< init_ $short_name >]<B: GraphicsBackend>(name: &str, shader_context: &ShaderContext<B>, value: $type_name) -> Uniform<$type_name, B> {
                 let u = Uniform::new(name, shader_context);
                 u.write_unchecked(value);
                 u
@@ -109,14 +104,14 @@ macro_rules! impl_smart_uniform_creator_fns {
             #[allow(dead_code)]
             #[doc = "Creates a new `SmartUniform<" [< $short_name:upper _STR >] ">`."]
             /// Creates a new SmartUniform<> of the given type.
-            pub fn [< new_smart_ $short_name >](name: &str, shader_context: &ShaderContext) -> SmartUniform<$type_name> {
+            pub fn [< new_smart_ $short_name >]<B: GraphicsBackend>(name: &str, shader_context: &ShaderContext<B>) -> SmartUniform<$type_name, B> {
                 SmartUniform::new(name, shader_context)
             }
 
             #[allow(dead_code)]
             #[doc = "Creates and initializes a new `SmartUniform<" [< $short_name:upper _STR >] ">`."]
             /// Creates and initializes a new SmartUniform<> of the given type.
-            pub fn [< init_smart_ $short_name >](name: &str, shader_context: &ShaderContext, value: $type_name) -> SmartUniform<$type_name> {
+            pub fn [< init_smart_ $short_name >]<B: GraphicsBackend>(name: &str, shader_context: &ShaderContext<B>, value: $type_name) -> SmartUniform<$type_name, B> {
                 let mut u = SmartUniform::new(name, shader_context);
                 u.smart_write(value);
                 u
@@ -126,51 +121,83 @@ macro_rules! impl_smart_uniform_creator_fns {
 }
 
 macro_rules! impl_uniform {
-    // Self is a primitive type; pass self directly to the OpenGL function call
-    ($type_name:ty, $short_name:ident, $gl_call:ident) => {
-        impl UniformValue for $type_name {
-            fn write_to_program(self, context: &WebGl2RenderingContext, location: &Option<WebGlUniformLocation>) {
-                context.$gl_call(location.as_ref(), self);
-            }
-        }
+	// Self is handed to the backend as the given kind of `UniformData`
+	($type_name:ty, $short_name:ident, $data:ident) => {
+		impl UniformValue for $type_name {
+			fn to_uniform_data(self) -> UniformData { UniformData::$data(self) }
+		}
 
-        impl_uniform_creator_fns!($type_name, $short_name);
-        impl_smart_uniform_creator_fns!($type_name, $short_name);
-    };
+		impl_uniform_creator_fns!($type_name, $short_name);
+		impl_smart_uniform_creator_fns!($type_name, $short_name);
+	};
 
-    // Self is a primitive type, and its own short name; pass self directly to the OpenGL function call
-    ($type_name:ident, $gl_call:ident) => {
-        impl_uniform!($type_name, $type_name, $gl_call);
-    };
+	// Self is a primitive type, and its own short name
+	($type_name:ident, $data:ident) => {
+		impl_uniform!($type_name, $type_name, $data);
+	};
+}
 
+impl_uniform!(i32, Int);
+impl_uniform!(f32, Float);
+impl_uniform!(nglm::Vec3, vec3, Vec3);
+impl_uniform!(nglm::Vec4, vec4, Vec4);
+impl_uniform!(nglm::Mat4, mat4, Mat4);
+impl_uniform!(nglm::Mat4x3, mat4x3, Mat4x3);
 
-    // Ugly form that takes parameters. You can pass these things:
-    //    - self.some_field
-    //    - call self.some_method()
-    //    - just some_expression
-    // Definitely incomplete and hacky, but I'm learning macros.
-    (
-        $type_name:ty, $short_name:ident, $gl_call:ident,
-        $( $(self.$field:ident)? $(call self.$method:ident())? $(just $param:expr)? ),+
-    ) => {
-        impl UniformValue for $type_name {
-            fn write_to_program(self, context: &WebGl2RenderingContext, location: &Option<WebGlUniformLocation>) {
-                context.$gl_call(location.as_ref(), $( $(self.$field)* $(self.$method())* $($param)* ,)+);
-            }
-        }
+// TODO: Way more implementations
 
-        impl_uniform_creator_fns!($type_name, $short_name);
-        impl_smart_uniform_creator_fns!($type_name, $short_name);
-    };
-}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::application::shaders::get_sky_shaders;
+	use crate::render_core::recording_backend::{Command, RecordingBackend};
+
+	fn uniform_writes(backend: &RecordingBackend) -> Vec<(String, UniformData)> {
+		backend
+			.take_commands()
+			.into_iter()
+			.filter_map(|command| match command {
+				Command::Uniform { name, value } => Some((name, value)),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn smart_uniforms_only_write_changes() {
+		let backend = RecordingBackend::new();
+		let shader = get_sky_shaders(&backend).unwrap();
+		shader.use_shader();
+		backend.take_commands();
+
+		let mut density = init_smart_f32("u_starDensity", &shader, 1.0);
+		density.smart_write(1.0);
+		density.smart_write(2.0);
+		let _stars = init_i32("u_starsEnabled", &shader, 1);
+
+		assert_eq!(
+			uniform_writes(&backend),
+			vec![
+				("u_starDensity".to_owned(), UniformData::Float(1.0)),
+				("u_starDensity".to_owned(), UniformData::Float(2.0)),
+				("u_starsEnabled".to_owned(), UniformData::Int(1)),
+			]
+		);
+		assert!(backend.errors().is_empty());
+	}
 
-impl_uniform!(i32, uniform1i);
-impl_uniform!(f32, uniform1f);
-impl_uniform!(nglm::Vec3, vec3, uniform3f, self.x, self.y, self.z);
-impl_uniform!(nglm::Vec4, vec4, uniform4f, self.x, self.y, self.z, self.w);
-impl_uniform!(nglm::Mat4, mat4, uniform_matrix4fv_with_f32_array, just false, call self.as_slice());
+	#[test]
+	fn mismatched_types_are_caught() {
+		let backend = RecordingBackend::new();
+		let shader = get_sky_shaders(&backend).unwrap();
+		shader.use_shader();
 
-// TODO: Uh... This switches column/row. Is that expected?
-impl_uniform!(nglm::Mat4x3, mat4x3, uniform_matrix3x4fv_with_f32_array, just false, call self.as_slice());
+		init_f32("u_inverseViewProjection", &shader, 1.0);
+		init_f32("u_notInTheShader", &shader, 1.0);
 
-// TODO: Way more implementations
+		assert_eq!(
+			backend.errors(),
+			vec!["Uniform u_inverseViewProjection is a mat4, but was given Float(1.0)"]
+		);
+	}
+}
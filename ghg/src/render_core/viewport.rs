@@ -42,8 +42,6 @@ impl Viewport {
 		self.context.viewport(0, 0, width.round() as i32, height.round() as i32);
 	}
 
-	pub fn context(&self) -> &WebGl2RenderingContext { &self.context }
-
 	pub fn width(&self) -> f32 { self.width.borrow().clone() }
 
 	pub fn height(&self) -> f32 { self.height.borrow().clone() }
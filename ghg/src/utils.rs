@@ -45,17 +45,31 @@ pub fn alloc(len: usize) -> *mut u8 {
 	ptr
 }
 
+#[cfg(target_arch = "wasm32")]
 macro_rules! ghg_log {
-    // Note that this is using the `log` function imported above during
-    // `bare_bones`
-    ($($t:tt)*) => (log(&format_args!($($t)*).to_string()))
+    // Uses the `log` binding in the prelude above, by path so that callers
+    // don't have to import it
+    ($($t:tt)*) => ($crate::utils::prelude::log(&format_args!($($t)*).to_string()))
+}
+
+// There's no console outside the browser, so native builds (tests and tools)
+// print instead
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules! ghg_log {
+    ($($t:tt)*) => (println!($($t)*))
 }
 
 #[allow(unused_imports)]
 pub(crate) use ghg_log;
 
+#[cfg(target_arch = "wasm32")]
+macro_rules! ghg_error {
+    ($($t:tt)*) => ($crate::utils::prelude::error(&format_args!($($t)*).to_string()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 macro_rules! ghg_error {
-    ($($t:tt)*) => (error(&format_args!($($t)*).to_string()))
+    ($($t:tt)*) => (eprintln!($($t)*))
 }
 
 #[allow(unused_imports)]
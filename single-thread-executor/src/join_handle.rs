@@ -0,0 +1,259 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, Waker};
+
+use crate::slab::Key;
+use crate::Shared;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JoinError {
+	/// The task was aborted before it finished
+	Aborted,
+}
+
+impl fmt::Display for JoinError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			JoinError::Aborted => f.write_str("task was aborted"),
+		}
+	}
+}
+
+impl std::error::Error for JoinError {}
+
+/// What a task left behind for its `JoinHandle`
+pub(crate) struct JoinState<T> {
+	output: Option<Result<T, JoinError>>,
+	/// Set once the output has been handed out
+	taken: bool,
+	waiter: Option<Waker>,
+}
+
+impl<T> JoinState<T> {
+	pub fn new() -> Rc<RefCell<Self>> {
+		Rc::new(RefCell::new(Self { output: None, taken: false, waiter: None }))
+	}
+
+	/// Only the first outcome counts, so aborting a finished task keeps its
+	/// output
+	pub fn finish(&mut self, output: Result<T, JoinError>) {
+		if self.output.is_some() || self.taken {
+			return;
+		}
+		self.output = Some(output);
+		if let Some(waiter) = self.waiter.take() {
+			waiter.wake();
+		}
+	}
+
+	fn is_finished(&self) -> bool { self.output.is_some() || self.taken }
+}
+
+/// Awaits the output of a spawned task
+pub struct JoinHandle<T> {
+	executor: Weak<Shared>,
+	key: Key,
+	name: Option<Rc<str>>,
+	state: Rc<RefCell<JoinState<T>>>,
+	abort_on_drop: bool,
+}
+
+impl<T> JoinHandle<T> {
+	pub(crate) fn new(
+		executor: Weak<Shared>,
+		key: Key,
+		name: Option<Rc<str>>,
+		state: Rc<RefCell<JoinState<T>>>,
+	) -> Self {
+		Self { executor, key, name, state, abort_on_drop: false }
+	}
+
+	pub fn name(&self) -> Option<&str> { self.name.as_deref() }
+
+	/// True once the task has finished or been aborted
+	pub fn is_finished(&self) -> bool { self.state.borrow().is_finished() }
+
+	/// Stops the task, dropping its future. Awaiting the handle afterwards
+	/// gives `JoinError::Aborted`, unless the task had already finished.
+	pub fn abort(&self) {
+		self.abort_task();
+		self.state.borrow_mut().finish(Err(JoinError::Aborted));
+	}
+
+	fn abort_task(&self) {
+		if let Some(executor) = self.executor.upgrade() {
+			executor.abort(self.key);
+		}
+	}
+
+	/// Makes dropping the handle abort the task, instead of leaving it to run
+	pub fn abort_on_drop(mut self) -> Self {
+		self.abort_on_drop = true;
+		self
+	}
+}
+
+impl<T> Future for JoinHandle<T> {
+	type Output = Result<T, JoinError>;
+
+	fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+		let mut state = self.state.borrow_mut();
+		assert!(!state.taken, "JoinHandle polled after it finished");
+
+		match state.output.take() {
+			Some(output) => {
+				state.taken = true;
+				Poll::Ready(output)
+			}
+			None => {
+				state.waiter = Some(context.waker().clone());
+				Poll::Pending
+			}
+		}
+	}
+}
+
+impl<T> Drop for JoinHandle<T> {
+	fn drop(&mut self) {
+		if self.abort_on_drop && !self.is_finished() {
+			self.abort_task();
+		}
+	}
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("JoinHandle")
+			.field("name", &self.name())
+			.field("finished", &self.is_finished())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+	use std::time::Duration;
+
+	use super::*;
+	use crate::{new_executor_and_spawner_with_clock, sleep, Executor, ManualClock, Spawner};
+
+	fn executor() -> (Executor, Spawner) { new_executor_and_spawner_with_clock(ManualClock::new()) }
+
+	/// Sets a flag when dropped, to tell whether a task's future is gone
+	struct DropFlag(Rc<Cell<bool>>);
+
+	impl Drop for DropFlag {
+		fn drop(&mut self) { self.0.set(true); }
+	}
+
+	/// A task that never finishes, along with a flag for whether it was dropped
+	fn forever() -> (impl Future<Output = ()>, Rc<Cell<bool>>) {
+		let dropped = Rc::new(Cell::new(false));
+		let flag = DropFlag(dropped.clone());
+		let future = async move {
+			let _flag = flag;
+			std::future::pending::<()>().await
+		};
+		(future, dropped)
+	}
+
+	/// Spawns a task that awaits `handle`, returning where its result ends up
+	fn join<T: 'static>(
+		spawner: &Spawner,
+		handle: JoinHandle<T>,
+	) -> Rc<RefCell<Option<Result<T, JoinError>>>> {
+		let result = Rc::new(RefCell::new(None));
+		let task_result = result.clone();
+		spawner.spawn(async move {
+			let output = handle.await;
+			task_result.replace(Some(output));
+		});
+		result
+	}
+
+	#[test]
+	fn awaits_output() {
+		let (executor, spawner) = executor();
+		let handle = spawner.spawn_named("answer", async { 42 });
+		assert_eq!(handle.name(), Some("answer"));
+
+		let result = join(&spawner, handle);
+		executor.run_until_stalled();
+		assert_eq!(*result.borrow(), Some(Ok(42)));
+	}
+
+	#[test]
+	fn abort_drops_the_future() {
+		let (executor, spawner) = executor();
+		let (future, dropped) = forever();
+		let handle = spawner.spawn(future);
+		executor.run_until_stalled();
+
+		handle.abort();
+		assert!(dropped.get());
+		assert!(handle.is_finished());
+
+		let result = join(&spawner, handle);
+		executor.run_until_stalled();
+		assert_eq!(*result.borrow(), Some(Err(JoinError::Aborted)));
+	}
+
+	#[test]
+	fn aborting_a_finished_task_keeps_its_output() {
+		let (executor, spawner) = executor();
+		let handle = spawner.spawn(async { "done" });
+		executor.run_until_stalled();
+
+		handle.abort();
+		let result = join(&spawner, handle);
+		executor.run_until_stalled();
+		assert_eq!(*result.borrow(), Some(Ok("done")));
+	}
+
+	#[test]
+	fn tasks_can_abort_themselves() {
+		let (executor, spawner) = executor();
+		let own_handle: Rc<RefCell<Option<JoinHandle<()>>>> = Default::default();
+		let dropped = Rc::new(Cell::new(false));
+
+		let (task_handle, flag) = (own_handle.clone(), DropFlag(dropped.clone()));
+		let handle = spawner.spawn(async move {
+			let _flag = flag;
+			if let Some(handle) = task_handle.borrow().as_ref() {
+				handle.abort();
+			}
+			std::future::pending::<()>().await
+		});
+		own_handle.replace(Some(handle));
+
+		executor.run_until_stalled();
+		assert!(dropped.get());
+	}
+
+	#[test]
+	fn dropping_detaches_unless_asked_to_abort() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let finished = Rc::new(RefCell::new(Vec::new()));
+
+		for (name, abort_on_drop) in [("detached", false), ("aborted", true)] {
+			let task_finished = finished.clone();
+			let handle = spawner.spawn(async move {
+				sleep(Duration::from_millis(10)).await;
+				task_finished.borrow_mut().push(name);
+			});
+			if abort_on_drop {
+				drop(handle.abort_on_drop());
+			}
+		}
+
+		executor.run_until_stalled();
+		clock.advance(Duration::from_millis(10));
+		executor.run_until_stalled();
+		assert_eq!(*finished.borrow(), vec!["detached"]);
+	}
+}
@@ -1,78 +1,413 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::future::Future;
 use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::task::Context;
+use std::task::{Context, Poll, Wake, Waker};
 
-use async_channel::{Receiver, Sender};
-use futures::task::{waker_ref, ArcWake};
+use futures::task::AtomicWaker;
+use join_handle::JoinState;
+pub use join_handle::{JoinError, JoinHandle};
+use monitor::PollStatistics;
+pub use monitor::{Monitor, TaskSnapshot};
+use slab::{Key, Slab};
+pub use timer::{
+	interval, sleep, timeout, Clock, Elapsed, Interval, ManualClock, PerformanceClock, Sleep,
+	Timeout,
+};
+use timer::{with_timers, TimerQueue};
 
-/// Because this is a single-thread executor, we don't have to be as worried
-/// about Send requirements for the Futures we're executing. Here, we wrap our
-/// non-Send Futures in a type which we assert is Send, which lets us use
-/// Sender/Receiver for convenience.
-///
-/// I may be misunderstanding the actual problem with this solution, but it
-/// works for now, so I'm going to stick with it until I understand Rust async
-/// internals better.
-struct ISwearItsFine(Pin<Box<dyn Future<Output = ()> + 'static>>);
+mod join_handle;
+mod monitor;
+mod slab;
+mod timer;
 
-unsafe impl Send for ISwearItsFine {}
+/// Tasks are only ever touched from the thread that runs the executor, so they
+/// live in plain `Rc`/`RefCell` state. Wakers have to be `Send` though, so all
+/// a waker holds is the task's key, which it pushes onto this queue for the
+/// executor to pick up.
+struct WakeQueue {
+	woken: Mutex<VecDeque<Key>>,
+	/// Wakes `Executor::run` when there's something to poll
+	executor: AtomicWaker,
+}
+
+impl WakeQueue {
+	fn pop(&self) -> Option<Key> { self.woken.lock().unwrap().pop_front() }
+
+	fn is_empty(&self) -> bool { self.woken.lock().unwrap().is_empty() }
+}
+
+/// Wakes one task. A task is queued at most once until it's polled, so the
+/// queue never holds more entries than there are tasks.
+struct TaskWaker {
+	key: Key,
+	queued: AtomicBool,
+	queue: Arc<WakeQueue>,
+}
+
+impl Wake for TaskWaker {
+	fn wake(self: Arc<Self>) { self.wake_by_ref() }
+
+	fn wake_by_ref(self: &Arc<Self>) {
+		if !self.queued.swap(true, Ordering::AcqRel) {
+			self.queue.woken.lock().unwrap().push_back(self.key);
+			self.queue.executor.wake();
+		}
+	}
+}
 
-impl ISwearItsFine {
-	fn from(future: impl Future<Output = ()> + 'static) -> Self { Self(Box::pin(future)) }
+struct Task {
+	/// Taken out while the task is being polled, so that the task list isn't
+	/// borrowed while it runs
+	future: Option<Pin<Box<dyn Future<Output = ()>>>>,
+	waker: Arc<TaskWaker>,
+	name: Option<Rc<str>>,
+	statistics: PollStatistics,
+	/// Set when a task is aborted while being polled, so the executor drops it
+	/// afterwards instead of putting it back
+	aborted: bool,
+}
+
+struct Shared {
+	tasks: RefCell<Slab<Task>>,
+	wake_queue: Arc<WakeQueue>,
+	timers: Rc<TimerQueue>,
+	/// Whether polls are counted and timed
+	instrumented: Cell<bool>,
+}
+
+impl Shared {
+	/// Drops the task's future, straight away unless it's being polled
+	fn abort(&self, key: Key) {
+		let removed = {
+			let mut tasks = self.tasks.borrow_mut();
+			match tasks.get_mut(key) {
+				Some(task) if task.future.is_none() => {
+					task.aborted = true;
+					None
+				}
+				Some(_) => tasks.remove(key),
+				None => None,
+			}
+		};
+		// Dropping a future can run arbitrary code, including spawning or aborting
+		// other tasks, so the task list has to be released first
+		drop(removed);
+	}
 }
 
 pub struct Executor {
-	ready_queue: Receiver<Arc<Task>>,
+	shared: Rc<Shared>,
 }
 
-/// `Spawner` spawns new futures onto the task channel.
+/// `Spawner` spawns new futures onto the executor.
 #[derive(Clone)]
 pub struct Spawner {
-	task_sender: Sender<Arc<Task>>,
+	shared: Rc<Shared>,
 }
 
-/// A future that can reschedule itself to be polled by an `Executor`.
-struct Task {
-	future: Mutex<Option<ISwearItsFine>>,
-	task_sender: Sender<Arc<Task>>,
+/// Timers run on the browser's clock
+pub fn new_executor_and_spawner() -> (Executor, Spawner) {
+	new_executor_and_spawner_with_clock(PerformanceClock)
 }
 
-pub fn new_executor_and_spawner() -> (Executor, Spawner) {
-	const MAX_QUEUED_TASKS: usize = 10_000;
-	let (task_sender, ready_queue) = async_channel::bounded(MAX_QUEUED_TASKS);
-	(Executor { ready_queue }, Spawner { task_sender })
+pub fn new_executor_and_spawner_with_clock(clock: impl Clock + 'static) -> (Executor, Spawner) {
+	let shared = Rc::new(Shared {
+		tasks: RefCell::new(Slab::new()),
+		wake_queue: Arc::new(WakeQueue {
+			woken: Mutex::new(VecDeque::new()),
+			executor: AtomicWaker::new(),
+		}),
+		timers: TimerQueue::new(Rc::new(clock)),
+		instrumented: Cell::new(false),
+	});
+	(Executor { shared: shared.clone() }, Spawner { shared })
 }
 
 impl Spawner {
-	pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
-		let future = ISwearItsFine::from(future);
-		let task = Arc::new(Task {
-			future: Mutex::new(Some(future)),
-			task_sender: self.task_sender.clone(),
-		});
-		self.task_sender.try_send(task).expect("too many tasks queued");
+	/// Runs `future` to completion in the background. The handle can be awaited
+	/// for its output, or used to abort it. Dropping the handle leaves the task
+	/// running, unless it was made with `JoinHandle::abort_on_drop`.
+	pub fn spawn<T: 'static>(&self, future: impl Future<Output = T> + 'static) -> JoinHandle<T> {
+		self.spawn_task(None, future)
 	}
-}
 
-impl ArcWake for Task {
-	fn wake_by_ref(arc_self: &Arc<Self>) {
-		let cloned = arc_self.clone();
-		arc_self.task_sender.try_send(cloned).expect("too many tasks queued");
+	/// Like `spawn`, with a name to tell the task apart in diagnostics
+	pub fn spawn_named<T: 'static>(
+		&self,
+		name: impl Into<String>,
+		future: impl Future<Output = T> + 'static,
+	) -> JoinHandle<T> {
+		self.spawn_task(Some(name.into().into()), future)
+	}
+
+	fn spawn_task<T: 'static>(
+		&self,
+		name: Option<Rc<str>>,
+		future: impl Future<Output = T> + 'static,
+	) -> JoinHandle<T> {
+		let state = JoinState::new();
+		let task_state = state.clone();
+		let future = async move {
+			let output = future.await;
+			task_state.borrow_mut().finish(Ok(output));
+		};
+
+		let mut tasks = self.shared.tasks.borrow_mut();
+		let key = tasks.insert_with(|key| Task {
+			future: Some(Box::pin(future)),
+			waker: Arc::new(TaskWaker {
+				key,
+				queued: AtomicBool::new(false),
+				queue: self.shared.wake_queue.clone(),
+			}),
+			name: name.clone(),
+			statistics: PollStatistics::default(),
+			aborted: false,
+		});
+		let waker = tasks.get(key).expect("Task was just inserted").waker.clone();
+		drop(tasks);
+		waker.wake();
+
+		JoinHandle::new(Rc::downgrade(&self.shared), key, name, state)
 	}
 }
 
 impl Executor {
+	/// Starts counting and timing every poll, using the executor's clock.
+	/// The returned `Monitor` reads the results.
+	pub fn instrument(&self) -> Monitor {
+		self.shared.instrumented.set(true);
+		Monitor { executor: Rc::downgrade(&self.shared) }
+	}
+
+	/// Runs tasks as they're woken. Never finishes, so it's meant to be spawned
+	/// onto the browser's own executor.
 	pub async fn run(&self) {
-		while let Ok(task) = self.ready_queue.recv().await {
-			let mut future_slot = task.future.lock().unwrap();
-			if let Some(mut future) = future_slot.take() {
-				let waker = waker_ref(&task);
-				let context = &mut Context::from_waker(&waker);
-				if future.0.as_mut().poll(context).is_pending() {
-					*future_slot = Some(future);
+		loop {
+			self.run_until_stalled();
+			std::future::poll_fn(|context| {
+				let wake_queue = &self.shared.wake_queue;
+				wake_queue.executor.register(context.waker());
+				if wake_queue.is_empty() {
+					Poll::Pending
+				} else {
+					Poll::Ready(())
 				}
+			})
+			.await;
+		}
+	}
+
+	/// Polls tasks until none are ready, without waiting for any to be woken.
+	/// Along with `ManualClock`, this steps the executor deterministically.
+	pub fn run_until_stalled(&self) {
+		while let Some(key) = self.shared.wake_queue.pop() {
+			self.poll(key);
+		}
+	}
+
+	fn poll(&self, key: Key) {
+		let (mut future, waker) = {
+			let mut tasks = self.shared.tasks.borrow_mut();
+			// Tasks that finished or were aborted after being woken are gone
+			let Some(task) = tasks.get_mut(key) else {
+				return;
+			};
+			// Cleared before polling, so that a task waking itself is queued again
+			task.waker.queued.store(false, Ordering::Release);
+			let Some(future) = task.future.take() else {
+				return;
+			};
+			(future, Waker::from(task.waker.clone()))
+		};
+
+		let timers = &self.shared.timers;
+		let started = self.shared.instrumented.get().then(|| timers.now());
+		let context = &mut Context::from_waker(&waker);
+		let poll = with_timers(timers, || future.as_mut().poll(context));
+
+		let mut tasks = self.shared.tasks.borrow_mut();
+		let task = tasks.get_mut(key).expect("Tasks aren't removed while being polled");
+		if let Some(started) = started {
+			let statistics = &mut task.statistics;
+			statistics.polls += 1;
+			statistics.time_in_poll += timers.now().saturating_sub(started);
+			statistics.last_polled = Some(started);
+		}
+		if poll.is_pending() && !task.aborted {
+			task.future = Some(future);
+			return;
+		}
+
+		let removed = tasks.remove(key);
+		drop(tasks);
+		drop((removed, future));
+	}
+}
+
+impl Drop for Executor {
+	/// Drops every unfinished task. Tasks often hold a `Spawner`, which would
+	/// otherwise keep them all alive forever.
+	fn drop(&mut self) {
+		let tasks = self.shared.tasks.borrow_mut().drain();
+		drop(tasks);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+
+	use super::*;
+
+	fn executor() -> (Executor, Spawner) { new_executor_and_spawner_with_clock(ManualClock::new()) }
+
+	type SharedWaker = Rc<RefCell<Option<Waker>>>;
+
+	/// Pending until woken from outside, counting its polls and keeping its
+	/// latest waker where the test can reach it
+	struct Parked {
+		polls: Rc<Cell<usize>>,
+		waker: SharedWaker,
+		/// Finishes on this poll
+		finish_on: usize,
+	}
+
+	impl Future for Parked {
+		type Output = ();
+
+		fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+			self.polls.set(self.polls.get() + 1);
+			if self.polls.get() == self.finish_on {
+				return Poll::Ready(());
 			}
+			self.waker.replace(Some(context.waker().clone()));
+			Poll::Pending
 		}
 	}
+
+	fn parked(finish_on: usize) -> (Parked, Rc<Cell<usize>>, SharedWaker) {
+		let (polls, waker) = (Rc::new(Cell::new(0)), Rc::new(RefCell::new(None)));
+		(Parked { polls: polls.clone(), waker: waker.clone(), finish_on }, polls, waker)
+	}
+
+	/// Pending `times` times, waking itself each time
+	async fn yield_times(times: usize) {
+		let mut remaining = times;
+		std::future::poll_fn(|context| {
+			if remaining == 0 {
+				return Poll::Ready(());
+			}
+			remaining -= 1;
+			context.waker().wake_by_ref();
+			Poll::Pending
+		})
+		.await
+	}
+
+	#[test]
+	fn wakes_before_poll_are_merged() {
+		let (executor, spawner) = executor();
+		let (future, polls, waker) = parked(3);
+		spawner.spawn(future);
+
+		executor.run_until_stalled();
+		assert_eq!(polls.get(), 1);
+
+		let waker = waker.take().unwrap();
+		waker.wake_by_ref();
+		waker.wake_by_ref();
+		executor.run_until_stalled();
+		assert_eq!(polls.get(), 2);
+
+		waker.wake_by_ref();
+		executor.run_until_stalled();
+		assert_eq!(polls.get(), 3);
+		assert_eq!(executor.shared.tasks.borrow().len(), 0);
+
+		// The task is gone, so this is ignored
+		waker.wake();
+		executor.run_until_stalled();
+		assert_eq!(polls.get(), 3);
+	}
+
+	#[test]
+	fn tasks_can_wake_themselves() {
+		let (executor, spawner) = executor();
+		let order = Rc::new(RefCell::new(Vec::new()));
+
+		for name in ["a", "b"] {
+			let order = order.clone();
+			spawner.spawn(async move {
+				for _ in 0..2 {
+					order.borrow_mut().push(name);
+					yield_times(1).await;
+				}
+			});
+		}
+
+		executor.run_until_stalled();
+		assert_eq!(*order.borrow(), vec!["a", "b", "a", "b"]);
+		assert_eq!(executor.shared.tasks.borrow().len(), 0);
+	}
+
+	#[test]
+	fn tasks_can_spawn_tasks() {
+		let (executor, spawner) = executor();
+		let total = Rc::new(Cell::new(0));
+
+		let (task_spawner, task_total) = (spawner.clone(), total.clone());
+		spawner.spawn(async move {
+			let handles: Vec<_> = (1..=3)
+				.map(|i| {
+					task_spawner.spawn(async move {
+						yield_times(i).await;
+						i
+					})
+				})
+				.collect();
+			for handle in handles {
+				task_total.set(task_total.get() + handle.await.unwrap());
+			}
+		});
+
+		executor.run_until_stalled();
+		assert_eq!(total.get(), 6);
+		assert_eq!(executor.shared.tasks.borrow().len(), 0);
+	}
+
+	#[test]
+	fn wakers_can_be_sent_to_other_threads() {
+		let (executor, spawner) = executor();
+		let (future, polls, waker) = parked(2);
+		spawner.spawn(future);
+		executor.run_until_stalled();
+
+		let waker = waker.take().unwrap();
+		std::thread::spawn(move || waker.wake()).join().unwrap();
+
+		executor.run_until_stalled();
+		assert_eq!(polls.get(), 2);
+	}
+
+	#[test]
+	fn dropping_the_executor_drops_its_tasks() {
+		let (executor, spawner) = executor();
+		let spawner_users = Rc::new(());
+
+		let (task_spawner, user) = (spawner.clone(), spawner_users.clone());
+		spawner.spawn(async move {
+			let _keep = (task_spawner, user);
+			std::future::pending::<()>().await
+		});
+		executor.run_until_stalled();
+		assert_eq!(Rc::strong_count(&spawner_users), 2);
+
+		drop(executor);
+		assert_eq!(Rc::strong_count(&spawner_users), 1);
+	}
 }
@@ -0,0 +1,116 @@
+use std::rc::Weak;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::Shared;
+
+/// Counted for each task while the executor is instrumented
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct PollStatistics {
+	pub polls: u64,
+	pub time_in_poll: Duration,
+	pub last_polled: Option<Duration>,
+}
+
+/// One task, as of when the snapshot was taken
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaskSnapshot {
+	pub name: Option<String>,
+	pub polls: u64,
+	/// Total time spent inside the task's `poll`, by the executor's clock
+	pub time_in_poll: Duration,
+	/// Clock time when the task was last polled
+	pub last_polled: Option<Duration>,
+	/// Woken, and waiting for the executor to get to it
+	pub queued: bool,
+}
+
+/// Reads the statistics of an instrumented executor. Finished tasks drop out
+/// of the snapshots, and once the executor is gone they're empty.
+#[derive(Clone)]
+pub struct Monitor {
+	pub(crate) executor: Weak<Shared>,
+}
+
+impl Monitor {
+	/// Every unfinished task, in the order they were spawned, apart from slots
+	/// being reused
+	pub fn snapshot(&self) -> Vec<TaskSnapshot> {
+		let Some(executor) = self.executor.upgrade() else {
+			return Vec::new();
+		};
+		let tasks = executor.tasks.borrow();
+		tasks
+			.iter()
+			.map(|task| TaskSnapshot {
+				name: task.name.as_deref().map(str::to_owned),
+				polls: task.statistics.polls,
+				time_in_poll: task.statistics.time_in_poll,
+				last_polled: task.statistics.last_polled,
+				queued: task.waker.queued.load(Ordering::Acquire),
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{new_executor_and_spawner_with_clock, sleep, ManualClock};
+
+	fn ms(milliseconds: u64) -> Duration { Duration::from_millis(milliseconds) }
+
+	#[test]
+	fn counts_and_times_polls() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let monitor = executor.instrument();
+
+		let task_clock = clock.clone();
+		spawner.spawn_named("busy", async move {
+			loop {
+				// Pretend each poll takes 3ms of work
+				task_clock.advance(ms(3));
+				sleep(ms(10)).await;
+			}
+		});
+		spawner.spawn(async {});
+
+		executor.run_until_stalled();
+		clock.advance(ms(10));
+		executor.run_until_stalled();
+
+		assert_eq!(
+			monitor.snapshot(),
+			vec![TaskSnapshot {
+				name: Some("busy".to_owned()),
+				polls: 2,
+				time_in_poll: ms(6),
+				last_polled: Some(ms(13)),
+				queued: false,
+			}]
+		);
+	}
+
+	#[test]
+	fn polls_before_instrumenting_are_not_counted() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock);
+		spawner.spawn(std::future::pending::<()>());
+		executor.run_until_stalled();
+
+		let snapshot = executor.instrument().snapshot();
+		assert_eq!(snapshot.len(), 1);
+		assert_eq!(snapshot[0].polls, 0);
+	}
+
+	#[test]
+	fn snapshots_outlive_the_executor() {
+		let (executor, spawner) = new_executor_and_spawner_with_clock(ManualClock::new());
+		let monitor = executor.instrument();
+		spawner.spawn(std::future::pending::<()>());
+
+		drop(executor);
+		assert!(monitor.snapshot().is_empty());
+	}
+}
@@ -0,0 +1,107 @@
+/// Where a value lives in a `Slab`. Slots are reused, but each reuse gets a
+/// new generation, so a key for a removed value never finds its replacement.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct Key {
+	index: usize,
+	generation: u64,
+}
+
+struct Entry<T> {
+	generation: u64,
+	value: Option<T>,
+}
+
+/// Values stored by key, reusing the slots of removed ones
+pub(crate) struct Slab<T> {
+	entries: Vec<Entry<T>>,
+	free: Vec<usize>,
+	len: usize,
+}
+
+impl<T> Slab<T> {
+	pub fn new() -> Self { Self { entries: Vec::new(), free: Vec::new(), len: 0 } }
+
+	/// Stores the value `make` returns, which gets to know its own key first
+	pub fn insert_with(&mut self, make: impl FnOnce(Key) -> T) -> Key {
+		let index = self.free.pop().unwrap_or_else(|| {
+			self.entries.push(Entry { generation: 0, value: None });
+			self.entries.len() - 1
+		});
+
+		let entry = &mut self.entries[index];
+		let key = Key { index, generation: entry.generation };
+		entry.value = Some(make(key));
+		self.len += 1;
+		key
+	}
+
+	pub fn get(&self, key: Key) -> Option<&T> {
+		self.entries
+			.get(key.index)
+			.filter(|entry| entry.generation == key.generation)
+			.and_then(|entry| entry.value.as_ref())
+	}
+
+	pub fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+		self.entries
+			.get_mut(key.index)
+			.filter(|entry| entry.generation == key.generation)
+			.and_then(|entry| entry.value.as_mut())
+	}
+
+	pub fn remove(&mut self, key: Key) -> Option<T> {
+		let entry = self.entries.get_mut(key.index)?;
+		if entry.generation != key.generation {
+			return None;
+		}
+
+		let value = entry.value.take()?;
+		entry.generation += 1;
+		self.free.push(key.index);
+		self.len -= 1;
+		Some(value)
+	}
+
+	/// Empties the slab, handing back everything in it
+	pub fn drain(&mut self) -> Vec<T> {
+		let keys: Vec<Key> = (0..self.entries.len())
+			.map(|index| Key { index, generation: self.entries[index].generation })
+			.collect();
+		keys.into_iter().filter_map(|key| self.remove(key)).collect()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &T> {
+		self.entries.iter().filter_map(|entry| entry.value.as_ref())
+	}
+
+	#[cfg(test)]
+	pub fn len(&self) -> usize { self.len }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reused_slots_get_new_keys() {
+		let mut slab = Slab::new();
+		let first = slab.insert_with(|_| "first");
+		assert_eq!(slab.remove(first), Some("first"));
+
+		let second = slab.insert_with(|_| "second");
+		assert_ne!(first, second);
+		assert_eq!(slab.get(first), None);
+		assert_eq!(slab.remove(first), None);
+		assert_eq!(slab.get(second), Some(&"second"));
+		assert_eq!(slab.len(), 1);
+	}
+
+	#[test]
+	fn values_know_their_key() {
+		let mut slab = Slab::new();
+		let keys: Vec<Key> = (0..3).map(|_| slab.insert_with(|key| key)).collect();
+		for key in keys {
+			assert_eq!(slab.get(key), Some(&key));
+		}
+	}
+}
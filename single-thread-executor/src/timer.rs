@@ -0,0 +1,482 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+
+/// Where the executor gets its time from. Times are measured from whatever
+/// point the clock likes, but must never go backwards.
+pub trait Clock {
+	fn now(&self) -> Duration;
+
+	/// Calls `wake` once `now()` has reached `deadline`. Waking early is
+	/// allowed, as anything not yet due just asks again.
+	fn wake_at(&self, deadline: Duration, wake: Box<dyn FnOnce()>);
+}
+
+#[wasm_bindgen]
+extern "C" {
+	#[wasm_bindgen(js_namespace = performance, js_name = now)]
+	fn performance_now() -> f64;
+
+	#[wasm_bindgen(js_name = setTimeout)]
+	fn set_timeout(callback: &JsValue, milliseconds: i32) -> i32;
+}
+
+/// The browser's clock, using `performance.now` and `setTimeout`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PerformanceClock;
+
+impl Clock for PerformanceClock {
+	fn now(&self) -> Duration { Duration::from_secs_f64(performance_now() / 1000.0) }
+
+	fn wake_at(&self, deadline: Duration, wake: Box<dyn FnOnce()>) {
+		let delay = deadline.saturating_sub(self.now());
+		let milliseconds = (delay.as_secs_f64() * 1000.0).ceil() as i32;
+		set_timeout(&Closure::once_into_js(wake), milliseconds);
+	}
+}
+
+type PendingWake = (Duration, Box<dyn FnOnce()>);
+
+/// A clock that only moves when told to, so tests decide exactly when timers
+/// fire. Clones share the same time.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+	now: Rc<Cell<Duration>>,
+	pending: Rc<RefCell<Vec<PendingWake>>>,
+}
+
+impl ManualClock {
+	pub fn new() -> Self { Self::default() }
+
+	/// Moves time forward, calling everything that became due, earliest first
+	pub fn advance(&self, by: Duration) {
+		self.now.set(self.now.get() + by);
+
+		let now = self.now.get();
+		let mut due: Vec<_> = {
+			let mut pending = self.pending.borrow_mut();
+			let (due, later) = pending.drain(..).partition(|(deadline, _)| *deadline <= now);
+			*pending = later;
+			due
+		};
+		due.sort_by_key(|(deadline, _)| *deadline);
+		due.into_iter().for_each(|(_, wake)| wake());
+	}
+}
+
+impl Clock for ManualClock {
+	fn now(&self) -> Duration { self.now.get() }
+
+	fn wake_at(&self, deadline: Duration, wake: Box<dyn FnOnce()>) {
+		self.pending.borrow_mut().push((deadline, wake));
+	}
+}
+
+/// Distinguishes timers with the same deadline, and orders them by creation
+type TimerKey = (Duration, u64);
+
+/// Every timer waiting on the clock. Only the earliest deadline is ever passed
+/// to the clock, and it's asked again for the next one when that fires.
+pub(crate) struct TimerQueue {
+	clock: Rc<dyn Clock>,
+	timers: RefCell<BTreeMap<TimerKey, Waker>>,
+	next_id: Cell<u64>,
+	/// The earliest deadline the clock has been asked to wake us for
+	requested: Cell<Option<Duration>>,
+	this: Weak<TimerQueue>,
+}
+
+impl TimerQueue {
+	pub fn new(clock: Rc<dyn Clock>) -> Rc<Self> {
+		Rc::new_cyclic(|this| Self {
+			clock,
+			timers: Default::default(),
+			next_id: Cell::new(0),
+			requested: Cell::new(None),
+			this: this.clone(),
+		})
+	}
+
+	pub fn now(&self) -> Duration { self.clock.now() }
+
+	fn register(&self, deadline: Duration, waker: Waker) -> TimerKey {
+		let key = (deadline, self.next_id.get());
+		self.next_id.set(key.1 + 1);
+		self.timers.borrow_mut().insert(key, waker);
+		self.request_wake();
+		key
+	}
+
+	fn update(&self, key: TimerKey, waker: &Waker) {
+		if let Some(registered) = self.timers.borrow_mut().get_mut(&key) {
+			if !registered.will_wake(waker) {
+				*registered = waker.clone();
+			}
+		}
+	}
+
+	fn cancel(&self, key: TimerKey) { self.timers.borrow_mut().remove(&key); }
+
+	fn request_wake(&self) {
+		let Some(&(earliest, _)) = self.timers.borrow().keys().next() else {
+			return;
+		};
+		if self.requested.get().is_some_and(|requested| requested <= earliest) {
+			return;
+		}
+
+		self.requested.set(Some(earliest));
+		let this = self.this.clone();
+		self.clock.wake_at(
+			earliest,
+			Box::new(move || {
+				if let Some(queue) = this.upgrade() {
+					queue.fire();
+				}
+			}),
+		);
+	}
+
+	/// Wakes every timer that's due, then asks the clock about the rest. The
+	/// clock may have woken us early, so the request is always renewed.
+	fn fire(&self) {
+		let now = self.now();
+		self.requested.set(None);
+
+		let due = {
+			let mut timers = self.timers.borrow_mut();
+			let later = timers.split_off(&(now, u64::MAX));
+			std::mem::replace(&mut *timers, later)
+		};
+		due.into_values().for_each(Waker::wake);
+
+		self.request_wake();
+	}
+}
+
+thread_local! {
+	/// Timers of the executor currently polling a task
+	static CURRENT_TIMERS: RefCell<Option<Rc<TimerQueue>>> = const { RefCell::new(None) };
+}
+
+/// Makes `timers` available to `sleep` and friends while `poll` runs
+pub(crate) fn with_timers<T>(timers: &Rc<TimerQueue>, poll: impl FnOnce() -> T) -> T {
+	let previous = CURRENT_TIMERS.with(|current| current.replace(Some(timers.clone())));
+	let result = poll();
+	CURRENT_TIMERS.with(|current| *current.borrow_mut() = previous);
+	result
+}
+
+fn current_timers() -> Rc<TimerQueue> {
+	CURRENT_TIMERS.with(|current| {
+		current.borrow().clone().expect("Timers can only be used by tasks on an Executor")
+	})
+}
+
+/// Finishes once `duration` has passed. Time starts counting from the first
+/// poll, which must happen inside a task on an `Executor`.
+pub fn sleep(duration: Duration) -> Sleep {
+	Sleep { deadline: Deadline::After(duration), timer: None }
+}
+
+enum Deadline {
+	/// Relative to the first poll
+	After(Duration),
+	At(Duration),
+}
+
+pub struct Sleep {
+	deadline: Deadline,
+	timer: Option<(Rc<TimerQueue>, TimerKey)>,
+}
+
+impl Sleep {
+	fn at(deadline: Duration) -> Self { Self { deadline: Deadline::At(deadline), timer: None } }
+}
+
+impl Future for Sleep {
+	type Output = ();
+
+	fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+		if let Some((timers, key)) = &self.timer {
+			if timers.now() >= key.0 {
+				self.timer = None;
+				return Poll::Ready(());
+			}
+			timers.update(*key, context.waker());
+			return Poll::Pending;
+		}
+
+		let timers = current_timers();
+		let deadline = match self.deadline {
+			Deadline::After(duration) => timers.now() + duration,
+			Deadline::At(deadline) => deadline,
+		};
+		self.deadline = Deadline::At(deadline);
+		if timers.now() >= deadline {
+			return Poll::Ready(());
+		}
+
+		let key = timers.register(deadline, context.waker().clone());
+		self.timer = Some((timers, key));
+		Poll::Pending
+	}
+}
+
+impl Drop for Sleep {
+	fn drop(&mut self) {
+		if let Some((timers, key)) = self.timer.take() {
+			timers.cancel(key);
+		}
+	}
+}
+
+/// Returned by `timeout` when the future took too long
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str("deadline has elapsed") }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// Waits at most `duration` for `future`, giving up on it if it isn't done by
+/// then
+pub fn timeout<F: Future>(future: F, duration: Duration) -> Timeout<F> {
+	Timeout { future: Box::pin(future), sleep: sleep(duration) }
+}
+
+pub struct Timeout<F: Future> {
+	future: Pin<Box<F>>,
+	sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+	type Output = Result<F::Output, Elapsed>;
+
+	fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+		if let Poll::Ready(output) = self.future.as_mut().poll(context) {
+			return Poll::Ready(Ok(output));
+		}
+		Pin::new(&mut self.sleep).poll(context).map(|()| Err(Elapsed))
+	}
+}
+
+/// Ticks every `period`, starting one period after the first tick is awaited.
+/// Ticks missed while the task was busy are skipped rather than bunched up.
+pub fn interval(period: Duration) -> Interval {
+	assert!(!period.is_zero(), "Interval period must be non-zero");
+	Interval { period, next: None }
+}
+
+pub struct Interval {
+	period: Duration,
+	next: Option<Duration>,
+}
+
+impl Interval {
+	/// Waits for the next tick, returning the time it was due
+	pub async fn tick(&mut self) -> Duration {
+		let timers = current_timers();
+		let due = self.next.unwrap_or_else(|| timers.now() + self.period);
+		Sleep::at(due).await;
+
+		let now = timers.now();
+		let mut next = due + self.period;
+		if next <= now {
+			let missed = (now - due).as_nanos() / self.period.as_nanos();
+			next = due + self.period * (missed as u32 + 1);
+		}
+		self.next = Some(next);
+		due
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::new_executor_and_spawner_with_clock;
+
+	fn ms(milliseconds: u64) -> Duration { Duration::from_millis(milliseconds) }
+
+	/// Records `(name, time)` pairs as tasks reach them
+	#[derive(Clone, Default)]
+	struct Log(Rc<RefCell<Vec<(&'static str, Duration)>>>);
+
+	impl Log {
+		fn push(&self, name: &'static str, clock: &ManualClock) {
+			self.0.borrow_mut().push((name, clock.now()));
+		}
+
+		fn names(&self) -> Vec<&'static str> { self.0.borrow().iter().map(|e| e.0).collect() }
+	}
+
+	#[test]
+	fn sleeps_finish_in_deadline_order() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let log = Log::default();
+
+		for (name, delay) in [("slow", 30), ("fast", 10), ("medium", 20)] {
+			let (log, clock) = (log.clone(), clock.clone());
+			spawner.spawn(async move {
+				sleep(ms(delay)).await;
+				log.push(name, &clock);
+			});
+		}
+
+		executor.run_until_stalled();
+		assert!(log.names().is_empty());
+
+		clock.advance(ms(15));
+		executor.run_until_stalled();
+		assert_eq!(log.names(), vec!["fast"]);
+
+		clock.advance(ms(100));
+		executor.run_until_stalled();
+		assert_eq!(log.names(), vec!["fast", "medium", "slow"]);
+	}
+
+	/// Wakes `early_by` before every deadline, as clocks are allowed to
+	#[derive(Clone)]
+	struct EarlyClock {
+		clock: ManualClock,
+		early_by: Duration,
+	}
+
+	impl Clock for EarlyClock {
+		fn now(&self) -> Duration { self.clock.now() }
+
+		fn wake_at(&self, deadline: Duration, wake: Box<dyn FnOnce()>) {
+			self.clock.wake_at(deadline.saturating_sub(self.early_by), wake);
+		}
+	}
+
+	#[test]
+	fn sleeps_finish_when_the_clock_wakes_early() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(EarlyClock {
+			clock: clock.clone(),
+			early_by: ms(5),
+		});
+		let log = Log::default();
+
+		let (task_log, task_clock) = (log.clone(), clock.clone());
+		spawner.spawn(async move {
+			sleep(ms(10)).await;
+			task_log.push("done", &task_clock);
+		});
+
+		executor.run_until_stalled();
+		clock.advance(ms(5));
+		executor.run_until_stalled();
+		assert!(log.names().is_empty(), "Woken early");
+
+		clock.advance(ms(5));
+		executor.run_until_stalled();
+		assert_eq!(log.names(), vec!["done"]);
+	}
+
+	#[test]
+	fn zero_sleep_is_immediate() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let log = Log::default();
+
+		let (task_log, task_clock) = (log.clone(), clock.clone());
+		spawner.spawn(async move {
+			sleep(Duration::ZERO).await;
+			task_log.push("done", &task_clock);
+		});
+
+		executor.run_until_stalled();
+		assert_eq!(log.names(), vec!["done"]);
+	}
+
+	#[test]
+	fn timeout_elapses() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let results = Rc::new(RefCell::new(Vec::new()));
+
+		let quick = results.clone();
+		spawner.spawn(async move {
+			let result = timeout(sleep(ms(5)), ms(10)).await;
+			quick.borrow_mut().push(result);
+		});
+		let slow = results.clone();
+		spawner.spawn(async move {
+			let result = timeout(sleep(ms(50)), ms(10)).await;
+			slow.borrow_mut().push(result);
+		});
+
+		executor.run_until_stalled();
+		clock.advance(ms(5));
+		executor.run_until_stalled();
+		assert_eq!(*results.borrow(), vec![Ok(())]);
+
+		clock.advance(ms(5));
+		executor.run_until_stalled();
+		assert_eq!(*results.borrow(), vec![Ok(()), Err(Elapsed)]);
+	}
+
+	#[test]
+	fn interval_skips_missed_ticks() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let ticks = Rc::new(RefCell::new(Vec::new()));
+
+		let task_ticks = ticks.clone();
+		spawner.spawn(async move {
+			let mut interval = interval(ms(10));
+			loop {
+				let due = interval.tick().await;
+				task_ticks.borrow_mut().push(due);
+			}
+		});
+
+		executor.run_until_stalled();
+		for _ in 0..2 {
+			clock.advance(ms(10));
+			executor.run_until_stalled();
+		}
+		clock.advance(ms(35));
+		executor.run_until_stalled();
+		clock.advance(ms(5));
+		executor.run_until_stalled();
+
+		assert_eq!(*ticks.borrow(), vec![ms(10), ms(20), ms(30), ms(60)]);
+	}
+
+	#[test]
+	fn dropped_sleeps_are_cancelled() {
+		let clock = ManualClock::new();
+		let (executor, spawner) = new_executor_and_spawner_with_clock(clock.clone());
+		let timers = executor.shared.timers.clone();
+
+		spawner.spawn(async move {
+			let _ = timeout(std::future::pending::<()>(), ms(10)).await;
+		});
+		spawner.spawn(async move {
+			let _ = timeout(async {}, ms(20)).await;
+		});
+
+		executor.run_until_stalled();
+		assert_eq!(timers.timers.borrow().len(), 1);
+
+		clock.advance(ms(10));
+		executor.run_until_stalled();
+		assert!(timers.timers.borrow().is_empty());
+	}
+}